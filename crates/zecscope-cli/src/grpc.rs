@@ -0,0 +1,391 @@
+//! gRPC server exposing the scanner, for backend-to-backend integration.
+//!
+//! Serves the `zecscope.v1.Scanner` service of `proto/zecscope.proto`. Like
+//! the darksidewalletd client, its messages and service are declared here by
+//! hand, so no build script is needed.
+
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use tokio::runtime::Runtime;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::body::Body;
+use tonic::codegen::{http, BoxFuture, Service};
+use tonic::server::NamedService;
+use tonic::transport::Server;
+use tonic::Status;
+use zecscope_scanner::{
+    redact_viewing_key, BlockScanResult, BlockSource, CompactBlock, ScanError, ScanResult,
+    ScanState, Scanner, ShieldedPool, ZecTransactionProto,
+};
+
+use crate::metrics::Metrics;
+use crate::{source_error, Source};
+
+/// Path of the `Scan` method.
+const SCAN_PATH: &str = "/zecscope.v1.Scanner/Scan";
+
+/// Scanned blocks buffered for a client before the scan waits for it.
+const BLOCK_BUFFER: usize = 100;
+
+/// `ScanRequest`, whose `Debug` output redacts the viewing key
+#[derive(Clone, PartialEq, prost::Message)]
+#[prost(skip_debug)]
+pub struct ScanRequest {
+    #[prost(string, tag = "1")]
+    pub viewing_key: String,
+    #[prost(string, tag = "2")]
+    pub key_id: String,
+    #[prost(uint64, tag = "3")]
+    pub start_height: u64,
+    #[prost(uint64, tag = "4")]
+    pub end_height: u64,
+    #[prost(message, optional, tag = "5")]
+    pub options: Option<ScanOptions>,
+}
+
+impl std::fmt::Debug for ScanRequest {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ScanRequest")
+            .field("viewing_key", &redact_viewing_key(&self.viewing_key))
+            .field("key_id", &self.key_id)
+            .field("start_height", &self.start_height)
+            .field("end_height", &self.end_height)
+            .field("options", &self.options)
+            .finish()
+    }
+}
+
+/// `ScanOptions`
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ScanOptions {
+    #[prost(bool, tag = "1")]
+    pub include_change: bool,
+    #[prost(string, repeated, tag = "2")]
+    pub pools: Vec<String>,
+    #[prost(uint64, optional, tag = "3")]
+    pub min_value_zat: Option<u64>,
+    #[prost(bool, tag = "4")]
+    pub lenient: bool,
+}
+
+/// `BlockResult`: what a scan found in one block.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct BlockResult {
+    #[prost(uint64, tag = "1")]
+    pub height: u64,
+    #[prost(string, tag = "2")]
+    pub hash: String,
+    #[prost(int64, tag = "3")]
+    pub time: i64,
+    #[prost(message, repeated, tag = "4")]
+    pub transactions: Vec<ZecTransactionProto>,
+}
+
+impl From<BlockScanResult> for BlockResult {
+    fn from(block: BlockScanResult) -> Self {
+        Self {
+            height: block.height,
+            hash: block.hash,
+            time: block.time,
+            transactions: block.transactions.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+/// Opens the block source a scan fetches from.
+pub type Connect = dyn Fn() -> ScanResult<Box<dyn Source>> + Send + Sync;
+
+/// The `zecscope.v1.Scanner` service.
+///
+/// Each scan runs on its own thread, with a source opened by `connect`, and
+/// stops at the next chunk of blocks once its client has gone away.
+#[derive(Clone)]
+pub struct ScannerService {
+    scanner: Arc<Scanner>,
+    connect: Arc<Connect>,
+    metrics: Arc<Metrics>,
+}
+
+impl ScannerService {
+    /// Scan with `scanner`, fetching blocks from sources opened by `connect`.
+    pub fn new(scanner: Scanner, connect: Arc<Connect>) -> Self {
+        Self {
+            scanner: Arc::new(scanner),
+            connect,
+            metrics: Arc::default(),
+        }
+    }
+
+    /// Count scans, and their requests to the block server, in `metrics`.
+    pub fn with_metrics(mut self, metrics: Arc<Metrics>) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
+    /// Serve on `addr` until the process exits.
+    pub fn serve(self, addr: SocketAddr) -> ScanResult<()> {
+        let runtime = Runtime::new().map_err(source_error)?;
+        runtime
+            .block_on(Server::builder().add_service(self).serve(addr))
+            .map_err(source_error)
+    }
+
+    /// Start scanning for `request`, returning the stream of its results.
+    fn scan(&self, request: ScanRequest) -> ReceiverStream<Result<BlockResult, Status>> {
+        let (sender, receiver) = mpsc::channel(BLOCK_BUFFER);
+        let service = self.clone();
+        // Sources block on a runtime of their own, which can't be started
+        // from one of the server's threads
+        std::thread::spawn(move || {
+            if let Err(e) = service.run_scan(request, &sender) {
+                let _ = sender.blocking_send(Err(e));
+            }
+        });
+        ReceiverStream::new(receiver)
+    }
+
+    fn run_scan(
+        &self,
+        request: ScanRequest,
+        sender: &mpsc::Sender<Result<BlockResult, Status>>,
+    ) -> Result<(), Status> {
+        let mut scan = self.metrics.start_scan();
+        let options = request.options.unwrap_or_default();
+        let pools = options
+            .pools
+            .iter()
+            .map(|pool| match pool.as_str() {
+                "sapling" => Ok(ShieldedPool::Sapling),
+                "orchard" => Ok(ShieldedPool::Orchard),
+                "transparent" => Ok(ShieldedPool::Transparent),
+                _ => Err(Status::invalid_argument(format!("unknown pool {pool:?}"))),
+            })
+            .collect::<Result<_, _>>()?;
+        let scan_request = zecscope_scanner::ScanRequest {
+            viewing_key: request.viewing_key,
+            key_id: request.key_id,
+            compact_blocks: Vec::new(),
+            options: zecscope_scanner::ScanOptions {
+                include_change: options.include_change,
+                pools,
+                min_value_zat: options.min_value_zat,
+                lenient: options.lenient,
+                ..Default::default()
+            },
+            nullifiers: Default::default(),
+        };
+        // Reject a bad key before connecting to the server
+        self.scanner
+            .prepare_key(&scan_request.viewing_key)
+            .map_err(status)?;
+
+        let mut source = (self.connect)()
+            .inspect_err(|_| self.metrics.fetch_failed())
+            .map_err(status)?;
+        let mut source = self.metrics.meter(source.as_mut());
+        let end = match request.end_height {
+            0 => source.latest_height().map_err(status)?,
+            end => end,
+        };
+        let mut source = UntilClosed {
+            source: &mut source,
+            sender,
+        };
+        self.scanner
+            .scan_range_with(
+                &mut source,
+                request.start_height,
+                end,
+                &scan_request,
+                &mut ScanState::default(),
+                |block| {
+                    scan.block(&block);
+                    // A client that went away stops the scan at the next chunk
+                    let _ = sender.blocking_send(Ok(block.into()));
+                },
+            )
+            .map_err(status)?;
+        scan.complete();
+        Ok(())
+    }
+}
+
+impl NamedService for ScannerService {
+    const NAME: &'static str = "zecscope.v1.Scanner";
+}
+
+impl Service<http::Request<Body>> for ScannerService {
+    type Response = http::Response<Body>;
+    type Error = Infallible;
+    type Future = BoxFuture<Self::Response, Self::Error>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: http::Request<Body>) -> Self::Future {
+        match request.uri().path() {
+            SCAN_PATH => {
+                let service = self.clone();
+                let method = tower::service_fn(move |request: tonic::Request<ScanRequest>| {
+                    let stream = service.scan(request.into_inner());
+                    async move { Ok::<_, Status>(tonic::Response::new(stream)) }
+                });
+                Box::pin(async move {
+                    let mut grpc = tonic::server::Grpc::new(tonic_prost::ProstCodec::default());
+                    Ok(grpc.server_streaming(method, request).await)
+                })
+            }
+            path => {
+                let response = Status::unimplemented(format!("no method {path}")).into_http();
+                Box::pin(async move { Ok(response) })
+            }
+        }
+    }
+}
+
+/// A source that fails once the client receiving the scan's results has
+/// gone away.
+struct UntilClosed<'a, T> {
+    source: &'a mut dyn Source,
+    sender: &'a mpsc::Sender<T>,
+}
+
+impl<T> BlockSource for UntilClosed<'_, T> {
+    fn get_block_range(&mut self, start: u64, end: u64) -> ScanResult<Vec<CompactBlock>> {
+        if self.sender.is_closed() {
+            return Err(ScanError::BlockSource("client disconnected".to_string()));
+        }
+        self.source.get_block_range(start, end)
+    }
+}
+
+/// The gRPC status a failed scan ends with.
+fn status(e: ScanError) -> Status {
+    match e {
+        ScanError::InvalidViewingKey(_) | ScanError::InvalidRange { .. } => {
+            Status::invalid_argument(e.to_string())
+        }
+        ScanError::BlockSource(_) => Status::unavailable(e.to_string()),
+        _ => Status::internal(e.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tonic::codegen::http::uri::PathAndQuery;
+    use tonic::transport::Channel;
+    use zecscope_scanner::test_vectors::TEST_UFVK;
+    use zecscope_scanner::{MemoryBlockSource, MockChain, RawTransaction, TransactionSource};
+
+    /// A chain held in memory, with a fixed tip.
+    struct TestSource(MemoryBlockSource, u64);
+
+    impl BlockSource for TestSource {
+        fn get_block_range(&mut self, start: u64, end: u64) -> ScanResult<Vec<CompactBlock>> {
+            self.0.get_block_range(start, end)
+        }
+    }
+
+    impl TransactionSource for TestSource {
+        fn get_transactions(&mut self, _txids: &[String]) -> ScanResult<Vec<RawTransaction>> {
+            Ok(Vec::new())
+        }
+    }
+
+    impl Source for TestSource {
+        fn latest_height(&mut self) -> ScanResult<u64> {
+            Ok(self.1)
+        }
+    }
+
+    #[test]
+    fn test_scan_service() {
+        let mut chain = MockChain::new(TEST_UFVK).unwrap();
+        chain.empty_blocks(2);
+        let note = chain.pay_sapling(25_000).unwrap();
+        chain.empty_blocks(2);
+        let start = chain.blocks()[0].height;
+        let tip = chain.next_height() - 1;
+        let blocks = chain.into_blocks();
+        let connect: Arc<Connect> = Arc::new(move || {
+            Ok(
+                Box::new(TestSource(MemoryBlockSource::new(blocks.clone()), tip))
+                    as Box<dyn Source>,
+            )
+        });
+        let service = ScannerService::new(Scanner::mainnet(), connect);
+
+        let runtime = Runtime::new().unwrap();
+        let (results, error) = runtime.block_on(async {
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+            tokio::spawn(
+                Server::builder()
+                    .add_service(service)
+                    .serve_with_incoming(tokio_stream::wrappers::TcpListenerStream::new(listener)),
+            );
+            let channel = Channel::from_shared(format!("http://{addr}"))
+                .unwrap()
+                .connect()
+                .await
+                .unwrap();
+            let grpc = tonic::client::Grpc::new(channel);
+            let call = |request: ScanRequest| {
+                let mut grpc = grpc.clone();
+                async move {
+                    grpc.ready().await.unwrap();
+                    grpc.server_streaming::<_, BlockResult, _>(
+                        tonic::Request::new(request),
+                        PathAndQuery::from_static(SCAN_PATH),
+                        tonic_prost::ProstCodec::default(),
+                    )
+                    .await
+                }
+            };
+
+            let request = ScanRequest {
+                viewing_key: TEST_UFVK.to_string(),
+                key_id: "grpc".to_string(),
+                start_height: start,
+                end_height: 0,
+                options: None,
+            };
+            let mut stream = call(request.clone()).await.unwrap().into_inner();
+            let mut results = Vec::new();
+            while let Some(block) = stream.message().await.unwrap() {
+                results.push(block);
+            }
+            let error = call(ScanRequest {
+                viewing_key: "uview1invalid".to_string(),
+                ..request
+            })
+            .await
+            .unwrap()
+            .into_inner()
+            .message()
+            .await
+            .unwrap_err();
+            (results, error)
+        });
+
+        assert_eq!(results.len() as u64, tip - start + 1);
+        let found: Vec<_> = results
+            .iter()
+            .flat_map(|block| &block.transactions)
+            .collect();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].txid, note.txid);
+        assert_eq!(found[0].amount_zat, "25000");
+        assert_eq!(found[0].direction, "in");
+        assert_eq!(found[0].pool, "sapling");
+        assert_eq!(found[0].key_id, "grpc");
+        // Failures end the stream with a status instead of results
+        assert_eq!(error.code(), tonic::Code::InvalidArgument);
+    }
+}
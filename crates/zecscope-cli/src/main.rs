@@ -0,0 +1,722 @@
+//! `zecscope` — scan a lightwalletd server with a viewing key from the
+//! command line.
+
+mod grpc;
+mod metrics;
+mod push;
+
+use std::io::{self, Write};
+use std::net::SocketAddr;
+use std::process::ExitCode;
+use std::sync::Arc;
+use std::time::Duration;
+
+use clap::{Parser, Subcommand, ValueEnum};
+use zecscope_scanner::{
+    to_csv, Aggregation, BlockSource, ContentType, DarksideClient, LightwalletdSource, Network,
+    Proxy, RetryPolicy, ScanError, ScanOptions, ScanRequest, ScanResult, ScanState, Scanner,
+    ShieldedPool, TransactionSource, TxDirection, ZebradBlockSource, ZecTransaction,
+};
+
+use crate::grpc::ScannerService;
+use crate::metrics::Metrics;
+use crate::push::EventPush;
+
+#[derive(Parser)]
+#[command(name = "zecscope", version, about = "Zcash viewing key scanner")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Scan a block range for transactions visible to a viewing key
+    Scan(ScanArgs),
+    /// Control the chain a darksidewalletd test server serves
+    Darkside(DarksideArgs),
+    /// Print a pool's note commitment subtree roots as JSON
+    SubtreeRoots(SubtreeRootsArgs),
+    /// Serve scans over gRPC (see proto/zecscope.proto)
+    Serve(ServeArgs),
+    /// Scan up to the chain tip, then follow it, printing each transaction
+    /// found as a JSON line
+    Watch(WatchArgs),
+}
+
+#[derive(clap::Args)]
+struct SubtreeRootsArgs {
+    /// lightwalletd gRPC endpoint
+    #[arg(long, default_value = "https://mainnet.lightwalletd.com:9067")]
+    server: String,
+    /// SOCKS5 proxy to connect through, e.g. socks5h://127.0.0.1:9050 for Tor
+    #[arg(long)]
+    proxy: Option<String>,
+    /// Pool whose subtree roots to fetch
+    #[arg(long, value_enum, default_value_t = PoolArg::Sapling)]
+    pool: PoolArg,
+    /// Index of the first subtree root
+    #[arg(long, default_value_t = 0)]
+    start_index: u32,
+    /// Maximum number of roots (0 for all)
+    #[arg(long, default_value_t = 0)]
+    max_entries: u32,
+}
+
+#[derive(clap::Args)]
+struct DarksideArgs {
+    /// darksidewalletd gRPC endpoint
+    #[arg(long, default_value = "http://127.0.0.1:9067")]
+    server: String,
+    #[command(subcommand)]
+    action: DarksideAction,
+}
+
+#[derive(Subcommand)]
+enum DarksideAction {
+    /// Discard the chain and start a new one
+    Reset {
+        /// Sapling activation height
+        #[arg(long, default_value_t = 419_200)]
+        sapling_activation: u64,
+        /// Consensus branch ID to serve blocks under, in hex
+        #[arg(long, default_value = "c2d6d0b4")]
+        branch_id: String,
+        /// Chain name: main or test
+        #[arg(long, default_value = "main")]
+        chain_name: String,
+    },
+    /// Stage blocks from a file of hex-encoded blocks, one per line
+    Stage {
+        /// URL of the file
+        #[arg(long)]
+        url: String,
+    },
+    /// Stage empty blocks
+    StageEmpty {
+        /// Height of the first block
+        #[arg(long)]
+        height: u64,
+        /// Number of blocks
+        #[arg(long, default_value_t = 1)]
+        count: u32,
+        /// Varies the blocks' hashes, for reorgs
+        #[arg(long, default_value_t = 0)]
+        nonce: i32,
+    },
+    /// Add transactions from a file of hex-encoded transactions, one per
+    /// line, to a staged block
+    StageTransactions {
+        /// Height of the staged block
+        #[arg(long)]
+        height: u64,
+        /// URL of the file
+        #[arg(long)]
+        url: String,
+    },
+    /// Serve the staged blocks up to a tip height
+    Apply {
+        /// Height of the new chain tip
+        #[arg(long)]
+        height: u64,
+    },
+    /// Replace the chain from a height with new empty blocks
+    Reorg {
+        /// First height to replace
+        #[arg(long)]
+        height: u64,
+        /// Number of new blocks; the last becomes the tip
+        #[arg(long, default_value_t = 1)]
+        count: u32,
+        /// Varies the blocks' hashes; use a different one per reorg
+        #[arg(long, default_value_t = 1)]
+        nonce: i32,
+    },
+}
+
+#[derive(clap::Args)]
+struct ScanArgs {
+    /// Unified Full Viewing Key (uview1... or uviewtest1...)
+    #[arg(long)]
+    ufvk: String,
+    #[command(flatten)]
+    source: SourceArgs,
+    /// First block height to scan
+    #[arg(long)]
+    start: u64,
+    /// Last block height to scan (defaults to the chain tip)
+    #[arg(long)]
+    end: Option<u64>,
+    /// Output format
+    #[arg(long, value_enum, default_value_t = Format::Table)]
+    format: Format,
+    /// Include change outputs
+    #[arg(long)]
+    include_change: bool,
+    /// Combine entries of the same transaction
+    #[arg(long, value_enum, default_value_t = AggregateArg::None)]
+    aggregate: AggregateArg,
+    /// Skip malformed blocks with a warning instead of aborting the scan
+    #[arg(long)]
+    lenient: bool,
+    /// Trial-decrypt at most this many outputs per block, for a fast scan
+    /// that may miss notes in heavily padded blocks
+    #[arg(long)]
+    max_outputs_per_block: Option<usize>,
+    /// Tag incoming dust that looks like spam with `likelySpam`
+    #[arg(long)]
+    flag_spam: bool,
+    /// Outputs trial-decrypted together; larger batches are faster but
+    /// hold more blocks in memory
+    #[arg(long)]
+    batch_size: Option<usize>,
+    /// Fetch the full transactions found, to add memos, addresses, fees and
+    /// expiry heights
+    #[arg(long)]
+    enhance: bool,
+}
+
+#[derive(clap::Args)]
+struct WatchArgs {
+    /// Unified Full Viewing Key (uview1... or uviewtest1...)
+    #[arg(long)]
+    ufvk: String,
+    #[command(flatten)]
+    source: SourceArgs,
+    /// First block height to scan
+    #[arg(long)]
+    start: u64,
+    /// Include change outputs
+    #[arg(long)]
+    include_change: bool,
+    /// Seconds between checks for new blocks
+    #[arg(long, default_value_t = 30)]
+    poll_interval_secs: u64,
+    /// Also push each transaction to Server-Sent Events clients connecting
+    /// to this address
+    #[arg(long)]
+    sse: Option<SocketAddr>,
+    /// Serve Prometheus metrics to `GET /metrics` requests on this address
+    #[arg(long)]
+    metrics: Option<SocketAddr>,
+}
+
+#[derive(clap::Args)]
+struct ServeArgs {
+    /// Address to listen on for gRPC clients
+    #[arg(long, default_value = "127.0.0.1:50051")]
+    listen: SocketAddr,
+    #[command(flatten)]
+    source: SourceArgs,
+    /// Serve Prometheus metrics to `GET /metrics` requests on this address
+    #[arg(long)]
+    metrics: Option<SocketAddr>,
+}
+
+/// Where blocks are fetched from.
+#[derive(clap::Args, Clone)]
+struct SourceArgs {
+    /// Kind of server to fetch blocks from
+    #[arg(long = "source", value_enum, default_value_t = SourceArg::Lightwalletd)]
+    kind: SourceArg,
+    /// Server URL (defaults to a public lightwalletd server, or a local
+    /// zebrad's RPC port)
+    #[arg(long)]
+    server: Option<String>,
+    /// zebrad RPC credentials as USER:PASSWORD (e.g. the cookie file contents)
+    #[arg(long)]
+    rpc_auth: Option<String>,
+    /// SOCKS5 proxy to connect through, e.g. socks5h://127.0.0.1:9050 for Tor
+    #[arg(long)]
+    proxy: Option<String>,
+    /// Times to retry a failed request before giving up
+    #[arg(long, default_value_t = 3)]
+    retries: u32,
+    /// Wait before the first retry in milliseconds, doubling on each retry
+    #[arg(long, default_value_t = 500)]
+    retry_delay_ms: u64,
+    /// Network the key and server belong to
+    #[arg(long, value_enum, default_value_t = NetworkArg::Mainnet)]
+    network: NetworkArg,
+}
+
+impl SourceArgs {
+    fn network(&self) -> Network {
+        match self.network {
+            NetworkArg::Mainnet => Network::MainNetwork,
+            NetworkArg::Testnet => Network::TestNetwork,
+        }
+    }
+
+    /// Connect to the server.
+    fn connect(&self) -> ScanResult<Box<dyn Source>> {
+        let proxy = self.proxy.as_deref().map(Proxy::parse).transpose()?;
+        let retry = RetryPolicy {
+            max_retries: self.retries,
+            initial_backoff: Duration::from_millis(self.retry_delay_ms),
+            ..RetryPolicy::default()
+        };
+        match self.kind {
+            SourceArg::Lightwalletd => {
+                let url = self
+                    .server
+                    .as_deref()
+                    .unwrap_or("https://mainnet.lightwalletd.com:9067");
+                let source = LightwalletdSource::connect(url, proxy.as_ref())?.with_retry(retry);
+                Ok(Box::new(source))
+            }
+            SourceArg::Zebrad => {
+                let url = self.server.as_deref().unwrap_or(match self.network {
+                    NetworkArg::Mainnet => "http://127.0.0.1:8232",
+                    NetworkArg::Testnet => "http://127.0.0.1:18232",
+                });
+                let mut source = ZebradBlockSource::new(url, self.network()).with_retry(retry);
+                if let Some(credentials) = &self.rpc_auth {
+                    source = source.with_auth(credentials);
+                }
+                if let Some(proxy) = &proxy {
+                    source = source.with_proxy(proxy)?;
+                }
+                Ok(Box::new(source))
+            }
+        }
+    }
+}
+
+/// Wrap an I/O or transport error, keeping its causes.
+pub(crate) fn source_error<E: std::error::Error>(e: E) -> ScanError {
+    let mut message = e.to_string();
+    let mut source = e.source();
+    while let Some(cause) = source {
+        message.push_str(&format!(": {cause}"));
+        source = cause.source();
+    }
+    ScanError::BlockSource(message)
+}
+
+/// A server that serves both compact blocks and full transactions.
+trait Source: BlockSource + TransactionSource {
+    /// Height of the server's current chain tip.
+    fn latest_height(&mut self) -> ScanResult<u64>;
+}
+
+impl Source for LightwalletdSource {
+    fn latest_height(&mut self) -> ScanResult<u64> {
+        LightwalletdSource::latest_height(self)
+    }
+}
+
+impl Source for ZebradBlockSource {
+    fn latest_height(&mut self) -> ScanResult<u64> {
+        ZebradBlockSource::latest_height(self)
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum SourceArg {
+    Lightwalletd,
+    Zebrad,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum NetworkArg {
+    Mainnet,
+    Testnet,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum PoolArg {
+    Sapling,
+    Orchard,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum AggregateArg {
+    None,
+    TxidPool,
+    Txid,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Format {
+    Table,
+    Json,
+    Csv,
+    /// Binary CBOR of the JSON fields
+    Cbor,
+    /// Binary MessagePack of the JSON fields
+    Msgpack,
+}
+
+fn main() -> ExitCode {
+    let result = match Cli::parse().command {
+        Command::Scan(args) => scan(args),
+        Command::Darkside(args) => darkside(args),
+        Command::SubtreeRoots(args) => subtree_roots(args),
+        Command::Serve(args) => serve(args),
+        Command::Watch(args) => watch(args),
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn scan(args: ScanArgs) -> ScanResult<()> {
+    let scanner = Scanner::new(args.source.network());
+
+    // Reject a bad key before connecting to the server
+    scanner.prepare_key(&args.ufvk)?;
+    let mut source = args.source.connect()?;
+    let end = match args.end {
+        Some(end) => end,
+        None => source.latest_height()?,
+    };
+
+    let request = ScanRequest {
+        viewing_key: args.ufvk,
+        key_id: "cli".to_string(),
+        compact_blocks: Vec::new(),
+        options: ScanOptions {
+            include_change: args.include_change,
+            aggregation: match args.aggregate {
+                AggregateArg::None => Aggregation::None,
+                AggregateArg::TxidPool => Aggregation::TxidPool,
+                AggregateArg::Txid => Aggregation::Txid,
+            },
+            lenient: args.lenient,
+            max_outputs_per_block: args.max_outputs_per_block,
+            flag_spam: args.flag_spam,
+            batch_size: args.batch_size,
+            ..ScanOptions::default()
+        },
+        nullifiers: Default::default(),
+    };
+    let mut summary = scanner.scan_range(source.as_mut(), args.start, end, &request)?;
+    if args.enhance {
+        scanner.enhance(
+            &request.viewing_key,
+            &mut summary.transactions,
+            source.as_mut(),
+        )?;
+    }
+    for warning in &summary.warnings {
+        match warning.height {
+            Some(height) => eprintln!("warning: skipped block {height}: {}", warning.reason),
+            None => eprintln!("warning: skipped block: {}", warning.reason),
+        }
+    }
+    if summary.skipped_outputs > 0 {
+        eprintln!(
+            "warning: {} output(s) past --max-outputs-per-block not trial-decrypted; notes in them are missed",
+            summary.skipped_outputs
+        );
+    }
+    if summary.incoming_only {
+        eprintln!(
+            "warning: scanned with an incoming viewing key; spends, outgoing payments and change are not shown"
+        );
+    }
+    let orchard = summary.decryption_stats.orchard;
+    if orchard.attempted < orchard.seen {
+        eprintln!(
+            "warning: {} Orchard action(s) not trial-decrypted; the scanner was built without the `orchard` feature",
+            orchard.seen - orchard.attempted
+        );
+    }
+
+    match args.format {
+        Format::Json => println!("{}", serde_json::to_string_pretty(&summary.transactions)?),
+        Format::Csv => print!("{}", to_csv(&summary.transactions)),
+        Format::Cbor | Format::Msgpack => {
+            let content_type = match args.format {
+                Format::Cbor => ContentType::Cbor,
+                _ => ContentType::MessagePack,
+            };
+            let bytes = content_type.encode(&summary.transactions)?;
+            io::stdout()
+                .write_all(&bytes)
+                .map_err(|e| ScanError::Encoding(e.to_string()))?;
+        }
+        Format::Table => {
+            print!("{}", format_table(&summary.transactions));
+            eprintln!(
+                "Scanned blocks {}..={}: {} transaction(s), {} output(s) and {} action(s) in {} ms",
+                summary.start_height,
+                summary.end_height,
+                summary.transactions.len(),
+                summary.outputs_scanned,
+                summary.actions_scanned,
+                summary.elapsed_ms
+            );
+        }
+    }
+    Ok(())
+}
+
+fn serve(args: ServeArgs) -> ScanResult<()> {
+    let scanner = Scanner::new(args.source.network());
+    let source = args.source;
+    let metrics = Arc::new(Metrics::default());
+    serve_metrics(&metrics, args.metrics)?;
+    let service =
+        ScannerService::new(scanner, Arc::new(move || source.connect())).with_metrics(metrics);
+    eprintln!("Serving zecscope.v1.Scanner on {}", args.listen);
+    service.serve(args.listen)
+}
+
+fn watch(args: WatchArgs) -> ScanResult<()> {
+    let scanner = Scanner::new(args.source.network());
+    scanner.prepare_key(&args.ufvk)?;
+    let metrics = Arc::new(Metrics::default());
+    serve_metrics(&metrics, args.metrics)?;
+    let mut source = args
+        .source
+        .connect()
+        .inspect_err(|_| metrics.fetch_failed())?;
+    let mut source = metrics.meter(source.as_mut());
+    let push = args.sse.map(EventPush::bind).transpose()?;
+    if let Some(push) = &push {
+        eprintln!("Pushing events to clients of http://{}", push.local_addr());
+    }
+
+    let request = ScanRequest {
+        viewing_key: args.ufvk,
+        key_id: "cli".to_string(),
+        compact_blocks: Vec::new(),
+        options: ScanOptions {
+            include_change: args.include_change,
+            ..ScanOptions::default()
+        },
+        nullifiers: Default::default(),
+    };
+    // Carries found notes across polls, so their spends are reported
+    let mut state = ScanState::default();
+    // States at the end of recent polls, to roll back to on a reorg
+    let mut checkpoints = Vec::new();
+    let retry = RetryPolicy {
+        initial_backoff: Duration::from_secs(args.poll_interval_secs),
+        max_backoff: MAX_WATCH_BACKOFF,
+        ..RetryPolicy::default()
+    };
+    let mut failures = 0;
+    loop {
+        let start = state.next_height().unwrap_or(args.start);
+        let poll = source.latest_height().and_then(|tip| {
+            metrics.set_chain_tip(tip);
+            let end = tip.min(start.saturating_add(MAX_POLL_BLOCKS - 1));
+            if end < start {
+                if let Some(push) = &push {
+                    push.keepalive();
+                }
+                return Ok(true);
+            }
+            let mut scan = metrics.start_scan();
+            scanner.scan_range_with(&mut source, start, end, &request, &mut state, |block| {
+                scan.block(&block);
+                for tx in &block.transactions {
+                    let json = serde_json::to_string(tx).unwrap_or_default();
+                    println!("{json}");
+                    if let Some(push) = &push {
+                        push.send("transaction", &json);
+                    }
+                }
+            })?;
+            scan.complete();
+            if end < tip {
+                return Ok(false);
+            }
+            if let Some(push) = &push {
+                push.send("tip", &format!("{{\"height\":{tip}}}"));
+            }
+            Ok(true)
+        });
+        let wait = match poll {
+            Ok(caught_up) => {
+                failures = 0;
+                record_checkpoint(&mut checkpoints, &state);
+                caught_up.then(|| Duration::from_secs(args.poll_interval_secs))
+            }
+            Err(ScanError::ChainDiscontinuity { height, .. }) => {
+                state = roll_back(&mut checkpoints, height);
+                let from = state.next_height().unwrap_or(args.start);
+                eprintln!("Chain reorganized at height {height}; rescanning from {from}");
+                if let Some(push) = &push {
+                    push.send("reorg", &format!("{{\"height\":{from}}}"));
+                }
+                None
+            }
+            Err(e @ ScanError::BlockSource(_)) => {
+                let wait = retry.backoff(failures);
+                failures = failures.saturating_add(1);
+                eprintln!("{e}; retrying in {}s", wait.as_secs());
+                Some(wait)
+            }
+            Err(e) => return Err(e),
+        };
+        if let Some(wait) = wait {
+            std::thread::sleep(wait);
+        }
+    }
+}
+
+/// Most blocks `watch` scans in one poll, so that catching up still leaves
+/// checkpoints to roll back to.
+const MAX_POLL_BLOCKS: u64 = 1_000;
+
+/// Most checkpoints `watch` keeps.
+const MAX_CHECKPOINTS: usize = 100;
+
+/// Longest `watch` waits before retrying a failing block source.
+const MAX_WATCH_BACKOFF: Duration = Duration::from_secs(600);
+
+/// Keep `state` as the latest of `checkpoints`, dropping the oldest beyond
+/// [`MAX_CHECKPOINTS`].
+fn record_checkpoint(checkpoints: &mut Vec<ScanState>, state: &ScanState) {
+    if checkpoints.last() == Some(state) {
+        return;
+    }
+    if checkpoints.len() >= MAX_CHECKPOINTS {
+        checkpoints.remove(0);
+    }
+    checkpoints.push(state.clone());
+}
+
+/// The state to rescan from after the block at `height` failed to follow
+/// the one before it: the latest checkpoint below `height - 1`, or a fresh
+/// state if none is left. A fork deeper than that fails again further down,
+/// rolling back again.
+fn roll_back(checkpoints: &mut Vec<ScanState>, height: u64) -> ScanState {
+    while checkpoints
+        .last()
+        .is_some_and(|c| c.last_height() >= Some(height.saturating_sub(1)))
+    {
+        checkpoints.pop();
+    }
+    checkpoints.last().cloned().unwrap_or_default()
+}
+
+/// Serve `metrics` on `addr`, if given.
+fn serve_metrics(metrics: &Arc<Metrics>, addr: Option<SocketAddr>) -> ScanResult<()> {
+    if let Some(addr) = addr {
+        let addr = metrics.serve(addr)?;
+        eprintln!("Serving metrics on http://{addr}/metrics");
+    }
+    Ok(())
+}
+
+fn darkside(args: DarksideArgs) -> ScanResult<()> {
+    let mut client = DarksideClient::connect(&args.server)?;
+    match args.action {
+        DarksideAction::Reset {
+            sapling_activation,
+            branch_id,
+            chain_name,
+        } => client.reset(sapling_activation, &branch_id, &chain_name),
+        DarksideAction::Stage { url } => client.stage_blocks(&url),
+        DarksideAction::StageEmpty {
+            height,
+            count,
+            nonce,
+        } => client.stage_empty_blocks(height, count, nonce),
+        DarksideAction::StageTransactions { height, url } => {
+            client.stage_transactions(height, &url)
+        }
+        DarksideAction::Apply { height } => client.apply_staged(height),
+        DarksideAction::Reorg {
+            height,
+            count,
+            nonce,
+        } => client.reorg(height, count, nonce),
+    }
+}
+
+fn subtree_roots(args: SubtreeRootsArgs) -> ScanResult<()> {
+    let proxy = args.proxy.as_deref().map(Proxy::parse).transpose()?;
+    let mut source = LightwalletdSource::connect(&args.server, proxy.as_ref())?;
+    let pool = match args.pool {
+        PoolArg::Sapling => ShieldedPool::Sapling,
+        PoolArg::Orchard => ShieldedPool::Orchard,
+    };
+    let roots = source.subtree_roots(pool, args.start_index, args.max_entries)?;
+    println!("{}", serde_json::to_string_pretty(&roots)?);
+    Ok(())
+}
+
+/// Render transactions as a plain-text table, one row per transaction.
+fn format_table(transactions: &[ZecTransaction]) -> String {
+    let mut table = format!(
+        "{:>8}  {:<64}  {:<3}  {:<9}  {:>16}  {}\n",
+        "HEIGHT", "TXID", "DIR", "POOL", "AMOUNT (ZEC)", "MEMO"
+    );
+    for tx in transactions {
+        let direction = match tx.direction {
+            TxDirection::In => "in",
+            TxDirection::Out => "out",
+        };
+        table.push_str(&format!(
+            "{:>8}  {:<64}  {:<3}  {:<9}  {:>16.8}  {}\n",
+            tx.height,
+            tx.txid,
+            direction,
+            tx.pool.to_string(),
+            tx.amount_zec(),
+            tx.memo.as_deref().unwrap_or("").replace('\n', " ")
+        ));
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    #[test]
+    fn test_cli() {
+        Cli::command().debug_assert();
+    }
+
+    fn state_at(height: u64) -> ScanState {
+        ScanState {
+            last_block: Some(zecscope_scanner::BlockCheckpoint {
+                height,
+                hash: hex::encode(height.to_le_bytes()),
+                sapling_tree_size: None,
+                orchard_tree_size: None,
+            }),
+            ..ScanState::default()
+        }
+    }
+
+    #[test]
+    fn test_roll_back() {
+        let mut checkpoints = Vec::new();
+        for height in [100, 110, 120, 120] {
+            record_checkpoint(&mut checkpoints, &state_at(height));
+        }
+        assert_eq!(checkpoints.len(), 3);
+
+        // Block 121 doesn't follow 120: 120 is stale, rescan from 110
+        assert_eq!(roll_back(&mut checkpoints, 121), state_at(110));
+        assert_eq!(checkpoints.len(), 2);
+        // Block 111 doesn't follow 110 either
+        assert_eq!(roll_back(&mut checkpoints, 111), state_at(100));
+        // Nothing left to roll back to
+        assert_eq!(roll_back(&mut checkpoints, 50), ScanState::default());
+        assert!(checkpoints.is_empty());
+    }
+
+    #[test]
+    fn test_checkpoints_capped() {
+        let mut checkpoints = Vec::new();
+        for height in 0..MAX_CHECKPOINTS as u64 + 5 {
+            record_checkpoint(&mut checkpoints, &state_at(height));
+        }
+        assert_eq!(checkpoints.len(), MAX_CHECKPOINTS);
+        assert_eq!(checkpoints[0], state_at(5));
+    }
+}
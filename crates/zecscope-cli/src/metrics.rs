@@ -0,0 +1,360 @@
+//! Prometheus metrics of the scans `serve` and `watch` run, so operators can
+//! alert when scanning stalls or the block server keeps failing.
+//!
+//! Metrics are counted in process and served, in the Prometheus text format,
+//! to `GET /metrics` requests on a port of their own.
+
+use std::fmt::Write as _;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use zecscope_scanner::{
+    BlockScanResult, BlockSource, CompactBlock, RawTransaction, ScanResult, ShieldedPool,
+    TransactionSource,
+};
+
+use crate::{source_error, Source};
+
+/// How long a scraper may take to send its request or read the response
+/// before it is dropped.
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Counters and gauges of the scans run by this process.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    scans_started: AtomicU64,
+    scans_completed: AtomicU64,
+    scans_failed: AtomicU64,
+    blocks_scanned: AtomicU64,
+    /// Notes decrypted, per pool: Sapling, Orchard, transparent
+    notes_decrypted: [AtomicU64; 3],
+    fetch_errors: AtomicU64,
+    /// `f64` bits of the rate of the last scan completed
+    blocks_per_second: AtomicU64,
+    scanned_height: AtomicU64,
+    chain_tip_height: AtomicU64,
+}
+
+impl Metrics {
+    /// Count a scan as started, returning the handle to record its
+    /// progress with.
+    pub fn start_scan(&self) -> ActiveScan<'_> {
+        self.scans_started.fetch_add(1, Ordering::Relaxed);
+        ActiveScan {
+            metrics: self,
+            started: Instant::now(),
+            blocks: 0,
+            completed: false,
+        }
+    }
+
+    /// Count a failed request to the block server.
+    pub fn fetch_failed(&self) {
+        self.fetch_errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Record the height of the block server's chain tip.
+    pub fn set_chain_tip(&self, height: u64) {
+        self.chain_tip_height.store(height, Ordering::Relaxed);
+    }
+
+    /// Wrap `source`, counting its failed requests as fetch errors.
+    pub fn meter<'a>(&'a self, source: &'a mut dyn Source) -> Metered<'a> {
+        Metered {
+            source,
+            metrics: self,
+        }
+    }
+
+    /// The metrics in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let load = |value: &AtomicU64| value.load(Ordering::Relaxed);
+        let started = load(&self.scans_started);
+        let completed = load(&self.scans_completed);
+        let failed = load(&self.scans_failed);
+        let notes = [
+            ShieldedPool::Sapling,
+            ShieldedPool::Orchard,
+            ShieldedPool::Transparent,
+        ]
+        .map(|pool| {
+            let count = load(&self.notes_decrypted[pool_index(pool)]);
+            (format!("{{pool=\"{pool}\"}}"), count as f64)
+        });
+
+        let mut out = String::new();
+        let mut metric = |name: &str, kind: &str, help: &str, samples: &[(String, f64)]| {
+            let _ = writeln!(out, "# HELP {name} {help}");
+            let _ = writeln!(out, "# TYPE {name} {kind}");
+            for (labels, value) in samples {
+                let _ = writeln!(out, "{name}{labels} {value}");
+            }
+        };
+        let value = |value: u64| [(String::new(), value as f64)];
+        metric(
+            "zecscope_scans_started_total",
+            "counter",
+            "Scans started.",
+            &value(started),
+        );
+        metric(
+            "zecscope_scans_completed_total",
+            "counter",
+            "Scans that reached their end height.",
+            &value(completed),
+        );
+        metric(
+            "zecscope_scans_failed_total",
+            "counter",
+            "Scans that stopped with an error.",
+            &value(failed),
+        );
+        metric(
+            "zecscope_scans_active",
+            "gauge",
+            "Scans running.",
+            &value(started.saturating_sub(completed + failed)),
+        );
+        metric(
+            "zecscope_blocks_scanned_total",
+            "counter",
+            "Blocks scanned.",
+            &value(load(&self.blocks_scanned)),
+        );
+        metric(
+            "zecscope_scan_blocks_per_second",
+            "gauge",
+            "Blocks per second of the last scan completed.",
+            &[(String::new(), f64::from_bits(load(&self.blocks_per_second)))],
+        );
+        metric(
+            "zecscope_notes_decrypted_total",
+            "counter",
+            "Notes decrypted with the scanned keys, including change.",
+            &notes,
+        );
+        metric(
+            "zecscope_fetch_errors_total",
+            "counter",
+            "Failed requests to the block server, after retries.",
+            &value(load(&self.fetch_errors)),
+        );
+        metric(
+            "zecscope_scanned_height",
+            "gauge",
+            "Highest block height scanned.",
+            &value(load(&self.scanned_height)),
+        );
+        metric(
+            "zecscope_chain_tip_height",
+            "gauge",
+            "Height of the block server's chain tip when last asked.",
+            &value(load(&self.chain_tip_height)),
+        );
+        out
+    }
+
+    /// Serve the metrics to `GET /metrics` requests on `addr`, on a thread
+    /// of their own. Returns the address listened on.
+    pub fn serve(self: &Arc<Self>, addr: SocketAddr) -> ScanResult<SocketAddr> {
+        let listener = TcpListener::bind(addr).map_err(source_error)?;
+        let addr = listener.local_addr().map_err(source_error)?;
+        let metrics = Arc::clone(self);
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                // A failed scrape only loses that scrape
+                let _ = stream.and_then(|stream| respond(stream, &metrics));
+            }
+        });
+        Ok(addr)
+    }
+}
+
+/// A scan counted by [`Metrics::start_scan`]. Dropping it before calling
+/// [`complete`](ActiveScan::complete) counts the scan as failed.
+pub struct ActiveScan<'a> {
+    metrics: &'a Metrics,
+    started: Instant,
+    blocks: u64,
+    completed: bool,
+}
+
+impl ActiveScan<'_> {
+    /// Record a scanned block.
+    pub fn block(&mut self, block: &BlockScanResult) {
+        let metrics = self.metrics;
+        self.blocks += 1;
+        metrics.blocks_scanned.fetch_add(1, Ordering::Relaxed);
+        metrics
+            .scanned_height
+            .fetch_max(block.height, Ordering::Relaxed);
+        for note in &block.received_notes {
+            metrics.notes_decrypted[pool_index(note.pool)].fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Count the scan as completed.
+    pub fn complete(mut self) {
+        self.completed = true;
+        let metrics = self.metrics;
+        metrics.scans_completed.fetch_add(1, Ordering::Relaxed);
+        let elapsed = self.started.elapsed().as_secs_f64();
+        if elapsed > 0.0 {
+            let rate = self.blocks as f64 / elapsed;
+            metrics
+                .blocks_per_second
+                .store(rate.to_bits(), Ordering::Relaxed);
+        }
+    }
+}
+
+impl Drop for ActiveScan<'_> {
+    fn drop(&mut self) {
+        if !self.completed {
+            self.metrics.scans_failed.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// A source whose failed requests [`Metrics::meter`] counts.
+pub struct Metered<'a> {
+    source: &'a mut dyn Source,
+    metrics: &'a Metrics,
+}
+
+impl Metered<'_> {
+    fn count<T>(&self, result: ScanResult<T>) -> ScanResult<T> {
+        if result.is_err() {
+            self.metrics.fetch_failed();
+        }
+        result
+    }
+}
+
+impl BlockSource for Metered<'_> {
+    fn get_block_range(&mut self, start: u64, end: u64) -> ScanResult<Vec<CompactBlock>> {
+        let result = self.source.get_block_range(start, end);
+        self.count(result)
+    }
+}
+
+impl TransactionSource for Metered<'_> {
+    fn get_transactions(&mut self, txids: &[String]) -> ScanResult<Vec<RawTransaction>> {
+        let result = self.source.get_transactions(txids);
+        self.count(result)
+    }
+}
+
+impl Source for Metered<'_> {
+    fn latest_height(&mut self) -> ScanResult<u64> {
+        let result = self.source.latest_height();
+        self.count(result)
+    }
+}
+
+fn pool_index(pool: ShieldedPool) -> usize {
+    match pool {
+        ShieldedPool::Sapling => 0,
+        ShieldedPool::Orchard => 1,
+        ShieldedPool::Transparent => 2,
+    }
+}
+
+/// Answer a scraper's request with the metrics, or a 404 for any path but
+/// `/metrics`.
+fn respond(mut stream: TcpStream, metrics: &Metrics) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+    stream.set_write_timeout(Some(CLIENT_TIMEOUT))?;
+    let mut reader = BufReader::new(&stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 || line.trim_end().is_empty() {
+            break;
+        }
+    }
+    let path = request_line.split_whitespace().nth(1).unwrap_or_default();
+    let (status, content_type, body) = match path.split('?').next() {
+        Some("/metrics") => (
+            "200 OK",
+            "text/plain; version=0.0.4; charset=utf-8",
+            metrics.render(),
+        ),
+        _ => ("404 Not Found", "text/plain", "not found\n".to_string()),
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    )?;
+    stream.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    fn get(addr: SocketAddr, path: &str) -> String {
+        let mut client = TcpStream::connect(addr).unwrap();
+        client.set_read_timeout(Some(CLIENT_TIMEOUT)).unwrap();
+        write!(client, "GET {path} HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+        let mut response = String::new();
+        client.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn test_metrics() {
+        let metrics = Arc::new(Metrics::default());
+        let block = |height: u64| -> BlockScanResult {
+            serde_json::from_value(serde_json::json!({
+                "height": height,
+                "hash": "00",
+                "time": 0,
+                "transactions": [],
+                "receivedNotes": [{
+                    "pool": "orchard",
+                    "nullifier": "00",
+                    "valueZat": "1000",
+                }],
+            }))
+            .unwrap()
+        };
+        let mut scan = metrics.start_scan();
+        scan.block(&block(2_000_000));
+        scan.block(&block(2_000_001));
+        scan.complete();
+        drop(metrics.start_scan());
+        let _running = metrics.start_scan();
+        metrics.fetch_failed();
+        metrics.set_chain_tip(2_000_010);
+
+        let addr = metrics.serve("127.0.0.1:0".parse().unwrap()).unwrap();
+        let response = get(addr, "/metrics");
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        assert!(head.starts_with("HTTP/1.1 200 OK"));
+        assert!(head.contains("Content-Type: text/plain; version=0.0.4"));
+        for sample in [
+            "zecscope_scans_started_total 3",
+            "zecscope_scans_completed_total 1",
+            "zecscope_scans_failed_total 1",
+            "zecscope_scans_active 1",
+            "zecscope_blocks_scanned_total 2",
+            "zecscope_notes_decrypted_total{pool=\"orchard\"} 2",
+            "zecscope_notes_decrypted_total{pool=\"sapling\"} 0",
+            "zecscope_fetch_errors_total 1",
+            "zecscope_scanned_height 2000001",
+            "zecscope_chain_tip_height 2000010",
+        ] {
+            assert!(body.lines().any(|line| line == sample), "missing {sample}");
+        }
+        assert!(body.contains("# TYPE zecscope_blocks_scanned_total counter\n"));
+        assert!(get(addr, "/").starts_with("HTTP/1.1 404 Not Found"));
+    }
+}
@@ -0,0 +1,135 @@
+//! Server-Sent Events push of what a watch finds, for dashboards and
+//! payment processors that want real-time notifications.
+//!
+//! Any `GET` request to the server opens an event stream; browsers can
+//! subscribe with `new EventSource(url)`. Events are sent as they happen and
+//! not replayed, so a client only sees what is found after it connects.
+
+use std::io::{BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+
+use zecscope_scanner::ScanResult;
+
+use crate::source_error;
+
+/// How long a client may take to send its request or accept an event
+/// before it is dropped.
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Response head opening an event stream.
+const STREAM_HEAD: &str = "HTTP/1.1 200 OK\r\n\
+    Content-Type: text/event-stream\r\n\
+    Cache-Control: no-cache\r\n\
+    Connection: keep-alive\r\n\
+    Access-Control-Allow-Origin: *\r\n\r\n";
+
+/// A Server-Sent Events server, pushing each event to every connected
+/// client.
+pub struct EventPush {
+    addr: SocketAddr,
+    clients: Arc<Mutex<Vec<TcpStream>>>,
+}
+
+impl EventPush {
+    /// Listen for clients on `addr`, accepting them on a thread of their own.
+    pub fn bind(addr: SocketAddr) -> ScanResult<Self> {
+        let listener = TcpListener::bind(addr).map_err(source_error)?;
+        let addr = listener.local_addr().map_err(source_error)?;
+        let clients = Arc::new(Mutex::new(Vec::new()));
+        let accepted = Arc::clone(&clients);
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                // A client that fails to open its stream only loses that stream
+                if let Ok(stream) = stream.and_then(open_stream) {
+                    lock(&accepted).push(stream);
+                }
+            }
+        });
+        Ok(Self { addr, clients })
+    }
+
+    /// Address the server listens on.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Send `data`, a line of JSON, to every client as an `event` event.
+    /// Clients that can't be written to are dropped.
+    pub fn send(&self, event: &str, data: &str) {
+        self.write(&format!("event: {event}\ndata: {data}\n\n"));
+    }
+
+    /// Send a comment, which clients ignore, to find out which of them have
+    /// gone away while nothing was sent.
+    pub fn keepalive(&self) {
+        self.write(": keepalive\n\n");
+    }
+
+    fn write(&self, message: &str) {
+        lock(&self.clients).retain_mut(|client| {
+            client
+                .write_all(message.as_bytes())
+                .and_then(|()| client.flush())
+                .is_ok()
+        });
+    }
+}
+
+/// Read a client's request and answer it with the head of an event stream.
+fn open_stream(mut stream: TcpStream) -> std::io::Result<TcpStream> {
+    stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+    stream.set_write_timeout(Some(CLIENT_TIMEOUT))?;
+    let mut reader = BufReader::new(&stream);
+    let mut line = String::new();
+    // The request itself doesn't matter, only that it has been sent
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 || line.trim_end().is_empty() {
+            break;
+        }
+    }
+    stream.write_all(STREAM_HEAD.as_bytes())?;
+    Ok(stream)
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use std::time::Instant;
+
+    #[test]
+    fn test_event_push() {
+        let push = EventPush::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        let mut client = TcpStream::connect(push.local_addr()).unwrap();
+        client.set_read_timeout(Some(CLIENT_TIMEOUT)).unwrap();
+        client
+            .write_all(b"GET /events HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .unwrap();
+        let started = Instant::now();
+        while lock(&push.clients).is_empty() {
+            assert!(started.elapsed() < CLIENT_TIMEOUT, "client not accepted");
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        push.send("transaction", r#"{"txid":"aa"}"#);
+        let mut response = Vec::new();
+        let mut buf = [0; 256];
+        while !response.ends_with(b"\n\n") {
+            let read = client.read(&mut buf).unwrap();
+            assert!(read > 0, "stream closed");
+            response.extend_from_slice(&buf[..read]);
+        }
+        let response = String::from_utf8(response).unwrap();
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        assert!(head.starts_with("HTTP/1.1 200 OK"));
+        assert!(head.contains("Content-Type: text/event-stream"));
+        assert_eq!(body, "event: transaction\ndata: {\"txid\":\"aa\"}\n\n");
+    }
+}
@@ -0,0 +1,44 @@
+/*
+ * C interface to zecscope-scanner.
+ *
+ * Requests and results are JSON. Strings returned by zecscope_scan_json are
+ * owned by the caller and must be released with zecscope_string_free.
+ */
+
+#ifndef ZECSCOPE_H
+#define ZECSCOPE_H
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/*
+ * Scan compact blocks described by a JSON request:
+ *
+ *   {"network": "mainnet", "viewing_key": "uview1...", "key_id": "...",
+ *    "compact_blocks": [...], "options": {...}}
+ *
+ * "network" ("mainnet" or "testnet") and "options" are optional. Returns a
+ * JSON array of discovered transactions, or NULL on error (see
+ * zecscope_last_error).
+ */
+char *zecscope_scan_json(const char *request_json);
+
+/*
+ * Message describing the last error on the calling thread, or NULL if the
+ * last scan succeeded. Owned by the library: valid until the next scan on
+ * the same thread, and must not be freed.
+ */
+const char *zecscope_last_error(void);
+
+/* Free a string returned by zecscope_scan_json. NULL is ignored. */
+void zecscope_string_free(char *s);
+
+/* Library version, as a static string. */
+const char *zecscope_version(void);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* ZECSCOPE_H */
@@ -0,0 +1,189 @@
+//! C ABI for zecscope-scanner.
+//!
+//! Lets C, C++, Go (through cgo) and anything else that can call C embed
+//! the scanner without going through WASM. The interface is JSON in, JSON
+//! out, mirroring [`Scanner::scan_json`]; `include/zecscope.h` declares it.
+//!
+//! Strings returned by the library are owned by the caller and must be
+//! released with [`zecscope_string_free`]. Functions that fail return NULL
+//! and record a message that [`zecscope_last_error`] returns.
+
+use std::cell::RefCell;
+use std::ffi::{c_char, CStr, CString};
+use std::panic::{self, AssertUnwindSafe};
+
+use zecscope_scanner::{Network, ScanRequest, Scanner};
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// A [`ScanRequest`] with the network to scan on.
+#[derive(serde::Deserialize)]
+struct FfiScanRequest {
+    #[serde(default)]
+    network: FfiNetwork,
+    #[serde(flatten)]
+    request: ScanRequest,
+}
+
+#[derive(serde::Deserialize, Default, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+enum FfiNetwork {
+    #[default]
+    Mainnet,
+    Testnet,
+}
+
+impl From<FfiNetwork> for Network {
+    fn from(network: FfiNetwork) -> Self {
+        match network {
+            FfiNetwork::Mainnet => Network::MainNetwork,
+            FfiNetwork::Testnet => Network::TestNetwork,
+        }
+    }
+}
+
+/// Scan compact blocks described by a JSON request.
+///
+/// `request_json` is a NUL-terminated UTF-8 [`ScanRequest`] (`viewing_key`,
+/// `key_id`, `compact_blocks` and optional `options`), plus an optional
+/// `network` of `"mainnet"` (the default) or `"testnet"`. Returns a JSON
+/// array of discovered transactions, to be freed with
+/// [`zecscope_string_free`], or NULL on error.
+///
+/// # Safety
+///
+/// `request_json` must be NULL or point to a NUL-terminated string that
+/// stays valid for the duration of the call.
+#[no_mangle]
+pub unsafe extern "C" fn zecscope_scan_json(request_json: *const c_char) -> *mut c_char {
+    if request_json.is_null() {
+        set_last_error("request_json is NULL".to_string());
+        return std::ptr::null_mut();
+    }
+    let request_json = CStr::from_ptr(request_json);
+
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        let request_json = request_json
+            .to_str()
+            .map_err(|e| format!("request is not valid UTF-8: {e}"))?;
+        scan_json(request_json).map_err(|e| e.to_string())
+    }))
+    .unwrap_or_else(|_| Err("scanner panicked".to_string()));
+
+    match result.and_then(|json| CString::new(json).map_err(|e| e.to_string())) {
+        Ok(json) => {
+            clear_last_error();
+            json.into_raw()
+        }
+        Err(message) => {
+            set_last_error(message);
+            std::ptr::null_mut()
+        }
+    }
+}
+
+fn scan_json(request_json: &str) -> zecscope_scanner::ScanResult<String> {
+    let FfiScanRequest { network, request } = serde_json::from_str(request_json)?;
+    let transactions = Scanner::new(network.into()).scan(&request)?;
+    Ok(serde_json::to_string(&transactions)?)
+}
+
+/// Message describing the last error on the calling thread, or NULL if the
+/// last scan succeeded.
+///
+/// The string is owned by the library and stays valid until the next scan
+/// on the same thread; do not free it.
+#[no_mangle]
+pub extern "C" fn zecscope_last_error() -> *const c_char {
+    LAST_ERROR.with(|error| {
+        error
+            .borrow()
+            .as_ref()
+            .map_or(std::ptr::null(), |message| message.as_ptr())
+    })
+}
+
+/// Free a string returned by the library. Passing NULL is a no-op.
+///
+/// # Safety
+///
+/// `s` must be NULL or a pointer returned by this library that has not
+/// been freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn zecscope_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
+
+/// Version of the library, as a static NUL-terminated string.
+#[no_mangle]
+pub extern "C" fn zecscope_version() -> *const c_char {
+    concat!(env!("CARGO_PKG_VERSION"), "\0").as_ptr().cast()
+}
+
+fn set_last_error(message: String) {
+    // Interior NULs can't be represented in a C string
+    let message = CString::new(message.replace('\0', " ")).unwrap_or_default();
+    LAST_ERROR.with(|error| *error.borrow_mut() = Some(message));
+}
+
+fn clear_last_error() {
+    LAST_ERROR.with(|error| *error.borrow_mut() = None);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn last_error() -> Option<String> {
+        let error = zecscope_last_error();
+        (!error.is_null()).then(|| unsafe { CStr::from_ptr(error) }.to_string_lossy().into())
+    }
+
+    #[test]
+    fn test_scan_json() {
+        let network = Network::TestNetwork;
+        let ufvk = zcash_keys::keys::UnifiedSpendingKey::from_seed(
+            &network,
+            &[0; 32],
+            zip32::AccountId::ZERO,
+        )
+        .unwrap()
+        .to_unified_full_viewing_key()
+        .encode(&network);
+        let request = CString::new(format!(
+            r#"{{"network": "testnet", "viewing_key": "{ufvk}", "key_id": "c", "compact_blocks": []}}"#
+        ))
+        .unwrap();
+        let result = unsafe { zecscope_scan_json(request.as_ptr()) };
+        assert!(!result.is_null());
+        assert_eq!(unsafe { CStr::from_ptr(result) }.to_str().unwrap(), "[]");
+        assert_eq!(last_error(), None);
+        unsafe { zecscope_string_free(result) };
+
+        let request = CString::new(
+            r#"{"viewing_key": "uview1invalid", "key_id": "c", "compact_blocks": []}"#,
+        )
+        .unwrap();
+        let result = unsafe { zecscope_scan_json(request.as_ptr()) };
+        assert!(result.is_null());
+        assert!(last_error().unwrap().contains("Invalid viewing key"));
+
+        let malformed = CString::new("{").unwrap();
+        assert!(unsafe { zecscope_scan_json(malformed.as_ptr()) }.is_null());
+        assert!(last_error().unwrap().contains("JSON"));
+
+        assert!(unsafe { zecscope_scan_json(std::ptr::null()) }.is_null());
+        unsafe { zecscope_string_free(std::ptr::null_mut()) };
+    }
+
+    #[test]
+    fn test_version() {
+        let version = unsafe { CStr::from_ptr(zecscope_version()) };
+        assert_eq!(version.to_str().unwrap(), env!("CARGO_PKG_VERSION"));
+        assert_eq!(last_error(), None);
+    }
+}
@@ -0,0 +1,29 @@
+import assert from 'node:assert/strict'
+import { createRequire } from 'node:module'
+import { test } from 'node:test'
+
+const { scan } = createRequire(import.meta.url)('../zecscope.node')
+
+// Testnet UFVK of account 0 of the all-zero seed
+const viewingKey =
+  'uviewtest1p53s5ef25ygw6g7qjun04gwee87ll3pl2mwapqkj0dp737qk7xhemr4vpgddcsrzr2ep9pf5tdjq2kd2up4txkyuhavmqpaaxcsp09zlxeh7tzu590ewgs9aenhz5nsd584xhr5vdmw9relxhduanfaf04ljvc3h5qqyzy3r7wxn27dlpv3elhwjemccmhvu2mh9s3fr37hjvqm04vzmzgdda3r0u7hgvfan6txk68fkylmz0h58x7vfjvmuc0mhldcgu98cgdy8ecpcdcgw7zu6agtgl7y565eq8wz980dxskn0fp9fwzudqt9cpcjmjjv6p77a976c90n4d3lc97gv2vm3f0kk8dxp6pe6ya6ht0h6pnueevd3y5pngxkx87gvxcqsj03qf'
+
+test('scans an empty batch', async () => {
+  const txs = await scan(Buffer.alloc(0), [{ viewingKey, keyId: 'test' }], { network: 'testnet' })
+  assert.deepEqual(txs, [])
+})
+
+test('rejects a bad key with its key id', async () => {
+  await assert.rejects(scan(Buffer.alloc(0), [{ viewingKey: 'uview1invalid', keyId: 'bad' }]), /bad: Invalid viewing key/)
+})
+
+test('rejects truncated blocks', async () => {
+  await assert.rejects(
+    scan(Buffer.from([0x05, 0x08]), [{ viewingKey, keyId: 'test' }], { network: 'testnet' }),
+    /Invalid protobuf/,
+  )
+})
+
+test('rejects an unknown network', () => {
+  assert.throws(() => scan(Buffer.alloc(0), [], { network: 'regtest' }), /unknown network/)
+})
@@ -0,0 +1,66 @@
+/// <reference types="node" />
+
+/** A viewing key to scan with. */
+export interface ScanKey {
+  /** Unified Full Viewing Key (uview1... or uviewtest1...) */
+  viewingKey: string
+  /** Identifier reported on the transactions this key finds (default: the key's fingerprint) */
+  keyId?: string
+}
+
+export interface ScanOptions {
+  /** Network of the keys and blocks (default `"mainnet"`) */
+  network?: 'mainnet' | 'testnet'
+  /** Also report change outputs */
+  includeChange?: boolean
+}
+
+/** A discovered transaction, as returned by the WASM build. */
+export interface ZecTransaction {
+  txid: string
+  height: number
+  time: number
+  /** Amount in zatoshis (as string to avoid precision loss) */
+  amountZat: string
+  direction: 'in' | 'out'
+  memo: string | null
+  keyId: string
+  pool: 'sapling' | 'orchard' | 'transparent'
+  isChange: boolean
+  address: string | null
+  /** Recipient of an outgoing payment, decrypted from a full transaction */
+  recipientAddress: string | null
+  feeZat: string | null
+  /** Height after which the transaction can't be mined, from the full transaction */
+  expiryHeight: number | null
+  fiatValue: string | null
+  fiatCurrency: string | null
+  diversifierIndex: number | null
+  outputIndex: number | null
+  actionIndex: number | null
+  /** Still unmined (height and time are 0) */
+  unconfirmed: boolean
+  /** Scope the note was received on (incoming entries, with `includeChange`) */
+  scope: 'external' | 'internal' | null
+  /** ZIP 32 account index, for multi-account scans */
+  accountId: number | null
+  /** Invoice ID the memo starts with, when memos are known */
+  invoiceId: string | null
+  /** Position of the note in its pool's commitment tree (incoming entries) */
+  notePosition: number | null
+  /** Coinbase output, spendable after 100 confirmations */
+  isCoinbase: boolean
+  /** Part of a transaction moving the wallet's own funds between pools */
+  selfTransfer: boolean
+}
+
+/**
+ * Scan `blocks` (length-delimited protobuf `CompactBlock` messages,
+ * optionally gzip- or zstd-compressed) with every key in `keys`, on the
+ * libuv threadpool.
+ */
+export function scan(
+  blocks: Buffer,
+  keys: Array<ScanKey>,
+  options?: ScanOptions,
+): Promise<Array<ZecTransaction>>
@@ -0,0 +1,12 @@
+// Build the addon and copy it to zecscope.node, where index.js loads it from.
+import { execFileSync } from 'node:child_process'
+import { copyFileSync } from 'node:fs'
+
+const release = !process.argv.includes('--debug')
+execFileSync('cargo', ['build', ...(release ? ['--release'] : [])], { stdio: 'inherit' })
+
+const library = {
+  darwin: 'libzecscope_node.dylib',
+  win32: 'zecscope_node.dll',
+}[process.platform] ?? 'libzecscope_node.so'
+copyFileSync(`target/${release ? 'release' : 'debug'}/${library}`, 'zecscope.node')
@@ -0,0 +1,162 @@
+//! Unified address derivation from a UFVK.
+
+use serde::{Deserialize, Serialize};
+use zcash_keys::address::UnifiedAddress;
+use zcash_keys::encoding::encode_payment_address_p;
+use zcash_keys::keys::{
+    AddressGenerationError, UnifiedAddressRequest, UnifiedFullViewingKey, UnifiedIncomingViewingKey,
+};
+use zcash_protocol::consensus::Parameters;
+use zip32::DiversifierIndex;
+
+use crate::error::{ScanError, ScanResult};
+
+/// A unified address of a viewing key, with its per-pool receivers.
+///
+/// `sapling_address` and `orchard_address` use the same encodings as
+/// [`ZecTransaction::address`](crate::ZecTransaction::address), so scan
+/// results can be matched back to the address that received them.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DerivedAddress {
+    /// Diversifier index the address was derived at
+    pub diversifier_index: u64,
+    /// Unified address (`u1…`) with a receiver for each pool in the key
+    pub address: String,
+    /// Sapling receiver (`zs1…`), if the key has a Sapling component
+    #[serde(default)]
+    pub sapling_address: Option<String>,
+    /// Orchard receiver as an Orchard-only unified address, if the key has
+    /// an Orchard component
+    #[serde(default)]
+    pub orchard_address: Option<String>,
+}
+
+impl DerivedAddress {
+    fn new<P: Parameters>(network: &P, index: DiversifierIndex, ua: &UnifiedAddress) -> Self {
+        Self {
+            diversifier_index: u64::try_from(index).unwrap_or(u64::MAX),
+            address: ua.encode(network),
+            sapling_address: ua
+                .sapling()
+                .map(|addr| encode_payment_address_p(network, addr)),
+            #[cfg(feature = "orchard")]
+            orchard_address: ua.orchard().and_then(|addr| {
+                UnifiedAddress::from_receivers(Some(*addr), None, None).map(|ua| ua.encode(network))
+            }),
+            #[cfg(not(feature = "orchard"))]
+            orchard_address: None,
+        }
+    }
+}
+
+/// The unified address at the first valid diversifier index.
+pub(crate) fn default_address<P: Parameters>(
+    network: &P,
+    ufvk: &UnifiedFullViewingKey,
+) -> ScanResult<DerivedAddress> {
+    let (ua, index) = ufvk
+        .default_address(UnifiedAddressRequest::AllAvailableKeys)
+        .map_err(address_error)?;
+    Ok(DerivedAddress::new(network, index, &ua))
+}
+
+/// The unified address at diversifier index `index`, or `None` if the index
+/// doesn't yield a valid receiver for every pool in the key.
+pub(crate) fn address_at<P: Parameters>(
+    network: &P,
+    ufvk: &UnifiedFullViewingKey,
+    index: u64,
+) -> ScanResult<Option<DerivedAddress>> {
+    let index = DiversifierIndex::from(index);
+    match ufvk.address(index, UnifiedAddressRequest::AllAvailableKeys) {
+        Ok(ua) => Ok(Some(DerivedAddress::new(network, index, &ua))),
+        Err(AddressGenerationError::InvalidSaplingDiversifierIndex(_)) => Ok(None),
+        #[cfg(feature = "transparent")]
+        Err(AddressGenerationError::InvalidTransparentChildIndex(_)) => Ok(None),
+        Err(e) => Err(address_error(e)),
+    }
+}
+
+/// The first `count` unified addresses, at ascending valid diversifier indices.
+pub(crate) fn derive_addresses<P: Parameters>(
+    network: &P,
+    ufvk: &UnifiedFullViewingKey,
+    count: usize,
+) -> ScanResult<Vec<DerivedAddress>> {
+    let mut addresses = Vec::with_capacity(count);
+    let mut index = DiversifierIndex::new();
+    while addresses.len() < count {
+        let (ua, found) = ufvk
+            .find_address(index, UnifiedAddressRequest::AllAvailableKeys)
+            .map_err(address_error)?;
+        addresses.push(DerivedAddress::new(network, found, &ua));
+        index = found;
+        if index.increment().is_err() {
+            break;
+        }
+    }
+    Ok(addresses)
+}
+
+/// Diversifier index of `address` if it's one of the key's external Sapling
+/// addresses.
+pub(crate) fn sapling_diversifier_index(
+    uivk: &UnifiedIncomingViewingKey,
+    address: &sapling::PaymentAddress,
+) -> Option<u64> {
+    let index = uivk.sapling().as_ref()?.decrypt_diversifier(address)?;
+    u64::try_from(index).ok()
+}
+
+/// Diversifier index of `address` if it's one of the key's external Orchard
+/// addresses.
+#[cfg(feature = "orchard")]
+pub(crate) fn orchard_diversifier_index(
+    uivk: &UnifiedIncomingViewingKey,
+    address: &orchard::Address,
+) -> Option<u64> {
+    let index = uivk.orchard().as_ref()?.diversifier_index(address)?;
+    u64::try_from(index).ok()
+}
+
+fn address_error(e: AddressGenerationError) -> ScanError {
+    ScanError::InvalidViewingKey(e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use zcash_keys::keys::UnifiedSpendingKey;
+    use zcash_protocol::consensus::Network;
+
+    #[test]
+    fn test_address_at() {
+        let network = Network::MainNetwork;
+        let ufvk = UnifiedSpendingKey::from_seed(&network, &[0u8; 32], zip32::AccountId::ZERO)
+            .unwrap()
+            .to_unified_full_viewing_key();
+
+        let default = default_address(&network, &ufvk).unwrap();
+        assert!(default.address.starts_with("u1"));
+        assert!(default
+            .sapling_address
+            .as_deref()
+            .unwrap()
+            .starts_with("zs1"));
+        assert_eq!(
+            address_at(&network, &ufvk, default.diversifier_index).unwrap(),
+            Some(default)
+        );
+
+        // Some of the first few indices have no valid Sapling diversifier
+        let addresses: Vec<_> = (0..10)
+            .map(|i| address_at(&network, &ufvk, i).unwrap())
+            .collect();
+        assert!(addresses.iter().any(Option::is_none));
+        assert!(addresses
+            .iter()
+            .flatten()
+            .all(|a| a.sapling_address.is_some()));
+    }
+}
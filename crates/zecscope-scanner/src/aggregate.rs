@@ -0,0 +1,107 @@
+//! Collapsing results into one row per transaction.
+
+use crate::types::{Aggregation, ZecTransaction};
+
+/// Merge entries of `transactions` that share a txid and direction (and
+/// pool, for [`Aggregation::TxidPool`]), summing their amounts.
+///
+/// Each merged entry takes the place of the first entry it absorbs. Fields
+/// the merged entries disagree on are cleared, except `pool` (the first
+/// entry's), and `is_change` and `likely_spam` (set only if all entries had
+/// them); output and action indices are cleared whenever entries are merged.
+pub(crate) fn aggregate(transactions: Vec<ZecTransaction>, by: Aggregation) -> Vec<ZecTransaction> {
+    if by == Aggregation::None {
+        return transactions;
+    }
+
+    let mut merged: Vec<ZecTransaction> = Vec::with_capacity(transactions.len());
+    for tx in transactions {
+        let existing = merged.iter_mut().find(|m| {
+            m.txid == tx.txid
+                && m.direction == tx.direction
+                && (by == Aggregation::Txid || m.pool == tx.pool)
+        });
+        match existing {
+            Some(m) => merge(m, tx),
+            None => merged.push(tx),
+        }
+    }
+    merged
+}
+
+fn merge(into: &mut ZecTransaction, tx: ZecTransaction) {
+    into.amount_zat = (into.amount_zatoshis() + tx.amount_zatoshis()).to_string();
+    into.is_change &= tx.is_change;
+    into.likely_spam &= tx.likely_spam;
+    into.output_index = None;
+    into.action_index = None;
+    into.note_position = None;
+    into.fee_zat = into.fee_zat.take().or(tx.fee_zat);
+    keep_if_equal(&mut into.memo, tx.memo);
+    keep_if_equal(&mut into.address, tx.address);
+    keep_if_equal(&mut into.recipient_address, tx.recipient_address);
+    keep_if_equal(&mut into.expiry_height, tx.expiry_height);
+    keep_if_equal(&mut into.diversifier_index, tx.diversifier_index);
+    keep_if_equal(&mut into.scope, tx.scope);
+    keep_if_equal(&mut into.account_id, tx.account_id);
+    keep_if_equal(&mut into.invoice_id, tx.invoice_id);
+    // Prices are attached after aggregation; a partial sum would mislead
+    into.fiat_value = None;
+    into.fiat_currency = None;
+}
+
+fn keep_if_equal<T: PartialEq>(into: &mut Option<T>, other: Option<T>) {
+    if *into != other {
+        *into = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{ShieldedPool, TxDirection};
+
+    fn test_tx(txid: &str, pool: ShieldedPool, amount_zat: u64) -> ZecTransaction {
+        ZecTransaction {
+            address: Some(format!("{pool}-address")),
+            diversifier_index: Some(0),
+            output_index: Some(0),
+            ..ZecTransaction::test_entry(txid, amount_zat, TxDirection::In, pool)
+        }
+    }
+
+    #[test]
+    fn test_aggregate() {
+        let txs = vec![
+            test_tx("aa", ShieldedPool::Sapling, 100),
+            test_tx("bb", ShieldedPool::Sapling, 5),
+            test_tx("aa", ShieldedPool::Orchard, 20),
+            test_tx("aa", ShieldedPool::Sapling, 3),
+        ];
+        assert_eq!(aggregate(txs.clone(), Aggregation::None).len(), 4);
+
+        let by_pool = aggregate(txs.clone(), Aggregation::TxidPool);
+        let rows: Vec<_> = by_pool
+            .iter()
+            .map(|t| (t.txid.as_str(), t.pool, t.amount_zat.as_str()))
+            .collect();
+        assert_eq!(
+            rows,
+            vec![
+                ("aa", ShieldedPool::Sapling, "103"),
+                ("bb", ShieldedPool::Sapling, "5"),
+                ("aa", ShieldedPool::Orchard, "20"),
+            ]
+        );
+        assert_eq!(by_pool[0].address.as_deref(), Some("sapling-address"));
+        assert_eq!(by_pool[0].output_index, None);
+        assert_eq!(by_pool[1].output_index, Some(0));
+
+        let by_txid = aggregate(txs, Aggregation::Txid);
+        assert_eq!(by_txid.len(), 2);
+        assert_eq!(by_txid[0].amount_zat, "123");
+        assert_eq!(by_txid[0].pool, ShieldedPool::Sapling);
+        assert_eq!(by_txid[0].address, None);
+        assert_eq!(by_txid[0].diversifier_index, Some(0));
+    }
+}
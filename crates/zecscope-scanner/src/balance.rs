@@ -0,0 +1,202 @@
+//! Wallet balances derived from scan results.
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::state::{ScanState, TrackedNote};
+use crate::types::{BlockScanResult, ShieldedPool};
+
+/// Confirmations a note needs before it counts as spendable, by default.
+pub const DEFAULT_MIN_CONFIRMATIONS: u32 = 10;
+
+/// Point-in-time balance of a viewing key, in zatoshis.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Balance {
+    /// Value of all unspent notes
+    pub total_zat: u64,
+    /// Value of unspent notes with enough confirmations to spend
+    pub spendable_zat: u64,
+    /// Value of unspent Sapling notes
+    pub sapling_zat: u64,
+    /// Value of unspent Orchard notes
+    pub orchard_zat: u64,
+}
+
+/// Tracks unspent notes as blocks are scanned and reports the resulting balance.
+///
+/// Feed it each [`BlockScanResult`] from [`Scanner::scan_with`], or seed it
+/// from a [`ScanState`] after a resumed scan. Unlike summing
+/// [`ZecTransaction`](crate::ZecTransaction)s, this nets out spends and counts
+/// change notes even when they aren't reported as transactions.
+///
+/// [`Scanner::scan_with`]: crate::Scanner::scan_with
+#[derive(Debug, Clone)]
+pub struct BalanceTracker {
+    min_confirmations: u32,
+    tip_height: Option<u64>,
+    unspent: HashMap<(ShieldedPool, String), TrackedNote>,
+}
+
+impl Default for BalanceTracker {
+    fn default() -> Self {
+        Self::new(DEFAULT_MIN_CONFIRMATIONS)
+    }
+}
+
+impl BalanceTracker {
+    /// Create an empty tracker requiring `min_confirmations` for spendability.
+    pub fn new(min_confirmations: u32) -> Self {
+        Self {
+            min_confirmations,
+            tip_height: None,
+            unspent: HashMap::new(),
+        }
+    }
+
+    /// Create a tracker holding the unspent notes recorded in `state`.
+    pub fn from_state(state: &ScanState, min_confirmations: u32) -> Self {
+        let mut tracker = Self::new(min_confirmations);
+        tracker.tip_height = state.last_height();
+        for note in &state.tracked_notes {
+            tracker.add_note(note.clone());
+        }
+        tracker
+    }
+
+    /// Apply the notes received and spent in a scanned block.
+    pub fn apply_block(&mut self, block: &BlockScanResult) {
+        for note in &block.received_notes {
+            self.add_note(note.clone());
+        }
+        for spend in &block.spent_notes {
+            self.unspent
+                .remove(&(spend.note.pool, spend.note.nullifier.clone()));
+        }
+        self.tip_height = Some(
+            self.tip_height
+                .map_or(block.height, |h| h.max(block.height)),
+        );
+    }
+
+    /// Height of the latest block applied, if any.
+    pub fn tip_height(&self) -> Option<u64> {
+        self.tip_height
+    }
+
+    /// Value of all unspent notes.
+    pub fn total(&self) -> u64 {
+        self.unspent.values().map(TrackedNote::value_zatoshis).sum()
+    }
+
+    /// Value of unspent notes with at least `min_confirmations` confirmations.
+    pub fn spendable(&self) -> u64 {
+        self.unspent
+            .values()
+            .filter(|note| self.is_spendable(note))
+            .map(TrackedNote::value_zatoshis)
+            .sum()
+    }
+
+    /// Value of unspent notes in the given pool.
+    pub fn pool_balance(&self, pool: ShieldedPool) -> u64 {
+        self.unspent
+            .values()
+            .filter(|note| note.pool == pool)
+            .map(TrackedNote::value_zatoshis)
+            .sum()
+    }
+
+    /// Snapshot of all balances.
+    pub fn balance(&self) -> Balance {
+        Balance {
+            total_zat: self.total(),
+            spendable_zat: self.spendable(),
+            sapling_zat: self.pool_balance(ShieldedPool::Sapling),
+            orchard_zat: self.pool_balance(ShieldedPool::Orchard),
+        }
+    }
+
+    fn add_note(&mut self, note: TrackedNote) {
+        self.unspent
+            .insert((note.pool, note.nullifier.clone()), note);
+    }
+
+    fn is_spendable(&self, note: &TrackedNote) -> bool {
+        let Some(tip) = self.tip_height else {
+            return false;
+        };
+        // A note mined at the tip has one confirmation
+        tip + 1 >= note.height + self.min_confirmations as u64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::NoteSpend;
+
+    fn note(pool: ShieldedPool, nullifier: &str, value: u64, height: u64) -> TrackedNote {
+        TrackedNote {
+            pool,
+            nullifier: nullifier.to_string(),
+            value_zat: value.to_string(),
+            height,
+            txid: "aa".to_string(),
+            position: None,
+        }
+    }
+
+    fn block(height: u64, received: Vec<TrackedNote>, spent: Vec<TrackedNote>) -> BlockScanResult {
+        BlockScanResult {
+            height,
+            hash: String::new(),
+            time: 0,
+            transactions: Vec::new(),
+            received_notes: received,
+            decrypted_notes: Vec::new(),
+            spent_notes: spent
+                .into_iter()
+                .map(|note| NoteSpend {
+                    note,
+                    spent_txid: "bb".to_string(),
+                    spent_height: height,
+                })
+                .collect(),
+            sapling_anchor: None,
+            orchard_anchor: None,
+            skipped_outputs: 0,
+            spam_outputs: 0,
+            spam_ratio: 0.0,
+        }
+    }
+
+    #[test]
+    fn test_balance_nets_spends() {
+        let mut tracker = BalanceTracker::new(3);
+        let a = note(ShieldedPool::Sapling, "01", 1_000, 100);
+        let b = note(ShieldedPool::Orchard, "02", 500, 101);
+
+        tracker.apply_block(&block(100, vec![a.clone()], vec![]));
+        tracker.apply_block(&block(101, vec![b], vec![]));
+        assert_eq!(tracker.total(), 1_500);
+        assert_eq!(tracker.pool_balance(ShieldedPool::Orchard), 500);
+        // Neither note has 3 confirmations yet
+        assert_eq!(tracker.spendable(), 0);
+
+        tracker.apply_block(&block(102, vec![], vec![]));
+        assert_eq!(tracker.spendable(), 1_000);
+
+        tracker.apply_block(&block(103, vec![], vec![a]));
+        assert_eq!(
+            tracker.balance(),
+            Balance {
+                total_zat: 500,
+                spendable_zat: 500,
+                sapling_zat: 0,
+                orchard_zat: 500,
+            }
+        );
+    }
+}
@@ -0,0 +1,339 @@
+//! Batched trial decryption across blocks.
+
+use sapling::note_encryption::{CompactOutputDescription, SaplingDomain};
+use zcash_client_backend::proto::compact_formats::CompactBlock;
+use zcash_keys::keys::{UnifiedFullViewingKey, UnifiedIncomingViewingKey};
+use zcash_note_encryption::batch::try_compact_note_decryption;
+use zcash_note_encryption::{BatchDomain, ShieldedOutput, COMPACT_NOTE_SIZE};
+use zcash_primitives::transaction::components::sapling::zip212_enforcement;
+use zcash_protocol::consensus::Parameters;
+use zip32::Scope;
+
+use crate::types::{ScanOptions, ShieldedPool};
+
+#[cfg(feature = "orchard")]
+use orchard::note_encryption::{CompactAction, OrchardDomain};
+#[cfg(feature = "rayon")]
+use std::sync::Arc;
+
+/// Default number of outputs trial-decrypted together (see
+/// [`ScannerBuilder::batch_size`](crate::ScannerBuilder::batch_size)):
+/// 10,000, or 2,000 in WebAssembly, whose single thread and small memory
+/// are better served by batches that report progress sooner and hold fewer
+/// blocks.
+pub const DEFAULT_BATCH_SIZE: usize = if cfg!(target_arch = "wasm32") {
+    2_000
+} else {
+    10_000
+};
+
+/// Fewest outputs worth handing to a thread of their own.
+#[cfg(feature = "rayon")]
+const MIN_THREAD_OUTPUTS: usize = 256;
+
+/// Threads a batch is trial-decrypted on.
+///
+/// Without the `rayon` feature, batches are decrypted on the calling thread.
+/// With it, each batch's outputs are split into chunks decrypted in
+/// parallel, on a dedicated pool if one was configured and on rayon's global
+/// pool otherwise. Results are collected in output order either way, so
+/// scans find the same notes in the same order.
+#[derive(Clone, Default)]
+pub(crate) struct Threads {
+    #[cfg(feature = "rayon")]
+    pool: Option<Arc<rayon::ThreadPool>>,
+}
+
+impl Threads {
+    /// Decrypt on a dedicated pool of `threads` threads, or on the global
+    /// pool if `threads` is 0 or the pool can't be started.
+    #[cfg(feature = "rayon")]
+    pub(crate) fn new(threads: usize) -> Self {
+        let pool = (threads > 0)
+            .then(|| {
+                rayon::ThreadPoolBuilder::new()
+                    .num_threads(threads)
+                    .thread_name(|i| format!("zecscope-scan-{i}"))
+                    .build()
+                    .ok()
+            })
+            .flatten();
+        Self {
+            pool: pool.map(Arc::new),
+        }
+    }
+
+    /// Whether each of `outputs` decrypts with one of `ivks`.
+    fn decrypt<D, Output>(
+        &self,
+        ivks: &[D::IncomingViewingKey],
+        outputs: &[(D, Output)],
+    ) -> Vec<bool>
+    where
+        D: BatchDomain + Sync,
+        D::IncomingViewingKey: Sync,
+        Output: ShieldedOutput<D, COMPACT_NOTE_SIZE> + Sync,
+    {
+        #[cfg(feature = "rayon")]
+        {
+            use rayon::prelude::*;
+
+            let threads = match &self.pool {
+                Some(pool) => pool.current_num_threads(),
+                None => rayon::current_num_threads(),
+            };
+            let chunk_size = outputs.len().div_ceil(threads).max(MIN_THREAD_OUTPUTS);
+            let decrypt_chunks = || {
+                outputs
+                    .par_chunks(chunk_size)
+                    .flat_map_iter(|chunk| decrypt_sequential(ivks, chunk))
+                    .collect()
+            };
+            match &self.pool {
+                Some(pool) => pool.install(decrypt_chunks),
+                None => decrypt_chunks(),
+            }
+        }
+        #[cfg(not(feature = "rayon"))]
+        decrypt_sequential(ivks, outputs)
+    }
+}
+
+fn decrypt_sequential<D: BatchDomain, Output: ShieldedOutput<D, COMPACT_NOTE_SIZE>>(
+    ivks: &[D::IncomingViewingKey],
+    outputs: &[(D, Output)],
+) -> Vec<bool> {
+    try_compact_note_decryption(ivks, outputs)
+        .iter()
+        .map(Option::is_some)
+        .collect()
+}
+
+/// A key's incoming viewing keys, prepared for batch trial decryption.
+///
+/// Key agreement dominates trial decryption, and batching it over many
+/// outputs makes it several times cheaper per output. Blocks are checked
+/// here in batches first; only those holding notes for the key are
+/// decrypted again by the full scan.
+pub(crate) struct BatchDecryptor {
+    sapling: Vec<sapling::keys::PreparedIncomingViewingKey>,
+    #[cfg(feature = "orchard")]
+    orchard: Vec<orchard::keys::PreparedIncomingViewingKey>,
+}
+
+impl BatchDecryptor {
+    /// Prepare the external and internal incoming viewing keys of `ufvk`.
+    pub(crate) fn new(ufvk: &UnifiedFullViewingKey) -> Self {
+        let scopes = [Scope::External, Scope::Internal];
+        Self {
+            sapling: ufvk
+                .sapling()
+                .map(|dfvk| {
+                    scopes
+                        .iter()
+                        .map(|&scope| {
+                            sapling::keys::PreparedIncomingViewingKey::new(&dfvk.to_ivk(scope))
+                        })
+                        .collect()
+                })
+                .unwrap_or_default(),
+            #[cfg(feature = "orchard")]
+            orchard: ufvk
+                .orchard()
+                .map(|fvk| {
+                    scopes
+                        .iter()
+                        .map(|&scope| {
+                            orchard::keys::PreparedIncomingViewingKey::new(&fvk.to_ivk(scope))
+                        })
+                        .collect()
+                })
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Prepare the external incoming viewing keys of `uivk`, which is all a
+    /// UIVK has.
+    pub(crate) fn incoming(uivk: &UnifiedIncomingViewingKey) -> Self {
+        Self {
+            sapling: uivk.sapling().iter().map(|ivk| ivk.prepare()).collect(),
+            #[cfg(feature = "orchard")]
+            orchard: uivk
+                .orchard()
+                .iter()
+                .map(orchard::keys::PreparedIncomingViewingKey::new)
+                .collect(),
+        }
+    }
+
+    /// Whether each of `blocks` holds a note for this key, in the pools
+    /// selected by `options`.
+    ///
+    /// Blocks with outputs that don't parse are reported as holding notes,
+    /// so the full scan gets to report the encoding error.
+    pub(crate) fn find_notes<P: Parameters>(
+        &self,
+        params: &P,
+        blocks: &[CompactBlock],
+        options: &ScanOptions,
+        threads: &Threads,
+    ) -> Vec<bool> {
+        let sapling = options.includes_pool(ShieldedPool::Sapling);
+        #[cfg(feature = "orchard")]
+        let orchard = options.includes_pool(ShieldedPool::Orchard);
+
+        let mut found = vec![false; blocks.len()];
+        // Outputs of all blocks, each with the index of its block
+        let mut sapling_outputs = Vec::new();
+        let mut sapling_blocks = Vec::new();
+        #[cfg(feature = "orchard")]
+        let mut orchard_outputs = Vec::new();
+        #[cfg(feature = "orchard")]
+        let mut orchard_blocks = Vec::new();
+
+        for (i, block) in blocks.iter().enumerate() {
+            let zip212 = zip212_enforcement(params, block.height());
+            for tx in &block.vtx {
+                for output in tx.outputs.iter().filter(|_| sapling) {
+                    match CompactOutputDescription::try_from(output) {
+                        Ok(output) => {
+                            sapling_outputs.push((SaplingDomain::new(zip212), output));
+                            sapling_blocks.push(i);
+                        }
+                        Err(_) => found[i] = true,
+                    }
+                }
+                #[cfg(feature = "orchard")]
+                for action in tx.actions.iter().filter(|_| orchard) {
+                    match CompactAction::try_from(action) {
+                        Ok(action) => {
+                            orchard_outputs
+                                .push((OrchardDomain::for_compact_action(&action), action));
+                            orchard_blocks.push(i);
+                        }
+                        Err(_) => found[i] = true,
+                    }
+                }
+            }
+        }
+
+        mark_found(
+            &mut found,
+            &sapling_blocks,
+            threads.decrypt(&self.sapling, &sapling_outputs),
+        );
+        #[cfg(feature = "orchard")]
+        mark_found(
+            &mut found,
+            &orchard_blocks,
+            threads.decrypt(&self.orchard, &orchard_outputs),
+        );
+        found
+    }
+}
+
+fn mark_found(found: &mut [bool], blocks: &[usize], decrypted: Vec<bool>) {
+    for (&i, decrypted) in blocks.iter().zip(decrypted) {
+        found[i] |= decrypted;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sapling::value::NoteValue;
+    use sapling::Rseed;
+    use zcash_client_backend::proto::compact_formats::{CompactSaplingOutput, CompactTx};
+    use zcash_keys::keys::UnifiedSpendingKey;
+    use zcash_note_encryption::{Domain, NoteEncryption, COMPACT_NOTE_SIZE};
+    use zcash_protocol::consensus::Network;
+
+    fn test_ufvk(seed: u8) -> UnifiedFullViewingKey {
+        UnifiedSpendingKey::from_seed(&Network::MainNetwork, &[seed; 32], zip32::AccountId::ZERO)
+            .unwrap()
+            .to_unified_full_viewing_key()
+    }
+
+    /// A compact output paying the default Sapling address of `ufvk`.
+    fn sapling_output(ufvk: &UnifiedFullViewingKey) -> CompactSaplingOutput {
+        let (_, address) = ufvk.sapling().unwrap().default_address();
+        let note = address.create_note(NoteValue::from_raw(50_000), Rseed::AfterZip212([7; 32]));
+        let cmu = note.cmu();
+        let encryption = NoteEncryption::<SaplingDomain>::new(None, note, [0; 512]);
+        CompactSaplingOutput {
+            cmu: cmu.to_bytes().to_vec(),
+            ephemeral_key: SaplingDomain::epk_bytes(encryption.epk()).0.to_vec(),
+            ciphertext: encryption.encrypt_note_plaintext()[..COMPACT_NOTE_SIZE].to_vec(),
+        }
+    }
+
+    fn block(height: u64, outputs: Vec<CompactSaplingOutput>) -> CompactBlock {
+        CompactBlock {
+            height,
+            vtx: vec![CompactTx {
+                outputs,
+                ..Default::default()
+            }],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_find_notes() {
+        let ufvk = test_ufvk(0);
+        let other = test_ufvk(1);
+        let blocks = vec![
+            block(2_000_000, vec![sapling_output(&other)]),
+            block(
+                2_000_001,
+                vec![sapling_output(&other), sapling_output(&ufvk)],
+            ),
+            block(2_000_002, Vec::new()),
+            // Not a valid note commitment
+            block(
+                2_000_003,
+                vec![CompactSaplingOutput {
+                    cmu: vec![0xff; 32],
+                    ..sapling_output(&other)
+                }],
+            ),
+        ];
+        let decryptor = BatchDecryptor::new(&ufvk);
+        let options = ScanOptions::default();
+        let threads = Threads::default();
+        let found = decryptor.find_notes(&Network::MainNetwork, &blocks, &options, &threads);
+        assert_eq!(found, vec![false, true, false, true]);
+
+        let options = ScanOptions {
+            pools: vec![ShieldedPool::Orchard],
+            ..ScanOptions::default()
+        };
+        let found = decryptor.find_notes(&Network::MainNetwork, &blocks, &options, &threads);
+        assert_eq!(found, vec![false; 4]);
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn test_find_notes_parallel() {
+        let ufvk = test_ufvk(0);
+        let other = test_ufvk(1);
+        // Enough outputs to be split across threads, with notes in a few
+        let blocks: Vec<_> = (0..20)
+            .map(|i| {
+                let mut outputs = vec![sapling_output(&other); 16];
+                if i % 7 == 3 {
+                    outputs[i % 16] = sapling_output(&ufvk);
+                }
+                block(2_000_000 + i as u64, outputs)
+            })
+            .collect();
+        let decryptor = BatchDecryptor::new(&ufvk);
+        let options = ScanOptions::default();
+
+        let expected: Vec<_> = (0..20).map(|i| i % 7 == 3).collect();
+        for threads in [Threads::default(), Threads::new(1), Threads::new(4)] {
+            let found = decryptor.find_notes(&Network::MainNetwork, &blocks, &options, &threads);
+            assert_eq!(found, expected);
+        }
+    }
+}
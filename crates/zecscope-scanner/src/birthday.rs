@@ -0,0 +1,79 @@
+//! Wallet birthday estimation from a date.
+
+use zcash_protocol::consensus::{NetworkType, NetworkUpgrade, Parameters};
+
+/// Mainnet blocks and the start (UTC midnight) of the day each was mined:
+/// the activation block of every network upgrade from Sapling on.
+const MAINNET_CHECKPOINTS: &[(u64, i64)] = &[
+    (419_200, 1_540_684_800),   // Sapling, 2018-10-28
+    (653_600, 1_576_022_400),   // Blossom, 2019-12-11
+    (903_000, 1_594_857_600),   // Heartwood, 2020-07-16
+    (1_046_400, 1_605_657_600), // Canopy, 2020-11-18
+    (1_687_104, 1_653_955_200), // NU5, 2022-05-31
+    (2_726_400, 1_732_320_000), // NU6, 2024-11-23
+];
+
+/// Target block spacing since Blossom, in seconds.
+const BLOCK_SPACING: i64 = 75;
+
+/// Blocks taken off every estimate so it stays below the true height:
+/// about a week of blocks.
+const SAFETY_MARGIN: u64 = 8_064;
+
+/// Estimate a height mined before `time` (Unix seconds), for starting the
+/// scan of a wallet created then.
+///
+/// Mainnet heights are interpolated between embedded checkpoints (and
+/// extrapolated past the last one), then moved back about a week so that
+/// an approximate date is still safe. Other networks, and times before
+/// Sapling activation, get the Sapling activation height.
+pub(crate) fn estimate_birthday<P: Parameters>(params: &P, time: i64) -> u64 {
+    let sapling = params
+        .activation_height(NetworkUpgrade::Sapling)
+        .map_or(0, |h| u32::from(h) as u64);
+    if params.network_type() != NetworkType::Main {
+        return sapling;
+    }
+    let Some(i) = MAINNET_CHECKPOINTS.iter().rposition(|&(_, t)| t <= time) else {
+        return sapling;
+    };
+
+    let (height, start) = MAINNET_CHECKPOINTS[i];
+    let elapsed = (time - start) as u64;
+    let estimate = match MAINNET_CHECKPOINTS.get(i + 1) {
+        Some(&(next_height, next_start)) => {
+            height + (next_height - height) * elapsed / (next_start - start) as u64
+        }
+        None => height + elapsed / BLOCK_SPACING as u64,
+    };
+    estimate.saturating_sub(SAFETY_MARGIN).max(sapling)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use zcash_protocol::consensus::Network;
+
+    #[test]
+    fn test_estimate_birthday() {
+        let mainnet = Network::MainNetwork;
+        assert_eq!(estimate_birthday(&mainnet, 0), 419_200);
+        assert_eq!(estimate_birthday(&mainnet, 1_540_684_800 + 86_400), 419_200);
+
+        // A year after NU5: below the height actually mined by then, but
+        // not far below
+        let estimate = estimate_birthday(&mainnet, 1_653_955_200 + 365 * 86_400);
+        assert!((2_090_000..2_105_000).contains(&estimate));
+
+        // Later dates never give earlier heights
+        let heights: Vec<_> = (0..20)
+            .map(|year| estimate_birthday(&mainnet, 1_500_000_000 + year * 31_536_000))
+            .collect();
+        assert!(heights.windows(2).all(|w| w[0] <= w[1]));
+
+        assert_eq!(
+            estimate_birthday(&Network::TestNetwork, 1_700_000_000),
+            280_000
+        );
+    }
+}
@@ -0,0 +1,259 @@
+//! Disk-backed compact block cache.
+
+use std::fs;
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+use crate::error::{ScanError, ScanResult};
+use crate::source::BlockSource;
+use crate::types::CompactBlock;
+
+/// Length of the BLAKE2b digest stored in front of each cached block.
+const DIGEST_LEN: usize = 32;
+
+/// Personalization for cache entry digests.
+const DIGEST_PERSONAL: &[u8; 16] = b"zecscope_blkcach";
+
+/// A directory of compact blocks, one file per height.
+///
+/// Each entry holds the block (with its hash and height) behind a BLAKE2b
+/// digest of its contents. Entries that fail the digest check or don't match
+/// the height they're filed under are treated as missing and removed, so a
+/// truncated write or a corrupted disk only costs a re-download.
+///
+/// Wrap a network [`BlockSource`] in a [`CachedBlockSource`] to consult the
+/// cache before fetching.
+#[derive(Debug, Clone)]
+pub struct BlockCache {
+    dir: PathBuf,
+}
+
+impl BlockCache {
+    /// Open (creating if needed) a cache in `dir`.
+    pub fn open(dir: impl Into<PathBuf>) -> ScanResult<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir).map_err(|e| cache_error(&dir, e))?;
+        Ok(Self { dir })
+    }
+
+    /// Directory the cache lives in.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Fetch the cached block at `height`, if present and intact.
+    pub fn get(&self, height: u64) -> ScanResult<Option<CompactBlock>> {
+        let path = self.path(height);
+        let bytes = match fs::read(&path) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(cache_error(&path, e)),
+        };
+
+        match decode_entry(&bytes) {
+            Some(block) if block.height == height => Ok(Some(block)),
+            _ => {
+                // Corrupt or misfiled entry; drop it so it gets re-fetched
+                self.remove(height)?;
+                Ok(None)
+            }
+        }
+    }
+
+    /// Fetch the cached block at `height` only if its hash is `hash` (hex).
+    pub fn get_with_hash(&self, height: u64, hash: &str) -> ScanResult<Option<CompactBlock>> {
+        Ok(self
+            .get(height)?
+            .filter(|block| block.hash.eq_ignore_ascii_case(hash)))
+    }
+
+    /// Store `block`, replacing any entry at the same height.
+    pub fn insert(&self, block: &CompactBlock) -> ScanResult<()> {
+        let path = self.path(block.height);
+        let tmp = path.with_extension("tmp");
+        let entry = encode_entry(block)?;
+
+        // Write then rename, so readers never see a partial entry
+        let write = || -> std::io::Result<()> {
+            let mut file = fs::File::create(&tmp)?;
+            file.write_all(&entry)?;
+            file.sync_all()?;
+            fs::rename(&tmp, &path)
+        };
+        write().map_err(|e| cache_error(&path, e))
+    }
+
+    /// Remove the entry at `height`, if any.
+    pub fn remove(&self, height: u64) -> ScanResult<()> {
+        let path = self.path(height);
+        match fs::remove_file(&path) {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(cache_error(&path, e)),
+        }
+    }
+
+    /// Remove every entry at or above `height`, e.g. after a reorg.
+    pub fn truncate_from(&self, height: u64) -> ScanResult<()> {
+        let entries = fs::read_dir(&self.dir).map_err(|e| cache_error(&self.dir, e))?;
+        for entry in entries {
+            let entry = entry.map_err(|e| cache_error(&self.dir, e))?;
+            let cached_height = entry
+                .path()
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .and_then(|stem| stem.parse::<u64>().ok());
+            if cached_height.is_some_and(|h| h >= height) {
+                fs::remove_file(entry.path()).map_err(|e| cache_error(&entry.path(), e))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn path(&self, height: u64) -> PathBuf {
+        self.dir.join(format!("{height:010}.blk"))
+    }
+}
+
+/// A [`BlockSource`] that serves blocks from a [`BlockCache`] and fetches
+/// only the missing ones from `inner`, caching them on the way through.
+#[derive(Debug)]
+pub struct CachedBlockSource<S> {
+    cache: BlockCache,
+    inner: S,
+}
+
+impl<S: BlockSource> CachedBlockSource<S> {
+    /// Serve blocks from `cache`, falling back to `inner`.
+    pub fn new(cache: BlockCache, inner: S) -> Self {
+        Self { cache, inner }
+    }
+
+    /// The underlying cache.
+    pub fn cache(&self) -> &BlockCache {
+        &self.cache
+    }
+
+    /// Unwrap into the cache and the inner source.
+    pub fn into_parts(self) -> (BlockCache, S) {
+        (self.cache, self.inner)
+    }
+
+    fn fetch(&mut self, start: u64, end: u64, blocks: &mut Vec<CompactBlock>) -> ScanResult<()> {
+        let fetched = self.inner.get_block_range(start, end)?;
+        for block in &fetched {
+            self.cache.insert(block)?;
+        }
+        blocks.extend(fetched);
+        Ok(())
+    }
+}
+
+impl<S: BlockSource> BlockSource for CachedBlockSource<S> {
+    fn get_block_range(&mut self, start: u64, end: u64) -> ScanResult<Vec<CompactBlock>> {
+        let mut blocks = Vec::new();
+        // Start of the current run of heights missing from the cache
+        let mut missing_from = None;
+
+        for height in start..=end {
+            match self.cache.get(height)? {
+                Some(block) => {
+                    if let Some(from) = missing_from.take() {
+                        self.fetch(from, height - 1, &mut blocks)?;
+                    }
+                    blocks.push(block);
+                }
+                None => {
+                    missing_from.get_or_insert(height);
+                }
+            }
+        }
+        if let Some(from) = missing_from {
+            self.fetch(from, end, &mut blocks)?;
+        }
+
+        Ok(blocks)
+    }
+}
+
+fn digest(payload: &[u8]) -> blake2b_simd::Hash {
+    blake2b_simd::Params::new()
+        .hash_length(DIGEST_LEN)
+        .personal(DIGEST_PERSONAL)
+        .hash(payload)
+}
+
+fn encode_entry(block: &CompactBlock) -> ScanResult<Vec<u8>> {
+    let payload = serde_json::to_vec(block)?;
+    let mut entry = digest(&payload).as_bytes().to_vec();
+    entry.extend_from_slice(&payload);
+    Ok(entry)
+}
+
+fn decode_entry(entry: &[u8]) -> Option<CompactBlock> {
+    if entry.len() < DIGEST_LEN {
+        return None;
+    }
+    let (stored, payload) = entry.split_at(DIGEST_LEN);
+    if digest(payload).as_bytes() != stored {
+        return None;
+    }
+    serde_json::from_slice(payload).ok()
+}
+
+fn cache_error(path: &Path, e: std::io::Error) -> ScanError {
+    ScanError::BlockCache(format!("{}: {e}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::MemoryBlockSource;
+
+    fn test_block(height: u64) -> CompactBlock {
+        CompactBlock {
+            proto_version: 1,
+            height,
+            hash: format!("{height:064x}"),
+            prev_hash: format!("{:064x}", height - 1),
+            time: 0,
+            vtx: Vec::new(),
+            chain_metadata: None,
+        }
+    }
+
+    #[test]
+    fn test_block_cache() {
+        let dir = std::env::temp_dir().join(format!("zecscope-cache-{}", std::process::id()));
+        let cache = BlockCache::open(&dir).unwrap();
+
+        let inner = MemoryBlockSource::new((1..=10).map(test_block).collect());
+        let mut source = CachedBlockSource::new(cache.clone(), inner);
+        assert_eq!(source.get_block_range(3, 6).unwrap().len(), 4);
+        assert_eq!(cache.get(4).unwrap().unwrap().hash, test_block(4).hash);
+        assert!(cache
+            .get_with_hash(4, &test_block(5).hash)
+            .unwrap()
+            .is_none());
+
+        // Partially cached ranges come back complete and in order
+        let heights: Vec<_> = source
+            .get_block_range(1, 8)
+            .unwrap()
+            .iter()
+            .map(|b| b.height)
+            .collect();
+        assert_eq!(heights, (1..=8).collect::<Vec<_>>());
+
+        // Corrupt entries are dropped
+        fs::write(cache.path(2), b"garbage").unwrap();
+        assert!(cache.get(2).unwrap().is_none());
+        assert!(!cache.path(2).exists());
+
+        cache.truncate_from(5).unwrap();
+        assert!(cache.get(6).unwrap().is_none());
+        assert!(cache.get(4).unwrap().is_some());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,80 @@
+//! What this build of the scanner can do.
+
+use serde::{Deserialize, Serialize};
+
+use crate::types::ShieldedPool;
+
+/// Pools and optional features compiled into this build, so bindings can
+/// tell their callers (e.g. warn that Orchard notes won't be found).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "wasm", derive(tsify::Tsify), tsify(missing_as_null))]
+#[serde(rename_all = "camelCase")]
+pub struct Capabilities {
+    /// Version of zecscope-scanner
+    pub version: String,
+    /// Pools the scanner can find notes in
+    pub pools: Vec<ShieldedPool>,
+    /// Enabled cargo features
+    pub features: Vec<String>,
+}
+
+/// Describe this build of the scanner.
+pub fn capabilities() -> Capabilities {
+    let features = [
+        ("sapling", cfg!(feature = "sapling")),
+        ("orchard", cfg!(feature = "orchard")),
+        ("transparent", cfg!(feature = "transparent")),
+        ("sqlite", cfg!(feature = "sqlite")),
+        ("rayon", cfg!(feature = "rayon")),
+        ("jobs", cfg!(feature = "jobs")),
+        ("encryption", cfg!(feature = "encryption")),
+        ("cbor", cfg!(feature = "cbor")),
+        ("msgpack", cfg!(feature = "msgpack")),
+        ("parquet", cfg!(feature = "parquet")),
+        ("spending-key", cfg!(feature = "spending-key")),
+        ("derivation", cfg!(feature = "derivation")),
+        ("lightwalletd", cfg!(feature = "lightwalletd")),
+        ("zebrad", cfg!(feature = "zebrad")),
+        ("darkside", cfg!(feature = "darkside")),
+        ("testing", cfg!(feature = "testing")),
+        ("test-vectors", cfg!(feature = "test-vectors")),
+        ("wasm", cfg!(feature = "wasm")),
+    ];
+    let pools = [
+        (ShieldedPool::Sapling, true),
+        (ShieldedPool::Orchard, cfg!(feature = "orchard")),
+        (ShieldedPool::Transparent, cfg!(feature = "transparent")),
+    ];
+    Capabilities {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        pools: pools
+            .into_iter()
+            .filter(|&(_, enabled)| enabled)
+            .map(|(pool, _)| pool)
+            .collect(),
+        features: features
+            .into_iter()
+            .filter(|&(_, enabled)| enabled)
+            .map(|(feature, _)| feature.to_string())
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_capabilities() {
+        let capabilities = capabilities();
+        assert_eq!(capabilities.pools[0], ShieldedPool::Sapling);
+        assert_eq!(
+            capabilities.pools.contains(&ShieldedPool::Orchard),
+            cfg!(feature = "orchard")
+        );
+        assert_eq!(
+            capabilities.features.iter().any(|f| f == "orchard"),
+            cfg!(feature = "orchard")
+        );
+    }
+}
@@ -0,0 +1,82 @@
+//! Transparent decompression of gzip- and zstd-compressed input.
+
+use std::borrow::Cow;
+use std::io::{BufRead, BufReader, Read};
+
+use crate::error::{ScanError, ScanResult};
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+/// Decompress `bytes` if they start with a gzip or zstd header, and return
+/// them unchanged otherwise.
+///
+/// Neither header can begin a JSON document, nor an encoded `CompactBlock`
+/// with a height and hash (alone or behind a length prefix), so real
+/// uncompressed input is never mistaken for compressed input.
+pub(crate) fn decompress(bytes: &[u8]) -> ScanResult<Cow<'_, [u8]>> {
+    let mut decompressed = Vec::new();
+    if bytes.starts_with(&GZIP_MAGIC) {
+        flate2::read::MultiGzDecoder::new(bytes)
+            .read_to_end(&mut decompressed)
+            .map_err(|e| invalid("gzip", e))?;
+    } else if bytes.starts_with(&ZSTD_MAGIC) {
+        ruzstd::decoding::StreamingDecoder::new(bytes)
+            .map_err(|e| invalid("zstd", e))?
+            .read_to_end(&mut decompressed)
+            .map_err(|e| invalid("zstd", e))?;
+    } else {
+        return Ok(Cow::Borrowed(bytes));
+    }
+    Ok(Cow::Owned(decompressed))
+}
+
+/// Decompress a stream on the fly if it starts with a gzip or zstd header,
+/// like [`decompress`] does for a buffer, and pass it through otherwise.
+pub(crate) fn decompress_reader<'a>(reader: impl Read + 'a) -> ScanResult<Box<dyn Read + 'a>> {
+    let mut reader = BufReader::new(reader);
+    let header = reader
+        .fill_buf()
+        .map_err(|e| ScanError::BlockSource(e.to_string()))?;
+    if header.starts_with(&GZIP_MAGIC) {
+        Ok(Box::new(flate2::read::MultiGzDecoder::new(reader)))
+    } else if header.starts_with(&ZSTD_MAGIC) {
+        let decoder =
+            ruzstd::decoding::StreamingDecoder::new(reader).map_err(|e| invalid("zstd", e))?;
+        Ok(Box::new(decoder))
+    } else {
+        Ok(Box::new(reader))
+    }
+}
+
+fn invalid(format: &str, e: impl std::fmt::Display) -> ScanError {
+    ScanError::Decompression(format!("{format}: {e}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn test_decompress() {
+        let payload = br#"{"viewingKey": "uview1"}"#.repeat(10);
+
+        let mut gzip = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+        gzip.write_all(&payload).unwrap();
+        let gzip = gzip.finish().unwrap();
+        assert_eq!(decompress(&gzip).unwrap(), &payload[..]);
+
+        let zstd = ruzstd::encoding::compress_to_vec(
+            &payload[..],
+            ruzstd::encoding::CompressionLevel::Fastest,
+        );
+        assert_eq!(decompress(&zstd).unwrap(), &payload[..]);
+
+        assert!(matches!(decompress(&payload).unwrap(), Cow::Borrowed(_)));
+        assert!(matches!(
+            decompress(&gzip[..gzip.len() / 2]),
+            Err(ScanError::Decompression(_))
+        ));
+    }
+}
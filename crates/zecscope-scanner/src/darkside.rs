@@ -0,0 +1,273 @@
+//! Client for darksidewalletd, lightwalletd's test mode.
+//!
+//! A darksidewalletd server serves a chain the caller controls: blocks are
+//! staged, then applied up to a height, and applying different blocks at a
+//! height it already serves reorgs the chain. Its `DarksideStreamer`
+//! messages are declared here by hand, so no build script is needed.
+
+use tokio::runtime::Runtime;
+use tonic::codegen::http::uri::PathAndQuery;
+use tonic::transport::Channel;
+use zcash_client_backend::proto::service::Empty;
+
+use crate::error::ScanResult;
+use crate::lightwalletd::{connect_channel, source_error};
+
+/// `DarksideMetaState`: the chain a reset starts.
+#[derive(Clone, PartialEq, prost::Message)]
+struct DarksideMetaState {
+    #[prost(int32, tag = "1")]
+    sapling_activation: i32,
+    #[prost(string, tag = "2")]
+    branch_id: String,
+    #[prost(string, tag = "3")]
+    chain_name: String,
+    #[prost(uint32, tag = "4")]
+    start_sapling_commitment_tree_size: u32,
+    #[prost(uint32, tag = "5")]
+    start_orchard_commitment_tree_size: u32,
+}
+
+/// `DarksideBlocksURL`: a file of hex-encoded blocks, one per line.
+#[derive(Clone, PartialEq, prost::Message)]
+struct DarksideBlocksUrl {
+    #[prost(string, tag = "1")]
+    url: String,
+}
+
+/// `DarksideTransactionsURL`: a file of hex-encoded transactions to add to
+/// the staged block at `height`.
+#[derive(Clone, PartialEq, prost::Message)]
+struct DarksideTransactionsUrl {
+    #[prost(int32, tag = "1")]
+    height: i32,
+    #[prost(string, tag = "2")]
+    url: String,
+}
+
+/// `DarksideHeight`
+#[derive(Clone, PartialEq, prost::Message)]
+struct DarksideHeight {
+    #[prost(int32, tag = "1")]
+    height: i32,
+}
+
+/// `DarksideEmptyBlocks`: `count` blocks from `height`, whose hashes
+/// `nonce` varies.
+#[derive(Clone, PartialEq, prost::Message)]
+struct DarksideEmptyBlocks {
+    #[prost(int32, tag = "1")]
+    height: i32,
+    #[prost(int32, tag = "2")]
+    nonce: i32,
+    #[prost(int32, tag = "3")]
+    count: i32,
+}
+
+/// Drives a darksidewalletd server, blocking on each request.
+///
+/// The server also serves the regular lightwalletd API, so scan it with a
+/// [`LightwalletdSource`](crate::LightwalletdSource) connected
+/// to the same URL.
+pub struct DarksideClient {
+    runtime: Runtime,
+    grpc: tonic::client::Grpc<Channel>,
+}
+
+impl DarksideClient {
+    /// Connect to the darksidewalletd server at `url`.
+    pub fn connect(url: &str) -> ScanResult<Self> {
+        let runtime = Runtime::new().map_err(source_error)?;
+        let channel = runtime.block_on(connect_channel(url, None))?;
+        Ok(Self {
+            runtime,
+            grpc: tonic::client::Grpc::new(channel),
+        })
+    }
+
+    /// Discard the server's chain and staged blocks, and start a new chain
+    /// with Sapling active from `sapling_activation`.
+    ///
+    /// `branch_id` is the hex consensus branch ID blocks are served under
+    /// (e.g. `c2d6d0b4` for NU5) and `chain_name` its network (`main` or
+    /// `test`).
+    pub fn reset(
+        &mut self,
+        sapling_activation: u64,
+        branch_id: &str,
+        chain_name: &str,
+    ) -> ScanResult<()> {
+        self.call(
+            "Reset",
+            DarksideMetaState {
+                sapling_activation: height(sapling_activation),
+                branch_id: branch_id.to_string(),
+                chain_name: chain_name.to_string(),
+                ..Default::default()
+            },
+        )
+    }
+
+    /// Stage the blocks in the file at `url`, hex-encoded one per line.
+    pub fn stage_blocks(&mut self, url: &str) -> ScanResult<()> {
+        self.call(
+            "StageBlocks",
+            DarksideBlocksUrl {
+                url: url.to_string(),
+            },
+        )
+    }
+
+    /// Stage `count` empty blocks from `height`. Blocks staged with another
+    /// `nonce` get different hashes, so staging again at a height the server
+    /// serves and applying reorgs the chain there.
+    pub fn stage_empty_blocks(&mut self, height: u64, count: u32, nonce: i32) -> ScanResult<()> {
+        self.call(
+            "StageBlocksCreate",
+            DarksideEmptyBlocks {
+                height: self::height(height),
+                nonce,
+                count: i32::try_from(count).unwrap_or(i32::MAX),
+            },
+        )
+    }
+
+    /// Add the transactions in the file at `url`, hex-encoded one per line,
+    /// to the staged block at `height`.
+    pub fn stage_transactions(&mut self, height: u64, url: &str) -> ScanResult<()> {
+        self.call(
+            "StageTransactions",
+            DarksideTransactionsUrl {
+                height: self::height(height),
+                url: url.to_string(),
+            },
+        )
+    }
+
+    /// Serve the staged blocks, with the chain tip at `height`.
+    pub fn apply_staged(&mut self, height: u64) -> ScanResult<()> {
+        self.call(
+            "ApplyStaged",
+            DarksideHeight {
+                height: self::height(height),
+            },
+        )
+    }
+
+    /// Replace the chain from `height` with `count` fresh empty blocks, and
+    /// make the last of them the tip.
+    pub fn reorg(&mut self, height: u64, count: u32, nonce: i32) -> ScanResult<()> {
+        self.stage_empty_blocks(height, count, nonce)?;
+        self.apply_staged(height + u64::from(count.max(1)) - 1)
+    }
+
+    /// Call `DarksideStreamer/{method}`, a unary call returning `Empty`.
+    fn call<M>(&mut self, method: &str, message: M) -> ScanResult<()>
+    where
+        M: prost::Message + Send + Sync + 'static,
+    {
+        let path =
+            PathAndQuery::try_from(format!("/cash.z.wallet.sdk.rpc.DarksideStreamer/{method}"))
+                .map_err(source_error)?;
+        let grpc = &mut self.grpc;
+        self.runtime.block_on(async {
+            grpc.ready().await.map_err(source_error)?;
+            grpc.unary::<M, Empty, _>(
+                tonic::Request::new(message),
+                path,
+                tonic_prost::ProstCodec::default(),
+            )
+            .await
+            .map_err(source_error)?;
+            Ok(())
+        })
+    }
+}
+
+/// A height as darksidewalletd's `int32` fields take it.
+fn height(height: u64) -> i32 {
+    i32::try_from(height).unwrap_or(i32::MAX)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prost::Message;
+
+    #[test]
+    fn test_darkside_messages() {
+        let blocks = DarksideEmptyBlocks {
+            height: 1_000,
+            nonce: 1,
+            count: 3,
+        };
+        // Fields as darkside.proto numbers them
+        assert_eq!(
+            blocks.encode_to_vec(),
+            [0x08, 0xe8, 0x07, 0x10, 0x01, 0x18, 0x03]
+        );
+        assert_eq!(height(5_000_000_000), i32::MAX);
+    }
+
+    /// Scans a darksidewalletd chain before and after a reorg. Needs a server
+    /// started with `lightwalletd --darkside-very-insecure`, at
+    /// `ZECSCOPE_DARKSIDE_URL` (default `http://127.0.0.1:9067`).
+    #[test]
+    #[cfg(feature = "test-vectors")]
+    #[ignore = "needs a running darksidewalletd"]
+    fn test_darkside_reorg() {
+        use crate::error::ScanError;
+        use crate::lightwalletd::LightwalletdSource;
+        use crate::scanner::Scanner;
+        use crate::source::BlockSource;
+        use crate::state::ScanState;
+        use crate::types::ScanRequest;
+
+        let url = std::env::var("ZECSCOPE_DARKSIDE_URL")
+            .unwrap_or_else(|_| "http://127.0.0.1:9067".to_string());
+        let scanner = Scanner::mainnet();
+        let request = ScanRequest {
+            viewing_key: crate::test_vectors::TEST_UFVK.to_string(),
+            key_id: "darkside".to_string(),
+            compact_blocks: Vec::new(),
+            options: Default::default(),
+            nullifiers: Default::default(),
+        };
+
+        let mut darkside = DarksideClient::connect(&url).unwrap();
+        darkside.reset(419_200, "c2d6d0b4", "main").unwrap();
+        darkside.reorg(2_000_000, 10, 0).unwrap();
+        let mut source = LightwalletdSource::connect(&url, None).unwrap();
+        let mut state = ScanState::default();
+        scanner
+            .scan_with_state(
+                &ScanRequest {
+                    compact_blocks: source.get_block_range(2_000_000, 2_000_009).unwrap(),
+                    ..request.clone()
+                },
+                &mut state,
+            )
+            .unwrap();
+        assert_eq!(state.last_height(), Some(2_000_009));
+
+        // Blocks from the reorged chain don't follow the scanned ones
+        darkside.reorg(2_000_005, 10, 1).unwrap();
+        let blocks = source.get_block_range(2_000_010, 2_000_014).unwrap();
+        let error = scanner
+            .scan_with_state(
+                &ScanRequest {
+                    compact_blocks: blocks,
+                    ..request
+                },
+                &mut state,
+            )
+            .unwrap_err();
+        assert!(matches!(
+            error,
+            ScanError::ChainDiscontinuity {
+                height: 2_000_010,
+                ..
+            }
+        ));
+    }
+}
@@ -0,0 +1,134 @@
+//! Full note components of discovered notes.
+
+use serde::{Deserialize, Serialize};
+
+use crate::types::ShieldedPool;
+
+/// A discovered note with everything needed to reconstruct it.
+///
+/// With these components other Zcash tooling can rebuild the note, e.g. to
+/// import it into a spending wallet: a Sapling note from `recipient`,
+/// `value_zat` and `rseed` (or `rcm` for notes from before ZIP 212), an
+/// Orchard note from `recipient`, `value_zat`, `rho` and `rseed`. Byte
+/// strings are hex-encoded in their canonical encodings.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DecryptedNote {
+    /// Pool the note belongs to
+    pub pool: ShieldedPool,
+    /// Transaction that created the note (hex-encoded)
+    pub txid: String,
+    /// Height of the block the note was received in
+    pub height: u64,
+    /// Index of the Sapling output or Orchard action within the transaction
+    pub index: u32,
+    /// Note value in zatoshis (as string to avoid precision loss)
+    pub value_zat: String,
+    /// Raw 43-byte recipient address
+    pub recipient: String,
+    /// 11-byte diversifier of the recipient address
+    pub diversifier: String,
+    /// 32-byte note seed; `None` for Sapling notes from before ZIP 212,
+    /// which carry only `rcm`
+    pub rseed: Option<String>,
+    /// Commitment trapdoor (Sapling only)
+    pub rcm: Option<String>,
+    /// Nullifier of the note spent by the same action (Orchard only)
+    pub rho: Option<String>,
+    /// Note commitment (`cmu` for Sapling, `cmx` for Orchard)
+    pub commitment: String,
+    /// Note nullifier, if derivable with the viewing key
+    pub nullifier: Option<String>,
+    /// Position of the note commitment in its pool's commitment tree
+    pub position: Option<u64>,
+    /// Whether the note was received on an internal (change) address
+    pub is_change: bool,
+}
+
+impl DecryptedNote {
+    pub(crate) fn sapling(note: &sapling::Note, txid: &str, height: u64, index: u32) -> Self {
+        let recipient = note.recipient();
+        let rseed = match note.rseed() {
+            sapling::Rseed::AfterZip212(rseed) => Some(hex::encode(rseed)),
+            sapling::Rseed::BeforeZip212(_) => None,
+        };
+        Self {
+            pool: ShieldedPool::Sapling,
+            txid: txid.to_string(),
+            height,
+            index,
+            value_zat: note.value().inner().to_string(),
+            recipient: hex::encode(recipient.to_bytes()),
+            diversifier: hex::encode(recipient.diversifier().0),
+            rseed,
+            rcm: Some(hex::encode(note.rcm().to_bytes())),
+            rho: None,
+            commitment: hex::encode(note.cmu().to_bytes()),
+            nullifier: None,
+            position: None,
+            is_change: false,
+        }
+    }
+
+    #[cfg(feature = "orchard")]
+    pub(crate) fn orchard(note: &orchard::Note, txid: &str, height: u64, index: u32) -> Self {
+        let recipient = note.recipient();
+        let cmx = orchard::note::ExtractedNoteCommitment::from(note.commitment());
+        Self {
+            pool: ShieldedPool::Orchard,
+            txid: txid.to_string(),
+            height,
+            index,
+            value_zat: note.value().inner().to_string(),
+            recipient: hex::encode(recipient.to_raw_address_bytes()),
+            diversifier: hex::encode(recipient.diversifier().as_array()),
+            rseed: Some(hex::encode(note.rseed().as_bytes())),
+            rcm: None,
+            rho: Some(hex::encode(note.rho().to_bytes())),
+            commitment: hex::encode(cmx.to_bytes()),
+            nullifier: None,
+            position: None,
+            is_change: false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sapling::value::NoteValue;
+    use sapling::{PaymentAddress, Rseed};
+    use zcash_keys::keys::UnifiedSpendingKey;
+    use zcash_protocol::consensus::Network;
+
+    #[test]
+    fn test_sapling_note_roundtrip() {
+        let usk =
+            UnifiedSpendingKey::from_seed(&Network::MainNetwork, &[0; 32], zip32::AccountId::ZERO)
+                .unwrap();
+        let (_, address) = usk
+            .to_unified_full_viewing_key()
+            .sapling()
+            .unwrap()
+            .default_address();
+        let note = address.create_note(NoteValue::from_raw(12_345), Rseed::AfterZip212([9; 32]));
+
+        let decrypted = DecryptedNote::sapling(&note, "aa", 2_000_000, 1);
+        assert_eq!(decrypted.value_zat, "12345");
+        assert_eq!(decrypted.diversifier, hex::encode(address.diversifier().0));
+
+        // The note can be rebuilt from its serialized parts
+        let recipient: [u8; 43] = hex::decode(&decrypted.recipient)
+            .unwrap()
+            .try_into()
+            .unwrap();
+        let rseed: [u8; 32] = hex::decode(decrypted.rseed.unwrap())
+            .unwrap()
+            .try_into()
+            .unwrap();
+        let rebuilt = PaymentAddress::from_bytes(&recipient)
+            .unwrap()
+            .create_note(NoteValue::from_raw(12_345), Rseed::AfterZip212(rseed));
+        assert_eq!(hex::encode(rebuilt.cmu().to_bytes()), decrypted.commitment);
+    }
+}
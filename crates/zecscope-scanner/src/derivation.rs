@@ -0,0 +1,77 @@
+//! Viewing keys derived from a wallet's seed phrase, for recovery tools that
+//! start from the phrase rather than an exported key.
+
+use zcash_keys::keys::UnifiedSpendingKey;
+use zcash_protocol::consensus::Parameters;
+use zeroize::Zeroizing;
+
+use crate::error::{ScanError, ScanResult};
+
+/// The UFVK of ZIP 32 account `account_index` of the wallet whose BIP 39
+/// seed phrase is `mnemonic`, encoded for `network`.
+///
+/// The phrase is an English one of 12 to 24 words, with no passphrase, as
+/// Zcash wallets such as Zashi and Ywallet show it. Fails with
+/// [`ScanError::Derivation`] if it isn't a valid phrase (its words aren't
+/// echoed) or `account_index` is 2³¹ or more.
+pub fn derive_ufvk<P: Parameters>(
+    mnemonic: &str,
+    account_index: u32,
+    network: &P,
+) -> ScanResult<String> {
+    let mnemonic = bip39::Mnemonic::parse_in_normalized(
+        bip39::Language::English,
+        &mnemonic.split_whitespace().collect::<Vec<_>>().join(" "),
+    )
+    .map_err(|e| ScanError::Derivation(format!("invalid seed phrase: {e}")))?;
+    let seed = Zeroizing::new(mnemonic.to_seed(""));
+    let account = zip32::AccountId::try_from(account_index)
+        .map_err(|_| ScanError::Derivation(format!("invalid account index {account_index}")))?;
+    let usk = UnifiedSpendingKey::from_seed(network, &seed[..], account)
+        .map_err(|e| ScanError::Derivation(e.to_string()))?;
+    Ok(usk.to_unified_full_viewing_key().encode(network))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scanner::Scanner;
+    use zcash_protocol::consensus::Network;
+
+    const MNEMONIC: &str = "abandon abandon abandon abandon abandon abandon \
+                            abandon abandon abandon abandon abandon about";
+
+    #[test]
+    fn test_derive_ufvk() {
+        let seed = bip39::Mnemonic::parse(MNEMONIC).unwrap().to_seed("");
+        let expected = UnifiedSpendingKey::from_seed(
+            &Network::MainNetwork,
+            &seed,
+            zip32::AccountId::try_from(1).unwrap(),
+        )
+        .unwrap()
+        .to_unified_full_viewing_key()
+        .encode(&Network::MainNetwork);
+        let ufvk = derive_ufvk(&format!("  {MNEMONIC}\n"), 1, &Network::MainNetwork).unwrap();
+        assert_eq!(ufvk, expected);
+        assert!(Scanner::mainnet().inspect_key(&ufvk).unwrap().scannable);
+        assert_ne!(
+            derive_ufvk(MNEMONIC, 0, &Network::MainNetwork).unwrap(),
+            ufvk
+        );
+        assert!(derive_ufvk(MNEMONIC, 0, &Network::TestNetwork)
+            .unwrap()
+            .starts_with("uviewtest1"));
+
+        let wrong_checksum = MNEMONIC.replace("about", "abandon");
+        for (mnemonic, account) in [
+            (wrong_checksum.as_str(), 0),
+            ("abandon zcashword", 0),
+            (MNEMONIC, 1 << 31),
+        ] {
+            let err = derive_ufvk(mnemonic, account, &Network::MainNetwork).unwrap_err();
+            assert!(matches!(err, ScanError::Derivation(_)));
+            assert!(!err.to_string().contains("zcashword"));
+        }
+    }
+}
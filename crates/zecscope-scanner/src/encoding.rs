@@ -0,0 +1,184 @@
+//! Binary serializations of requests and results, as alternatives to JSON.
+
+use std::str::FromStr;
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::compression::decompress;
+use crate::error::{ScanError, ScanResult};
+
+/// A format requests and results are serialized in, chosen by content type.
+///
+/// JSON is always available. CBOR needs the `cbor` feature and MessagePack
+/// the `msgpack` feature; both encode the same fields as JSON, under the same
+/// names, in a fraction of the size (amounts and hashes stay strings).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "wasm", derive(tsify::Tsify))]
+#[serde(rename_all = "lowercase")]
+pub enum ContentType {
+    /// `application/json`
+    Json,
+    /// `application/cbor` (RFC 8949)
+    Cbor,
+    /// `application/msgpack`
+    #[serde(rename = "msgpack")]
+    MessagePack,
+}
+
+impl ContentType {
+    /// The format's MIME type.
+    pub fn mime_type(self) -> &'static str {
+        match self {
+            ContentType::Json => "application/json",
+            ContentType::Cbor => "application/cbor",
+            ContentType::MessagePack => "application/msgpack",
+        }
+    }
+
+    /// Whether this build can encode and decode the format.
+    pub fn is_supported(self) -> bool {
+        match self {
+            ContentType::Json => true,
+            ContentType::Cbor => cfg!(feature = "cbor"),
+            ContentType::MessagePack => cfg!(feature = "msgpack"),
+        }
+    }
+
+    /// Serialize `value` in this format.
+    ///
+    /// Fails with [`ScanError::UnsupportedContentType`] if the format's
+    /// feature isn't enabled.
+    pub fn encode<T: Serialize>(self, value: &T) -> ScanResult<Vec<u8>> {
+        match self {
+            ContentType::Json => Ok(serde_json::to_vec(value)?),
+            #[cfg(feature = "cbor")]
+            ContentType::Cbor => {
+                let mut bytes = Vec::new();
+                ciborium::into_writer(value, &mut bytes).map_err(encoding_error)?;
+                Ok(bytes)
+            }
+            // Maps with field names, not arrays, so the fields JSON has
+            // defaults for may be left out
+            #[cfg(feature = "msgpack")]
+            ContentType::MessagePack => rmp_serde::to_vec_named(value).map_err(encoding_error),
+            #[allow(unreachable_patterns)]
+            _ => Err(self.unsupported()),
+        }
+    }
+
+    /// Deserialize a value in this format from `bytes`, which may be gzip-
+    /// or zstd-compressed.
+    ///
+    /// Fails with [`ScanError::Json`] or [`ScanError::Encoding`] if `bytes`
+    /// don't hold a valid `T`.
+    pub fn decode<T: DeserializeOwned>(self, bytes: &[u8]) -> ScanResult<T> {
+        let bytes = decompress(bytes)?;
+        match self {
+            ContentType::Json => Ok(serde_json::from_slice(&bytes)?),
+            #[cfg(feature = "cbor")]
+            ContentType::Cbor => ciborium::from_reader(&bytes[..]).map_err(encoding_error),
+            #[cfg(feature = "msgpack")]
+            ContentType::MessagePack => rmp_serde::from_slice(&bytes).map_err(encoding_error),
+            #[allow(unreachable_patterns)]
+            _ => Err(self.unsupported()),
+        }
+    }
+
+    fn unsupported(self) -> ScanError {
+        let feature = match self {
+            ContentType::MessagePack => "msgpack",
+            _ => "cbor",
+        };
+        ScanError::UnsupportedContentType(format!(
+            "{} needs the `{feature}` feature",
+            self.mime_type()
+        ))
+    }
+}
+
+/// Parses a MIME type, ignoring parameters such as `; charset=utf-8`, or the
+/// short names `json`, `cbor` and `msgpack`.
+impl FromStr for ContentType {
+    type Err = ScanError;
+
+    fn from_str(s: &str) -> ScanResult<Self> {
+        let essence = s.split(';').next().unwrap_or_default().trim();
+        match essence.to_ascii_lowercase().as_str() {
+            "json" | "application/json" => Ok(ContentType::Json),
+            "cbor" | "application/cbor" => Ok(ContentType::Cbor),
+            "msgpack"
+            | "messagepack"
+            | "application/msgpack"
+            | "application/x-msgpack"
+            | "application/vnd.msgpack" => Ok(ContentType::MessagePack),
+            _ => Err(ScanError::UnsupportedContentType(s.to_string())),
+        }
+    }
+}
+
+#[cfg(any(feature = "cbor", feature = "msgpack"))]
+fn encoding_error(e: impl std::fmt::Display) -> ScanError {
+    ScanError::Encoding(e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{ScanOptions, ScanRequest};
+
+    #[test]
+    fn test_content_type() {
+        assert_eq!(
+            "application/json; charset=utf-8"
+                .parse::<ContentType>()
+                .unwrap(),
+            ContentType::Json
+        );
+        assert_eq!("CBOR".parse::<ContentType>().unwrap(), ContentType::Cbor);
+        assert_eq!(
+            "application/x-msgpack".parse::<ContentType>().unwrap(),
+            ContentType::MessagePack
+        );
+        assert!(matches!(
+            "text/plain".parse::<ContentType>(),
+            Err(ScanError::UnsupportedContentType(_))
+        ));
+        assert_eq!(
+            serde_json::to_string(&ContentType::MessagePack).unwrap(),
+            r#""msgpack""#
+        );
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let request = ScanRequest {
+            viewing_key: "uview1test".to_string(),
+            key_id: "wallet".to_string(),
+            compact_blocks: Vec::new(),
+            options: ScanOptions {
+                include_change: true,
+                ..Default::default()
+            },
+            nullifiers: Default::default(),
+        };
+        let json = serde_json::to_value(&request).unwrap();
+        for content_type in [
+            ContentType::Json,
+            ContentType::Cbor,
+            ContentType::MessagePack,
+        ] {
+            if !content_type.is_supported() {
+                assert!(matches!(
+                    content_type.encode(&request),
+                    Err(ScanError::UnsupportedContentType(_))
+                ));
+                continue;
+            }
+            let bytes = content_type.encode(&request).unwrap();
+            let decoded: ScanRequest = content_type.decode(&bytes).unwrap();
+            assert_eq!(serde_json::to_value(&decoded).unwrap(), json);
+            assert!(content_type.decode::<ScanRequest>(&bytes[..4]).is_err());
+        }
+    }
+}
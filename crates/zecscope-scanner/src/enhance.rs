@@ -0,0 +1,165 @@
+//! Full transaction sources for enhancing compact scan results.
+
+use std::collections::HashMap;
+
+use crate::error::ScanResult;
+
+/// Number of transactions [`Scanner::enhance`](crate::Scanner::enhance)
+/// requests from a [`TransactionSource`] at once.
+pub const ENHANCE_BATCH_SIZE: usize = 50;
+
+/// A serialized transaction fetched from a [`TransactionSource`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RawTransaction {
+    /// Transaction ID (hex-encoded, as scan results report it)
+    pub txid: String,
+    /// Raw transaction bytes
+    pub data: Vec<u8>,
+}
+
+/// Somewhere full transactions can be fetched by txid.
+///
+/// Implement this for your own transport (lightwalletd's `GetTransaction`,
+/// a node's `getrawtransaction`) to use
+/// [`Scanner::enhance`](crate::Scanner::enhance).
+pub trait TransactionSource {
+    /// Fetch the transactions with `txids`, hex-encoded as scan results
+    /// report them, in any order. Transactions the source doesn't know are
+    /// left out.
+    fn get_transactions(&mut self, txids: &[String]) -> ScanResult<Vec<RawTransaction>>;
+}
+
+impl<S: TransactionSource + ?Sized> TransactionSource for &mut S {
+    fn get_transactions(&mut self, txids: &[String]) -> ScanResult<Vec<RawTransaction>> {
+        (**self).get_transactions(txids)
+    }
+}
+
+/// A [`TransactionSource`] backed by transactions already held in memory.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryTransactionSource {
+    transactions: HashMap<String, Vec<u8>>,
+}
+
+impl MemoryTransactionSource {
+    /// Create a source from a set of transactions.
+    pub fn new(transactions: impl IntoIterator<Item = RawTransaction>) -> Self {
+        let mut source = Self::default();
+        for tx in transactions {
+            source.insert(tx);
+        }
+        source
+    }
+
+    /// Add `tx`, replacing any transaction with the same txid.
+    pub fn insert(&mut self, tx: RawTransaction) {
+        self.transactions.insert(tx.txid, tx.data);
+    }
+
+    /// Number of transactions held.
+    pub fn len(&self) -> usize {
+        self.transactions.len()
+    }
+
+    /// Whether no transactions are held.
+    pub fn is_empty(&self) -> bool {
+        self.transactions.is_empty()
+    }
+}
+
+impl TransactionSource for MemoryTransactionSource {
+    fn get_transactions(&mut self, txids: &[String]) -> ScanResult<Vec<RawTransaction>> {
+        Ok(txids
+            .iter()
+            .filter_map(|txid| {
+                self.transactions.get(txid).map(|data| RawTransaction {
+                    txid: txid.clone(),
+                    data: data.clone(),
+                })
+            })
+            .collect())
+    }
+}
+
+/// A [`TransactionSource`] that keeps what another source returned, so
+/// enhancing overlapping results (e.g. after a rescan) fetches each
+/// transaction only once.
+pub struct CachedTransactionSource<S> {
+    source: S,
+    cache: MemoryTransactionSource,
+}
+
+impl<S: TransactionSource> CachedTransactionSource<S> {
+    /// Cache the transactions fetched from `source` in memory.
+    pub fn new(source: S) -> Self {
+        Self {
+            source,
+            cache: MemoryTransactionSource::default(),
+        }
+    }
+
+    /// The transactions fetched so far.
+    pub fn cache(&self) -> &MemoryTransactionSource {
+        &self.cache
+    }
+
+    /// Get the wrapped source back.
+    pub fn into_inner(self) -> S {
+        self.source
+    }
+}
+
+impl<S: TransactionSource> TransactionSource for CachedTransactionSource<S> {
+    fn get_transactions(&mut self, txids: &[String]) -> ScanResult<Vec<RawTransaction>> {
+        let missing: Vec<String> = txids
+            .iter()
+            .filter(|txid| !self.cache.transactions.contains_key(*txid))
+            .cloned()
+            .collect();
+        if !missing.is_empty() {
+            for tx in self.source.get_transactions(&missing)? {
+                self.cache.insert(tx);
+            }
+        }
+        self.cache.get_transactions(txids)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Counts the transactions it is asked for.
+    struct CountingSource {
+        inner: MemoryTransactionSource,
+        requested: usize,
+    }
+
+    impl TransactionSource for CountingSource {
+        fn get_transactions(&mut self, txids: &[String]) -> ScanResult<Vec<RawTransaction>> {
+            self.requested += txids.len();
+            self.inner.get_transactions(txids)
+        }
+    }
+
+    #[test]
+    fn test_cached_transaction_source() {
+        let tx = |txid: &str| RawTransaction {
+            txid: txid.to_string(),
+            data: vec![1, 2, 3],
+        };
+        let mut source = CachedTransactionSource::new(CountingSource {
+            inner: MemoryTransactionSource::new([tx("aa"), tx("bb")]),
+            requested: 0,
+        });
+
+        let ids = |ids: &[&str]| ids.iter().map(|id| id.to_string()).collect::<Vec<_>>();
+        assert_eq!(source.get_transactions(&ids(&["aa"])).unwrap(), [tx("aa")]);
+        // Unknown transactions are left out, and asked for again next time
+        let found = source.get_transactions(&ids(&["aa", "bb", "cc"])).unwrap();
+        assert_eq!(found, [tx("aa"), tx("bb")]);
+        source.get_transactions(&ids(&["bb", "cc"])).unwrap();
+        assert_eq!(source.cache().len(), 2);
+        assert_eq!(source.into_inner().requested, 4);
+    }
+}
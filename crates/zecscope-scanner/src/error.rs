@@ -1,7 +1,12 @@
 //! Error types for the scanner.
 
+use serde::ser::{Serialize, SerializeStruct, Serializer};
 use thiserror::Error;
 
+use crate::fingerprint::redact_viewing_key;
+use crate::state::ScanState;
+use crate::types::{KeyNetwork, ZecTransaction};
+
 /// Result type alias for scanner operations.
 pub type ScanResult<T> = Result<T, ScanError>;
 
@@ -12,19 +17,225 @@ pub enum ScanError {
     #[error("Invalid viewing key: {0}")]
     InvalidViewingKey(String),
 
+    /// The viewing key is encoded for another network than the scanner's.
+    #[error("Viewing key is for {key_network}, but the scanner is for {scanner_network}")]
+    NetworkMismatch {
+        key_network: KeyNetwork,
+        scanner_network: KeyNetwork,
+    },
+
     /// Failed to parse compact block data.
     #[error("Invalid compact block at height {height}: {message}")]
     InvalidCompactBlock { height: u64, message: String },
 
+    /// Failed to decode a protobuf-encoded compact block.
+    #[error("Invalid protobuf compact block at index {index}: {message}")]
+    InvalidProtobuf { index: usize, message: String },
+
+    /// Blocks don't form a continuous chain: either the height doesn't follow
+    /// the previous block, or `prev_hash` doesn't match the previous block's hash.
+    #[error("Chain discontinuity at height {height}: expected {expected}, found {found}")]
+    ChainDiscontinuity {
+        expected: String,
+        found: String,
+        height: u64,
+    },
+
     /// Failed to decode hex string.
     #[error("Invalid hex in {field}: {message}")]
     InvalidHex { field: String, message: String },
 
+    /// Compressed input could not be decompressed.
+    #[error("Invalid compressed input: {0}")]
+    Decompression(String),
+
+    /// Failed to parse a full transaction.
+    #[error("Invalid transaction: {0}")]
+    InvalidTransaction(String),
+
     /// Error during block scanning.
     #[error("Scan error at height {height}: {message}")]
     ScanFailed { height: u32, message: String },
 
+    /// A height range was empty or reversed, or a height was past
+    /// `u32::MAX`.
+    #[error("Invalid height range {start}..={end}")]
+    InvalidRange { start: u64, end: u64 },
+
+    /// A block source failed to provide the requested blocks.
+    #[error("Block source error: {0}")]
+    BlockSource(String),
+
+    /// The on-disk block cache could not be read or written.
+    #[error("Block cache error: {0}")]
+    BlockCache(String),
+
+    /// A persistence backend failed.
+    #[error("Storage error: {0}")]
+    Storage(String),
+
+    /// Note commitment tree could not be updated or queried.
+    #[error("Commitment tree error: {0}")]
+    CommitmentTree(String),
+
+    /// A price provider failed or returned an unusable price.
+    #[error("Price provider error: {0}")]
+    PriceProvider(String),
+
+    /// Viewing keys could not be encrypted or decrypted for storage.
+    #[error("Key encryption error: {0}")]
+    KeyEncryption(String),
+
+    /// A content type wasn't recognized, or its feature isn't enabled.
+    #[error("Unsupported content type: {0}")]
+    UnsupportedContentType(String),
+
+    /// CBOR or MessagePack serialization/deserialization error.
+    #[error("Encoding error: {0}")]
+    Encoding(String),
+
+    /// Scan results could not be exported to a file.
+    #[error("Export error: {0}")]
+    Export(String),
+
+    /// A viewing key could not be derived from a seed phrase.
+    #[error("Derivation error: {0}")]
+    Derivation(String),
+
     /// JSON serialization/deserialization error.
     #[error("JSON error: {0}")]
     Json(#[from] serde_json::Error),
 }
+
+impl ScanError {
+    /// An [`InvalidViewingKey`](ScanError::InvalidViewingKey) error about
+    /// `viewing_key`, with any copy of the key in `message` redacted.
+    pub(crate) fn invalid_viewing_key(viewing_key: &str, message: impl std::fmt::Display) -> Self {
+        let message = message.to_string();
+        let viewing_key = viewing_key.trim();
+        if viewing_key.is_empty() {
+            return ScanError::InvalidViewingKey(message);
+        }
+        ScanError::InvalidViewingKey(message.replace(viewing_key, &redact_viewing_key(viewing_key)))
+    }
+
+    /// Stable, machine-readable name of the error kind, such as
+    /// `"INVALID_VIEWING_KEY"`, for callers that branch on it (e.g. from
+    /// JavaScript) rather than parse the message.
+    pub fn code(&self) -> &'static str {
+        match self {
+            ScanError::InvalidViewingKey(_) => "INVALID_VIEWING_KEY",
+            ScanError::NetworkMismatch { .. } => "NETWORK_MISMATCH",
+            ScanError::InvalidCompactBlock { .. } => "INVALID_COMPACT_BLOCK",
+            ScanError::InvalidProtobuf { .. } => "INVALID_PROTOBUF",
+            ScanError::ChainDiscontinuity { .. } => "CHAIN_DISCONTINUITY",
+            ScanError::InvalidHex { .. } => "INVALID_HEX",
+            ScanError::Decompression(_) => "DECOMPRESSION",
+            ScanError::InvalidTransaction(_) => "INVALID_TRANSACTION",
+            ScanError::ScanFailed { .. } => "SCAN_FAILED",
+            ScanError::InvalidRange { .. } => "INVALID_RANGE",
+            ScanError::BlockSource(_) => "BLOCK_SOURCE",
+            ScanError::BlockCache(_) => "BLOCK_CACHE",
+            ScanError::Storage(_) => "STORAGE",
+            ScanError::CommitmentTree(_) => "COMMITMENT_TREE",
+            ScanError::PriceProvider(_) => "PRICE_PROVIDER",
+            ScanError::KeyEncryption(_) => "KEY_ENCRYPTION",
+            ScanError::UnsupportedContentType(_) => "UNSUPPORTED_CONTENT_TYPE",
+            ScanError::Encoding(_) => "INVALID_ENCODING",
+            ScanError::Export(_) => "EXPORT",
+            ScanError::Derivation(_) => "DERIVATION",
+            ScanError::Json(_) => "INVALID_JSON",
+        }
+    }
+
+    /// Height of the block the error is about, if it is about one.
+    pub fn height(&self) -> Option<u64> {
+        match self {
+            ScanError::InvalidCompactBlock { height, .. }
+            | ScanError::ChainDiscontinuity { height, .. } => Some(*height),
+            ScanError::ScanFailed { height, .. } => Some(*height as u64),
+            _ => None,
+        }
+    }
+}
+
+/// Serializes as `{"code": ..., "message": ..., "height": ...}`, with
+/// `height` only present when [`ScanError::height`] is known.
+impl Serialize for ScanError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let height = self.height();
+        let mut error = serializer.serialize_struct("ScanError", 2 + height.is_some() as usize)?;
+        error.serialize_field("code", self.code())?;
+        error.serialize_field("message", &self.to_string())?;
+        if let Some(height) = height {
+            error.serialize_field("height", &height)?;
+        }
+        error.end()
+    }
+}
+
+/// A scan that failed part-way, with what it found before failing.
+///
+/// Returned by [`Scanner::scan_partial`](crate::Scanner::scan_partial).
+#[derive(Error, Debug)]
+#[error("{error} ({} transaction(s) found before the failure)", transactions.len())]
+pub struct PartialScanError {
+    /// Transactions found in the blocks scanned before the failure
+    pub transactions: Vec<ZecTransaction>,
+    /// Scan state as of the last block scanned successfully; resume with
+    /// [`Scanner::scan_with_state`](crate::Scanner::scan_with_state) from
+    /// [`ScanState::next_height`]
+    pub state: ScanState,
+    /// What stopped the scan
+    #[source]
+    pub error: ScanError,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_serialize() {
+        let error = ScanError::ChainDiscontinuity {
+            expected: "height 11".to_string(),
+            found: "height 12".to_string(),
+            height: 12,
+        };
+        assert_eq!(
+            serde_json::to_value(&error).unwrap(),
+            serde_json::json!({
+                "code": "CHAIN_DISCONTINUITY",
+                "message": "Chain discontinuity at height 12: expected height 11, found height 12",
+                "height": 12,
+            })
+        );
+
+        let error = ScanError::InvalidViewingKey("bad checksum".to_string());
+        assert_eq!(
+            serde_json::to_value(&error).unwrap(),
+            serde_json::json!({
+                "code": "INVALID_VIEWING_KEY",
+                "message": "Invalid viewing key: bad checksum",
+            })
+        );
+    }
+
+    #[test]
+    fn test_invalid_viewing_key_redacted() {
+        let key = "uview1qqqqsecret";
+        let error = ScanError::invalid_viewing_key(key, format!("cannot decode {key}"));
+        let message = error.to_string();
+        assert!(!message.contains(key));
+        assert!(message.starts_with("Invalid viewing key: cannot decode uview1…["));
+
+        let request = crate::ScanRequest {
+            viewing_key: key.to_string(),
+            key_id: "test".to_string(),
+            compact_blocks: Vec::new(),
+            options: Default::default(),
+            nullifiers: Default::default(),
+        };
+        assert!(!format!("{request:?}").contains("secret"));
+    }
+}
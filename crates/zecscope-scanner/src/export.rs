@@ -0,0 +1,242 @@
+//! Spreadsheet-friendly export of scan results.
+
+#[cfg(feature = "parquet")]
+use crate::error::{ScanError, ScanResult};
+use crate::types::{TxDirection, ZecTransaction};
+
+/// Column headers written by [`to_csv`].
+const CSV_HEADER: &str = "txid,height,date,pool,direction,amount_zec,memo,key_id";
+
+/// Render transactions as CSV, one row per transaction, with a header row.
+///
+/// Dates are the block time in UTC (`YYYY-MM-DD HH:MM:SS`) and amounts are
+/// exact decimal ZEC. Fields are quoted as needed per RFC 4180, and text that
+/// a spreadsheet would evaluate as a formula (an `=`, `+`, `-` or `@` at the
+/// start, e.g. in a memo) is prefixed with `'` so it opens as plain text.
+pub fn to_csv(transactions: &[ZecTransaction]) -> String {
+    let mut csv = String::from(CSV_HEADER);
+    csv.push_str("\r\n");
+    for tx in transactions {
+        let fields = [
+            tx.txid.clone(),
+            tx.height.to_string(),
+            format_date(tx.time),
+            tx.pool.to_string(),
+            direction(tx).to_string(),
+            format_zec(tx.amount_zatoshis()),
+            tx.memo.clone().unwrap_or_default(),
+            tx.key_id.clone(),
+        ];
+        let row: Vec<String> = fields.iter().map(|field| escape_field(field)).collect();
+        csv.push_str(&row.join(","));
+        csv.push_str("\r\n");
+    }
+    csv
+}
+
+/// Schema of the files written by [`export_parquet`].
+#[cfg(feature = "parquet")]
+const PARQUET_SCHEMA: &str = "
+    message zec_transaction {
+        REQUIRED BYTE_ARRAY txid (STRING);
+        REQUIRED INT64 height;
+        REQUIRED INT64 time (TIMESTAMP(MILLIS, true));
+        REQUIRED BYTE_ARRAY pool (STRING);
+        REQUIRED BYTE_ARRAY direction (STRING);
+        REQUIRED INT64 amount_zat;
+        REQUIRED BYTE_ARRAY key_id (STRING);
+    }
+";
+
+/// Write transactions to a Snappy-compressed Parquet file at `path`, one
+/// row per transaction, for loading into DuckDB, Spark or pandas.
+///
+/// Columns are `txid`, `height`, `time` (a UTC timestamp), `pool`,
+/// `direction` (`in` or `out`), `amount_zat` (an integer number of
+/// zatoshis) and `key_id`. An existing file at `path` is replaced.
+#[cfg(feature = "parquet")]
+pub fn export_parquet(
+    transactions: &[ZecTransaction],
+    path: impl AsRef<std::path::Path>,
+) -> ScanResult<()> {
+    let path = path.as_ref();
+    let file = std::fs::File::create(path)
+        .map_err(|e| ScanError::Export(format!("can't create {}: {e}", path.display())))?;
+    write_parquet(transactions, file).map_err(|e| ScanError::Export(e.to_string()))
+}
+
+#[cfg(feature = "parquet")]
+fn write_parquet(
+    transactions: &[ZecTransaction],
+    file: std::fs::File,
+) -> parquet::errors::Result<()> {
+    use std::sync::Arc;
+
+    use parquet::basic::Compression;
+    use parquet::data_type::{ByteArray, ByteArrayType, DataType, Int64Type};
+    use parquet::file::properties::WriterProperties;
+    use parquet::file::writer::{SerializedFileWriter, SerializedRowGroupWriter};
+    use parquet::schema::parser::parse_message_type;
+
+    fn write_column<T: DataType>(
+        row_group: &mut SerializedRowGroupWriter<'_, std::fs::File>,
+        values: &[T::T],
+    ) -> parquet::errors::Result<()> {
+        let mut column = row_group
+            .next_column()?
+            .expect("PARQUET_SCHEMA has a column for every write");
+        column.typed::<T>().write_batch(values, None, None)?;
+        column.close()
+    }
+    let strings = |field: fn(&ZecTransaction) -> String| -> Vec<ByteArray> {
+        transactions
+            .iter()
+            .map(|tx| field(tx).into_bytes().into())
+            .collect()
+    };
+    let integers = |field: fn(&ZecTransaction) -> i64| -> Vec<i64> {
+        transactions.iter().map(field).collect()
+    };
+
+    let schema = Arc::new(parse_message_type(PARQUET_SCHEMA)?);
+    let properties = WriterProperties::builder()
+        .set_compression(Compression::SNAPPY)
+        .build();
+    let mut writer = SerializedFileWriter::new(file, schema, Arc::new(properties))?;
+    let mut row_group = writer.next_row_group()?;
+    write_column::<ByteArrayType>(&mut row_group, &strings(|tx| tx.txid.clone()))?;
+    write_column::<Int64Type>(&mut row_group, &integers(|tx| tx.height as i64))?;
+    write_column::<Int64Type>(&mut row_group, &integers(|tx| tx.time.saturating_mul(1000)))?;
+    write_column::<ByteArrayType>(&mut row_group, &strings(|tx| tx.pool.to_string()))?;
+    write_column::<ByteArrayType>(&mut row_group, &strings(|tx| direction(tx).to_string()))?;
+    write_column::<Int64Type>(&mut row_group, &integers(ZecTransaction::amount_zatoshis))?;
+    write_column::<ByteArrayType>(&mut row_group, &strings(|tx| tx.key_id.clone()))?;
+    row_group.close()?;
+    writer.close()?;
+    Ok(())
+}
+
+fn direction(tx: &ZecTransaction) -> &'static str {
+    match tx.direction {
+        TxDirection::In => "in",
+        TxDirection::Out => "out",
+    }
+}
+
+/// Format a Unix timestamp as a UTC date and time.
+fn format_date(time: i64) -> String {
+    match time::OffsetDateTime::from_unix_timestamp(time) {
+        Ok(dt) => format!(
+            "{} {:02}:{:02}:{:02}",
+            dt.date(),
+            dt.hour(),
+            dt.minute(),
+            dt.second()
+        ),
+        Err(_) => String::new(),
+    }
+}
+
+/// Format zatoshis as a decimal ZEC amount without going through floats.
+fn format_zec(zatoshis: i64) -> String {
+    let sign = if zatoshis < 0 { "-" } else { "" };
+    let zatoshis = zatoshis.unsigned_abs();
+    format!(
+        "{sign}{}.{:08}",
+        zatoshis / 100_000_000,
+        zatoshis % 100_000_000
+    )
+}
+
+fn escape_field(field: &str) -> String {
+    let field = if field.starts_with(['=', '+', '-', '@']) {
+        format!("'{field}")
+    } else {
+        field.to_string()
+    };
+    if field.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::ShieldedPool;
+
+    fn transaction() -> ZecTransaction {
+        ZecTransaction {
+            time: 1_672_531_200,
+            memo: Some("=HYPERLINK(\"x\"), thanks".to_string()),
+            key_id: "wallet".to_string(),
+            ..ZecTransaction::test_entry(
+                &"ab".repeat(32),
+                150_000_000,
+                TxDirection::In,
+                ShieldedPool::Orchard,
+            )
+        }
+    }
+
+    #[test]
+    fn test_to_csv() {
+        let csv = to_csv(&[transaction()]);
+        let mut lines = csv.lines();
+        assert_eq!(lines.next(), Some(CSV_HEADER));
+        assert_eq!(
+            lines.next().unwrap(),
+            format!(
+                "{},2000000,2023-01-01 00:00:00,orchard,in,1.50000000,\"'=HYPERLINK(\"\"x\"\"), thanks\",wallet",
+                "ab".repeat(32)
+            )
+        );
+        assert_eq!(format_zec(5), "0.00000005");
+    }
+
+    #[cfg(feature = "parquet")]
+    #[test]
+    fn test_export_parquet() {
+        use parquet::file::reader::{FileReader, SerializedFileReader};
+        use parquet::record::RowAccessor;
+
+        let mut spend = transaction();
+        spend.direction = TxDirection::Out;
+        spend.amount_zat = "20000".to_string();
+        spend.pool = ShieldedPool::Sapling;
+        let path = std::env::temp_dir().join(format!("zecscope-{}.parquet", std::process::id()));
+        export_parquet(&[transaction(), spend], &path).unwrap();
+
+        let reader = SerializedFileReader::new(std::fs::File::open(&path).unwrap()).unwrap();
+        let schema = reader.metadata().file_metadata().schema_descr();
+        let columns: Vec<_> = schema.columns().iter().map(|c| c.name()).collect();
+        assert_eq!(
+            columns,
+            [
+                "txid",
+                "height",
+                "time",
+                "pool",
+                "direction",
+                "amount_zat",
+                "key_id"
+            ]
+        );
+        let rows: Vec<_> = reader
+            .get_row_iter(None)
+            .unwrap()
+            .map(|row| row.unwrap())
+            .collect();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].get_string(0).unwrap(), &"ab".repeat(32));
+        assert_eq!(rows[0].get_long(1).unwrap(), 2_000_000);
+        assert_eq!(rows[0].get_timestamp_millis(2).unwrap(), 1_672_531_200_000);
+        assert_eq!(rows[0].get_long(5).unwrap(), 150_000_000);
+        assert_eq!(rows[1].get_string(3).unwrap(), "sapling");
+        assert_eq!(rows[1].get_string(4).unwrap(), "out");
+        assert_eq!(rows[1].get_long(5).unwrap(), 20_000);
+        assert_eq!(rows[1].get_string(6).unwrap(), "wallet");
+        std::fs::remove_file(&path).unwrap();
+    }
+}
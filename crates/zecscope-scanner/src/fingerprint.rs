@@ -0,0 +1,67 @@
+//! Stable identifiers for viewing keys.
+
+/// Length of the BLAKE2b digest a fingerprint is made of.
+const FINGERPRINT_LEN: usize = 16;
+
+/// Personalization for viewing key fingerprints.
+const FINGERPRINT_PERSONAL: &[u8; 16] = b"zecscope_UFVK_FP";
+
+/// A short, stable identifier for `viewing_key`, suitable as a `key_id`.
+///
+/// Like ZIP 32 seed fingerprints, this is a personalized BLAKE2b digest
+/// (here 128 bits, hex-encoded) of the key's encoding, so it identifies the
+/// key without revealing it. Surrounding whitespace and letter case (which
+/// Bech32m ignores) don't change the result. The key isn't validated; an
+/// invalid key simply gets a fingerprint no valid key shares.
+pub fn ufvk_fingerprint(viewing_key: &str) -> String {
+    let encoding = viewing_key.trim().to_ascii_lowercase();
+    let digest = blake2b_simd::Params::new()
+        .hash_length(FINGERPRINT_LEN)
+        .personal(FINGERPRINT_PERSONAL)
+        .hash(encoding.as_bytes());
+    hex::encode(digest.as_bytes())
+}
+
+/// Hex digits of the fingerprint shown by [`redact_viewing_key`].
+const REDACTED_FINGERPRINT_LEN: usize = 8;
+
+/// How `viewing_key` is shown in logs, errors and `Debug` output: its
+/// human-readable prefix and the start of its [`ufvk_fingerprint`], such as
+/// `uview1…[1a2b3c4d]`, enough to tell keys apart without revealing one.
+pub fn redact_viewing_key(viewing_key: &str) -> String {
+    let fingerprint = &ufvk_fingerprint(viewing_key)[..REDACTED_FINGERPRINT_LEN];
+    let hrp = viewing_key
+        .trim()
+        .split_once('1')
+        .map(|(hrp, _)| hrp)
+        .filter(|hrp| hrp.len() <= 16 && hrp.bytes().all(|b| b.is_ascii_alphabetic()));
+    match hrp {
+        Some(hrp) if !hrp.is_empty() => format!("{hrp}1…[{fingerprint}]"),
+        _ => format!("…[{fingerprint}]"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ufvk_fingerprint() {
+        let fingerprint = ufvk_fingerprint("uview1abc");
+        assert_eq!(fingerprint.len(), 2 * FINGERPRINT_LEN);
+        assert_eq!(ufvk_fingerprint(" UVIEW1ABC\n"), fingerprint);
+        assert_ne!(ufvk_fingerprint("uview1abd"), fingerprint);
+    }
+
+    #[test]
+    fn test_redact_viewing_key() {
+        let fingerprint = &ufvk_fingerprint("uview1abc")[..REDACTED_FINGERPRINT_LEN];
+        assert_eq!(
+            redact_viewing_key("uview1abc"),
+            format!("uview1…[{fingerprint}]")
+        );
+        assert!(redact_viewing_key("uviewtest1abc").starts_with("uviewtest1…["));
+        assert!(redact_viewing_key("not a key").starts_with("…["));
+        assert!(redact_viewing_key("secret-1-abc").starts_with("…["));
+    }
+}
@@ -0,0 +1,66 @@
+//! Matching memos against expected invoice IDs.
+
+use crate::types::{TxDirection, ZecTransaction};
+
+/// Set `invoice_id` on each incoming transaction whose memo starts with one
+/// of `invoice_ids`, for point-of-sale flows that ask payers to put an
+/// order number in the memo.
+///
+/// Memos are only known after [`Scanner::decrypt_memos`](crate::Scanner::decrypt_memos)
+/// (or from [`Scanner::scan_transaction`](crate::Scanner::scan_transaction),
+/// which matches [`ScanOptions::invoice_ids`](crate::ScanOptions::invoice_ids)
+/// itself), so call this once they have been filled in. Leading whitespace
+/// in the memo is ignored; when several IDs match, the longest wins.
+///
+/// Returns the number of transactions tagged.
+pub fn match_invoices(transactions: &mut [ZecTransaction], invoice_ids: &[String]) -> usize {
+    let mut tagged = 0;
+    for tx in transactions
+        .iter_mut()
+        .filter(|tx| tx.direction == TxDirection::In)
+    {
+        tx.invoice_id = matching_id(tx.memo.as_deref(), invoice_ids);
+        tagged += tx.invoice_id.is_some() as usize;
+    }
+    tagged
+}
+
+/// The longest of `invoice_ids` that `memo` starts with.
+pub(crate) fn matching_id(memo: Option<&str>, invoice_ids: &[String]) -> Option<String> {
+    let memo = memo?.trim_start();
+    invoice_ids
+        .iter()
+        .filter(|id| !id.is_empty() && memo.starts_with(id.as_str()))
+        .max_by_key(|id| id.len())
+        .cloned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::ShieldedPool;
+
+    fn test_tx(memo: Option<&str>, direction: TxDirection) -> ZecTransaction {
+        ZecTransaction {
+            memo: memo.map(str::to_string),
+            key_id: "shop".to_string(),
+            action_index: Some(0),
+            ..ZecTransaction::test_entry("aa", 5_000, direction, ShieldedPool::Orchard)
+        }
+    }
+
+    #[test]
+    fn test_match_invoices() {
+        let ids = vec!["INV-1".to_string(), "INV-12".to_string(), String::new()];
+        let mut txs = vec![
+            test_tx(Some("  INV-12 thanks!"), TxDirection::In),
+            test_tx(Some("INV-1"), TxDirection::In),
+            test_tx(Some("order INV-1"), TxDirection::In),
+            test_tx(None, TxDirection::In),
+            test_tx(Some("INV-1"), TxDirection::Out),
+        ];
+        assert_eq!(match_invoices(&mut txs, &ids), 2);
+        let matched: Vec<_> = txs.iter().map(|tx| tx.invoice_id.as_deref()).collect();
+        assert_eq!(matched, [Some("INV-12"), Some("INV-1"), None, None, None]);
+    }
+}
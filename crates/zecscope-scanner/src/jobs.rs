@@ -0,0 +1,451 @@
+//! Scheduling scans of many keys on a pool of worker threads.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError};
+use std::thread::JoinHandle;
+
+use serde::{Deserialize, Serialize};
+use zcash_protocol::consensus::{Network, Parameters};
+
+use crate::error::{ScanError, ScanResult};
+use crate::progress::ScanProgress;
+use crate::scanner::Scanner;
+use crate::source::BlockSource;
+use crate::types::{CompactBlock, ScanRequest, ScanSummary};
+
+/// Identifies a job submitted to a [`JobManager`].
+pub type JobId = u64;
+
+/// A range scan to run as a job.
+pub struct ScanJob {
+    /// Viewing key, key id and options to scan with; `compact_blocks` are
+    /// ignored
+    pub request: ScanRequest,
+    /// Where the job fetches its blocks from
+    pub source: Box<dyn BlockSource + Send>,
+    /// First height to scan
+    pub start: u64,
+    /// Last height to scan
+    pub end: u64,
+}
+
+/// Where a job is in its life.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobState {
+    /// Waiting for a worker
+    Queued,
+    /// Being scanned
+    Running,
+    /// Scanned; its summary is ready
+    Done,
+    /// Stopped by an error
+    Failed,
+    /// Cancelled before it finished
+    Cancelled,
+}
+
+impl JobState {
+    /// Whether the job has stopped for good.
+    pub fn is_finished(self) -> bool {
+        matches!(self, Self::Done | Self::Failed | Self::Cancelled)
+    }
+}
+
+/// What a [`JobManager`] reports about a job.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JobStatus {
+    /// The job's id
+    pub id: JobId,
+    /// Key id of the job's request
+    pub key_id: String,
+    /// Where the job is in its life
+    pub state: JobState,
+    /// How far the job has got, once it has started
+    pub progress: Option<ScanProgress>,
+    /// Why a failed job failed
+    pub error: Option<String>,
+}
+
+/// Queues range scans and runs a limited number of them at once.
+///
+/// A service scanning for many keys submits a [`ScanJob`] per key, polls
+/// [`JobManager::status`] to show progress and collects each summary with
+/// [`JobManager::take_summary`]. Jobs start in submission order, on
+/// `max_concurrent` worker threads. Cancelling a running job stops it at
+/// its next chunk of blocks (see
+/// [`ScannerBuilder::range_chunk_size`](crate::ScannerBuilder::range_chunk_size)).
+///
+/// Dropping the manager cancels every job that hasn't finished and waits
+/// for the running ones to stop.
+pub struct JobManager<P = Network> {
+    shared: Arc<Shared<P>>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+struct Shared<P> {
+    scanner: Scanner<P>,
+    jobs: Mutex<Jobs>,
+    /// Signalled when a job is queued and when the manager shuts down
+    queued: Condvar,
+    /// Signalled when a job finishes
+    finished: Condvar,
+}
+
+#[derive(Default)]
+struct Jobs {
+    next_id: JobId,
+    queue: VecDeque<JobId>,
+    jobs: BTreeMap<JobId, Job>,
+    shutdown: bool,
+}
+
+struct Job {
+    status: JobStatus,
+    cancelled: Arc<AtomicBool>,
+    /// Taken by the worker that runs the job
+    work: Option<ScanJob>,
+    summary: Option<ScanSummary>,
+}
+
+impl<P: Parameters + Send + Sync + 'static> JobManager<P> {
+    /// Run jobs with `scanner`, at most `max_concurrent` (at least 1) at a
+    /// time.
+    pub fn new(scanner: Scanner<P>, max_concurrent: usize) -> Self {
+        let shared = Arc::new(Shared {
+            scanner,
+            jobs: Mutex::new(Jobs::default()),
+            queued: Condvar::new(),
+            finished: Condvar::new(),
+        });
+        let workers = (0..max_concurrent.max(1))
+            .map(|i| {
+                let shared = Arc::clone(&shared);
+                std::thread::Builder::new()
+                    .name(format!("zecscope-job-{i}"))
+                    .spawn(move || shared.work())
+                    .expect("failed to spawn a job worker")
+            })
+            .collect();
+        Self { shared, workers }
+    }
+
+    /// Queue `job`, returning its id.
+    pub fn submit(&self, job: ScanJob) -> JobId {
+        let mut jobs = self.shared.lock();
+        let id = jobs.next_id;
+        jobs.next_id += 1;
+        let status = JobStatus {
+            id,
+            key_id: job.request.key_id().into_owned(),
+            state: JobState::Queued,
+            progress: None,
+            error: None,
+        };
+        jobs.jobs.insert(
+            id,
+            Job {
+                status,
+                cancelled: Arc::new(AtomicBool::new(false)),
+                work: Some(job),
+                summary: None,
+            },
+        );
+        jobs.queue.push_back(id);
+        self.shared.queued.notify_one();
+        id
+    }
+
+    /// The status of job `id`, or `None` if there's no such job.
+    pub fn status(&self, id: JobId) -> Option<JobStatus> {
+        Some(self.shared.lock().jobs.get(&id)?.status.clone())
+    }
+
+    /// The status of every job not yet taken, in submission order.
+    pub fn jobs(&self) -> Vec<JobStatus> {
+        let jobs = self.shared.lock();
+        jobs.jobs.values().map(|job| job.status.clone()).collect()
+    }
+
+    /// Cancel job `id`. A queued job is cancelled at once, a running one at
+    /// its next chunk of blocks. Returns `false` if there's no such job or
+    /// it had already finished.
+    pub fn cancel(&self, id: JobId) -> bool {
+        let mut jobs = self.shared.lock();
+        let Some(job) = jobs.jobs.get_mut(&id) else {
+            return false;
+        };
+        match job.status.state {
+            JobState::Queued => {
+                job.status.state = JobState::Cancelled;
+                job.work = None;
+                jobs.queue.retain(|queued| *queued != id);
+                self.shared.finished.notify_all();
+            }
+            JobState::Running => job.cancelled.store(true, Ordering::Relaxed),
+            _ => return false,
+        }
+        true
+    }
+
+    /// Block until job `id` has finished, returning its final status, or
+    /// `None` if there's no such job.
+    pub fn wait(&self, id: JobId) -> Option<JobStatus> {
+        let mut jobs = self.shared.lock();
+        loop {
+            let status = &jobs.jobs.get(&id)?.status;
+            if status.state.is_finished() {
+                return Some(status.clone());
+            }
+            jobs = self
+                .shared
+                .finished
+                .wait(jobs)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+    }
+
+    /// Take the summary of job `id` once it is [`JobState::Done`], and
+    /// forget the job. Finished jobs without a summary are forgotten too.
+    pub fn take_summary(&self, id: JobId) -> Option<ScanSummary> {
+        let mut jobs = self.shared.lock();
+        if !jobs.jobs.get(&id)?.status.state.is_finished() {
+            return None;
+        }
+        jobs.jobs.remove(&id)?.summary
+    }
+}
+
+impl<P> Drop for JobManager<P> {
+    fn drop(&mut self) {
+        {
+            let mut jobs = self.shared.lock();
+            jobs.shutdown = true;
+            for job in jobs.jobs.values() {
+                job.cancelled.store(true, Ordering::Relaxed);
+            }
+            self.shared.queued.notify_all();
+        }
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+impl<P> Shared<P> {
+    fn lock(&self) -> MutexGuard<'_, Jobs> {
+        self.jobs.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl<P: Parameters + Send + Sync + 'static> Shared<P> {
+    /// Run queued jobs until the manager shuts down.
+    fn work(&self) {
+        while let Some((id, job, cancelled)) = self.next_job() {
+            let result = self.run(id, job, &cancelled);
+            let mut jobs = self.lock();
+            if let Some(entry) = jobs.jobs.get_mut(&id) {
+                match result {
+                    _ if cancelled.load(Ordering::Relaxed) => {
+                        entry.status.state = JobState::Cancelled;
+                    }
+                    Ok(summary) => {
+                        entry.status.state = JobState::Done;
+                        entry.summary = Some(summary);
+                    }
+                    Err(e) => {
+                        entry.status.state = JobState::Failed;
+                        entry.status.error = Some(e.to_string());
+                    }
+                }
+            }
+            self.finished.notify_all();
+        }
+    }
+
+    /// Wait for a queued job and mark it running.
+    fn next_job(&self) -> Option<(JobId, ScanJob, Arc<AtomicBool>)> {
+        let mut jobs = self.lock();
+        loop {
+            if jobs.shutdown {
+                return None;
+            }
+            if let Some(id) = jobs.queue.pop_front() {
+                let job = jobs.jobs.get_mut(&id)?;
+                job.status.state = JobState::Running;
+                let work = job.work.take()?;
+                return Some((id, work, Arc::clone(&job.cancelled)));
+            }
+            jobs = self
+                .queued
+                .wait(jobs)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+    }
+
+    fn run(&self, id: JobId, job: ScanJob, cancelled: &AtomicBool) -> ScanResult<ScanSummary> {
+        let ScanJob {
+            request,
+            mut source,
+            start,
+            end,
+        } = job;
+        let mut source = Cancellable {
+            source: source.as_mut(),
+            cancelled,
+        };
+        self.scanner.scan_range_with_progress(
+            &mut source,
+            start,
+            end,
+            &request,
+            &mut |progress: &ScanProgress| {
+                if let Some(job) = self.lock().jobs.get_mut(&id) {
+                    job.status.progress = Some(*progress);
+                }
+            },
+        )
+    }
+}
+
+/// A source that fails once its job has been cancelled.
+struct Cancellable<'a> {
+    source: &'a mut (dyn BlockSource + Send),
+    cancelled: &'a AtomicBool,
+}
+
+impl BlockSource for Cancellable<'_> {
+    fn get_block_range(&mut self, start: u64, end: u64) -> ScanResult<Vec<CompactBlock>> {
+        if self.cancelled.load(Ordering::Relaxed) {
+            return Err(ScanError::BlockSource("job cancelled".to_string()));
+        }
+        self.source.get_block_range(start, end)
+    }
+}
+
+#[cfg(all(test, feature = "testing"))]
+mod tests {
+    use super::*;
+    use crate::source::MemoryBlockSource;
+    use crate::testing::MockChain;
+    use std::sync::mpsc;
+    use zcash_keys::keys::UnifiedSpendingKey;
+
+    fn test_ufvk() -> String {
+        UnifiedSpendingKey::from_seed(&Network::MainNetwork, &[0; 32], zip32::AccountId::ZERO)
+            .unwrap()
+            .to_unified_full_viewing_key()
+            .encode(&Network::MainNetwork)
+    }
+
+    /// Blocks until told to go on, then serves `inner`.
+    struct GatedSource {
+        inner: MemoryBlockSource,
+        gate: mpsc::Receiver<()>,
+    }
+
+    impl BlockSource for GatedSource {
+        fn get_block_range(&mut self, start: u64, end: u64) -> ScanResult<Vec<CompactBlock>> {
+            let _ = self.gate.recv();
+            self.inner.get_block_range(start, end)
+        }
+    }
+
+    fn wait_for_progress(manager: &JobManager, id: JobId) -> ScanProgress {
+        loop {
+            if let Some(progress) = manager.status(id).unwrap().progress {
+                return progress;
+            }
+            std::thread::yield_now();
+        }
+    }
+
+    #[test]
+    fn test_job_manager() {
+        let mut chain = MockChain::new(&test_ufvk()).unwrap();
+        chain.pay_sapling(10_000).unwrap();
+        chain.empty_blocks(9);
+        let start = chain.blocks()[0].height;
+        let end = chain.next_height() - 1;
+        let request = chain.scan_request();
+        let job = |key_id: &str, gate: mpsc::Receiver<()>| ScanJob {
+            request: ScanRequest {
+                key_id: key_id.to_string(),
+                ..request.clone()
+            },
+            source: Box::new(GatedSource {
+                inner: MemoryBlockSource::new(request.compact_blocks.clone()),
+                gate,
+            }),
+            start,
+            end,
+        };
+
+        // One chunk of 5 blocks per gate opening
+        let manager = JobManager::new(Scanner::builder().range_chunk_size(5).build(), 1);
+        let (open_first, gate) = mpsc::channel();
+        let first = manager.submit(job("first", gate));
+        let (_open_second, gate) = mpsc::channel();
+        let second = manager.submit(job("second", gate));
+        let (_, gate) = mpsc::channel();
+        let failing = manager.submit(ScanJob {
+            end: start - 1,
+            ..job("failing", gate)
+        });
+
+        // Only one job runs at a time
+        open_first.send(()).unwrap();
+        let progress = wait_for_progress(&manager, first);
+        assert_eq!((progress.blocks_scanned, progress.total_blocks), (5, 10));
+        let status = manager.status(first).unwrap();
+        assert_eq!(status.state, JobState::Running);
+        assert_eq!(status.key_id, "first");
+        assert_eq!(manager.status(second).unwrap().state, JobState::Queued);
+
+        assert!(manager.cancel(second));
+        assert_eq!(manager.status(second).unwrap().state, JobState::Cancelled);
+        assert!(!manager.cancel(second));
+        open_first.send(()).unwrap();
+        assert_eq!(manager.wait(first).unwrap().state, JobState::Done);
+        let summary = manager.take_summary(first).unwrap();
+        assert_eq!(summary.transactions.len(), 1);
+        assert_eq!(summary.transactions[0].key_id, "first");
+        assert!(manager.status(first).is_none());
+
+        let status = manager.wait(failing).unwrap();
+        assert_eq!(status.state, JobState::Failed);
+        assert_eq!(
+            status.error.unwrap(),
+            format!("Invalid height range {start}..={}", start - 1)
+        );
+        assert_eq!(manager.jobs().len(), 2);
+    }
+
+    #[test]
+    fn test_cancel_running_job() {
+        let mut chain = MockChain::new(&test_ufvk()).unwrap();
+        chain.empty_blocks(10);
+        let start = chain.blocks()[0].height;
+        let (open, gate) = mpsc::channel();
+        let manager = JobManager::new(Scanner::builder().range_chunk_size(5).build(), 2);
+        let id = manager.submit(ScanJob {
+            request: chain.scan_request(),
+            source: Box::new(GatedSource {
+                inner: MemoryBlockSource::new(chain.into_blocks()),
+                gate,
+            }),
+            start,
+            end: start + 9,
+        });
+
+        open.send(()).unwrap();
+        wait_for_progress(&manager, id);
+        assert!(manager.cancel(id));
+        // The job stops before fetching its second chunk
+        open.send(()).unwrap();
+        assert_eq!(manager.wait(id).unwrap().state, JobState::Cancelled);
+        assert!(manager.take_summary(id).is_none());
+    }
+}
@@ -11,12 +11,41 @@
 //! - **Simple API**: Just provide a UFVK and compact blocks, get transactions
 //! - **Sapling + Orchard**: Scans both shielded pools (Orchard requires `orchard` feature)
 //! - **WASM-compatible**: Use in browsers via WebAssembly (enable `wasm` feature)
+//! - **Progress reporting**: Track long scans through a [`ProgressSink`]
+//! - **Scan queue**: Scan the chain tip first and backfill history later with a [`ScanQueue`]
+//! - **Scan jobs**: Run many keys' range scans a few at a time with a `JobManager` (enable `jobs` feature)
+//! - **Key registry**: Keep many tenants' keys and scan heights in a [`KeyRegistry`], sealed with a key-encryption key for storage (enable `encryption` feature)
+//! - **Resumable**: Checkpoint long scans with a serializable [`ScanState`]
+//! - **Block cache**: Avoid re-downloading ranges with a disk-backed [`BlockCache`]
+//! - **lightwalletd**: Fetch blocks and transactions from a lightwalletd server with a `LightwalletdSource`, optionally through a SOCKS5 proxy (enable `lightwalletd` feature)
+//! - **zebrad**: Build compact blocks from a zebrad full node's JSON-RPC interface with a `ZebradBlockSource` (enable `zebrad` feature)
+//! - **Balances**: Net received and spent notes with a [`BalanceTracker`]
+//! - **Note history**: Query received and spent notes through a [`NoteStore`]
+//! - **Witnesses**: Track note commitment trees with a [`WitnessTracker`]
+//! - **Per-key reports**: Group multi-key results with totals per key in a [`ScanReport`]
+//! - **Wallet restore**: Export notes for a `zcash_client_sqlite` wallet with a [`WalletNoteExport`]
+//! - **CSV export**: Hand results to a spreadsheet with [`to_csv`]
+//! - **Parquet export**: Load results into DuckDB or Spark from an `export_parquet` file (enable `parquet` feature)
+//! - **Invoice matching**: Tag payments whose memo carries an order ID with [`match_invoices`]
+//! - **Fiat values**: Price results at block time through a [`PriceProvider`]
+//! - **Addresses**: Derive a key's unified receiving addresses with [`Scanner::default_address`]
+//! - **Birthdays**: Start scans near a wallet's creation date with [`Scanner::estimate_birthday`]
+//! - **Key fingerprints**: Identify keys without inventing a `key_id` with [`ufvk_fingerprint`], and keep them out of logs with [`redact_viewing_key`]
+//! - **Raw transactions**: Decrypt a single full transaction with [`decrypt_raw_tx`]
+//! - **Darkside chains**: Stage and reorg the chain a darksidewalletd test server serves with a `DarksideClient` (enable `darkside` feature)
+//! - **Test chains**: Fabricate blocks paying a key for integration tests with a `MockChain` (enable `testing` feature)
+//! - **Test vectors**: Check an integration against golden scan results in [`test_vectors`] (enable `test-vectors` feature)
+//! - **Protobuf results**: Skip JSON on the way out as well as in with [`Scanner::scan_to_proto`]
+//! - **Spending keys**: Scan with a unified spending key, or its bytes, with [`Scanner::prepare_spending_key`] (enable `spending-key` feature)
+//! - **Seed phrases**: Go from a wallet's seed phrase to a UFVK with `derive_ufvk` (enable `derivation` feature)
+//! - **Binary formats**: CBOR and MessagePack requests and results with [`Scanner::scan_encoded`] (enable `cbor` and `msgpack` features)
+//! - **Compressed input**: gzip- and zstd-compressed JSON and protobuf blocks are decompressed transparently
 //! - **Serde support**: All types serialize/deserialize for easy JSON interop
 //!
 //! ## Example
 //!
 //! ```rust,ignore
-//! use zecscope_scanner::{Scanner, ScanRequest, Network};
+//! use zecscope_scanner::{Scanner, ScanRequest, ScanOptions, Network};
 //!
 //! // Create a scanner for mainnet
 //! let scanner = Scanner::new(Network::Mainnet);
@@ -26,6 +55,8 @@
 //!     viewing_key: "uview1...".to_string(),
 //!     key_id: "my-wallet".to_string(),
 //!     compact_blocks: blocks, // Vec<CompactBlock>
+//!     options: ScanOptions::default(),
+//!     nullifiers: Default::default(),
 //! };
 //!
 //! let transactions = scanner.scan(&request)?;
@@ -35,13 +66,119 @@
 //! }
 //! ```
 
+mod addresses;
+mod aggregate;
+mod balance;
+mod batch;
+mod birthday;
+mod cache;
+mod capabilities;
+mod compression;
+#[cfg(feature = "darkside")]
+mod darkside;
+mod decrypted;
+#[cfg(feature = "derivation")]
+mod derivation;
+mod encoding;
+mod enhance;
 mod error;
+mod export;
+mod fingerprint;
+mod invoice;
+#[cfg(feature = "jobs")]
+mod jobs;
+#[cfg(feature = "lightwalletd")]
+mod lightwalletd;
+mod notes;
+mod nullifiers;
+mod price;
+mod progress;
+mod proto;
+#[cfg(feature = "lightwalletd")]
+mod proxy;
+mod queue;
+mod raw_tx;
+mod registry;
+mod report;
+#[cfg(feature = "lightwalletd")]
+mod retry;
 mod scanner;
+#[cfg(feature = "encryption")]
+mod sealed;
+mod source;
+mod spam;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+mod state;
+#[cfg(feature = "test-vectors")]
+pub mod test_vectors;
+#[cfg(feature = "testing")]
+mod testing;
+#[cfg(feature = "transparent")]
+mod transparent;
 mod types;
+mod wallet_export;
+mod witness;
+#[cfg(feature = "zebrad")]
+mod zebrad;
 
-pub use error::{ScanError, ScanResult};
-pub use scanner::Scanner;
+pub use addresses::DerivedAddress;
+pub use balance::{Balance, BalanceTracker, DEFAULT_MIN_CONFIRMATIONS};
+pub use batch::DEFAULT_BATCH_SIZE;
+pub use cache::{BlockCache, CachedBlockSource};
+pub use capabilities::{capabilities, Capabilities};
+#[cfg(feature = "darkside")]
+pub use darkside::DarksideClient;
+pub use decrypted::DecryptedNote;
+pub use encoding::ContentType;
+pub use enhance::{
+    CachedTransactionSource, MemoryTransactionSource, RawTransaction, TransactionSource,
+    ENHANCE_BATCH_SIZE,
+};
+#[cfg(feature = "derivation")]
+pub use derivation::derive_ufvk;
+pub use error::{PartialScanError, ScanError, ScanResult};
+pub use export::to_csv;
+#[cfg(feature = "parquet")]
+pub use export::export_parquet;
+pub use fingerprint::{redact_viewing_key, ufvk_fingerprint};
+pub use invoice::match_invoices;
+#[cfg(feature = "jobs")]
+pub use jobs::{JobId, JobManager, JobState, JobStatus, ScanJob};
+#[cfg(feature = "lightwalletd")]
+pub use lightwalletd::LightwalletdSource;
+pub use notes::{NoteStore, StoredNote};
+pub use nullifiers::NullifierSet;
+pub use price::{enrich_with_prices, PriceProvider, PriceTable};
+pub use progress::{NoProgress, ProgressSink, ScanProgress};
+pub use proto::{ScanResultProto, ScanWarningProto, ZecTransactionProto};
+#[cfg(feature = "lightwalletd")]
+pub use proxy::Proxy;
+pub use queue::{ScanPriority, ScanQueue, ScanRange, CHAIN_TIP_BLOCKS, FOUND_NOTE_BLOCKS};
+pub use raw_tx::{decrypt_raw_tx, DecryptedTransaction};
+pub use registry::{KeyRegistry, RegisteredKey, RegistryScan};
+pub use report::{KeyReport, ScanReport};
+#[cfg(feature = "lightwalletd")]
+pub use retry::RetryPolicy;
+pub use scanner::{PreparedKey, ScanSession, Scanner, ScannerBuilder};
+#[cfg(feature = "encryption")]
+pub use sealed::{KeyEncryptionKey, SealedKey, SealedRegistry};
+pub use source::{BlockSource, MemoryBlockSource};
+#[cfg(feature = "sqlite")]
+pub use sqlite::SqliteWalletStore;
+pub use state::{BlockCheckpoint, NoteSpend, ScanState, TrackedNote};
+#[cfg(feature = "testing")]
+pub use testing::{MockChain, MockNote};
+#[cfg(feature = "transparent")]
+pub use transparent::TRANSPARENT_ADDRESS_LOOKAHEAD;
 pub use types::*;
+pub use wallet_export::{ReceivedNoteRow, WalletNoteExport};
+pub use witness::{NoteWitness, SubtreeRoot, WitnessTracker};
+#[cfg(feature = "zebrad")]
+pub use zebrad::ZebradBlockSource;
 
 // Re-export useful types from zcash crates
-pub use zcash_protocol::consensus::Network;
+#[cfg(feature = "spending-key")]
+pub use zcash_keys::keys::UnifiedSpendingKey;
+pub use zcash_protocol::consensus::{BlockHeight, Network, NetworkUpgrade, Parameters};
+pub use zcash_protocol::local_consensus::LocalNetwork;
@@ -0,0 +1,295 @@
+//! Block source backed by a lightwalletd gRPC server.
+
+use tokio::runtime::Runtime;
+use tonic::transport::{Channel, ClientTlsConfig, Endpoint};
+use tower::service_fn;
+use zcash_client_backend::proto::compact_formats;
+use zcash_client_backend::proto::service::{
+    self, compact_tx_streamer_client::CompactTxStreamerClient, BlockId, BlockRange, ChainSpec,
+    GetSubtreeRootsArg, ShieldedProtocol, TxFilter,
+};
+
+use crate::enhance::{RawTransaction, TransactionSource};
+use crate::error::{ScanError, ScanResult};
+use crate::proxy::Proxy;
+use crate::retry::RetryPolicy;
+use crate::source::BlockSource;
+use crate::types::{
+    ChainMetadata, CompactBlock, CompactOrchardAction, CompactSaplingOutput, CompactSaplingSpend,
+    CompactTx, ShieldedPool,
+};
+use crate::witness::SubtreeRoot;
+
+/// Fetches compact blocks from lightwalletd, blocking on each request.
+pub struct LightwalletdSource {
+    runtime: Runtime,
+    client: CompactTxStreamerClient<Channel>,
+    retry: RetryPolicy,
+}
+
+impl LightwalletdSource {
+    /// Connect to the lightwalletd server at `url`, using TLS for `https://` URLs
+    /// and tunnelling through `proxy` if one is given.
+    pub fn connect(url: &str, proxy: Option<&Proxy>) -> ScanResult<Self> {
+        let runtime = Runtime::new().map_err(source_error)?;
+        let channel = runtime.block_on(connect_channel(url, proxy))?;
+        Ok(Self {
+            runtime,
+            client: CompactTxStreamerClient::new(channel),
+            retry: RetryPolicy::default(),
+        })
+    }
+
+    /// Retry failed requests according to `retry`.
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Height of the server's current chain tip.
+    pub fn latest_height(&mut self) -> ScanResult<u64> {
+        let block = self.retry.run(|| {
+            self.runtime
+                .block_on(self.client.get_latest_block(ChainSpec {}))
+                .map_err(source_error)
+        })?;
+        Ok(block.into_inner().height)
+    }
+
+    /// Roots of the completed note commitment subtrees of `pool`, from
+    /// `start_index`, at most `max_entries` of them (0 for all), to bootstrap
+    /// a [`WitnessTracker`](crate::WitnessTracker).
+    pub fn subtree_roots(
+        &mut self,
+        pool: ShieldedPool,
+        start_index: u32,
+        max_entries: u32,
+    ) -> ScanResult<Vec<SubtreeRoot>> {
+        let protocol = match pool {
+            ShieldedPool::Sapling => ShieldedProtocol::Sapling,
+            ShieldedPool::Orchard => ShieldedProtocol::Orchard,
+            _ => {
+                return Err(ScanError::BlockSource(format!(
+                    "the {pool} pool has no subtree roots"
+                )))
+            }
+        };
+        let request = GetSubtreeRootsArg {
+            start_index,
+            shielded_protocol: protocol as i32,
+            max_entries,
+        };
+        let client = &mut self.client;
+        let runtime = &self.runtime;
+        self.retry.run(|| {
+            runtime.block_on(async {
+                let mut stream = client
+                    .get_subtree_roots(request)
+                    .await
+                    .map_err(source_error)?
+                    .into_inner();
+                let mut roots = Vec::new();
+                while let Some(root) = stream.message().await.map_err(source_error)? {
+                    roots.push(subtree_root(
+                        u64::from(start_index) + roots.len() as u64,
+                        root,
+                    ));
+                }
+                Ok(roots)
+            })
+        })
+    }
+}
+
+impl BlockSource for LightwalletdSource {
+    /// Streams the range, reconnecting after the last block received if the
+    /// stream drops.
+    fn get_block_range(&mut self, start: u64, end: u64) -> ScanResult<Vec<CompactBlock>> {
+        let client = &mut self.client;
+        let runtime = &self.runtime;
+        self.retry.fetch_range(start, end, |from, blocks| {
+            let range = BlockRange {
+                start: Some(BlockId {
+                    height: from,
+                    hash: Vec::new(),
+                }),
+                end: Some(BlockId {
+                    height: end,
+                    hash: Vec::new(),
+                }),
+            };
+            runtime.block_on(async {
+                let mut stream = client
+                    .get_block_range(range)
+                    .await
+                    .map_err(source_error)?
+                    .into_inner();
+                while let Some(block) = stream.message().await.map_err(source_error)? {
+                    blocks.push(block.into());
+                }
+                Ok(())
+            })
+        })
+    }
+}
+
+impl TransactionSource for LightwalletdSource {
+    /// Fetches the transactions one `GetTransaction` call at a time.
+    fn get_transactions(&mut self, txids: &[String]) -> ScanResult<Vec<RawTransaction>> {
+        let client = &mut self.client;
+        let runtime = &self.runtime;
+        let mut transactions = Vec::with_capacity(txids.len());
+        for txid in txids {
+            let filter = TxFilter {
+                block: None,
+                index: 0,
+                hash: hex::decode(txid).map_err(source_error)?,
+            };
+            let tx = self.retry.run(|| {
+                runtime
+                    .block_on(client.get_transaction(filter.clone()))
+                    .map_err(source_error)
+            })?;
+            transactions.push(RawTransaction {
+                txid: txid.clone(),
+                data: tx.into_inner().data,
+            });
+        }
+        Ok(transactions)
+    }
+}
+
+/// Open a gRPC channel to `url`, using TLS for `https://` URLs and tunnelling
+/// through `proxy` if one is given.
+pub(crate) async fn connect_channel(url: &str, proxy: Option<&Proxy>) -> ScanResult<Channel> {
+    let mut endpoint = Endpoint::from_shared(url.to_string()).map_err(source_error)?;
+    if url.starts_with("https://") {
+        endpoint = endpoint
+            .tls_config(ClientTlsConfig::new().with_webpki_roots())
+            .map_err(source_error)?;
+    }
+    match proxy.cloned() {
+        Some(proxy) => endpoint
+            .connect_with_connector(service_fn(move |uri| {
+                let proxy = proxy.clone();
+                async move { proxy.connect(uri).await }
+            }))
+            .await
+            .map_err(source_error),
+        None => endpoint.connect().await.map_err(source_error),
+    }
+}
+
+/// Converts lightwalletd's protobuf compact blocks into the scanner's
+/// hex-encoded form.
+impl From<compact_formats::CompactBlock> for CompactBlock {
+    fn from(block: compact_formats::CompactBlock) -> Self {
+        Self {
+            proto_version: block.proto_version,
+            height: block.height,
+            hash: hex::encode(&block.hash),
+            prev_hash: hex::encode(&block.prev_hash),
+            time: block.time,
+            vtx: block
+                .vtx
+                .into_iter()
+                .map(|tx| CompactTx {
+                    index: tx.index,
+                    txid: hex::encode(&tx.hash),
+                    fee: (tx.fee > 0).then_some(tx.fee),
+                    spends: tx
+                        .spends
+                        .iter()
+                        .map(|s| CompactSaplingSpend {
+                            nf: hex::encode(&s.nf),
+                        })
+                        .collect(),
+                    outputs: tx
+                        .outputs
+                        .iter()
+                        .map(|o| CompactSaplingOutput {
+                            cmu: hex::encode(&o.cmu),
+                            ephemeral_key: hex::encode(&o.ephemeral_key),
+                            ciphertext: hex::encode(&o.ciphertext),
+                        })
+                        .collect(),
+                    actions: tx
+                        .actions
+                        .iter()
+                        .map(|a| CompactOrchardAction {
+                            nf: hex::encode(&a.nullifier),
+                            cmx: hex::encode(&a.cmx),
+                            ephemeral_key: hex::encode(&a.ephemeral_key),
+                            ciphertext: hex::encode(&a.ciphertext),
+                        })
+                        .collect(),
+                })
+                .collect(),
+            chain_metadata: block.chain_metadata.map(|m| ChainMetadata {
+                sapling_commitment_tree_size: m.sapling_commitment_tree_size,
+                orchard_commitment_tree_size: Some(m.orchard_commitment_tree_size),
+            }),
+        }
+    }
+}
+
+/// Convert the `index`th subtree root of a `GetSubtreeRoots` stream.
+fn subtree_root(index: u64, root: service::SubtreeRoot) -> SubtreeRoot {
+    SubtreeRoot {
+        index,
+        root_hash: hex::encode(&root.root_hash),
+        completing_block_height: root.completing_block_height,
+    }
+}
+
+/// Wrap a transport error, keeping its causes (tonic's own message is terse).
+pub(crate) fn source_error<E: std::error::Error>(e: E) -> ScanError {
+    let mut message = e.to_string();
+    let mut source = e.source();
+    while let Some(cause) = source {
+        message.push_str(&format!(": {cause}"));
+        source = cause.source();
+    }
+    ScanError::BlockSource(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_proto() {
+        let block = CompactBlock::from(compact_formats::CompactBlock {
+            proto_version: 1,
+            height: 10,
+            hash: vec![0xab; 32],
+            prev_hash: vec![0xcd; 32],
+            time: 5,
+            vtx: vec![compact_formats::CompactTx {
+                hash: vec![1, 2],
+                fee: 0,
+                ..Default::default()
+            }],
+            ..Default::default()
+        });
+        assert_eq!(block.hash, "ab".repeat(32));
+        assert_eq!(block.vtx[0].txid, "0102");
+        assert_eq!(block.vtx[0].fee, None);
+        assert!(block.chain_metadata.is_none());
+    }
+
+    #[test]
+    fn test_subtree_root() {
+        let root = subtree_root(
+            7,
+            service::SubtreeRoot {
+                root_hash: vec![0xab; 32],
+                completing_block_hash: vec![0xcd; 32],
+                completing_block_height: 2_100_000,
+            },
+        );
+        assert_eq!(root.index, 7);
+        assert_eq!(root.root_hash, "ab".repeat(32));
+        assert_eq!(root.completing_block_height, 2_100_000);
+    }
+}
@@ -0,0 +1,202 @@
+//! Record of every note discovered by a scan, spent or not.
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::state::{NoteSpend, TrackedNote};
+use crate::types::{BlockScanResult, ShieldedPool};
+
+/// A discovered note and, once seen, the transaction that spent it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StoredNote {
+    /// The note as discovered
+    #[serde(flatten)]
+    pub note: TrackedNote,
+    /// Transaction that spent the note (hex-encoded), if any
+    #[serde(default)]
+    pub spent_txid: Option<String>,
+    /// Height of the block containing the spend, if any
+    #[serde(default)]
+    pub spent_height: Option<u64>,
+}
+
+impl StoredNote {
+    /// Whether the note has been seen spent.
+    pub fn is_spent(&self) -> bool {
+        self.spent_txid.is_some()
+    }
+
+    /// Whether the note was received and not yet spent as of `height`.
+    pub fn is_unspent_at(&self, height: u64) -> bool {
+        self.note.height <= height && self.spent_height.is_none_or(|spent| spent > height)
+    }
+}
+
+/// Queryable store of the notes received and spent across scanned blocks.
+///
+/// Feed it each [`BlockScanResult`] from [`Scanner::scan_with`]. Notes are kept
+/// in the order they were received; the store serializes as a plain list of
+/// [`StoredNote`]s so it can be persisted alongside a [`ScanState`].
+///
+/// [`Scanner::scan_with`]: crate::Scanner::scan_with
+/// [`ScanState`]: crate::ScanState
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "Vec<StoredNote>", into = "Vec<StoredNote>")]
+pub struct NoteStore {
+    notes: Vec<StoredNote>,
+    by_nullifier: HashMap<(ShieldedPool, String), usize>,
+}
+
+impl NoteStore {
+    /// Create an empty store.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record the notes received and spent in a scanned block.
+    pub fn apply_block(&mut self, block: &BlockScanResult) {
+        for note in &block.received_notes {
+            self.insert(note.clone());
+        }
+        for spend in &block.spent_notes {
+            self.mark_spent(spend);
+        }
+    }
+
+    /// Record a received note. Re-inserting a known note leaves it unchanged.
+    pub fn insert(&mut self, note: TrackedNote) {
+        let key = (note.pool, note.nullifier.clone());
+        if self.by_nullifier.contains_key(&key) {
+            return;
+        }
+        self.by_nullifier.insert(key, self.notes.len());
+        self.notes.push(StoredNote {
+            note,
+            spent_txid: None,
+            spent_height: None,
+        });
+    }
+
+    /// Record a spend, adding the note first if it isn't known yet.
+    pub fn mark_spent(&mut self, spend: &NoteSpend) {
+        self.insert(spend.note.clone());
+        let index = self.by_nullifier[&(spend.note.pool, spend.note.nullifier.clone())];
+        let stored = &mut self.notes[index];
+        stored.spent_txid = Some(spend.spent_txid.clone());
+        stored.spent_height = Some(spend.spent_height);
+    }
+
+    /// Look up a note by pool and hex-encoded nullifier.
+    pub fn get(&self, pool: ShieldedPool, nullifier: &str) -> Option<&StoredNote> {
+        self.by_nullifier
+            .get(&(pool, nullifier.to_string()))
+            .map(|&index| &self.notes[index])
+    }
+
+    /// All notes, in the order they were received.
+    pub fn notes(&self) -> &[StoredNote] {
+        &self.notes
+    }
+
+    /// Notes that haven't been seen spent.
+    pub fn unspent_notes(&self) -> impl Iterator<Item = &StoredNote> {
+        self.notes.iter().filter(|stored| !stored.is_spent())
+    }
+
+    /// Notes that have been seen spent.
+    pub fn spent_notes(&self) -> impl Iterator<Item = &StoredNote> {
+        self.notes.iter().filter(|stored| stored.is_spent())
+    }
+
+    /// Notes that were received and still unspent as of `height`.
+    pub fn notes_at(&self, height: u64) -> impl Iterator<Item = &StoredNote> {
+        self.notes
+            .iter()
+            .filter(move |stored| stored.is_unspent_at(height))
+    }
+
+    /// Notes created or spent by the transaction `txid` (hex-encoded).
+    pub fn note_history<'a>(&'a self, txid: &'a str) -> impl Iterator<Item = &'a StoredNote> {
+        self.notes.iter().filter(move |stored| {
+            stored.note.txid == txid || stored.spent_txid.as_deref() == Some(txid)
+        })
+    }
+
+    /// Number of notes in the store.
+    pub fn len(&self) -> usize {
+        self.notes.len()
+    }
+
+    /// Whether the store holds no notes.
+    pub fn is_empty(&self) -> bool {
+        self.notes.is_empty()
+    }
+}
+
+impl From<Vec<StoredNote>> for NoteStore {
+    fn from(notes: Vec<StoredNote>) -> Self {
+        let by_nullifier = notes
+            .iter()
+            .enumerate()
+            .map(|(index, stored)| ((stored.note.pool, stored.note.nullifier.clone()), index))
+            .collect();
+        Self {
+            notes,
+            by_nullifier,
+        }
+    }
+}
+
+impl From<NoteStore> for Vec<StoredNote> {
+    fn from(store: NoteStore) -> Self {
+        store.notes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn note(nullifier: &str, height: u64, txid: &str) -> TrackedNote {
+        TrackedNote {
+            pool: ShieldedPool::Sapling,
+            nullifier: nullifier.to_string(),
+            value_zat: "1000".to_string(),
+            height,
+            txid: txid.to_string(),
+            position: None,
+        }
+    }
+
+    #[test]
+    fn test_note_store_queries() {
+        let mut store = NoteStore::new();
+        store.insert(note("01", 100, "aa"));
+        store.insert(note("02", 110, "bb"));
+        store.mark_spent(&NoteSpend {
+            note: note("01", 100, "aa"),
+            spent_txid: "bb".to_string(),
+            spent_height: 110,
+        });
+
+        let unspent: Vec<_> = store
+            .unspent_notes()
+            .map(|n| n.note.nullifier.as_str())
+            .collect();
+        assert_eq!(unspent, ["02"]);
+        assert_eq!(store.notes_at(105).count(), 1);
+        assert_eq!(store.notes_at(110).next().unwrap().note.nullifier, "02");
+        assert_eq!(store.note_history("bb").count(), 2);
+        assert_eq!(store.note_history("aa").count(), 1);
+
+        let json = serde_json::to_string(&store).unwrap();
+        let restored: NoteStore = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored, store);
+        assert!(restored
+            .get(ShieldedPool::Sapling, "01")
+            .unwrap()
+            .is_spent());
+    }
+}
@@ -0,0 +1,166 @@
+//! The notes whose nullifiers a scan watches for spends.
+
+use serde::{Deserialize, Serialize};
+
+use crate::state::TrackedNote;
+use crate::types::{BlockScanResult, ShieldedPool};
+
+/// Unspent notes, keyed by pool and nullifier, whose spends a scan reports.
+///
+/// A scan only sees a note spent if it knows the note's nullifier, so a
+/// process that restarts has to carry them over: collect the notes a scan
+/// left unspent (e.g. [`ScanSummary::unspent_notes`]), persist the set, and
+/// pass it back in [`ScanRequest::nullifiers`]. It serializes as a plain
+/// array of [`TrackedNote`]s.
+///
+/// [`ScanSummary::unspent_notes`]: crate::ScanSummary::unspent_notes
+/// [`ScanRequest::nullifiers`]: crate::ScanRequest::nullifiers
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "Vec<TrackedNote>", into = "Vec<TrackedNote>")]
+pub struct NullifierSet {
+    // Sorted by pool and nullifier, without duplicates
+    notes: Vec<TrackedNote>,
+}
+
+impl NullifierSet {
+    /// Create an empty set.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add `note`, replacing any note with the same pool and nullifier.
+    pub fn insert(&mut self, note: TrackedNote) {
+        match self.position(note.pool, &note.nullifier) {
+            Ok(index) => self.notes[index] = note,
+            Err(index) => self.notes.insert(index, note),
+        }
+    }
+
+    /// Remove and return the note with `nullifier` in `pool`.
+    pub fn remove(&mut self, pool: ShieldedPool, nullifier: &str) -> Option<TrackedNote> {
+        let index = self.position(pool, nullifier).ok()?;
+        Some(self.notes.remove(index))
+    }
+
+    /// Whether the set holds a note with `nullifier` in `pool`.
+    pub fn contains(&self, pool: ShieldedPool, nullifier: &str) -> bool {
+        self.position(pool, nullifier).is_ok()
+    }
+
+    /// Add the notes `block` received and remove those it spent.
+    pub fn apply_block(&mut self, block: &BlockScanResult) {
+        for note in &block.received_notes {
+            self.insert(note.clone());
+        }
+        for spend in &block.spent_notes {
+            self.remove(spend.note.pool, &spend.note.nullifier);
+        }
+    }
+
+    /// The notes, ordered by pool and nullifier.
+    pub fn notes(&self) -> &[TrackedNote] {
+        &self.notes
+    }
+
+    /// Number of notes in the set.
+    pub fn len(&self) -> usize {
+        self.notes.len()
+    }
+
+    /// Whether the set is empty.
+    pub fn is_empty(&self) -> bool {
+        self.notes.is_empty()
+    }
+
+    fn position(&self, pool: ShieldedPool, nullifier: &str) -> Result<usize, usize> {
+        self.notes
+            .binary_search_by(|note| (note.pool, note.nullifier.as_str()).cmp(&(pool, nullifier)))
+    }
+}
+
+impl FromIterator<TrackedNote> for NullifierSet {
+    fn from_iter<I: IntoIterator<Item = TrackedNote>>(notes: I) -> Self {
+        let mut set = Self::new();
+        set.extend(notes);
+        set
+    }
+}
+
+impl Extend<TrackedNote> for NullifierSet {
+    fn extend<I: IntoIterator<Item = TrackedNote>>(&mut self, notes: I) {
+        for note in notes {
+            self.insert(note);
+        }
+    }
+}
+
+impl From<Vec<TrackedNote>> for NullifierSet {
+    fn from(notes: Vec<TrackedNote>) -> Self {
+        notes.into_iter().collect()
+    }
+}
+
+impl From<NullifierSet> for Vec<TrackedNote> {
+    fn from(set: NullifierSet) -> Self {
+        set.notes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::NoteSpend;
+    use crate::types::ScanSummary;
+
+    #[test]
+    fn test_nullifier_set() {
+        let note = |nullifier: &str| TrackedNote {
+            pool: ShieldedPool::Sapling,
+            nullifier: nullifier.to_string(),
+            value_zat: "1000".to_string(),
+            height: 1,
+            txid: "aa".to_string(),
+            position: None,
+        };
+        let block = |received: Vec<TrackedNote>, spent: Vec<TrackedNote>| BlockScanResult {
+            height: 1,
+            hash: String::new(),
+            time: 0,
+            transactions: Vec::new(),
+            received_notes: received,
+            decrypted_notes: Vec::new(),
+            spent_notes: spent
+                .into_iter()
+                .map(|note| NoteSpend {
+                    note,
+                    spent_txid: "bb".to_string(),
+                    spent_height: 2,
+                })
+                .collect(),
+            sapling_anchor: None,
+            orchard_anchor: None,
+            skipped_outputs: 0,
+            spam_outputs: 0,
+            spam_ratio: 0.0,
+        };
+
+        let mut set = NullifierSet::new();
+        set.apply_block(&block(vec![note("03"), note("01")], Vec::new()));
+        set.apply_block(&block(vec![note("02")], vec![note("01")]));
+        assert!(!set.contains(ShieldedPool::Sapling, "01"));
+        assert!(!set.contains(ShieldedPool::Orchard, "02"));
+
+        let mut summary = ScanSummary::from_transactions(Vec::new(), 1, 2);
+        summary.unspent_notes = set.clone().into();
+        let nullifiers: Vec<_> = summary.nullifiers().collect();
+        assert_eq!(
+            nullifiers,
+            vec![(ShieldedPool::Sapling, "02"), (ShieldedPool::Sapling, "03")]
+        );
+
+        // Duplicates and order don't survive a round trip
+        let json = serde_json::to_string(&vec![note("03"), note("02"), note("03")]).unwrap();
+        assert_eq!(serde_json::from_str::<NullifierSet>(&json).unwrap(), set);
+        assert_eq!(serde_json::to_value(&set).unwrap()[0]["nullifier"], "02");
+    }
+}
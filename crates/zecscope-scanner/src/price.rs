@@ -0,0 +1,127 @@
+//! Fiat valuation of scan results.
+
+use crate::error::{ScanError, ScanResult};
+use crate::types::ZecTransaction;
+
+/// Somewhere historical ZEC prices can be looked up by time.
+///
+/// Implement this for your own price feed (an exchange API, a CSV of daily
+/// closes) to annotate scan results with [`enrich_with_prices`].
+pub trait PriceProvider {
+    /// Currency prices are quoted in, such as `"USD"`.
+    fn currency(&self) -> &str;
+
+    /// Price of 1 ZEC at Unix time `time`, or `None` if unknown.
+    fn price_at(&mut self, time: i64) -> ScanResult<Option<f64>>;
+}
+
+impl<P: PriceProvider + ?Sized> PriceProvider for &mut P {
+    fn currency(&self) -> &str {
+        (**self).currency()
+    }
+
+    fn price_at(&mut self, time: i64) -> ScanResult<Option<f64>> {
+        (**self).price_at(time)
+    }
+}
+
+/// A [`PriceProvider`] backed by a table of prices held in memory.
+///
+/// Each price holds from its timestamp until the next one, so a table of
+/// daily closes prices a transaction at the close of the preceding day.
+#[derive(Debug, Clone)]
+pub struct PriceTable {
+    currency: String,
+    prices: Vec<(i64, f64)>,
+}
+
+impl PriceTable {
+    /// Create a table of `(unix_time, price)` pairs (in any order).
+    pub fn new(currency: impl Into<String>, mut prices: Vec<(i64, f64)>) -> Self {
+        prices.sort_by_key(|&(time, _)| time);
+        Self {
+            currency: currency.into(),
+            prices,
+        }
+    }
+}
+
+impl PriceProvider for PriceTable {
+    fn currency(&self) -> &str {
+        &self.currency
+    }
+
+    fn price_at(&mut self, time: i64) -> ScanResult<Option<f64>> {
+        let after = self.prices.partition_point(|&(t, _)| t <= time);
+        Ok(after.checked_sub(1).map(|i| self.prices[i].1))
+    }
+}
+
+/// Set `fiat_value` and `fiat_currency` on each transaction from the price
+/// of ZEC at its block time.
+///
+/// Values are rounded to two decimal places. Transactions the provider has
+/// no price for are left without a fiat value.
+pub fn enrich_with_prices(
+    transactions: &mut [ZecTransaction],
+    mut provider: impl PriceProvider,
+) -> ScanResult<()> {
+    for tx in transactions {
+        let Some(price) = provider.price_at(tx.time)? else {
+            tx.fiat_value = None;
+            tx.fiat_currency = None;
+            continue;
+        };
+        if !price.is_finite() || price < 0.0 {
+            return Err(ScanError::PriceProvider(format!(
+                "invalid price {price} at time {}",
+                tx.time
+            )));
+        }
+        tx.fiat_value = Some(format!("{:.2}", tx.amount_zec() * price));
+        tx.fiat_currency = Some(provider.currency().to_string());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{ShieldedPool, TxDirection};
+
+    fn test_tx(time: i64, amount_zat: &str) -> ZecTransaction {
+        ZecTransaction {
+            height: 1,
+            time,
+            amount_zat: amount_zat.to_string(),
+            ..ZecTransaction::test_entry(
+                &"ab".repeat(32),
+                0,
+                TxDirection::In,
+                ShieldedPool::Sapling,
+            )
+        }
+    }
+
+    #[test]
+    fn test_enrich_with_prices() {
+        let mut prices = PriceTable::new("USD", vec![(200, 40.0), (100, 30.0)]);
+        let mut txs = vec![
+            test_tx(50, "100000000"),
+            test_tx(150, "250000000"),
+            test_tx(200, "50000000"),
+        ];
+        enrich_with_prices(&mut txs, &mut prices).unwrap();
+
+        assert_eq!(txs[0].fiat_value, None);
+        assert_eq!(txs[1].fiat_value.as_deref(), Some("75.00"));
+        assert_eq!(txs[1].fiat_currency.as_deref(), Some("USD"));
+        assert_eq!(txs[2].fiat_value.as_deref(), Some("20.00"));
+
+        let mut bad = PriceTable::new("USD", vec![(0, f64::NAN)]);
+        assert!(matches!(
+            enrich_with_prices(&mut txs, &mut bad),
+            Err(ScanError::PriceProvider(_))
+        ));
+    }
+}
@@ -0,0 +1,46 @@
+//! Progress reporting for long-running scans.
+
+use serde::{Deserialize, Serialize};
+
+/// Number of blocks between progress reports.
+pub(crate) const PROGRESS_INTERVAL: usize = 100;
+
+/// Snapshot of how far a scan has got.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "wasm", derive(tsify::Tsify), tsify(missing_as_null))]
+#[serde(rename_all = "camelCase")]
+pub struct ScanProgress {
+    /// Blocks scanned so far
+    pub blocks_scanned: usize,
+    /// Total blocks in the request
+    pub total_blocks: usize,
+    /// Height of the most recently scanned block
+    pub current_height: u64,
+    /// Sapling outputs and Orchard actions trial-decrypted so far
+    pub outputs_tried: usize,
+}
+
+/// Receives progress updates while a scan runs.
+///
+/// The scanner reports every 100 blocks and once more after
+/// the last block it scans. Closures taking a `&ScanProgress` implement this trait, so
+/// the same sink works natively and under WASM, where it can forward to a
+/// JavaScript callback.
+pub trait ProgressSink {
+    /// Called with the current scan progress.
+    fn on_progress(&mut self, progress: &ScanProgress);
+}
+
+impl<F: FnMut(&ScanProgress)> ProgressSink for F {
+    fn on_progress(&mut self, progress: &ScanProgress) {
+        self(progress)
+    }
+}
+
+/// A [`ProgressSink`] that discards all updates.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoProgress;
+
+impl ProgressSink for NoProgress {
+    fn on_progress(&mut self, _progress: &ScanProgress) {}
+}
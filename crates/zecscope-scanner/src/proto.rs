@@ -0,0 +1,147 @@
+//! Protobuf encodings of scan results, the messages of
+//! `proto/zecscope.proto` (package `zecscope.v1`).
+//!
+//! Like the JSON types, amounts are strings of zatoshis and enums are their
+//! lowercase JSON names.
+
+use crate::types::{ScanWarning, TxDirection, ZecTransaction};
+
+/// `zecscope.v1.ScanResult`: what [`Scanner::scan_to_proto`] found.
+///
+/// [`Scanner::scan_to_proto`]: crate::Scanner::scan_to_proto
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ScanResultProto {
+    /// Discovered transactions
+    #[prost(message, repeated, tag = "1")]
+    pub transactions: Vec<ZecTransactionProto>,
+    /// Number of blocks scanned
+    #[prost(uint64, tag = "2")]
+    pub blocks_scanned: u64,
+    /// Height of the first block scanned
+    #[prost(uint64, tag = "3")]
+    pub start_height: u64,
+    /// Height of the last block scanned
+    #[prost(uint64, tag = "4")]
+    pub end_height: u64,
+    /// Blocks a lenient scan skipped
+    #[prost(message, repeated, tag = "5")]
+    pub warnings: Vec<ScanWarningProto>,
+}
+
+/// `zecscope.v1.ScanWarning`: a block a lenient scan skipped.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ScanWarningProto {
+    /// Height of the skipped block, if known
+    #[prost(uint64, optional, tag = "1")]
+    pub height: Option<u64>,
+    /// Field that couldn't be parsed, if the block was malformed
+    #[prost(string, optional, tag = "2")]
+    pub field: Option<String>,
+    /// What went wrong
+    #[prost(string, tag = "3")]
+    pub reason: String,
+}
+
+/// `zecscope.v1.ZecTransaction`: a [`ZecTransaction`], field for field.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ZecTransactionProto {
+    #[prost(string, tag = "1")]
+    pub txid: String,
+    #[prost(uint64, tag = "2")]
+    pub height: u64,
+    #[prost(int64, tag = "3")]
+    pub time: i64,
+    #[prost(string, tag = "4")]
+    pub amount_zat: String,
+    #[prost(string, tag = "5")]
+    pub direction: String,
+    #[prost(string, optional, tag = "6")]
+    pub memo: Option<String>,
+    #[prost(string, tag = "7")]
+    pub key_id: String,
+    #[prost(string, tag = "8")]
+    pub pool: String,
+    #[prost(bool, tag = "9")]
+    pub is_change: bool,
+    #[prost(string, optional, tag = "10")]
+    pub address: Option<String>,
+    #[prost(string, optional, tag = "11")]
+    pub recipient_address: Option<String>,
+    #[prost(string, optional, tag = "12")]
+    pub fee_zat: Option<String>,
+    #[prost(uint64, optional, tag = "13")]
+    pub expiry_height: Option<u64>,
+    #[prost(string, optional, tag = "14")]
+    pub fiat_value: Option<String>,
+    #[prost(string, optional, tag = "15")]
+    pub fiat_currency: Option<String>,
+    #[prost(uint64, optional, tag = "16")]
+    pub diversifier_index: Option<u64>,
+    #[prost(uint32, optional, tag = "17")]
+    pub output_index: Option<u32>,
+    #[prost(uint32, optional, tag = "18")]
+    pub action_index: Option<u32>,
+    #[prost(bool, tag = "19")]
+    pub unconfirmed: bool,
+    #[prost(string, optional, tag = "20")]
+    pub scope: Option<String>,
+    #[prost(uint32, optional, tag = "21")]
+    pub account_id: Option<u32>,
+    #[prost(string, optional, tag = "22")]
+    pub invoice_id: Option<String>,
+    #[prost(uint64, optional, tag = "23")]
+    pub note_position: Option<u64>,
+    #[prost(bool, tag = "24")]
+    pub is_coinbase: bool,
+    #[prost(bool, tag = "25")]
+    pub self_transfer: bool,
+    #[prost(bool, tag = "26")]
+    pub likely_spam: bool,
+}
+
+impl From<ZecTransaction> for ZecTransactionProto {
+    fn from(tx: ZecTransaction) -> Self {
+        Self {
+            txid: tx.txid,
+            height: tx.height,
+            time: tx.time,
+            amount_zat: tx.amount_zat,
+            direction: match tx.direction {
+                TxDirection::In => "in",
+                TxDirection::Out => "out",
+            }
+            .to_string(),
+            memo: tx.memo,
+            key_id: tx.key_id,
+            pool: tx.pool.to_string(),
+            is_change: tx.is_change,
+            address: tx.address,
+            recipient_address: tx.recipient_address,
+            fee_zat: tx.fee_zat,
+            expiry_height: tx.expiry_height,
+            fiat_value: tx.fiat_value,
+            fiat_currency: tx.fiat_currency,
+            diversifier_index: tx.diversifier_index,
+            output_index: tx.output_index,
+            action_index: tx.action_index,
+            unconfirmed: tx.unconfirmed,
+            scope: tx.scope.map(|scope| scope.to_string()),
+            account_id: tx.account_id,
+            invoice_id: tx.invoice_id,
+            note_position: tx.note_position,
+            is_coinbase: tx.is_coinbase,
+            self_transfer: tx.self_transfer,
+            likely_spam: tx.likely_spam,
+        }
+    }
+}
+
+impl From<ScanWarning> for ScanWarningProto {
+    fn from(warning: ScanWarning) -> Self {
+        Self {
+            height: warning.height,
+            field: warning.field,
+            reason: warning.reason,
+        }
+    }
+}
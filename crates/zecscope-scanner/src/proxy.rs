@@ -0,0 +1,70 @@
+//! SOCKS5 proxy settings shared by the block sources.
+
+use hyper_util::rt::TokioIo;
+use tokio::net::TcpStream;
+use tokio_socks::tcp::Socks5Stream;
+use tonic::codegen::http::Uri;
+
+use crate::error::{ScanError, ScanResult};
+
+/// A SOCKS5 proxy, such as a local Tor client (`socks5h://127.0.0.1:9050`).
+///
+/// Connections always pass the server's host name to the proxy, so DNS
+/// lookups go through it too and `socks5://` behaves like `socks5h://`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Proxy {
+    host: String,
+    port: u16,
+}
+
+impl Proxy {
+    /// Parse a `socks5://host:port` or `socks5h://host:port` URL.
+    pub fn parse(url: &str) -> ScanResult<Self> {
+        let invalid = || ScanError::BlockSource(format!("invalid SOCKS5 proxy URL: {url}"));
+        let uri: Uri = url.parse().map_err(|_| invalid())?;
+        if !matches!(uri.scheme_str(), Some("socks5" | "socks5h")) {
+            return Err(invalid());
+        }
+        let authority = uri.authority().ok_or_else(invalid)?;
+        Ok(Self {
+            host: authority.host().to_string(),
+            port: authority.port_u16().ok_or_else(invalid)?,
+        })
+    }
+
+    /// The proxy as a `socks5://host:port` URL, for HTTP clients to use.
+    pub fn url(&self) -> String {
+        format!("socks5://{}:{}", self.host, self.port)
+    }
+
+    /// Open a TCP connection to the host and port of `uri` through the proxy.
+    pub async fn connect(&self, uri: Uri) -> std::io::Result<TokioIo<TcpStream>> {
+        let host = uri.host().unwrap_or_default();
+        let port = uri.port_u16().unwrap_or(match uri.scheme_str() {
+            Some("https") => 443,
+            _ => 80,
+        });
+        let stream = Socks5Stream::connect((self.host.as_str(), self.port), (host, port))
+            .await
+            .map_err(std::io::Error::other)?;
+        Ok(TokioIo::new(stream.into_inner()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let proxy = Proxy::parse("socks5h://127.0.0.1:9050").unwrap();
+        assert_eq!(proxy.host, "127.0.0.1");
+        assert_eq!(proxy.port, 9050);
+        assert_eq!(Proxy::parse("socks5://localhost:1080").unwrap().port, 1080);
+        assert_eq!(proxy.url(), "socks5://127.0.0.1:9050");
+
+        assert!(Proxy::parse("http://127.0.0.1:9050").is_err());
+        assert!(Proxy::parse("socks5h://127.0.0.1").is_err());
+        assert!(Proxy::parse("not a url").is_err());
+    }
+}
@@ -0,0 +1,262 @@
+//! Scanning block ranges in priority order.
+
+use serde::{Deserialize, Serialize};
+
+use crate::state::TrackedNote;
+
+/// Blocks at the chain tip scanned ahead of everything else.
+pub const CHAIN_TIP_BLOCKS: u64 = 100;
+
+/// Blocks after a found note scanned ahead of historic blocks, as they are
+/// likely to hold its spend or the change from it.
+pub const FOUND_NOTE_BLOCKS: u64 = 1_000;
+
+/// How urgently a range of blocks should be scanned, from lowest to highest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScanPriority {
+    /// Backfill of older blocks
+    Historic,
+    /// Blocks following a note the scan found
+    FoundNote,
+    /// Blocks near the chain tip, where new payments appear
+    ChainTip,
+}
+
+/// An inclusive range of heights waiting to be scanned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScanRange {
+    /// First height
+    pub start: u64,
+    /// Last height
+    pub end: u64,
+    /// How urgently the range should be scanned
+    pub priority: ScanPriority,
+}
+
+/// The block ranges a wallet-style scan still has to cover, by priority.
+///
+/// Scanning from a wallet's birthday to the tip takes long, but new payments
+/// are at the tip, so the queue hands out the newest blocks first: the last
+/// [`CHAIN_TIP_BLOCKS`] are [`ScanPriority::ChainTip`], the rest
+/// [`ScanPriority::Historic`]. When a scan finds a note, the
+/// [`FOUND_NOTE_BLOCKS`] after it are raised to [`ScanPriority::FoundNote`].
+///
+/// Feed it to [`Scanner::scan_queued`](crate::Scanner::scan_queued) until it
+/// is empty, calling [`ScanQueue::update_chain_tip`] as new blocks arrive.
+/// Notes found so far are kept in the queue so their spends are detected in
+/// later ranges, but spends in blocks scanned before the note was found are
+/// missed. Serialize the queue to resume it later.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScanQueue {
+    /// First height not yet queued
+    next_height: u64,
+    /// Disjoint, in height order, adjacent ranges of equal priority merged
+    ranges: Vec<ScanRange>,
+    #[serde(default)]
+    tracked_notes: Vec<TrackedNote>,
+}
+
+impl ScanQueue {
+    /// Create a queue for a wallet created at height `birthday`. Nothing is
+    /// queued until the first [`ScanQueue::update_chain_tip`].
+    pub fn new(birthday: u64) -> Self {
+        Self {
+            next_height: birthday,
+            ..Self::default()
+        }
+    }
+
+    /// Queue the blocks up to `tip` not queued yet, with the last
+    /// [`CHAIN_TIP_BLOCKS`] as [`ScanPriority::ChainTip`].
+    pub fn update_chain_tip(&mut self, tip: u64) {
+        if tip < self.next_height {
+            return;
+        }
+        self.insert(self.next_height, tip, ScanPriority::Historic);
+        let tip_start = tip
+            .saturating_sub(CHAIN_TIP_BLOCKS - 1)
+            .max(self.next_height);
+        self.insert(tip_start, tip, ScanPriority::ChainTip);
+        self.next_height = tip + 1;
+    }
+
+    /// Queue `start..=end` at `priority`. Blocks already queued at a higher
+    /// priority keep it.
+    pub fn insert(&mut self, start: u64, end: u64, priority: ScanPriority) {
+        self.update(start, end, |old| {
+            Some(old.map_or(priority, |old| old.max(priority)))
+        });
+    }
+
+    /// Raise the queued blocks after a note found at `height` to
+    /// [`ScanPriority::FoundNote`].
+    pub fn found_note(&mut self, height: u64) {
+        self.update(height + 1, height + FOUND_NOTE_BLOCKS, |old| {
+            old.map(|old| old.max(ScanPriority::FoundNote))
+        });
+    }
+
+    /// Remove `start..=end` from the queue.
+    pub fn mark_scanned(&mut self, start: u64, end: u64) {
+        self.update(start, end, |_| None);
+    }
+
+    /// The most urgent range, at most `max_blocks` long: the newest of the
+    /// ranges with the highest priority, cut short from its start.
+    pub fn next_range(&self, max_blocks: u64) -> Option<ScanRange> {
+        let range = self
+            .ranges
+            .iter()
+            .max_by_key(|range| (range.priority, range.start))?;
+        Some(ScanRange {
+            end: range.end.min(range.start + max_blocks.max(1) - 1),
+            ..*range
+        })
+    }
+
+    /// The queued ranges, in height order.
+    pub fn ranges(&self) -> &[ScanRange] {
+        &self.ranges
+    }
+
+    /// Whether every queued block has been scanned.
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    /// Notes found by the queue's scans that haven't been seen spent.
+    pub fn tracked_notes(&self) -> &[TrackedNote] {
+        &self.tracked_notes
+    }
+
+    pub(crate) fn take_tracked_notes(&mut self) -> Vec<TrackedNote> {
+        std::mem::take(&mut self.tracked_notes)
+    }
+
+    pub(crate) fn set_tracked_notes(&mut self, notes: Vec<TrackedNote>) {
+        self.tracked_notes = notes;
+    }
+
+    /// Set the priority of every height in `start..=end` to
+    /// `priority(current)`, where `None` means not queued.
+    fn update(
+        &mut self,
+        start: u64,
+        end: u64,
+        priority: impl Fn(Option<ScanPriority>) -> Option<ScanPriority>,
+    ) {
+        if end < start {
+            return;
+        }
+        let mut ranges = Vec::with_capacity(self.ranges.len() + 2);
+        let mut push = |start: u64, end: u64, priority: Option<ScanPriority>| {
+            if let Some(priority) = priority {
+                ranges.push(ScanRange {
+                    start,
+                    end,
+                    priority,
+                });
+            }
+        };
+        // First height of `start..=end` not yet visited
+        let mut cursor = start;
+        for range in self.ranges.drain(..) {
+            if range.end < start || range.start > end {
+                push(range.start, range.end, Some(range.priority));
+                continue;
+            }
+            if range.start < start {
+                push(range.start, start - 1, Some(range.priority));
+            }
+            if cursor < range.start {
+                push(cursor, range.start - 1, priority(None));
+            }
+            let overlap_end = range.end.min(end);
+            push(
+                cursor.max(range.start),
+                overlap_end,
+                priority(Some(range.priority)),
+            );
+            cursor = overlap_end + 1;
+            if range.end > end {
+                push(end + 1, range.end, Some(range.priority));
+            }
+        }
+        if cursor <= end {
+            push(cursor, end, priority(None));
+        }
+
+        ranges.sort_by_key(|range| range.start);
+        ranges.dedup_by(|next, prev| {
+            let merge = prev.end + 1 == next.start && prev.priority == next.priority;
+            if merge {
+                prev.end = next.end;
+            }
+            merge
+        });
+        self.ranges = ranges;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ranges(queue: &ScanQueue) -> Vec<(u64, u64, ScanPriority)> {
+        queue
+            .ranges()
+            .iter()
+            .map(|range| (range.start, range.end, range.priority))
+            .collect()
+    }
+
+    #[test]
+    fn test_scan_queue() {
+        use ScanPriority::*;
+
+        let mut queue = ScanQueue::new(1_000);
+        queue.update_chain_tip(5_000);
+        assert_eq!(
+            ranges(&queue),
+            [(1_000, 4_900, Historic), (4_901, 5_000, ChainTip)]
+        );
+        let next = queue.next_range(50).unwrap();
+        assert_eq!(
+            (next.start, next.end, next.priority),
+            (4_901, 4_950, ChainTip)
+        );
+
+        queue.mark_scanned(4_901, 5_000);
+        queue.found_note(2_000);
+        // Blocks already scanned aren't queued again
+        queue.found_note(4_950);
+        assert_eq!(
+            ranges(&queue),
+            [
+                (1_000, 2_000, Historic),
+                (2_001, 3_000, FoundNote),
+                (3_001, 4_900, Historic),
+            ]
+        );
+        assert_eq!(queue.next_range(5_000).unwrap().start, 2_001);
+
+        // New blocks at the tip come first
+        queue.update_chain_tip(5_010);
+        assert_eq!(ranges(&queue)[3], (5_001, 5_010, ChainTip));
+        queue.insert(900, 1_500, FoundNote);
+        assert_eq!(
+            ranges(&queue)[..2],
+            [(900, 1_500, FoundNote), (1_501, 2_000, Historic)]
+        );
+
+        queue.mark_scanned(0, 10_000);
+        assert!(queue.is_empty());
+        assert!(queue.next_range(100).is_none());
+
+        let json = serde_json::to_string(&queue).unwrap();
+        assert_eq!(serde_json::from_str::<ScanQueue>(&json).unwrap(), queue);
+    }
+}
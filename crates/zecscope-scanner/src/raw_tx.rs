@@ -0,0 +1,160 @@
+//! One-off decryption of a raw transaction.
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{ScanError, ScanResult};
+use crate::scanner::{block_height, Scanner};
+use crate::types::{KeyNetwork, ScanOptions, ScanRequest, TxDirection, ZecTransaction};
+
+/// What a viewing key can see of one full transaction.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DecryptedTransaction {
+    /// Transaction ID (hex-encoded)
+    pub txid: String,
+    /// Height the transaction was decrypted at
+    pub height: u64,
+    /// Fee paid by the transaction (in zatoshis), when it can be computed
+    /// from the transaction alone
+    pub fee_zat: Option<String>,
+    /// Notes received by the key, including change
+    pub received: Vec<ZecTransaction>,
+    /// Outputs to other recipients, recovered with the outgoing viewing key
+    pub sent: Vec<ZecTransaction>,
+}
+
+/// Decrypt a serialized transaction mined at (or, for mempool transactions,
+/// headed for) `height` with `viewing_key`.
+///
+/// `raw_tx` is either the raw transaction bytes or their hex encoding, as
+/// returned by `getrawtransaction`. The network is taken from the key, so
+/// no [`Scanner`] is needed; this is meant for support and debugging, e.g.
+/// checking what a key sees in a transaction a user points at. Every
+/// Sapling output and Orchard action is tried with both the incoming and
+/// outgoing viewing keys.
+pub fn decrypt_raw_tx(
+    raw_tx: impl AsRef<[u8]>,
+    viewing_key: &str,
+    height: u64,
+) -> ScanResult<DecryptedTransaction> {
+    let raw_tx = raw_tx.as_ref();
+    let raw_tx = match hex_text(raw_tx) {
+        Some(text) => hex::decode(text).map_err(|e| ScanError::InvalidHex {
+            field: "raw_tx".to_string(),
+            message: e.to_string(),
+        })?,
+        None => raw_tx.to_vec(),
+    };
+
+    let scanner = match Scanner::mainnet().inspect_key(viewing_key)?.network {
+        KeyNetwork::Mainnet => Scanner::mainnet(),
+        KeyNetwork::Testnet => Scanner::testnet(),
+        KeyNetwork::Regtest => {
+            return Err(ScanError::InvalidViewingKey(
+                "regtest keys need a Scanner with LocalNetwork parameters".to_string(),
+            ))
+        }
+    };
+    let request = ScanRequest {
+        viewing_key: viewing_key.to_string(),
+        key_id: String::new(),
+        compact_blocks: Vec::new(),
+        options: ScanOptions {
+            include_change: true,
+            ..ScanOptions::default()
+        },
+        nullifiers: Default::default(),
+    };
+    let txid = scanner
+        .read_transaction(&raw_tx, block_height(height)?)?
+        .txid();
+    let transactions = scanner.scan_transaction(&request, &raw_tx, height)?;
+
+    let fee_zat = transactions.iter().find_map(|tx| tx.fee_zat.clone());
+    let (sent, received) = transactions
+        .into_iter()
+        .partition(|tx| tx.direction == TxDirection::Out);
+    Ok(DecryptedTransaction {
+        txid: hex::encode(txid.as_ref()),
+        height,
+        fee_zat,
+        received,
+        sent,
+    })
+}
+
+/// `bytes` as text if they look like a hex encoding (surrounding whitespace
+/// allowed). Raw transactions never do: their version header starts with a
+/// non-ASCII byte.
+fn hex_text(bytes: &[u8]) -> Option<&str> {
+    let text = std::str::from_utf8(bytes).ok()?.trim();
+    (!text.is_empty() && text.bytes().all(|b| b.is_ascii_hexdigit())).then_some(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use zcash_keys::keys::UnifiedSpendingKey;
+    use zcash_primitives::transaction::{TransactionData, TxVersion};
+    use zcash_protocol::consensus::{BlockHeight, BranchId, Network};
+
+    fn test_ufvk(network: &Network) -> String {
+        UnifiedSpendingKey::from_seed(network, &[0; 32], zip32::AccountId::ZERO)
+            .unwrap()
+            .to_unified_full_viewing_key()
+            .encode(network)
+    }
+
+    /// A v5 transaction with no inputs or outputs.
+    fn empty_tx() -> (Vec<u8>, String) {
+        let tx = TransactionData::from_parts(
+            TxVersion::V5,
+            BranchId::Nu5,
+            0,
+            BlockHeight::from_u32(2_000_100),
+            None,
+            None,
+            None,
+            None,
+        )
+        .freeze()
+        .unwrap();
+        let mut raw = Vec::new();
+        tx.write(&mut raw).unwrap();
+        (raw, hex::encode(tx.txid().as_ref()))
+    }
+
+    #[test]
+    fn test_decrypt_raw_tx() {
+        let (raw, txid) = empty_tx();
+        let ufvk = test_ufvk(&Network::MainNetwork);
+
+        let from_bytes = decrypt_raw_tx(&raw, &ufvk, 2_000_000).unwrap();
+        assert_eq!(from_bytes.txid, txid);
+        assert!(from_bytes.received.is_empty() && from_bytes.sent.is_empty());
+
+        let from_hex = decrypt_raw_tx(format!(" {}\n", hex::encode(&raw)), &ufvk, 2_000_000);
+        assert_eq!(from_hex.unwrap().txid, txid);
+
+        assert!(matches!(
+            decrypt_raw_tx("abc", &ufvk, 2_000_000),
+            Err(ScanError::InvalidHex { .. })
+        ));
+        assert!(matches!(
+            decrypt_raw_tx([0x05, 0x00], &ufvk, 2_000_000),
+            Err(ScanError::InvalidTransaction(_))
+        ));
+        assert!(matches!(
+            decrypt_raw_tx(&raw, "uview1invalid", 2_000_000),
+            Err(ScanError::InvalidViewingKey(_))
+        ));
+    }
+
+    #[test]
+    fn test_hex_text() {
+        assert_eq!(hex_text(b"05 00"), None);
+        assert_eq!(hex_text(b" 050000800a\n"), Some("050000800a"));
+        assert_eq!(hex_text(&[0x05, 0x00, 0x00, 0x80]), None);
+        assert_eq!(hex_text(b""), None);
+    }
+}
@@ -0,0 +1,307 @@
+//! Viewing keys of many tenants, and how far each has been scanned.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use zcash_protocol::consensus::Parameters;
+
+use crate::error::{ScanError, ScanResult};
+use crate::fingerprint::{redact_viewing_key, ufvk_fingerprint};
+use crate::nullifiers::NullifierSet;
+use crate::scanner::Scanner;
+use crate::types::{ScanOptions, ScanRequest, ScanSummary};
+
+/// A viewing key held by a [`KeyRegistry`]. `Debug` shows it redacted.
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RegisteredKey {
+    /// Tenant the key belongs to
+    pub tenant: String,
+    /// Identifier of the key within its tenant
+    pub key_id: String,
+    /// Unified Full Viewing Key (uview1...)
+    pub viewing_key: String,
+    /// Height to start scanning the key from
+    pub birthday: u64,
+    /// Last height scanned with the key, if any
+    #[serde(default)]
+    pub last_scanned_height: Option<u64>,
+    /// Notes found with the key that weren't spent by the last height
+    /// scanned, so later scans report their spends
+    #[serde(default)]
+    pub nullifiers: NullifierSet,
+}
+
+impl RegisteredKey {
+    /// Height the key's next scan starts at.
+    pub fn next_height(&self) -> u64 {
+        self.last_scanned_height
+            .map_or(self.birthday, |height| height + 1)
+    }
+
+    /// A request to scan with the key: results carry its `key_id`, and
+    /// spends of its unspent notes are reported. Add blocks to scan them
+    /// with [`Scanner::scan`], or pass it to [`Scanner::scan_range`].
+    pub fn scan_request(&self, options: ScanOptions) -> ScanRequest {
+        ScanRequest {
+            viewing_key: self.viewing_key.clone(),
+            key_id: self.key_id.clone(),
+            compact_blocks: Vec::new(),
+            options,
+            nullifiers: self.nullifiers.clone(),
+        }
+    }
+}
+
+impl std::fmt::Debug for RegisteredKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RegisteredKey")
+            .field("tenant", &self.tenant)
+            .field("key_id", &self.key_id)
+            .field("viewing_key", &redact_viewing_key(&self.viewing_key))
+            .field("birthday", &self.birthday)
+            .field("last_scanned_height", &self.last_scanned_height)
+            .field("nullifiers", &self.nullifiers)
+            .finish()
+    }
+}
+
+/// What [`Scanner::scan_registry`] found with one registered key.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RegistryScan {
+    /// Tenant of the key
+    pub tenant: String,
+    /// The key's id within its tenant
+    pub key_id: String,
+    /// The key's scan of the blocks after its last scanned height
+    pub summary: ScanSummary,
+}
+
+/// Viewing keys of many tenants, keyed by tenant and key id.
+///
+/// Keys are checked when inserted, so a scan never fails on one, and each
+/// keeps its birthday, last scanned height and unspent notes. Scanning the
+/// registry with [`Scanner::scan_registry`] picks every key up where it
+/// left off and reports what it found under its tenant, so two tenants may
+/// use the same key id. The registry serializes, as an array of
+/// [`RegisteredKey`]s, to persist it between syncs.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "Vec<RegisteredKey>", into = "Vec<RegisteredKey>")]
+pub struct KeyRegistry {
+    keys: BTreeMap<(String, String), RegisteredKey>,
+}
+
+impl KeyRegistry {
+    /// Create an empty registry.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register `viewing_key` for `tenant` under `key_id`, to be scanned
+    /// from `birthday` (see [`Scanner::estimate_birthday`]). Left empty,
+    /// `key_id` is the key's [`ufvk_fingerprint`].
+    ///
+    /// Fails with [`ScanError::InvalidViewingKey`] unless `scanner` can scan
+    /// with the key. Returns the key it replaces, if `tenant` already had
+    /// one under `key_id`.
+    pub fn insert<P: Parameters + Send + 'static>(
+        &mut self,
+        scanner: &Scanner<P>,
+        tenant: &str,
+        key_id: &str,
+        viewing_key: &str,
+        birthday: u64,
+    ) -> ScanResult<Option<RegisteredKey>> {
+        let viewing_key = viewing_key.trim();
+        if !scanner.inspect_key(viewing_key)?.scannable {
+            return Err(ScanError::InvalidViewingKey(
+                "not a viewing key this scanner can scan: it is for another network, \
+                 or has no pool this build supports"
+                    .to_string(),
+            ));
+        }
+        let key_id = match key_id {
+            "" => ufvk_fingerprint(viewing_key),
+            key_id => key_id.to_string(),
+        };
+        let key = RegisteredKey {
+            tenant: tenant.to_string(),
+            key_id: key_id.clone(),
+            viewing_key: viewing_key.to_string(),
+            birthday,
+            last_scanned_height: None,
+            nullifiers: NullifierSet::new(),
+        };
+        Ok(self.keys.insert((tenant.to_string(), key_id), key))
+    }
+
+    /// The key `tenant` registered under `key_id`.
+    pub fn get(&self, tenant: &str, key_id: &str) -> Option<&RegisteredKey> {
+        self.keys.get(&(tenant.to_string(), key_id.to_string()))
+    }
+
+    /// Remove and return the key `tenant` registered under `key_id`.
+    pub fn remove(&mut self, tenant: &str, key_id: &str) -> Option<RegisteredKey> {
+        self.keys.remove(&(tenant.to_string(), key_id.to_string()))
+    }
+
+    /// Remove all of `tenant`'s keys, returning how many there were.
+    pub fn remove_tenant(&mut self, tenant: &str) -> usize {
+        let before = self.keys.len();
+        self.keys.retain(|(key_tenant, _), _| key_tenant != tenant);
+        before - self.keys.len()
+    }
+
+    /// All keys, ordered by tenant and key id.
+    pub fn keys(&self) -> impl Iterator<Item = &RegisteredKey> {
+        self.keys.values()
+    }
+
+    /// `tenant`'s keys, ordered by key id.
+    pub fn tenant_keys<'a>(&'a self, tenant: &'a str) -> impl Iterator<Item = &'a RegisteredKey> {
+        self.keys
+            .range((tenant.to_string(), String::new())..)
+            .map(|(_, key)| key)
+            .take_while(move |key| key.tenant == tenant)
+    }
+
+    /// Number of keys, across all tenants.
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    /// Whether no keys are registered.
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    /// Record that `summary` scanned the key `tenant` registered under
+    /// `key_id` up to its end height, keeping the notes it left unspent.
+    /// Returns `false`, changing nothing, if there's no such key or it has
+    /// already been scanned past that height.
+    pub fn record_scan(&mut self, tenant: &str, key_id: &str, summary: &ScanSummary) -> bool {
+        let Some(key) = self.keys.get_mut(&(tenant.to_string(), key_id.to_string())) else {
+            return false;
+        };
+        if key
+            .last_scanned_height
+            .is_some_and(|height| height >= summary.end_height)
+        {
+            return false;
+        }
+        key.last_scanned_height = Some(summary.end_height);
+        key.nullifiers = NullifierSet::from(summary.unspent_notes.clone());
+        true
+    }
+}
+
+impl From<Vec<RegisteredKey>> for KeyRegistry {
+    fn from(keys: Vec<RegisteredKey>) -> Self {
+        let keys = keys
+            .into_iter()
+            .map(|key| ((key.tenant.clone(), key.key_id.clone()), key))
+            .collect();
+        Self { keys }
+    }
+}
+
+impl From<KeyRegistry> for Vec<RegisteredKey> {
+    fn from(registry: KeyRegistry) -> Self {
+        registry.keys.into_values().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use zcash_keys::keys::UnifiedSpendingKey;
+    use zcash_protocol::consensus::Network;
+
+    fn ufvk(network: Network, seed: u8) -> String {
+        UnifiedSpendingKey::from_seed(&network, &[seed; 32], zip32::AccountId::ZERO)
+            .unwrap()
+            .to_unified_full_viewing_key()
+            .encode(&network)
+    }
+
+    #[test]
+    fn test_key_registry() {
+        let scanner = Scanner::mainnet();
+        let mut registry = KeyRegistry::new();
+        let alice = ufvk(Network::MainNetwork, 1);
+        let bob = ufvk(Network::MainNetwork, 2);
+        assert!(registry
+            .insert(&scanner, "alice", "main", &alice, 2_000_000)
+            .unwrap()
+            .is_none());
+        assert!(registry
+            .insert(&scanner, "bob", "main", &bob, 2_100_000)
+            .unwrap()
+            .is_none());
+        registry
+            .insert(&scanner, "alice", "", &format!(" {bob}\n"), 0)
+            .unwrap();
+
+        // Keys are only checked for the scanner's network and pools
+        let testnet = ufvk(Network::TestNetwork, 1);
+        for bad in ["uview1nope", testnet.as_str()] {
+            let err = registry
+                .insert(&scanner, "alice", "bad", bad, 0)
+                .unwrap_err();
+            assert!(matches!(err, ScanError::InvalidViewingKey(_)));
+        }
+
+        assert_eq!(registry.len(), 3);
+        let fingerprint = ufvk_fingerprint(&bob);
+        let alice_ids: Vec<_> = registry
+            .tenant_keys("alice")
+            .map(|key| key.key_id.as_str())
+            .collect();
+        assert_eq!(alice_ids, [fingerprint.as_str(), "main"]);
+        assert_eq!(
+            registry.get("alice", &fingerprint).unwrap().viewing_key,
+            bob
+        );
+        assert_eq!(
+            registry.get("bob", "main").unwrap().next_height(),
+            2_100_000
+        );
+        assert!(registry.get("carol", "main").is_none());
+
+        let summary = |end_height: u64| -> ScanSummary {
+            serde_json::from_value(serde_json::json!({
+                "transactions": [],
+                "blocksScanned": 0,
+                "startHeight": 0,
+                "endHeight": end_height,
+                "saplingCount": 0,
+                "orchardCount": 0,
+            }))
+            .unwrap()
+        };
+        assert!(registry.record_scan("bob", "main", &summary(2_100_500)));
+        assert!(!registry.record_scan("bob", "main", &summary(2_100_400)));
+        assert!(!registry.record_scan("carol", "main", &summary(2_100_500)));
+        let key = registry.get("bob", "main").unwrap();
+        assert_eq!(key.next_height(), 2_100_501);
+        let request = key.scan_request(ScanOptions::default());
+        assert_eq!(
+            (request.key_id.as_str(), request.viewing_key),
+            ("main", bob)
+        );
+
+        let json = serde_json::to_string(&registry).unwrap();
+        assert!(json.starts_with(r#"[{"tenant":"alice""#));
+        assert!(!format!("{registry:?}").contains(&alice));
+        assert!(json.contains(r#""lastScannedHeight":2100500"#));
+        assert_eq!(
+            serde_json::from_str::<KeyRegistry>(&json).unwrap(),
+            registry
+        );
+
+        assert_eq!(registry.remove_tenant("alice"), 2);
+        assert!(registry.remove("bob", "main").is_some());
+        assert!(registry.is_empty());
+    }
+}
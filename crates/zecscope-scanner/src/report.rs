@@ -0,0 +1,125 @@
+//! Grouping results by the key that found them.
+
+use serde::{Deserialize, Serialize};
+
+use crate::types::{ShieldedPool, TxDirection, ZecTransaction};
+
+/// Scan results grouped by `key_id`, for scans run with several keys.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "wasm", derive(tsify::Tsify), tsify(missing_as_null))]
+#[serde(rename_all = "camelCase")]
+pub struct ScanReport {
+    /// One entry per key, in the order their first transaction appears
+    pub keys: Vec<KeyReport>,
+}
+
+/// The transactions one key found, with a summary of them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "wasm", derive(tsify::Tsify), tsify(missing_as_null))]
+#[serde(rename_all = "camelCase")]
+pub struct KeyReport {
+    /// Key the transactions were found with
+    pub key_id: String,
+    /// Number of transactions
+    pub transaction_count: usize,
+    /// Sum of incoming amounts, excluding change (in zatoshis)
+    pub total_received_zat: String,
+    /// Pools the key found transactions in
+    pub pools: Vec<ShieldedPool>,
+    /// The key's transactions, in their original order
+    pub transactions: Vec<ZecTransaction>,
+}
+
+impl KeyReport {
+    fn new(key_id: String) -> Self {
+        Self {
+            key_id,
+            transaction_count: 0,
+            total_received_zat: "0".to_string(),
+            pools: Vec::new(),
+            transactions: Vec::new(),
+        }
+    }
+
+    fn push(&mut self, tx: ZecTransaction) {
+        if tx.direction == TxDirection::In && !tx.is_change {
+            let total = self.total_received_zat.parse::<i64>().unwrap_or(0);
+            self.total_received_zat = (total + tx.amount_zatoshis()).to_string();
+        }
+        if let Err(index) = self.pools.binary_search(&tx.pool) {
+            self.pools.insert(index, tx.pool);
+        }
+        self.transaction_count += 1;
+        self.transactions.push(tx);
+    }
+}
+
+impl ScanReport {
+    /// Group `transactions` by `key_id`.
+    pub fn from_transactions(transactions: impl IntoIterator<Item = ZecTransaction>) -> Self {
+        let mut keys: Vec<KeyReport> = Vec::new();
+        for tx in transactions {
+            let index = match keys.iter().position(|key| key.key_id == tx.key_id) {
+                Some(index) => index,
+                None => {
+                    keys.push(KeyReport::new(tx.key_id.clone()));
+                    keys.len() - 1
+                }
+            };
+            keys[index].push(tx);
+        }
+        Self { keys }
+    }
+
+    /// The report for `key_id`, if it found any transactions.
+    pub fn key(&self, key_id: &str) -> Option<&KeyReport> {
+        self.keys.iter().find(|key| key.key_id == key_id)
+    }
+
+    /// All transactions, key by key.
+    pub fn into_transactions(self) -> Vec<ZecTransaction> {
+        self.keys
+            .into_iter()
+            .flat_map(|key| key.transactions)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_tx(key_id: &str, amount: &str, pool: ShieldedPool, is_change: bool) -> ZecTransaction {
+        ZecTransaction {
+            amount_zat: amount.to_string(),
+            key_id: key_id.to_string(),
+            is_change,
+            ..ZecTransaction::test_entry("aa", 0, TxDirection::In, pool)
+        }
+    }
+
+    #[test]
+    fn test_scan_report() {
+        let mut sent = test_tx("shop", "700", ShieldedPool::Sapling, false);
+        sent.direction = TxDirection::Out;
+        let report = ScanReport::from_transactions(vec![
+            test_tx("shop", "1000", ShieldedPool::Orchard, false),
+            test_tx("tips", "50", ShieldedPool::Sapling, false),
+            sent,
+            test_tx("shop", "300", ShieldedPool::Orchard, true),
+            test_tx("shop", "2000", ShieldedPool::Sapling, false),
+        ]);
+
+        let ids: Vec<_> = report.keys.iter().map(|key| key.key_id.as_str()).collect();
+        assert_eq!(ids, ["shop", "tips"]);
+        let shop = report.key("shop").unwrap();
+        assert_eq!(shop.transaction_count, 4);
+        assert_eq!(shop.total_received_zat, "3000");
+        assert_eq!(shop.pools, [ShieldedPool::Sapling, ShieldedPool::Orchard]);
+        assert!(report.key("savings").is_none());
+
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["keys"][1]["totalReceivedZat"], "50");
+        assert_eq!(report.into_transactions().len(), 5);
+    }
+}
@@ -0,0 +1,175 @@
+//! Retrying failed requests with exponential backoff.
+
+use std::thread;
+use std::time::Duration;
+
+use crate::error::ScanResult;
+use crate::types::CompactBlock;
+
+/// How often, and how patiently, the block sources retry failed requests.
+///
+/// The wait before retry `n` (counting from zero) is `initial_backoff`
+/// doubled `n` times, capped at `max_backoff`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Retries after the first failure before giving up
+    pub max_retries: u32,
+    /// Wait before the first retry
+    pub initial_backoff: Duration,
+    /// Longest wait between retries
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(30),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Wait before retry number `retry` (counting from zero).
+    pub fn backoff(&self, retry: u32) -> Duration {
+        self.initial_backoff
+            .saturating_mul(2u32.saturating_pow(retry))
+            .min(self.max_backoff)
+    }
+
+    /// Run `request` until it succeeds or the retries run out.
+    pub fn run<T>(&self, mut request: impl FnMut() -> ScanResult<T>) -> ScanResult<T> {
+        let mut retries = 0;
+        loop {
+            match request() {
+                Ok(value) => return Ok(value),
+                Err(e) if retries >= self.max_retries => return Err(e),
+                Err(_) => {
+                    thread::sleep(self.backoff(retries));
+                    retries += 1;
+                }
+            }
+        }
+    }
+
+    /// Fetch blocks `start..=end`, resuming after the last block received
+    /// when a fetch fails part-way.
+    ///
+    /// `fetch(from, blocks)` should append blocks `from..=end` to `blocks` in
+    /// height order. Retries are counted per stall: a failed fetch that still
+    /// made progress starts the count over.
+    pub fn fetch_range(
+        &self,
+        start: u64,
+        end: u64,
+        mut fetch: impl FnMut(u64, &mut Vec<CompactBlock>) -> ScanResult<()>,
+    ) -> ScanResult<Vec<CompactBlock>> {
+        let mut blocks = Vec::new();
+        let mut retries = 0;
+        loop {
+            let from = blocks.last().map_or(start, |b: &CompactBlock| b.height + 1);
+            if from > end {
+                return Ok(blocks);
+            }
+            let received = blocks.len();
+            match fetch(from, &mut blocks) {
+                Ok(()) => return Ok(blocks),
+                Err(e) => {
+                    if blocks.len() > received {
+                        retries = 0;
+                    }
+                    if retries >= self.max_retries {
+                        return Err(e);
+                    }
+                    thread::sleep(self.backoff(retries));
+                    retries += 1;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ScanError;
+
+    fn policy(max_retries: u32) -> RetryPolicy {
+        RetryPolicy {
+            max_retries,
+            initial_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(4),
+        }
+    }
+
+    fn block(height: u64) -> CompactBlock {
+        CompactBlock {
+            proto_version: 1,
+            height,
+            hash: String::new(),
+            prev_hash: String::new(),
+            time: 0,
+            vtx: Vec::new(),
+            chain_metadata: None,
+        }
+    }
+
+    #[test]
+    fn test_backoff() {
+        let policy = policy(10);
+        let waits: Vec<_> = (0..4).map(|n| policy.backoff(n).as_millis()).collect();
+        assert_eq!(waits, vec![1, 2, 4, 4]);
+        assert_eq!(policy.backoff(u32::MAX), Duration::from_millis(4));
+    }
+
+    #[test]
+    fn test_run() {
+        let mut calls = 0;
+        let result = policy(2).run(|| {
+            calls += 1;
+            if calls < 3 {
+                Err(ScanError::BlockSource("unavailable".into()))
+            } else {
+                Ok(calls)
+            }
+        });
+        assert_eq!(result.unwrap(), 3);
+
+        let mut calls = 0;
+        let result: ScanResult<()> = policy(2).run(|| {
+            calls += 1;
+            Err(ScanError::BlockSource("unavailable".into()))
+        });
+        assert!(result.is_err());
+        assert_eq!(calls, 3);
+    }
+
+    #[test]
+    fn test_fetch_range_resumes() {
+        // Each fetch delivers two blocks and then drops
+        let mut requested = Vec::new();
+        let blocks = policy(1)
+            .fetch_range(10, 15, |from, blocks| {
+                requested.push(from);
+                blocks.extend((from..=15).take(2).map(block));
+                if blocks.last().unwrap().height < 15 {
+                    return Err(ScanError::BlockSource("stream dropped".into()));
+                }
+                Ok(())
+            })
+            .unwrap();
+        let heights: Vec<_> = blocks.iter().map(|b| b.height).collect();
+        assert_eq!(heights, (10..=15).collect::<Vec<_>>());
+        assert_eq!(requested, vec![10, 12, 14]);
+
+        // No progress at all exhausts the retries
+        let mut calls = 0;
+        let result = policy(1).fetch_range(10, 15, |_, _| {
+            calls += 1;
+            Err(ScanError::BlockSource("unavailable".into()))
+        });
+        assert!(result.is_err());
+        assert_eq!(calls, 2);
+    }
+}
@@ -1,28 +1,95 @@
 //! Core scanner implementation.
 
-use crate::error::{ScanError, ScanResult};
+use std::borrow::Cow;
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeMap, HashMap};
+use std::io::Read;
+
+use crate::addresses::{self, DerivedAddress};
+use crate::aggregate::aggregate;
+use crate::batch::{BatchDecryptor, Threads, DEFAULT_BATCH_SIZE};
+use crate::birthday;
+use crate::compression::{decompress, decompress_reader};
+use crate::decrypted::DecryptedNote;
+use crate::encoding::ContentType;
+use crate::enhance::{TransactionSource, ENHANCE_BATCH_SIZE};
+use crate::error::{PartialScanError, ScanError, ScanResult};
+use crate::fingerprint::ufvk_fingerprint;
+use crate::invoice;
+use crate::progress::{NoProgress, ProgressSink, ScanProgress, PROGRESS_INTERVAL};
+use crate::proto::{ScanResultProto, ZecTransactionProto};
+use crate::queue::ScanQueue;
+use crate::registry::{KeyRegistry, RegistryScan};
+use crate::source::BlockSource;
+use crate::spam::DustStorms;
+use crate::state::{BlockCheckpoint, NoteSpend, ScanState, TrackedNote};
+#[cfg(feature = "transparent")]
+use crate::transparent;
 use crate::types::*;
+use crate::witness::WitnessTracker;
+
+use prost::Message;
+use sapling::zip32::DiversifiableFullViewingKey;
+use web_time::Instant;
+use zcash_address::unified::{Container, Encoding, Fvk, Ivk, Ufvk, Uivk};
 use zcash_client_backend::{
     data_api::BlockMetadata,
+    decrypt_transaction,
     proto::compact_formats,
-    scanning::{scan_block, Nullifiers, ScanningKeys},
+    scanning::{scan_block, Nullifiers, ScanningKeyOps, ScanningKeys},
+    TransferType,
+};
+#[cfg(feature = "orchard")]
+use zcash_keys::address::UnifiedAddress;
+use zcash_keys::encoding::{
+    decode_extended_full_viewing_key, decode_extfvk_with_network, encode_payment_address_p,
+};
+#[cfg(feature = "spending-key")]
+use zcash_keys::keys::{Era, UnifiedSpendingKey};
+use zcash_keys::keys::{UnifiedFullViewingKey, UnifiedIncomingViewingKey};
+use zcash_primitives::transaction::{Transaction, TxId};
+use zcash_protocol::consensus::{
+    BlockHeight, BranchId, Network, NetworkConstants, NetworkType, NetworkUpgrade, Parameters,
 };
-use zcash_keys::keys::UnifiedFullViewingKey;
-use zcash_protocol::consensus::Network;
+use zcash_protocol::constants::{mainnet, regtest, testnet};
+use zcash_protocol::memo::{Memo, MemoBytes};
+use zcash_protocol::value::BalanceError;
 use zip32::Scope;
 
+/// Default number of blocks fetched and scanned at a time by
+/// [`Scanner::scan_range`].
+const DEFAULT_RANGE_CHUNK_SIZE: u64 = 1_000;
+
 /// High-level scanner for Zcash shielded transactions.
 ///
 /// The scanner takes compact blocks and a viewing key, and returns
 /// all transactions visible to that key.
-pub struct Scanner {
-    network: Network,
+///
+/// Scanners for mainnet and testnet use [`Network`]; for other chains (a
+/// private regtest network, or simulating a future upgrade) supply your own
+/// consensus [`Parameters`] to [`Scanner::with_params`], such as a
+/// [`LocalNetwork`](crate::LocalNetwork) with custom activation heights. The
+/// parameters set the network upgrade activation heights, and their network
+/// type selects the viewing key and address encodings (mainnet, testnet or
+/// regtest).
+///
+/// Use [`Scanner::builder`] to change other settings.
+pub struct Scanner<P = Network> {
+    network: P,
+    range_chunk_size: u64,
+    batch_size: usize,
+    threads: Threads,
 }
 
 impl Scanner {
     /// Create a new scanner for the given network.
     pub fn new(network: Network) -> Self {
-        Self { network }
+        Self::with_params(network)
+    }
+
+    /// Start configuring a scanner, by default for mainnet.
+    pub fn builder() -> ScannerBuilder {
+        ScannerBuilder::new()
     }
 
     /// Create a scanner for mainnet.
@@ -35,106 +102,1027 @@ impl Scanner {
         Self::new(Network::TestNetwork)
     }
 
+    /// Create a scanner for the network `viewing_key` is encoded for; see
+    /// [`ScannerBuilder::network_for_key`].
+    pub fn for_key(viewing_key: &str) -> ScanResult<Self> {
+        Ok(Self::builder().network_for_key(viewing_key)?.build())
+    }
+}
+
+impl<P: Parameters + Send + 'static> Scanner<P> {
+    /// Create a scanner for custom consensus parameters.
+    pub fn with_params(params: P) -> Self {
+        Self {
+            network: params,
+            range_chunk_size: DEFAULT_RANGE_CHUNK_SIZE,
+            batch_size: DEFAULT_BATCH_SIZE,
+            threads: Threads::default(),
+        }
+    }
+
+    /// The consensus parameters this scanner uses.
+    pub fn params(&self) -> &P {
+        &self.network
+    }
+
     /// Scan compact blocks with a viewing key.
     ///
     /// Returns all transactions visible to the viewing key in the given blocks.
+    /// Spends of notes discovered earlier in the same scan are reported as one
+    /// [`TxDirection::Out`] entry per transaction, carrying the net value that
+    /// left the wallet (spent notes minus change received) in the pool of the
+    /// first spent note.
     pub fn scan(&self, request: &ScanRequest) -> ScanResult<Vec<ZecTransaction>> {
-        // Normalize viewing key (strip any |uivk... suffix)
-        let viewing_key = normalize_viewing_key(&request.viewing_key);
+        self.scan_with_progress(request, &mut NoProgress)
+    }
+
+    /// Scan compact blocks like [`Scanner::scan`], keeping what was found if
+    /// the scan fails part-way.
+    ///
+    /// On failure, the (boxed) [`PartialScanError`] carries the transactions from
+    /// the blocks before the failing one, along with the scan state to
+    /// resume from once the bad block has been refetched.
+    pub fn scan_partial(
+        &self,
+        request: &ScanRequest,
+    ) -> Result<Vec<ZecTransaction>, Box<PartialScanError>> {
+        let mut transactions = Vec::new();
+        let mut state = ScanState::default();
+        let result = self.prepare_key(&request.viewing_key).and_then(|key| {
+            self.scan_blocks(
+                &key,
+                request,
+                request.compact_blocks.iter().map(map_compact_block),
+                &mut state,
+                &mut NoProgress,
+                None,
+                &mut |block| transactions.extend(block.transactions),
+            )
+        });
+        match result {
+            Ok(_) => Ok(transactions),
+            Err(error) => Err(Box::new(PartialScanError {
+                transactions,
+                state,
+                error,
+            })),
+        }
+    }
+
+    /// Scan compact blocks and summarize the results.
+    ///
+    /// Like [`Scanner::scan`], but the returned [`ScanSummary`] also carries
+    /// per-pool counts, the height bounds of the blocks actually scanned and
+    /// the notes left unspent. An empty request yields a summary with zero
+    /// blocks and heights of 0.
+    pub fn scan_summary(&self, request: &ScanRequest) -> ScanResult<ScanSummary> {
+        let started = Instant::now();
+        let mut transactions = Vec::new();
+        let mut unspent_notes = request.nullifiers.clone();
+        let mut bounds: Option<(u64, u64)> = None;
+        let mut blocks_scanned = 0;
+        let mut skipped_outputs = 0;
+        let mut spam_outputs = 0;
+        let mut stats = DecryptionStats::default();
+        count_outputs(
+            &self.network,
+            &mut stats,
+            &request.compact_blocks,
+            &request.options,
+        );
+        let key = self.prepare_key(&request.viewing_key)?;
+        let warnings = self.scan_blocks(
+            &key,
+            request,
+            request.compact_blocks.iter().map(map_compact_block),
+            &mut ScanState::default(),
+            &mut NoProgress,
+            None,
+            &mut |block| {
+                bounds = Some(bounds.map_or((block.height, block.height), |(start, _)| {
+                    (start, block.height)
+                }));
+                blocks_scanned += 1;
+                skipped_outputs += block.skipped_outputs;
+                spam_outputs += block.spam_outputs;
+                count_decrypted(&mut stats, &block.decrypted_notes);
+                unspent_notes.apply_block(&block);
+                transactions.extend(block.transactions);
+            },
+        )?;
+
+        let (start, end) = bounds.unwrap_or_default();
+        let mut summary = ScanSummary::from_transactions(transactions, start, end);
+        summary.blocks_scanned = blocks_scanned;
+        summary.skipped_outputs = skipped_outputs;
+        summary.spam_outputs = spam_outputs;
+        summary.unspent_notes = unspent_notes.into();
+        summary.set_decryption_stats(stats);
+        summary.elapsed_ms = started.elapsed().as_millis() as u64;
+        summary.warnings = warnings;
+        summary.incoming_only = key.incoming_only();
+        Ok(summary)
+    }
+
+    /// Scan compact blocks, reporting progress to `progress` as blocks are scanned.
+    ///
+    /// Behaves like [`Scanner::scan`]; see [`ProgressSink`] for how often
+    /// updates are delivered.
+    pub fn scan_with_progress(
+        &self,
+        request: &ScanRequest,
+        progress: &mut dyn ProgressSink,
+    ) -> ScanResult<Vec<ZecTransaction>> {
+        let mut transactions = Vec::new();
+        self.scan_blocks(
+            &self.prepare_key(&request.viewing_key)?,
+            request,
+            request.compact_blocks.iter().map(map_compact_block),
+            &mut ScanState::default(),
+            progress,
+            None,
+            &mut |block| transactions.extend(block.transactions),
+        )?;
+        Ok(transactions)
+    }
+
+    /// Scan compact blocks, continuing from (and updating) `state`.
+    ///
+    /// The blocks in `request` must directly follow the last block recorded in
+    /// `state`. Notes discovered by earlier calls stay tracked, so their spends
+    /// are still detected. On return, `state` reflects the last block scanned
+    /// successfully, including when an error stopped the scan part-way; retry
+    /// from [`ScanState::next_height`].
+    pub fn scan_with_state(
+        &self,
+        request: &ScanRequest,
+        state: &mut ScanState,
+    ) -> ScanResult<Vec<ZecTransaction>> {
+        let mut transactions = Vec::new();
+        self.scan_blocks(
+            &self.prepare_key(&request.viewing_key)?,
+            request,
+            request.compact_blocks.iter().map(map_compact_block),
+            state,
+            &mut NoProgress,
+            None,
+            &mut |block| transactions.extend(block.transactions),
+        )?;
+        Ok(transactions)
+    }
+
+    /// Decode and prepare `viewing_key` once, for reuse across scans.
+    ///
+    /// Every scan method otherwise decodes the key from the request; callers
+    /// scanning many batches with one key can prepare it up front and pass it
+    /// to [`Scanner::scan_with_key`] instead. The key is decoded for this
+    /// scanner's network and should only be used with it.
+    ///
+    /// A UIVK is scanned [incoming-only](PreparedKey::incoming_only).
+    pub fn prepare_key(&self, viewing_key: &str) -> ScanResult<PreparedKey> {
+        Ok(match self.decode_scan_key(viewing_key)? {
+            (Some(ufvk), _) => PreparedKey::full(ufvk),
+            (None, uivk) => PreparedKey {
+                scanning_keys: incoming_scanning_keys(&uivk, &ScanOptions::default()),
+                decryptor: BatchDecryptor::incoming(&uivk),
+                ufvk: None,
+                uivk,
+            },
+        })
+    }
+
+    /// Prepare a unified spending key for scanning, by its full viewing key.
+    ///
+    /// Saves callers that hold the spending key from encoding its UFVK only
+    /// for [`Scanner::prepare_key`] to decode it again.
+    #[cfg(feature = "spending-key")]
+    pub fn prepare_spending_key(&self, usk: &UnifiedSpendingKey) -> PreparedKey {
+        PreparedKey::full(usk.to_unified_full_viewing_key())
+    }
+
+    /// Like [`Scanner::prepare_spending_key`], for a spending key serialized
+    /// by `UnifiedSpendingKey::to_bytes` (of the Orchard era). The bytes
+    /// don't record a network; the key is scanned on the scanner's.
+    ///
+    /// Fails with [`ScanError::InvalidViewingKey`] if `bytes` don't hold a
+    /// spending key.
+    #[cfg(feature = "spending-key")]
+    pub fn prepare_spending_key_bytes(&self, bytes: &[u8]) -> ScanResult<PreparedKey> {
+        Ok(self.prepare_spending_key(&decode_spending_key(bytes)?))
+    }
+
+    /// The UFVK of a serialized spending key (see
+    /// [`Scanner::prepare_spending_key_bytes`]), encoded for this scanner's
+    /// network, to put in a [`ScanRequest`].
+    #[cfg(feature = "spending-key")]
+    pub fn spending_key_ufvk(&self, bytes: &[u8]) -> ScanResult<String> {
+        let usk = decode_spending_key(bytes)?;
+        Ok(usk.to_unified_full_viewing_key().encode(&self.network))
+    }
+
+    /// Start a [`ScanSession`], which prepares each viewing key once and reuses
+    /// it for every later scan with that key.
+    pub fn session(&self) -> ScanSession<'_, P> {
+        ScanSession {
+            scanner: self,
+            keys: HashMap::new(),
+        }
+    }
+
+    /// Decode a viewing key without scanning, reporting what it contains.
+    ///
+    /// Use this to validate user input before starting a long scan. Fails
+    /// with [`ScanError::InvalidViewingKey`] only if `viewing_key` isn't a
+    /// viewing key at all; a key for another network, or one this scanner
+    /// can't use, is reported through [`KeyInfo::scannable`]. Raw Sapling
+    /// keys don't say which network they're for, and are reported as being
+    /// for the scanner's.
+    pub fn inspect_key(&self, viewing_key: &str) -> ScanResult<KeyInfo> {
+        let viewing_key = normalize_viewing_key(viewing_key);
+        let invalid =
+            |e: zcash_address::unified::ParseError| ScanError::invalid_viewing_key(&viewing_key, e);
+
+        let (kind, network, has_orchard, has_sapling, has_transparent) =
+            if viewing_key.starts_with("uivk") {
+                let (network, uivk) = Uivk::decode(&viewing_key).map_err(invalid)?;
+                let items = uivk.items();
+                let has = |f: fn(&Ivk) -> bool| items.iter().any(f);
+                (
+                    KeyKind::Uivk,
+                    network,
+                    has(|i| matches!(i, Ivk::Orchard(_))),
+                    has(|i| matches!(i, Ivk::Sapling(_))),
+                    has(|i| matches!(i, Ivk::P2pkh(_))),
+                )
+            } else if viewing_key.starts_with("zxview") {
+                let (network, _) = decode_extfvk_with_network(&viewing_key)
+                    .map_err(|e| ScanError::invalid_viewing_key(&viewing_key, e))?;
+                (KeyKind::Extfvk, network, false, true, false)
+            } else if is_raw_dfvk(&viewing_key) {
+                decode_raw_dfvk(&viewing_key)
+                    .map_err(|e| ScanError::invalid_viewing_key(&viewing_key, e))?;
+                let network = self.network.network_type();
+                (KeyKind::Dfvk, network, false, true, false)
+            } else {
+                let (network, ufvk) = Ufvk::decode(&viewing_key).map_err(invalid)?;
+                let items = ufvk.items();
+                let has = |f: fn(&Fvk) -> bool| items.iter().any(f);
+                (
+                    KeyKind::Ufvk,
+                    network,
+                    has(|i| matches!(i, Fvk::Orchard(_))),
+                    has(|i| matches!(i, Fvk::Sapling(_))),
+                    has(|i| matches!(i, Fvk::P2pkh(_))),
+                )
+            };
+
+        let supported_pool = has_sapling || (has_orchard && cfg!(feature = "orchard"));
+        let scannable = network == self.network.network_type()
+            && supported_pool
+            && self.decode_scan_key(&viewing_key).is_ok();
+
+        Ok(KeyInfo {
+            kind,
+            network: network.into(),
+            has_orchard,
+            has_sapling,
+            has_transparent,
+            requires_orchard_feature: has_orchard,
+            scannable,
+        })
+    }
 
-        // Decode the UFVK
-        let ufvk = UnifiedFullViewingKey::decode(&self.network, &viewing_key)
-            .map_err(|e| ScanError::InvalidViewingKey(e.to_string()))?;
+    /// Scan compact blocks like [`Scanner::scan_with_state`], using a key
+    /// prepared by [`Scanner::prepare_key`]; `request.viewing_key` is ignored.
+    pub fn scan_with_key(
+        &self,
+        key: &PreparedKey,
+        request: &ScanRequest,
+        state: &mut ScanState,
+    ) -> ScanResult<Vec<ZecTransaction>> {
+        let mut transactions = Vec::new();
+        self.scan_blocks(
+            key,
+            request,
+            request.compact_blocks.iter().map(map_compact_block),
+            state,
+            &mut NoProgress,
+            None,
+            &mut |block| transactions.extend(block.transactions),
+        )?;
+        Ok(transactions)
+    }
+
+    /// Scan compact blocks for every account of a multi-account wallet.
+    ///
+    /// `accounts` maps ZIP 32 account indices to their viewing keys, which
+    /// replace `request.viewing_key`. Each account is scanned on its own, so
+    /// its spends are only netted against its own change, and every result
+    /// carries its [`ZecTransaction::account_id`]. Results are ordered by
+    /// height, then by account.
+    pub fn scan_accounts(
+        &self,
+        accounts: &BTreeMap<u32, String>,
+        request: &ScanRequest,
+    ) -> ScanResult<Vec<ZecTransaction>> {
+        let mut transactions = Vec::new();
+        for (&account_id, viewing_key) in accounts {
+            let key = self.prepare_key(viewing_key).map_err(|e| match e {
+                ScanError::InvalidViewingKey(message) => {
+                    ScanError::InvalidViewingKey(format!("account {account_id}: {message}"))
+                }
+                e => e,
+            })?;
+            let found = self.scan_with_key(&key, request, &mut ScanState::default())?;
+            // Fingerprint the account's key rather than the request's
+            let fingerprint = ufvk_fingerprint(viewing_key);
+            transactions.extend(found.into_iter().map(|tx| ZecTransaction {
+                account_id: Some(account_id),
+                key_id: match request.key_id.is_empty() {
+                    true => fingerprint.clone(),
+                    false => tx.key_id,
+                },
+                ..tx
+            }));
+        }
+        // Stable, so each account's entries keep their order within a block
+        transactions.sort_by_key(|tx| tx.height);
+        Ok(transactions)
+    }
+
+    /// Scan compact blocks, handing each block's results to `on_block` as
+    /// soon as the block has been scanned.
+    ///
+    /// Unlike [`Scanner::scan`], nothing is buffered across blocks, so callers
+    /// can persist results incrementally and keep memory bounded on large ranges.
+    /// `on_block` is called once per block, in order, including blocks with no
+    /// transactions for this key.
+    pub fn scan_with<F>(&self, request: &ScanRequest, mut on_block: F) -> ScanResult<()>
+    where
+        F: FnMut(BlockScanResult),
+    {
+        self.scan_blocks(
+            &self.prepare_key(&request.viewing_key)?,
+            request,
+            request.compact_blocks.iter().map(map_compact_block),
+            &mut ScanState::default(),
+            &mut NoProgress,
+            None,
+            &mut on_block,
+        )
+        .map(drop)
+    }
+
+    /// Scan compact blocks like [`Scanner::scan_with`], continuing from `state`
+    /// and appending each block's note commitments to `witnesses`.
+    ///
+    /// Every [`BlockScanResult`] then carries the Sapling and Orchard anchors as
+    /// of that block. `witnesses` must describe the trees as of the last block
+    /// recorded in `state` (or the block before the first scanned one), else
+    /// the scan fails with [`ScanError::CommitmentTree`].
+    pub fn scan_with_witnesses<F>(
+        &self,
+        request: &ScanRequest,
+        state: &mut ScanState,
+        witnesses: &mut WitnessTracker,
+        mut on_block: F,
+    ) -> ScanResult<()>
+    where
+        F: FnMut(BlockScanResult),
+    {
+        self.scan_blocks(
+            &self.prepare_key(&request.viewing_key)?,
+            request,
+            request.compact_blocks.iter().map(map_compact_block),
+            state,
+            &mut NoProgress,
+            Some(witnesses),
+            &mut on_block,
+        )
+        .map(drop)
+    }
+
+    /// Check a single compact transaction, mined at `height` and `time`, for
+    /// outputs to the viewing key.
+    ///
+    /// Useful when a txid of interest is already known (e.g. from a mempool
+    /// stream) and there's no block around it. Without the surrounding chain
+    /// the note commitment tree positions are unknown, so only incoming
+    /// outputs are reported (without a `note_position`) and no notes are
+    /// tracked. `request` supplies the
+    /// viewing key, key id and options — its `compact_blocks` are ignored.
+    pub fn scan_tx(
+        &self,
+        request: &ScanRequest,
+        tx: &CompactTx,
+        height: u64,
+        time: u32,
+    ) -> ScanResult<Vec<ZecTransaction>> {
+        block_height(height)?;
+        let tx = map_compact_tx(tx)?;
+        // Pretend the block starts from empty trees; positions are discarded
+        let chain_metadata = compact_formats::ChainMetadata {
+            sapling_commitment_tree_size: tx.outputs.len() as u32,
+            orchard_commitment_tree_size: tx.actions.len() as u32,
+        };
+        let mut block = compact_formats::CompactBlock {
+            proto_version: 1,
+            height,
+            hash: vec![0; 32],
+            time,
+            vtx: vec![tx],
+            chain_metadata: Some(chain_metadata),
+            ..Default::default()
+        };
+        skip_inactive_pools(&self.network, &mut block);
+        cap_outputs(&mut block, &request.options);
+
+        let key = self.prepare_key(&request.viewing_key)?;
+        let mut block_scanner =
+            BlockScanner::new(&self.network, &key, request, &ScanState::default(), None)?;
+        let mut transactions = block_scanner.scan_block(block, true)?.transactions;
+        for tx in &mut transactions {
+            tx.note_position = None;
+        }
+        Ok(transactions)
+    }
+
+    /// Check an unmined compact transaction, as streamed by lightwalletd's
+    /// `GetMempoolStream`, for outputs to the viewing key.
+    ///
+    /// Like [`Scanner::scan_tx`], but the entries are provisional: they're
+    /// marked `unconfirmed`, with a `height` and `time` of 0, so a wallet can
+    /// show incoming funds before they are mined. Outputs are decrypted under
+    /// the rules of the latest network upgrade the scanner's parameters know
+    /// of, which is what a transaction accepted into the mempool follows.
+    pub fn scan_mempool_tx(
+        &self,
+        request: &ScanRequest,
+        tx: &CompactTx,
+    ) -> ScanResult<Vec<ZecTransaction>> {
+        // The height scanned at isn't where the transaction will be mined
+        let request = ScanRequest {
+            viewing_key: request.viewing_key.clone(),
+            key_id: request.key_id.clone(),
+            compact_blocks: Vec::new(),
+            options: ScanOptions {
+                height_range: None,
+                ..request.options.clone()
+            },
+            nullifiers: request.nullifiers.clone(),
+        };
+        let mut transactions = self.scan_tx(&request, tx, mempool_height(&self.network), 0)?;
+        for tx in &mut transactions {
+            tx.height = 0;
+            tx.unconfirmed = true;
+            // Coinbase transactions never enter the mempool
+            tx.is_coinbase = false;
+        }
+        Ok(transactions)
+    }
+
+    /// Scan protobuf-encoded compact blocks, as streamed by lightwalletd.
+    ///
+    /// Each entry of `blocks` is one serialized `CompactBlock` message,
+    /// optionally gzip- or zstd-compressed; they are decoded directly, skipping
+    /// the JSON/hex representation. `request` supplies the viewing key, key id
+    /// and options — its `compact_blocks` are ignored.
+    pub fn scan_proto(
+        &self,
+        request: &ScanRequest,
+        blocks: &[Vec<u8>],
+    ) -> ScanResult<Vec<ZecTransaction>> {
+        let blocks = blocks
+            .iter()
+            .map(|b| decompress(b))
+            .collect::<ScanResult<Vec<_>>>()?;
+        let blocks: Vec<&[u8]> = blocks.iter().map(AsRef::as_ref).collect();
+        self.scan_proto_slices(&self.prepare_key(&request.viewing_key)?, request, &blocks)
+    }
 
-        // Convert compact blocks to protobuf format
-        let blocks = request
-            .compact_blocks
+    /// Scan protobuf-encoded compact blocks like [`Scanner::scan_proto`],
+    /// returning the results as a protobuf message too.
+    ///
+    /// Encode the result with [`prost::Message::encode_to_vec`] to hand it
+    /// on without going through JSON; its schema is `zecscope.v1.ScanResult`
+    /// in `proto/zecscope.proto`.
+    pub fn scan_to_proto(
+        &self,
+        request: &ScanRequest,
+        blocks: &[Vec<u8>],
+    ) -> ScanResult<ScanResultProto> {
+        let blocks = blocks
             .iter()
-            .map(|b| map_compact_block(b))
+            .map(|b| decompress(b))
             .collect::<ScanResult<Vec<_>>>()?;
+        let mut result = ScanResultProto::default();
+        let warnings = self.scan_blocks(
+            &self.prepare_key(&request.viewing_key)?,
+            request,
+            blocks
+                .iter()
+                .enumerate()
+                .map(|(i, b)| decode_proto_block(i, b)),
+            &mut ScanState::default(),
+            &mut NoProgress,
+            None,
+            &mut |block| {
+                if result.blocks_scanned == 0 {
+                    result.start_height = block.height;
+                }
+                result.end_height = block.height;
+                result.blocks_scanned += 1;
+                let transactions = block
+                    .transactions
+                    .into_iter()
+                    .map(ZecTransactionProto::from);
+                result.transactions.extend(transactions);
+            },
+        )?;
+        result.warnings = warnings.into_iter().map(Into::into).collect();
+        Ok(result)
+    }
 
-        // Set up scanning keys
-        type AccountId = u32;
-        let scanning_keys: ScanningKeys<AccountId, (AccountId, Scope)> =
-            ScanningKeys::from_account_ufvks(std::iter::once((0u32, ufvk)));
-        let nullifiers = Nullifiers::<AccountId>::empty();
+    /// Scan a buffer of length-delimited protobuf `CompactBlock` messages.
+    ///
+    /// Each message is preceded by its length as a varint, as written by
+    /// prost's `encode_length_delimited`. Blocks are decoded straight out of
+    /// `bytes` without copying the buffer, unless the whole buffer is gzip- or
+    /// zstd-compressed and has to be decompressed first. Fails with
+    /// [`ScanError::InvalidProtobuf`] if the buffer is truncated or a
+    /// message doesn't decode.
+    pub fn scan_proto_delimited(
+        &self,
+        request: &ScanRequest,
+        bytes: &[u8],
+    ) -> ScanResult<Vec<ZecTransaction>> {
+        self.scan_proto_delimited_with_key(&self.prepare_key(&request.viewing_key)?, request, bytes)
+    }
+
+    /// Like [`Scanner::scan_proto_delimited`], but with a key already prepared
+    /// by [`Scanner::prepare_key`]; `request.viewing_key` is ignored.
+    pub fn scan_proto_delimited_with_key(
+        &self,
+        key: &PreparedKey,
+        request: &ScanRequest,
+        bytes: &[u8],
+    ) -> ScanResult<Vec<ZecTransaction>> {
+        let bytes = decompress(bytes)?;
+        let blocks = split_length_delimited(&bytes)?;
+        self.scan_proto_slices(key, request, &blocks)
+    }
 
-        let mut prior_meta: Option<BlockMetadata> = None;
+    /// Scan a stream of length-delimited protobuf `CompactBlock` messages, in
+    /// the format [`Scanner::scan_proto_delimited`] takes, as it is read.
+    ///
+    /// Blocks are decoded and scanned a batch at a time, so a lightwalletd
+    /// dump can be scanned straight from a file or socket without holding it
+    /// in memory. A stream starting with a gzip or zstd header is decompressed
+    /// on the fly. Fails with [`ScanError::BlockSource`] if `reader` fails,
+    /// and with [`ScanError::InvalidProtobuf`] if the stream ends mid-message
+    /// or a message doesn't decode. `request` supplies the viewing key, key id
+    /// and options — its `compact_blocks` are ignored.
+    pub fn scan_stream(
+        &self,
+        reader: impl Read,
+        request: &ScanRequest,
+    ) -> ScanResult<Vec<ZecTransaction>> {
+        let key = self.prepare_key(&request.viewing_key)?;
+        let blocks = DelimitedBlocks::new(decompress_reader(reader)?);
         let mut transactions = Vec::new();
+        self.scan_blocks(
+            &key,
+            request,
+            blocks,
+            &mut ScanState::default(),
+            &mut NoProgress,
+            None,
+            &mut |block| transactions.extend(block.transactions),
+        )?;
+        Ok(transactions)
+    }
 
-        for block in blocks {
-            let scanned = scan_block(
-                &self.network,
-                block,
-                &scanning_keys,
-                &nullifiers,
-                prior_meta.as_ref(),
-            )
-            .map_err(|e| ScanError::ScanFailed {
-                height: e.at_height().into(),
-                message: e.to_string(),
-            })?;
+    fn scan_proto_slices(
+        &self,
+        key: &PreparedKey,
+        request: &ScanRequest,
+        blocks: &[&[u8]],
+    ) -> ScanResult<Vec<ZecTransaction>> {
+        let mut transactions = Vec::new();
+        self.scan_blocks(
+            key,
+            request,
+            blocks
+                .iter()
+                .enumerate()
+                .map(|(i, b)| decode_proto_block(i, b)),
+            &mut ScanState::default(),
+            &mut NoProgress,
+            None,
+            &mut |block| transactions.extend(block.transactions),
+        )?;
+        Ok(transactions)
+    }
 
-            let height: u32 = scanned.height().into();
-            let height = height as u64;
-            let time = scanned.block_time() as i64;
+    /// Fetch and scan the inclusive height range `start..=end` from `source`.
+    ///
+    /// Fails with [`ScanError::InvalidRange`] if `end < start`.
+    /// Blocks are requested in chunks (of 1,000 unless set with
+    /// [`ScannerBuilder::range_chunk_size`]) and scanned as they arrive, so
+    /// only one chunk is held in memory at a time. Spends are tracked across
+    /// chunks. `request` supplies the viewing key, key id and options — its
+    /// `compact_blocks` are ignored.
+    pub fn scan_range(
+        &self,
+        source: &mut dyn BlockSource,
+        start: u64,
+        end: u64,
+        request: &ScanRequest,
+    ) -> ScanResult<ScanSummary> {
+        self.scan_range_with_progress(source, start, end, request, &mut NoProgress)
+    }
 
-            for wtx in scanned.transactions() {
-                let txid = wtx.txid();
-                let txid_hex = hex::encode(txid.as_ref());
+    /// Fetch and scan `start..=end` from `source` like
+    /// [`Scanner::scan_range`], reporting progress across the whole range to
+    /// `progress`.
+    pub fn scan_range_with_progress(
+        &self,
+        source: &mut dyn BlockSource,
+        start: u64,
+        end: u64,
+        request: &ScanRequest,
+        progress: &mut dyn ProgressSink,
+    ) -> ScanResult<ScanSummary> {
+        self.scan_range_with_state(
+            source,
+            start,
+            end,
+            request,
+            &mut ScanState::default(),
+            progress,
+        )
+    }
 
-                // Process Sapling outputs
-                for out in wtx.sapling_outputs() {
-                    if out.is_change() {
-                        continue;
-                    }
-                    let note = out.note();
-                    let v = note.value().inner();
-                    if v == 0 {
-                        continue;
-                    }
+    /// Fetch and scan `start..=end` from `source` like
+    /// [`Scanner::scan_range`], continuing from `state` and handing each
+    /// block's results to `on_block` as soon as the block has been scanned.
+    ///
+    /// Nothing is buffered across blocks, so results can be streamed on
+    /// (e.g. to a client) while later chunks are still being fetched.
+    /// `on_block` is called once per block, in order, including blocks with
+    /// no transactions for this key. Returns the blocks a lenient scan
+    /// skipped.
+    pub fn scan_range_with<F>(
+        &self,
+        source: &mut dyn BlockSource,
+        start: u64,
+        end: u64,
+        request: &ScanRequest,
+        state: &mut ScanState,
+        mut on_block: F,
+    ) -> ScanResult<Vec<ScanWarning>>
+    where
+        F: FnMut(BlockScanResult),
+    {
+        if end < start {
+            return Err(ScanError::InvalidRange { start, end });
+        }
+        let key = self.prepare_key(&request.viewing_key)?;
+        let mut warnings = Vec::new();
+        self.scan_chunks(source, start, end, request, state, |blocks, state| {
+            warnings.extend(self.scan_blocks(
+                &key,
+                request,
+                blocks.iter().map(map_compact_block),
+                state,
+                &mut NoProgress,
+                None,
+                &mut on_block,
+            )?);
+            Ok(())
+        })?;
+        Ok(warnings)
+    }
 
-                    transactions.push(ZecTransaction {
-                        txid: txid_hex.clone(),
-                        height,
-                        time,
-                        amount_zat: v.to_string(),
-                        direction: TxDirection::In,
-                        memo: None,
-                        key_id: request.key_id.clone(),
-                        pool: ShieldedPool::Sapling,
-                    });
+    /// Scan every key in `registry` from its next height (see
+    /// [`RegisteredKey::next_height`](crate::RegisteredKey::next_height)) to
+    /// `end` with `options`, recording how far each got.
+    ///
+    /// Keys already scanned to `end` are skipped. Each key's transactions
+    /// are returned with the tenant that registered it, in registry order.
+    /// On failure, the keys scanned before the failing one keep their
+    /// progress.
+    pub fn scan_registry(
+        &self,
+        source: &mut dyn BlockSource,
+        registry: &mut KeyRegistry,
+        end: u64,
+        options: &ScanOptions,
+    ) -> ScanResult<Vec<RegistryScan>> {
+        let due: Vec<_> = registry
+            .keys()
+            .filter(|key| key.next_height() <= end)
+            .map(|key| {
+                (
+                    key.next_height(),
+                    key.scan_request(options.clone()),
+                    key.tenant.clone(),
+                )
+            })
+            .collect();
+        let mut scans = Vec::with_capacity(due.len());
+        for (start, request, tenant) in due {
+            let summary = self.scan_range(source, start, end, &request)?;
+            registry.record_scan(&tenant, &request.key_id, &summary);
+            scans.push(RegistryScan {
+                tenant,
+                key_id: request.key_id,
+                summary,
+            });
+        }
+        Ok(scans)
+    }
+
+    /// Scan the most urgent range in `queue`, at most one chunk long (see
+    /// [`ScannerBuilder::range_chunk_size`]), or return `None` if the queue is
+    /// empty.
+    ///
+    /// The range is then marked scanned, and the blocks following each note
+    /// it found raised to [`FoundNote`](crate::ScanPriority::FoundNote). Notes found by earlier
+    /// calls with `queue` stay tracked, so their spends are detected. Call
+    /// repeatedly, updating the queue's chain tip as new blocks arrive, to
+    /// scan the newest blocks first and backfill the rest. On failure the
+    /// range stays queued, to be retried by the next call.
+    pub fn scan_queued(
+        &self,
+        source: &mut dyn BlockSource,
+        queue: &mut ScanQueue,
+        request: &ScanRequest,
+    ) -> ScanResult<Option<ScanSummary>> {
+        let Some(range) = queue.next_range(self.range_chunk_size) else {
+            return Ok(None);
+        };
+        let mut state = ScanState {
+            last_block: None,
+            tracked_notes: queue.take_tracked_notes(),
+        };
+        let result = self.scan_range_with_state(
+            source,
+            range.start,
+            range.end,
+            request,
+            &mut state,
+            &mut NoProgress,
+        );
+        // Notes are tracked by nullifier, so rescanning after a failure
+        // doesn't track them twice
+        queue.set_tracked_notes(state.tracked_notes);
+        let summary = result?;
+
+        queue.mark_scanned(range.start, range.end);
+        for tx in &summary.transactions {
+            if tx.direction == TxDirection::In {
+                queue.found_note(tx.height);
+            }
+        }
+        Ok(Some(summary))
+    }
+
+    fn scan_range_with_state(
+        &self,
+        source: &mut dyn BlockSource,
+        start: u64,
+        end: u64,
+        request: &ScanRequest,
+        state: &mut ScanState,
+        progress: &mut dyn ProgressSink,
+    ) -> ScanResult<ScanSummary> {
+        if end < start {
+            return Err(ScanError::InvalidRange { start, end });
+        }
+
+        let started = Instant::now();
+        let key = self.prepare_key(&request.viewing_key)?;
+        let mut transactions = Vec::new();
+        let mut unspent_notes = request.nullifiers.clone();
+        let mut stats = DecryptionStats::default();
+        let mut skipped_outputs = 0;
+        let mut spam_outputs = 0;
+        let mut warnings = Vec::new();
+        let mut blocks_scanned = 0;
+        // Progress through the range, as of the end of the last chunk
+        let mut scanned = ScanProgress {
+            blocks_scanned: 0,
+            total_blocks: (end - start + 1) as usize,
+            current_height: start,
+            outputs_tried: 0,
+        };
+
+        self.scan_chunks(source, start, end, request, state, |blocks, state| {
+            count_outputs(&self.network, &mut stats, blocks, &request.options);
+            let before = scanned;
+            let mut chunk_progress = |chunk: &ScanProgress| {
+                scanned = ScanProgress {
+                    blocks_scanned: before.blocks_scanned + chunk.blocks_scanned,
+                    current_height: chunk.current_height,
+                    outputs_tried: before.outputs_tried + chunk.outputs_tried,
+                    ..before
+                };
+                progress.on_progress(&scanned);
+            };
+            warnings.extend(self.scan_blocks(
+                &key,
+                request,
+                blocks.iter().map(map_compact_block),
+                state,
+                &mut chunk_progress,
+                None,
+                &mut |block| {
+                    blocks_scanned += 1;
+                    skipped_outputs += block.skipped_outputs;
+                    spam_outputs += block.spam_outputs;
+                    count_decrypted(&mut stats, &block.decrypted_notes);
+                    unspent_notes.apply_block(&block);
+                    transactions.extend(block.transactions)
+                },
+            )?);
+            Ok(())
+        })?;
+
+        let mut summary = ScanSummary::from_transactions(transactions, start, end);
+        summary.blocks_scanned = blocks_scanned;
+        summary.skipped_outputs = skipped_outputs;
+        summary.spam_outputs = spam_outputs;
+        summary.unspent_notes = unspent_notes.into();
+        summary.set_decryption_stats(stats);
+        summary.elapsed_ms = started.elapsed().as_millis() as u64;
+        summary.warnings = warnings;
+        summary.incoming_only = key.incoming_only();
+        Ok(summary)
+    }
+
+    /// Fetch `start..=end` from `source` a chunk at a time, handing each
+    /// chunk to `scan_chunk` to be scanned from `state`.
+    fn scan_chunks(
+        &self,
+        source: &mut dyn BlockSource,
+        start: u64,
+        end: u64,
+        request: &ScanRequest,
+        state: &mut ScanState,
+        mut scan_chunk: impl FnMut(&[CompactBlock], &mut ScanState) -> ScanResult<()>,
+    ) -> ScanResult<()> {
+        let mut chunk_start = start;
+        while chunk_start <= end {
+            let chunk_end = end.min(chunk_start + self.range_chunk_size - 1);
+            let blocks = source.get_block_range(chunk_start, chunk_end)?;
+            scan_chunk(&blocks, state)?;
+
+            // A lenient scan may have skipped the last block of the chunk
+            if state.last_height() != Some(chunk_end) && !request.options.lenient {
+                return Err(ScanError::BlockSource(format!(
+                    "source did not return blocks up to height {chunk_end}"
+                )));
+            }
+            chunk_start = chunk_end + 1;
+        }
+        Ok(())
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn scan_blocks<I>(
+        &self,
+        key: &PreparedKey,
+        request: &ScanRequest,
+        blocks: I,
+        state: &mut ScanState,
+        progress: &mut dyn ProgressSink,
+        witnesses: Option<&mut WitnessTracker>,
+        on_block: &mut dyn FnMut(BlockScanResult),
+    ) -> ScanResult<Vec<ScanWarning>>
+    where
+        I: Iterator<Item = ScanResult<compact_formats::CompactBlock>>,
+    {
+        let mut block_scanner = BlockScanner::new(&self.network, key, request, state, witnesses)?;
+        let batch_size = request
+            .options
+            .batch_size
+            .map_or(self.batch_size, |outputs| outputs.max(1));
+
+        // Exact for every caller but streams, which report 0
+        let total_blocks = blocks.size_hint().0;
+        let mut outputs_tried = 0;
+        let lenient = request.options.lenient;
+        let mut warnings = Vec::new();
+
+        let mut prev = state
+            .last_block
+            .as_ref()
+            .map(|b| decode_hex(&b.hash, "checkpoint hash").map(|hash| (b.height, hash)))
+            .transpose()?;
+
+        // Progress as of the last block scanned, reported again at the end
+        let mut latest: Option<ScanProgress> = None;
+        let scan_all = || {
+            let mut blocks = blocks;
+            let mut blocks_scanned = 0;
+            loop {
+                // Blocks are converted to protobuf format as the batch fills
+                let mut batch = Vec::new();
+                let mut batch_skipped = Vec::new();
+                let mut batch_outputs = 0;
+                let mut failed = None;
+                while batch_outputs < batch_size {
+                    match blocks.next() {
+                        Some(Ok(mut block)) => {
+                            skip_inactive_pools(&self.network, &mut block);
+                            let skipped = cap_outputs(&mut block, &request.options);
+                            batch_outputs +=
+                                trial_decryption_count(&block, &request.options) - skipped;
+                            batch.push(block);
+                            batch_skipped.push(skipped);
+                        }
+                        Some(Err(e)) => {
+                            failed = Some(e);
+                            break;
+                        }
+                        None => break,
+                    }
+                }
+                if batch.is_empty() && (failed.is_none() || !lenient) {
+                    return failed.map_or(Ok(()), Err);
                 }
 
-                // Process Orchard outputs
-                #[cfg(feature = "orchard")]
-                for out in wtx.orchard_outputs() {
-                    if out.is_change() {
-                        continue;
+                let has_notes = key.decryptor.find_notes(
+                    &self.network,
+                    &batch,
+                    &request.options,
+                    &self.threads,
+                );
+                let batch = batch.into_iter().zip(batch_skipped).zip(has_notes);
+                for ((block, skipped), has_notes) in batch {
+                    let height = block.height;
+                    if let Err(e) = check_continuity(prev.as_ref(), &block) {
+                        if !lenient {
+                            return Err(e);
+                        }
+                        // Scan it anyway, picking the chain up from here
+                        warnings.push(ScanWarning::from_error(Some(height), &e));
+                        block_scanner.resync();
                     }
-                    let note = out.note();
-                    let v: u64 = note.value().inner();
-                    if v == 0 {
-                        continue;
+                    prev = Some((height, block.hash.clone()));
+
+                    outputs_tried += trial_decryption_count(&block, &request.options) - skipped;
+                    let mut result = match block_scanner.scan_block(block, has_notes) {
+                        Ok(result) => result,
+                        Err(e) if lenient => {
+                            warnings.push(ScanWarning::from_error(Some(height), &e));
+                            prev = None;
+                            block_scanner.resync();
+                            continue;
+                        }
+                        Err(e) => return Err(e),
+                    };
+                    result.skipped_outputs = skipped;
+
+                    blocks_scanned += 1;
+                    let scanned = ScanProgress {
+                        blocks_scanned,
+                        total_blocks,
+                        current_height: result.height,
+                        outputs_tried,
+                    };
+                    if blocks_scanned % PROGRESS_INTERVAL == 0 {
+                        progress.on_progress(&scanned);
                     }
+                    latest = Some(scanned);
 
-                    transactions.push(ZecTransaction {
-                        txid: txid_hex.clone(),
-                        height,
-                        time,
-                        amount_zat: v.to_string(),
-                        direction: TxDirection::In,
-                        memo: None,
-                        key_id: request.key_id.clone(),
-                        pool: ShieldedPool::Orchard,
-                    });
+                    on_block(result);
+                }
+                if let Some(e) = failed {
+                    if !lenient {
+                        return Err(e);
+                    }
+                    // The block that failed to decode directly follows the batch
+                    let height = prev.as_ref().map(|(height, _)| height + 1);
+                    warnings.push(ScanWarning::from_error(height, &e));
+                    prev = None;
+                    block_scanner.resync();
                 }
             }
-
-            prior_meta = Some(scanned.to_block_metadata());
+        };
+        let result = scan_all();
+        // Lenient scans may skip blocks, so the last block scanned needn't be
+        // the last of `total_blocks`
+        if let Some(latest) =
+            latest.filter(|p| result.is_ok() && p.blocks_scanned % PROGRESS_INTERVAL != 0)
+        {
+            progress.on_progress(&latest);
         }
 
-        Ok(transactions)
+        // Checkpoint whatever was scanned, even if we stopped early
+        block_scanner.save_state(state);
+        result.map(|()| warnings)
     }
 
     /// Scan compact blocks from JSON string.
@@ -142,89 +1130,1835 @@ impl Scanner {
     /// This is a convenience method for WASM and other environments
     /// where JSON is the primary data format.
     pub fn scan_json(&self, request_json: &str) -> ScanResult<String> {
-        let request: ScanRequest = serde_json::from_str(request_json)?;
+        self.scan_json_bytes(request_json.as_bytes())
+    }
+
+    /// Like [`Scanner::scan_json`], but taking the request as bytes, which
+    /// may be gzip- or zstd-compressed.
+    pub fn scan_json_bytes(&self, request_json: &[u8]) -> ScanResult<String> {
+        let request: ScanRequest = serde_json::from_slice(&decompress(request_json)?)?;
         let transactions = self.scan(&request)?;
         Ok(serde_json::to_string(&transactions)?)
     }
-}
 
-/// Normalize a viewing key string.
-///
-/// Some tools export UFVKs with an appended `|uivk...` segment.
-/// This function strips that suffix to get just the UFVK.
-fn normalize_viewing_key(raw: &str) -> String {
-    let trimmed = raw.trim();
-    if let Some(idx) = trimmed.find('|') {
-        trimmed[..idx].to_string()
-    } else {
-        trimmed.to_string()
+    /// Like [`Scanner::scan_json_bytes`], but with the request and the
+    /// returned transactions in `content_type`'s format, such as CBOR, which
+    /// is much smaller than JSON to pass across a WASM or FFI boundary.
+    pub fn scan_encoded(&self, request: &[u8], content_type: ContentType) -> ScanResult<Vec<u8>> {
+        let request: ScanRequest = content_type.decode(request)?;
+        content_type.encode(&self.scan(&request)?)
     }
-}
 
-/// Decode a hex string, returning a descriptive error.
-fn decode_hex(s: &str, field: &str) -> ScanResult<Vec<u8>> {
-    hex::decode(s).map_err(|e| ScanError::InvalidHex {
-        field: field.to_string(),
-        message: e.to_string(),
-    })
-}
+    /// Scan a full (non-compact) v4/v5 transaction with a viewing key.
+    ///
+    /// Decrypts the complete Sapling outputs and Orchard actions of the raw
+    /// transaction, so returned entries carry memos and receiving addresses.
+    /// `height` is the height the transaction was (or, for mempool
+    /// transactions, will be) mined at; it selects the consensus rules and
+    /// becomes the entries' `height`. `time` is left at 0.
+    ///
+    /// Outputs recovered with the outgoing viewing key are reported as
+    /// [`TxDirection::Out`] payments, with the recipient in
+    /// `recipient_address`. Change is skipped
+    /// unless `include_change` is set. `request` supplies the viewing key,
+    /// key id and options — its `compact_blocks` are ignored.
+    pub fn scan_transaction(
+        &self,
+        request: &ScanRequest,
+        raw_tx: &[u8],
+        height: u64,
+    ) -> ScanResult<Vec<ZecTransaction>> {
+        let tx = self.read_transaction(raw_tx, block_height(height)?)?;
+        self.transaction_entries(request, &tx, height)
+    }
 
-/// Convert our CompactBlock type to the protobuf format.
-fn map_compact_block(block: &CompactBlock) -> ScanResult<compact_formats::CompactBlock> {
-    let vtx = block
-        .vtx
-        .iter()
-        .map(map_compact_tx)
-        .collect::<ScanResult<Vec<_>>>()?;
+    /// The entries [`Scanner::scan_transaction`] reports for `tx`.
+    fn transaction_entries(
+        &self,
+        request: &ScanRequest,
+        tx: &Transaction,
+        height: u64,
+    ) -> ScanResult<Vec<ZecTransaction>> {
+        let ufvk = self.decode_viewing_key(&request.viewing_key)?;
+        let block_height = block_height(height)?;
+        let txid_hex = hex::encode(tx.txid().as_ref());
 
-    let chain_metadata = block.chain_metadata.as_ref().map(|m| {
-        compact_formats::ChainMetadata {
-            sapling_commitment_tree_size: m.sapling_commitment_tree_size,
-            orchard_commitment_tree_size: m.orchard_commitment_tree_size.unwrap_or(0),
+        let ufvks = HashMap::from([(0u32, ufvk)]);
+        let decrypted = decrypt_transaction(&self.network, Some(block_height), None, tx, &ufvks);
+        let uivk = ufvks[&0].to_unified_incoming_viewing_key();
+
+        let sapling_outputs = decrypted.sapling_outputs().iter().map(|out| {
+            (
+                ShieldedPool::Sapling,
+                out.index() as u32,
+                out.transfer_type(),
+                out.note_value().into_u64(),
+                out.memo(),
+                Some(encode_payment_address_p(
+                    &self.network,
+                    &out.note().recipient(),
+                )),
+                addresses::sapling_diversifier_index(&uivk, &out.note().recipient()),
+            )
+        });
+        #[cfg(feature = "orchard")]
+        let outputs = sapling_outputs.chain(decrypted.orchard_outputs().iter().map(|out| {
+            (
+                ShieldedPool::Orchard,
+                out.index() as u32,
+                out.transfer_type(),
+                out.note_value().into_u64(),
+                out.memo(),
+                UnifiedAddress::from_receivers(Some(out.note().recipient()), None, None)
+                    .map(|ua| ua.encode(&self.network)),
+                addresses::orchard_diversifier_index(&uivk, &out.note().recipient()),
+            )
+        }));
+        #[cfg(not(feature = "orchard"))]
+        let outputs = sapling_outputs;
+
+        let mut fee_zat = fee_paid(tx);
+        let is_coinbase = tx
+            .transparent_bundle()
+            .is_some_and(|bundle| bundle.is_coinbase());
+
+        let key_id = request.key_id();
+        let mut transactions = Vec::new();
+        for (pool, index, transfer_type, value, memo, address, diversifier_index) in outputs {
+            if !request.options.includes_value(value) || !request.options.includes_pool(pool) {
+                continue;
+            }
+            let is_change = transfer_type == TransferType::WalletInternal;
+            if is_change && !request.options.include_change {
+                continue;
+            }
+            let direction = match transfer_type {
+                TransferType::Outgoing => TxDirection::Out,
+                _ => TxDirection::In,
+            };
+            if direction == TxDirection::In
+                && !request.options.includes_diversifier(diversifier_index)
+            {
+                continue;
+            }
+            let scope = match transfer_type {
+                TransferType::Incoming => Some(KeyScope::External),
+                TransferType::WalletInternal => Some(KeyScope::Internal),
+                TransferType::Outgoing => None,
+            };
+            let memo = decode_memo(memo);
+            let invoice_id = match direction {
+                TxDirection::In => {
+                    invoice::matching_id(memo.as_deref(), &request.options.invoice_ids)
+                }
+                TxDirection::Out => None,
+            };
+            transactions.push(ZecTransaction {
+                txid: txid_hex.clone(),
+                height,
+                time: 0,
+                amount_zat: value.to_string(),
+                direction,
+                memo,
+                key_id: key_id.to_string(),
+                pool,
+                is_change,
+                address: address.clone().filter(|_| direction == TxDirection::In),
+                recipient_address: address.filter(|_| direction == TxDirection::Out),
+                fee_zat: match direction {
+                    TxDirection::Out => fee_zat.take(),
+                    TxDirection::In => None,
+                },
+                expiry_height: Some(u64::from(u32::from(tx.expiry_height()))),
+                fiat_value: None,
+                fiat_currency: None,
+                diversifier_index,
+                output_index: (pool == ShieldedPool::Sapling).then_some(index),
+                action_index: (pool == ShieldedPool::Orchard).then_some(index),
+                unconfirmed: false,
+                scope: scope.filter(|_| request.options.include_change),
+                account_id: None,
+                invoice_id,
+                note_position: None,
+                is_coinbase: is_coinbase && direction == TxDirection::In,
+                self_transfer: false,
+                likely_spam: false,
+            });
         }
-    });
 
-    Ok(compact_formats::CompactBlock {
-        proto_version: block.proto_version,
-        height: block.height,
-        hash: decode_hex(&block.hash, "block hash")?,
-        prev_hash: decode_hex(&block.prev_hash, "block prevHash")?,
-        time: block.time,
-        header: Vec::new(),
-        vtx,
-        chain_metadata,
-    })
-}
+        if request.options.flag_spam {
+            let mut storms = DustStorms::default();
+            let outputs = tx
+                .sapling_bundle()
+                .map_or(0, |bundle| bundle.shielded_outputs().len())
+                + tx.orchard_bundle()
+                    .map_or(0, |bundle| bundle.actions().len());
+            storms.add(&txid_hex, outputs);
+            storms.flag(&mut transactions);
+        }
+        Ok(finish(transactions, &request.options))
+    }
 
-/// Convert our CompactTx type to the protobuf format.
-fn map_compact_tx(tx: &CompactTx) -> ScanResult<compact_formats::CompactTx> {
-    let hash = decode_hex(&tx.txid, "txid")?;
+    /// Backfill memos from a full (non-compact) transaction.
+    ///
+    /// Compact blocks only carry the first 52 bytes of each note ciphertext, so
+    /// memos can't be recovered during [`Scanner::scan`]. Given the raw bytes of
+    /// a transaction mined at `height`, this decrypts its outputs with the viewing
+    /// key and fills in `memo` on matching entries in `transactions` (same txid,
+    /// pool, amount and output or action index if recorded, memo not yet set).
+    ///
+    /// Returns the number of transactions that received a memo.
+    pub fn decrypt_memos(
+        &self,
+        viewing_key: &str,
+        raw_tx: &[u8],
+        height: u64,
+        transactions: &mut [ZecTransaction],
+    ) -> ScanResult<usize> {
+        let ufvk = self.decode_viewing_key(viewing_key)?;
+        let height = block_height(height)?;
+        let tx = self.read_transaction(raw_tx, height)?;
+        let txid_hex = hex::encode(tx.txid().as_ref());
 
-    let spends = tx
-        .spends
-        .iter()
-        .map(|s| {
-            Ok(compact_formats::CompactSaplingSpend {
-                nf: decode_hex(&s.nf, "sapling spend nf")?,
-            })
-        })
-        .collect::<ScanResult<Vec<_>>>()?;
+        let ufvks = HashMap::from([(0u32, ufvk)]);
+        let decrypted = decrypt_transaction(&self.network, Some(height), None, &tx, &ufvks);
 
-    let outputs = tx
-        .outputs
-        .iter()
-        .map(|o| {
-            Ok(compact_formats::CompactSaplingOutput {
-                cmu: decode_hex(&o.cmu, "sapling output cmu")?,
-                ephemeral_key: decode_hex(&o.ephemeral_key, "sapling output ephemeralKey")?,
-                ciphertext: decode_hex(&o.ciphertext, "sapling output ciphertext")?,
-            })
-        })
-        .collect::<ScanResult<Vec<_>>>()?;
+        let sapling_memos = decrypted
+            .sapling_outputs()
+            .iter()
+            .filter(|out| out.transfer_type() != TransferType::Outgoing)
+            .map(|out| {
+                (
+                    ShieldedPool::Sapling,
+                    out.index() as u32,
+                    out.note_value().into_u64(),
+                    out.memo(),
+                )
+            });
+        #[cfg(feature = "orchard")]
+        let memos = sapling_memos.chain(
+            decrypted
+                .orchard_outputs()
+                .iter()
+                .filter(|out| out.transfer_type() != TransferType::Outgoing)
+                .map(|out| {
+                    (
+                        ShieldedPool::Orchard,
+                        out.index() as u32,
+                        out.note_value().into_u64(),
+                        out.memo(),
+                    )
+                }),
+        );
+        #[cfg(not(feature = "orchard"))]
+        let memos = sapling_memos;
 
-    let actions = tx
-        .actions
-        .iter()
+        let mut filled = 0;
+        for (pool, index, value, memo) in memos {
+            let Some(text) = decode_memo(memo) else {
+                continue;
+            };
+            let value = value.to_string();
+            if let Some(entry) = transactions.iter_mut().find(|t| {
+                t.memo.is_none()
+                    && t.direction == TxDirection::In
+                    && t.pool == pool
+                    && t.txid == txid_hex
+                    && t.amount_zat == value
+                    && t.output_index.or(t.action_index).is_none_or(|i| i == index)
+            }) {
+                entry.memo = Some(text);
+                filled += 1;
+            }
+        }
+
+        Ok(filled)
+    }
+
+    /// Enrich results of a compact scan with what only the full
+    /// transactions carry, fetched from `source`.
+    ///
+    /// The transactions in `transactions` are fetched once each, in batches
+    /// of [`ENHANCE_BATCH_SIZE`], and decrypted with the viewing key.
+    /// Incoming entries get their `memo` and `address`, outgoing ones their
+    /// `fee_zat` and, when the transaction paid a single recipient, its
+    /// `recipient_address` and `memo`; every entry gets its
+    /// `expiry_height`. Fields already set are kept, and transactions the
+    /// source doesn't return are left as they are. Wrap the source in a
+    /// [`CachedTransactionSource`](crate::CachedTransactionSource) to avoid
+    /// fetching again when enhancing overlapping results.
+    ///
+    /// Returns the number of transactions that were enhanced.
+    pub fn enhance<S: TransactionSource>(
+        &self,
+        viewing_key: &str,
+        transactions: &mut [ZecTransaction],
+        mut source: S,
+    ) -> ScanResult<usize> {
+        // Unique txids, with the height to parse each at
+        let mut heights: HashMap<&str, u64> = HashMap::new();
+        let mut txids = Vec::new();
+        for tx in transactions.iter() {
+            if let Entry::Vacant(entry) = heights.entry(&tx.txid) {
+                entry.insert(match tx.height {
+                    0 => mempool_height(&self.network),
+                    height => height,
+                });
+                txids.push(tx.txid.clone());
+            }
+        }
+
+        let request = ScanRequest {
+            viewing_key: viewing_key.to_string(),
+            key_id: String::new(),
+            compact_blocks: Vec::new(),
+            options: ScanOptions {
+                include_change: true,
+                ..ScanOptions::default()
+            },
+            nullifiers: Default::default(),
+        };
+        let mut full = HashMap::new();
+        for batch in txids.chunks(ENHANCE_BATCH_SIZE) {
+            for raw in source.get_transactions(batch)? {
+                let Some(&height) = heights.get(raw.txid.as_str()) else {
+                    continue;
+                };
+                let tx = self.read_transaction(&raw.data, block_height(height)?)?;
+                let txid = hex::encode(tx.txid().as_ref());
+                if txid != raw.txid {
+                    return Err(ScanError::InvalidTransaction(format!(
+                        "source returned transaction {txid} for {}",
+                        raw.txid
+                    )));
+                }
+                let expiry_height = u64::from(u32::from(tx.expiry_height()));
+                let entries = self.transaction_entries(&request, &tx, height)?;
+                full.insert(txid, (expiry_height, fee_paid(&tx), entries));
+            }
+        }
+
+        let mut enhanced = 0;
+        for tx in transactions.iter_mut() {
+            let Some((expiry_height, fee_zat, entries)) = full.get_mut(&tx.txid) else {
+                continue;
+            };
+            tx.expiry_height = Some(*expiry_height);
+            match tx.direction {
+                TxDirection::In => {
+                    let index = tx.output_index.or(tx.action_index);
+                    // Taken, so equal outputs match one entry each
+                    let found = entries.iter().position(|full| {
+                        full.direction == TxDirection::In
+                            && full.pool == tx.pool
+                            && full.amount_zat == tx.amount_zat
+                            && index
+                                .is_none_or(|i| full.output_index.or(full.action_index) == Some(i))
+                    });
+                    if let Some(full) = found.map(|i| entries.remove(i)) {
+                        tx.memo = tx.memo.take().or(full.memo);
+                        tx.address = tx.address.take().or(full.address);
+                    }
+                }
+                TxDirection::Out => {
+                    tx.fee_zat = tx.fee_zat.take().or_else(|| fee_zat.clone());
+                    let mut payments = entries
+                        .iter()
+                        .filter(|full| full.direction == TxDirection::Out);
+                    if let (Some(payment), None) = (payments.next(), payments.next()) {
+                        tx.recipient_address = tx
+                            .recipient_address
+                            .take()
+                            .or_else(|| payment.recipient_address.clone());
+                        tx.memo = tx.memo.take().or_else(|| payment.memo.clone());
+                    }
+                }
+            }
+            enhanced += 1;
+        }
+
+        Ok(enhanced)
+    }
+
+    /// Estimate a height to start scanning from for a wallet created at
+    /// `time` (Unix seconds), so its scan needn't start at Sapling
+    /// activation.
+    ///
+    /// The estimate errs early by about a week, so an approximate creation
+    /// date is fine. Only mainnet has checkpoints; other networks always
+    /// start at Sapling activation.
+    pub fn estimate_birthday(&self, time: i64) -> u64 {
+        birthday::estimate_birthday(&self.network, time)
+    }
+
+    /// Derive the default unified address of a viewing key: the one at the
+    /// first diversifier index that yields a receiver for each of its pools.
+    pub fn default_address(&self, viewing_key: &str) -> ScanResult<DerivedAddress> {
+        let ufvk = self.decode_viewing_key(viewing_key)?;
+        addresses::default_address(&self.network, &ufvk)
+    }
+
+    /// Derive the unified address of a viewing key at diversifier `index`.
+    ///
+    /// Returns `None` if there's no address at that index: about half of all
+    /// indices don't give a valid Sapling diversifier, so keys with a Sapling
+    /// component skip them.
+    pub fn address_at(&self, viewing_key: &str, index: u64) -> ScanResult<Option<DerivedAddress>> {
+        let ufvk = self.decode_viewing_key(viewing_key)?;
+        addresses::address_at(&self.network, &ufvk, index)
+    }
+
+    /// Derive the first `count` unified addresses of a viewing key, at
+    /// ascending valid diversifier indices starting from 0.
+    ///
+    /// Hand out one per customer and scan with
+    /// [`ScanOptions::diversifier_indices`] (or group results by
+    /// [`ZecTransaction::diversifier_index`]) to account deposits per address.
+    pub fn addresses(&self, viewing_key: &str, count: usize) -> ScanResult<Vec<DerivedAddress>> {
+        let ufvk = self.decode_viewing_key(viewing_key)?;
+        addresses::derive_addresses(&self.network, &ufvk, count)
+    }
+
+    /// Derive the first `count` external (receiving) transparent addresses of
+    /// a viewing key.
+    ///
+    /// Fails with [`ScanError::InvalidViewingKey`] if the key has no
+    /// transparent component.
+    #[cfg(feature = "transparent")]
+    pub fn transparent_addresses(&self, viewing_key: &str, count: u32) -> ScanResult<Vec<String>> {
+        let ufvk = self.decode_viewing_key(viewing_key)?;
+        let addresses =
+            transparent::derive_addresses(&self.network, &ufvk, Scope::External, count)?;
+        Ok(addresses.into_iter().map(|(_, address)| address).collect())
+    }
+
+    /// Pick out the transparent outputs (e.g. UTXOs from lightwalletd's
+    /// `GetAddressUtxos`) that pay the viewing key.
+    ///
+    /// Outputs are matched by address against the first
+    /// [`TRANSPARENT_ADDRESS_LOOKAHEAD`](crate::TRANSPARENT_ADDRESS_LOOKAHEAD)
+    /// external and internal addresses, and returned as incoming
+    /// [`ShieldedPool::Transparent`] transactions. Outputs to internal (change)
+    /// addresses are skipped unless `include_change` is set. `request` supplies
+    /// the viewing key, key id and options — its `compact_blocks` are ignored.
+    #[cfg(feature = "transparent")]
+    pub fn scan_transparent(
+        &self,
+        request: &ScanRequest,
+        outputs: &[TransparentOutput],
+    ) -> ScanResult<Vec<ZecTransaction>> {
+        let ufvk = self.decode_viewing_key(&request.viewing_key)?;
+        let addresses = transparent::address_map(
+            &self.network,
+            &ufvk,
+            transparent::TRANSPARENT_ADDRESS_LOOKAHEAD,
+        )?;
+
+        let key_id = request.key_id();
+        let mut transactions = Vec::new();
+        for output in outputs {
+            let Some(scope) = addresses.get(&output.address) else {
+                continue;
+            };
+            let is_change = *scope == Scope::Internal;
+            if is_change && !request.options.include_change {
+                continue;
+            }
+            transactions.push(ZecTransaction {
+                txid: output.txid.clone(),
+                height: output.height,
+                time: output.time,
+                amount_zat: output.value_zat.clone(),
+                direction: TxDirection::In,
+                memo: None,
+                key_id: key_id.to_string(),
+                pool: ShieldedPool::Transparent,
+                is_change,
+                address: Some(output.address.clone()),
+                recipient_address: None,
+                fee_zat: None,
+                expiry_height: None,
+                fiat_value: None,
+                fiat_currency: None,
+                diversifier_index: None,
+                output_index: Some(output.index),
+                action_index: None,
+                unconfirmed: false,
+                scope: request.options.include_change.then_some((*scope).into()),
+                account_id: None,
+                invoice_id: None,
+                note_position: None,
+                is_coinbase: false,
+                self_transfer: false,
+                likely_spam: false,
+            });
+        }
+        Ok(finish(transactions, &request.options))
+    }
+
+    /// Parse a raw transaction under the consensus rules at `height`.
+    pub(crate) fn read_transaction(
+        &self,
+        raw_tx: &[u8],
+        height: BlockHeight,
+    ) -> ScanResult<Transaction> {
+        Transaction::read(raw_tx, BranchId::for_height(&self.network, height))
+            .map_err(|e| ScanError::InvalidTransaction(e.to_string()))
+    }
+
+    /// Decode a viewing key string for this scanner's network.
+    ///
+    /// Sapling-only keys, which older wallets export, are scanned as a UFVK
+    /// holding just the Sapling component.
+    fn decode_viewing_key(&self, raw: &str) -> ScanResult<UnifiedFullViewingKey> {
+        // Normalize viewing key (strip any |uivk... suffix)
+        let viewing_key = normalize_viewing_key(raw);
+
+        if viewing_key.starts_with("uivk") {
+            return Err(ScanError::invalid_viewing_key(
+                &viewing_key,
+                "a UIVK can only be scanned for incoming notes; this needs a full viewing key",
+            ));
+        }
+        self.check_key_network(&viewing_key)?;
+        let sapling = if viewing_key.starts_with("zxview") {
+            let hrp = self.network.hrp_sapling_extended_full_viewing_key();
+            Some(
+                decode_extended_full_viewing_key(hrp, &viewing_key)
+                    .map(|extfvk| extfvk.to_diversifiable_full_viewing_key())
+                    .map_err(|e| format!("not a Sapling viewing key for {hrp}: {e}")),
+            )
+        } else if is_raw_dfvk(&viewing_key) {
+            Some(decode_raw_dfvk(&viewing_key))
+        } else {
+            None
+        };
+        let ufvk = match sapling {
+            Some(dfvk) => dfvk.and_then(|dfvk| sapling_ufvk(&dfvk)),
+            None => UnifiedFullViewingKey::decode(&self.network, &viewing_key),
+        };
+        ufvk.map_err(|e| ScanError::invalid_viewing_key(&viewing_key, e))
+    }
+
+    /// Decode a viewing key string to scan with: a full viewing key as
+    /// [`Scanner::decode_viewing_key`] does, along with its external
+    /// incoming viewing keys, or a UIVK alone.
+    fn decode_scan_key(
+        &self,
+        raw: &str,
+    ) -> ScanResult<(Option<UnifiedFullViewingKey>, UnifiedIncomingViewingKey)> {
+        let viewing_key = normalize_viewing_key(raw);
+        if viewing_key.starts_with("uivk") {
+            self.check_key_network(&viewing_key)?;
+            let uivk = UnifiedIncomingViewingKey::decode(&self.network, &viewing_key)
+                .map_err(|e| ScanError::invalid_viewing_key(&viewing_key, e))?;
+            return Ok((None, uivk));
+        }
+        let ufvk = self.decode_viewing_key(&viewing_key)?;
+        let uivk = ufvk.to_unified_incoming_viewing_key();
+        Ok((Some(ufvk), uivk))
+    }
+
+    /// Fail with [`ScanError::NetworkMismatch`] if `viewing_key`'s prefix
+    /// is another network's.
+    fn check_key_network(&self, viewing_key: &str) -> ScanResult<()> {
+        let scanner_network = self.network.network_type();
+        match encoded_network(viewing_key) {
+            Some(network) if network != scanner_network => Err(ScanError::NetworkMismatch {
+                key_network: network.into(),
+                scanner_network: scanner_network.into(),
+            }),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// The network a viewing key's human-readable prefix is for, if it is a
+/// prefix of Unified or Sapling viewing keys. Only the prefix is read, so a
+/// key mistyped after it still reports its network.
+fn encoded_network(viewing_key: &str) -> Option<NetworkType> {
+    let (hrp, _) = viewing_key.rsplit_once('1')?;
+    [
+        (
+            NetworkType::Main,
+            mainnet::HRP_UNIFIED_FVK,
+            mainnet::HRP_UNIFIED_IVK,
+        ),
+        (
+            NetworkType::Test,
+            testnet::HRP_UNIFIED_FVK,
+            testnet::HRP_UNIFIED_IVK,
+        ),
+        (
+            NetworkType::Regtest,
+            regtest::HRP_UNIFIED_FVK,
+            regtest::HRP_UNIFIED_IVK,
+        ),
+    ]
+    .into_iter()
+    .find(|&(network, ufvk, uivk)| {
+        hrp.eq_ignore_ascii_case(ufvk)
+            || hrp.eq_ignore_ascii_case(uivk)
+            || hrp.eq_ignore_ascii_case(network.hrp_sapling_extended_full_viewing_key())
+    })
+    .map(|(network, ..)| network)
+}
+
+/// Builder for a [`Scanner`] with non-default settings.
+///
+/// ```rust,ignore
+/// let scanner = Scanner::builder()
+///     .network(Network::TestNetwork)
+///     .range_chunk_size(500)
+///     .build();
+/// ```
+#[derive(Debug, Clone)]
+pub struct ScannerBuilder<P = Network> {
+    network: P,
+    range_chunk_size: u64,
+    batch_size: usize,
+    #[cfg(feature = "rayon")]
+    threads: usize,
+}
+
+impl ScannerBuilder {
+    /// Start from the defaults: mainnet, 1,000-block range chunks and
+    /// batches of [`DEFAULT_BATCH_SIZE`] outputs.
+    pub fn new() -> Self {
+        Self {
+            network: Network::MainNetwork,
+            range_chunk_size: DEFAULT_RANGE_CHUNK_SIZE,
+            batch_size: DEFAULT_BATCH_SIZE,
+            #[cfg(feature = "rayon")]
+            threads: 0,
+        }
+    }
+}
+
+impl Default for ScannerBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<P: Parameters + Send + 'static> ScannerBuilder<P> {
+    /// Scan mainnet or testnet.
+    pub fn network(self, network: Network) -> ScannerBuilder<Network> {
+        self.params(network)
+    }
+
+    /// Scan the network `viewing_key` is encoded for, mainnet or testnet,
+    /// instead of choosing one up front.
+    ///
+    /// Fails with [`ScanError::InvalidViewingKey`] if the key's prefix isn't
+    /// a viewing key's (raw Sapling keys don't say which network they're
+    /// for), or is regtest's: a regtest chain needs its own parameters,
+    /// given with [`ScannerBuilder::params`].
+    pub fn network_for_key(self, viewing_key: &str) -> ScanResult<ScannerBuilder<Network>> {
+        let viewing_key = normalize_viewing_key(viewing_key);
+        let network = match encoded_network(&viewing_key) {
+            Some(NetworkType::Main) => Network::MainNetwork,
+            Some(NetworkType::Test) => Network::TestNetwork,
+            Some(NetworkType::Regtest) => {
+                return Err(ScanError::invalid_viewing_key(
+                    &viewing_key,
+                    "a regtest key needs the regtest network's parameters",
+                ))
+            }
+            None => {
+                return Err(ScanError::invalid_viewing_key(
+                    &viewing_key,
+                    "can't tell which network the key is for",
+                ))
+            }
+        };
+        Ok(self.network(network))
+    }
+
+    /// Scan a chain with custom consensus parameters; see
+    /// [`Scanner::with_params`].
+    pub fn params<Q: Parameters + Send + 'static>(self, params: Q) -> ScannerBuilder<Q> {
+        ScannerBuilder {
+            network: params,
+            range_chunk_size: self.range_chunk_size,
+            batch_size: self.batch_size,
+            #[cfg(feature = "rayon")]
+            threads: self.threads,
+        }
+    }
+
+    /// Number of blocks [`Scanner::scan_range`] requests from its source at
+    /// a time (at least 1). Larger chunks mean fewer round trips but more
+    /// blocks held in memory.
+    pub fn range_chunk_size(mut self, blocks: u64) -> Self {
+        self.range_chunk_size = blocks.max(1);
+        self
+    }
+
+    /// Number of outputs trial-decrypted together (at least 1; by default
+    /// [`DEFAULT_BATCH_SIZE`]). A request's
+    /// [`ScanOptions::batch_size`] overrides it.
+    ///
+    /// Blocks are gathered until they hold this many outputs, and their
+    /// outputs decrypted in one batch before the blocks are scanned in
+    /// order. Larger batches amortize more of the decryption cost but hold
+    /// more blocks in memory. With the `rayon` feature, each batch is split
+    /// evenly between the [threads](ScannerBuilder::threads), in tasks of at
+    /// least 256 outputs, so a batch needs 256 outputs per thread to use
+    /// them all.
+    pub fn batch_size(mut self, outputs: usize) -> Self {
+        self.batch_size = outputs.max(1);
+        self
+    }
+
+    /// Number of threads each batch is trial-decrypted on.
+    ///
+    /// The scanner gets a rayon thread pool of its own with this many
+    /// threads; with 0 (the default) it shares rayon's global pool, which
+    /// has one thread per CPU. Results don't depend on the thread count.
+    #[cfg(feature = "rayon")]
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = threads;
+        self
+    }
+
+    /// Create the scanner.
+    pub fn build(self) -> Scanner<P> {
+        Scanner {
+            network: self.network,
+            range_chunk_size: self.range_chunk_size,
+            batch_size: self.batch_size,
+            #[cfg(feature = "rayon")]
+            threads: Threads::new(self.threads),
+            #[cfg(not(feature = "rayon"))]
+            threads: Threads::default(),
+        }
+    }
+}
+
+type AccountId = u32;
+
+/// A viewing key decoded and prepared for scanning.
+///
+/// Created by [`Scanner::prepare_key`].
+pub struct PreparedKey {
+    // `None` for a UIVK
+    ufvk: Option<UnifiedFullViewingKey>,
+    // External incoming viewing keys, for diversifier indexes
+    uivk: UnifiedIncomingViewingKey,
+    scanning_keys: ScanningKeys<AccountId, (AccountId, Scope)>,
+    decryptor: BatchDecryptor,
+}
+
+impl PreparedKey {
+    fn full(ufvk: UnifiedFullViewingKey) -> Self {
+        Self {
+            scanning_keys: ScanningKeys::from_account_ufvks(std::iter::once((0u32, ufvk.clone()))),
+            decryptor: BatchDecryptor::new(&ufvk),
+            uivk: ufvk.to_unified_incoming_viewing_key(),
+            ufvk: Some(ufvk),
+        }
+    }
+
+    /// Whether the key is a UIVK, which finds only the notes sent to its
+    /// addresses: without the nullifier and outgoing viewing keys, spends
+    /// (and so outgoing transactions) and change go undetected.
+    pub fn incoming_only(&self) -> bool {
+        self.ufvk.is_none()
+    }
+}
+
+/// A UIVK's incoming viewing key for one pool, scanned for the notes sent
+/// to its addresses. Without a nullifier key, their nullifiers aren't
+/// known.
+struct IncomingKey<K>(K);
+
+impl ScanningKeyOps<sapling::note_encryption::SaplingDomain, AccountId, sapling::Nullifier>
+    for IncomingKey<sapling::zip32::IncomingViewingKey>
+{
+    fn prepare(&self) -> sapling::note_encryption::PreparedIncomingViewingKey {
+        self.0.prepare()
+    }
+
+    fn account_id(&self) -> &AccountId {
+        &0
+    }
+
+    fn key_scope(&self) -> Option<Scope> {
+        Some(Scope::External)
+    }
+
+    fn nf(
+        &self,
+        _note: &sapling::Note,
+        _position: incrementalmerkletree::Position,
+    ) -> Option<sapling::Nullifier> {
+        None
+    }
+}
+
+#[cfg(feature = "orchard")]
+impl ScanningKeyOps<orchard::note_encryption::OrchardDomain, AccountId, orchard::note::Nullifier>
+    for IncomingKey<orchard::keys::IncomingViewingKey>
+{
+    fn prepare(&self) -> orchard::keys::PreparedIncomingViewingKey {
+        orchard::keys::PreparedIncomingViewingKey::new(&self.0)
+    }
+
+    fn account_id(&self) -> &AccountId {
+        &0
+    }
+
+    fn key_scope(&self) -> Option<Scope> {
+        Some(Scope::External)
+    }
+
+    fn nf(
+        &self,
+        _note: &orchard::Note,
+        _position: incrementalmerkletree::Position,
+    ) -> Option<orchard::note::Nullifier> {
+        None
+    }
+}
+
+/// Scans sharing the viewing keys they prepare.
+///
+/// Each [`Scanner`] scan method decodes the request's viewing key and
+/// prepares its incoming viewing keys and nullifier keys from scratch. A
+/// session does so the first time it sees a key and reuses the result for
+/// later requests with the same key, which for callers scanning many small
+/// batches can cost more than the scanning itself.
+///
+/// Created by [`Scanner::session`].
+pub struct ScanSession<'a, P = Network> {
+    scanner: &'a Scanner<P>,
+    // Keyed by the normalized viewing key string
+    keys: HashMap<String, PreparedKey>,
+}
+
+impl<P: Parameters + Send + 'static> ScanSession<'_, P> {
+    /// The prepared form of `viewing_key`, preparing it on first use.
+    ///
+    /// Keys that fail to decode aren't remembered.
+    pub fn key(&mut self, viewing_key: &str) -> ScanResult<&PreparedKey> {
+        match self.keys.entry(normalize_viewing_key(viewing_key)) {
+            Entry::Occupied(entry) => Ok(entry.into_mut()),
+            Entry::Vacant(entry) => {
+                let key = self.scanner.prepare_key(entry.key())?;
+                Ok(entry.insert(key))
+            }
+        }
+    }
+
+    /// Number of distinct keys prepared so far.
+    pub fn prepared_keys(&self) -> usize {
+        self.keys.len()
+    }
+
+    /// Forget every prepared key.
+    pub fn clear(&mut self) {
+        self.keys.clear();
+    }
+
+    /// Scan compact blocks like [`Scanner::scan`].
+    pub fn scan(&mut self, request: &ScanRequest) -> ScanResult<Vec<ZecTransaction>> {
+        self.scan_with_progress(request, &mut NoProgress)
+    }
+
+    /// Scan compact blocks like [`Scanner::scan_with_progress`].
+    pub fn scan_with_progress(
+        &mut self,
+        request: &ScanRequest,
+        progress: &mut dyn ProgressSink,
+    ) -> ScanResult<Vec<ZecTransaction>> {
+        let scanner = self.scanner;
+        let mut transactions = Vec::new();
+        scanner.scan_blocks(
+            self.key(&request.viewing_key)?,
+            request,
+            request.compact_blocks.iter().map(map_compact_block),
+            &mut ScanState::default(),
+            progress,
+            None,
+            &mut |block| transactions.extend(block.transactions),
+        )?;
+        Ok(transactions)
+    }
+
+    /// Scan compact blocks like [`Scanner::scan_with_state`].
+    pub fn scan_with_state(
+        &mut self,
+        request: &ScanRequest,
+        state: &mut ScanState,
+    ) -> ScanResult<Vec<ZecTransaction>> {
+        let scanner = self.scanner;
+        let key = self.key(&request.viewing_key)?;
+        scanner.scan_with_key(key, request, state)
+    }
+}
+
+/// Scanning state threaded from one block to the next.
+struct BlockScanner<'a, P> {
+    network: &'a P,
+    key_id: Cow<'a, str>,
+    options: &'a ScanOptions,
+    uivk: &'a UnifiedIncomingViewingKey,
+    scanning_keys: &'a ScanningKeys<AccountId, (AccountId, Scope)>,
+    // Replaces `scanning_keys` when the scan is limited to some pools
+    pool_keys: Option<ScanningKeys<AccountId, (AccountId, Scope)>>,
+    // Used for blocks that batch decryption found no notes in
+    no_keys: ScanningKeys<AccountId, (AccountId, Scope)>,
+    // We don't hand our nullifiers to `scan_block`; with an empty set every
+    // revealed nullifier ends up in the block's nullifier map, which we match
+    // against the notes discovered so far.
+    nullifiers: Nullifiers<AccountId>,
+    tracked_notes: HashMap<(ShieldedPool, [u8; 32]), TrackedNote>,
+    prior_meta: Option<BlockMetadata>,
+    // Set after a lenient scan skips a block, so the next one is scanned
+    // from its own chain metadata rather than linked to `prior_meta`
+    resync: bool,
+    witnesses: Option<&'a mut WitnessTracker>,
+}
+
+impl<'a, P: Parameters + Send + 'static> BlockScanner<'a, P> {
+    fn new(
+        network: &'a P,
+        key: &'a PreparedKey,
+        request: &'a ScanRequest,
+        state: &ScanState,
+        witnesses: Option<&'a mut WitnessTracker>,
+    ) -> ScanResult<Self> {
+        let tracked_notes = state
+            .tracked_notes
+            .iter()
+            .chain(request.nullifiers.notes())
+            .map(|note| {
+                let nf = decode_hex32(&note.nullifier, "tracked note nullifier")?;
+                Ok(((note.pool, nf), note.clone()))
+            })
+            .collect::<ScanResult<HashMap<_, _>>>()?;
+        let prior_meta = state
+            .last_block
+            .as_ref()
+            .map(BlockCheckpoint::to_metadata)
+            .transpose()?;
+
+        Ok(Self {
+            network,
+            key_id: request.key_id(),
+            options: &request.options,
+            uivk: &key.uivk,
+            scanning_keys: &key.scanning_keys,
+            pool_keys: match &key.ufvk {
+                Some(ufvk) => pool_scanning_keys(network, ufvk, &request.options)?,
+                None => incoming_pool_keys(&key.uivk, &request.options),
+            },
+            no_keys: ScanningKeys::empty(),
+            nullifiers: Nullifiers::empty(),
+            tracked_notes,
+            prior_meta,
+            resync: false,
+            witnesses,
+        })
+    }
+
+    /// Scan the next block without linking it to the last one scanned.
+    fn resync(&mut self) {
+        self.resync = true;
+    }
+
+    /// Record the current position and tracked notes into `state`.
+    fn save_state(&self, state: &mut ScanState) {
+        state.last_block = self.prior_meta.as_ref().map(BlockCheckpoint::from_metadata);
+        state.tracked_notes = self.tracked_notes.values().cloned().collect();
+        state
+            .tracked_notes
+            .sort_by(|a, b| a.nullifier.cmp(&b.nullifier));
+    }
+
+    /// Scan a single block, tracking discovered notes for spend detection.
+    ///
+    /// Without `has_notes`, outputs aren't trial-decrypted; spends, tree
+    /// sizes and commitments are still processed.
+    fn scan_block(
+        &mut self,
+        block: compact_formats::CompactBlock,
+        has_notes: bool,
+    ) -> ScanResult<BlockScanResult> {
+        let hash = hex::encode(&block.hash);
+        // `scan_block` drops fees, so note them while we still have the block
+        let fees: HashMap<Vec<u8>, u32> = block
+            .vtx
+            .iter()
+            .filter(|tx| tx.fee > 0)
+            .map(|tx| (tx.hash.clone(), tx.fee))
+            .collect();
+        let mut storms = self.options.flag_spam.then(|| DustStorms::in_block(&block));
+        let scanned = scan_block(
+            self.network,
+            block,
+            match (has_notes, &self.pool_keys) {
+                (false, _) => &self.no_keys,
+                (true, Some(keys)) => keys,
+                (true, None) => self.scanning_keys,
+            },
+            &self.nullifiers,
+            self.prior_meta.as_ref().filter(|_| !self.resync),
+        )
+        .map_err(|e| ScanError::ScanFailed {
+            height: e.at_height().into(),
+            message: e.to_string(),
+        })?;
+
+        let height: u32 = scanned.height().into();
+        let height = height as u64;
+        let time = scanned.block_time() as i64;
+        let mut transactions = Vec::new();
+        let mut received_notes = Vec::new();
+        let mut decrypted_notes = Vec::new();
+        let mut spent_notes = Vec::new();
+        // Change received per transaction, netted against its spends below
+        let mut change: HashMap<TxId, u64> = HashMap::new();
+
+        for wtx in scanned.transactions() {
+            let txid = wtx.txid();
+            let txid_hex = hex::encode(txid.as_ref());
+
+            // Process Sapling outputs
+            for out in wtx.sapling_outputs() {
+                let note = out.note();
+                let v = note.value().inner();
+                if !self.options.includes_value(v) {
+                    continue;
+                }
+                let address = encode_payment_address_p(self.network, &note.recipient());
+                let diversifier_index =
+                    addresses::sapling_diversifier_index(self.uivk, &note.recipient());
+                let is_change = is_change(out.recipient_key_scope());
+                decrypted_notes.push(DecryptedNote {
+                    nullifier: out.nf().map(|nf| hex::encode(nf.0)),
+                    position: Some(out.note_commitment_tree_position().into()),
+                    is_change,
+                    ..DecryptedNote::sapling(note, &txid_hex, height, out.index() as u32)
+                });
+                // Change notes are still ours, so track them for spends
+                if let Some(nf) = out.nf() {
+                    let note = TrackedNote {
+                        pool: ShieldedPool::Sapling,
+                        nullifier: hex::encode(nf.0),
+                        value_zat: v.to_string(),
+                        height,
+                        txid: txid_hex.clone(),
+                        position: Some(out.note_commitment_tree_position().into()),
+                    };
+                    self.tracked_notes
+                        .insert((ShieldedPool::Sapling, nf.0), note.clone());
+                    received_notes.push(note);
+                }
+                if is_change {
+                    *change.entry(txid).or_default() += v;
+                    if !self.options.include_change {
+                        continue;
+                    }
+                }
+                if !self.options.includes_diversifier(diversifier_index) {
+                    continue;
+                }
+
+                transactions.push(ZecTransaction {
+                    txid: txid_hex.clone(),
+                    height,
+                    time,
+                    amount_zat: v.to_string(),
+                    direction: TxDirection::In,
+                    memo: None,
+                    key_id: self.key_id.to_string(),
+                    pool: ShieldedPool::Sapling,
+                    is_change,
+                    address: Some(address),
+                    recipient_address: None,
+                    fee_zat: None,
+                    expiry_height: None,
+                    fiat_value: None,
+                    fiat_currency: None,
+                    diversifier_index,
+                    output_index: Some(out.index() as u32),
+                    action_index: None,
+                    unconfirmed: false,
+                    scope: out
+                        .recipient_key_scope()
+                        .filter(|_| self.options.include_change)
+                        .map(Into::into),
+                    account_id: None,
+                    invoice_id: None,
+                    note_position: Some(out.note_commitment_tree_position().into()),
+                    is_coinbase: wtx.block_index() == 0,
+                    self_transfer: false,
+                    likely_spam: false,
+                });
+            }
+
+            // Process Orchard outputs
+            #[cfg(feature = "orchard")]
+            for out in wtx.orchard_outputs() {
+                let note = out.note();
+                let v: u64 = note.value().inner();
+                if !self.options.includes_value(v) {
+                    continue;
+                }
+                let address = UnifiedAddress::from_receivers(Some(note.recipient()), None, None)
+                    .map(|ua| ua.encode(self.network));
+                let diversifier_index =
+                    addresses::orchard_diversifier_index(self.uivk, &note.recipient());
+                let is_change = is_change(out.recipient_key_scope());
+                decrypted_notes.push(DecryptedNote {
+                    nullifier: out.nf().map(|nf| hex::encode(nf.to_bytes())),
+                    position: Some(out.note_commitment_tree_position().into()),
+                    is_change,
+                    ..DecryptedNote::orchard(note, &txid_hex, height, out.index() as u32)
+                });
+                // Change notes are still ours, so track them for spends
+                if let Some(nf) = out.nf() {
+                    let note = TrackedNote {
+                        pool: ShieldedPool::Orchard,
+                        nullifier: hex::encode(nf.to_bytes()),
+                        value_zat: v.to_string(),
+                        height,
+                        txid: txid_hex.clone(),
+                        position: Some(out.note_commitment_tree_position().into()),
+                    };
+                    self.tracked_notes
+                        .insert((ShieldedPool::Orchard, nf.to_bytes()), note.clone());
+                    received_notes.push(note);
+                }
+                if is_change {
+                    *change.entry(txid).or_default() += v;
+                    if !self.options.include_change {
+                        continue;
+                    }
+                }
+                if !self.options.includes_diversifier(diversifier_index) {
+                    continue;
+                }
+
+                transactions.push(ZecTransaction {
+                    txid: txid_hex.clone(),
+                    height,
+                    time,
+                    amount_zat: v.to_string(),
+                    direction: TxDirection::In,
+                    memo: None,
+                    key_id: self.key_id.to_string(),
+                    pool: ShieldedPool::Orchard,
+                    is_change,
+                    address,
+                    recipient_address: None,
+                    fee_zat: None,
+                    expiry_height: None,
+                    fiat_value: None,
+                    fiat_currency: None,
+                    diversifier_index,
+                    output_index: None,
+                    action_index: Some(out.index() as u32),
+                    unconfirmed: false,
+                    scope: out
+                        .recipient_key_scope()
+                        .filter(|_| self.options.include_change)
+                        .map(Into::into),
+                    account_id: None,
+                    invoice_id: None,
+                    note_position: Some(out.note_commitment_tree_position().into()),
+                    is_coinbase: wtx.block_index() == 0,
+                    self_transfer: false,
+                    likely_spam: false,
+                });
+            }
+        }
+
+        // Detect spends of previously discovered notes
+        let sapling_spends = scanned
+            .sapling()
+            .nullifier_map()
+            .iter()
+            .flat_map(|(txid, _, nfs)| {
+                nfs.iter()
+                    .map(move |nf| (*txid, ShieldedPool::Sapling, nf.0))
+            });
+        #[cfg(feature = "orchard")]
+        let spends = sapling_spends.chain(scanned.orchard().nullifier_map().iter().flat_map(
+            |(txid, _, nfs)| {
+                nfs.iter()
+                    .map(move |nf| (*txid, ShieldedPool::Orchard, nf.to_bytes()))
+            },
+        ));
+        #[cfg(not(feature = "orchard"))]
+        let spends = sapling_spends;
+
+        // Total spent per transaction, in order of each transaction's first spend
+        let mut outgoing: Vec<(TxId, ShieldedPool, u64)> = Vec::new();
+        for (txid, pool, nf) in spends {
+            if let Some(note) = self.tracked_notes.remove(&(pool, nf)) {
+                match outgoing.iter_mut().find(|(id, _, _)| *id == txid) {
+                    Some((_, _, spent)) => *spent += note.value_zatoshis(),
+                    None => outgoing.push((txid, pool, note.value_zatoshis())),
+                }
+                spent_notes.push(NoteSpend {
+                    note,
+                    spent_txid: hex::encode(txid.as_ref()),
+                    spent_height: height,
+                });
+            }
+        }
+
+        // One entry per transaction for the value that left the wallet
+        for (txid, pool, spent) in outgoing {
+            let net = spent.saturating_sub(change.get(&txid).copied().unwrap_or(0));
+            transactions.push(ZecTransaction {
+                txid: hex::encode(txid.as_ref()),
+                height,
+                time,
+                amount_zat: net.to_string(),
+                direction: TxDirection::Out,
+                memo: None,
+                key_id: self.key_id.to_string(),
+                pool,
+                is_change: false,
+                address: None,
+                recipient_address: None,
+                fee_zat: fees.get(&txid.as_ref()[..]).map(|fee| fee.to_string()),
+                expiry_height: None,
+                fiat_value: None,
+                fiat_currency: None,
+                diversifier_index: None,
+                output_index: None,
+                action_index: None,
+                unconfirmed: false,
+                scope: None,
+                account_id: None,
+                invoice_id: None,
+                note_position: None,
+                is_coinbase: false,
+                self_transfer: false,
+                likely_spam: false,
+            });
+        }
+
+        let (sapling_anchor, orchard_anchor) = match self.witnesses.as_deref_mut() {
+            Some(witnesses) => {
+                witnesses.apply_block(&scanned)?;
+                (
+                    witnesses.anchor(ShieldedPool::Sapling)?,
+                    witnesses.anchor(ShieldedPool::Orchard)?,
+                )
+            }
+            None => (None, None),
+        };
+
+        tag_self_transfers(&mut transactions, &spent_notes);
+        if let Some(storms) = &mut storms {
+            for spend in &spent_notes {
+                storms.exclude(&spend.spent_txid);
+            }
+            storms.flag(&mut transactions);
+        }
+        self.prior_meta = Some(scanned.to_block_metadata());
+        self.resync = false;
+
+        Ok(BlockScanResult {
+            height,
+            hash,
+            time,
+            transactions: finish(transactions, self.options),
+            received_notes,
+            decrypted_notes,
+            spent_notes,
+            sapling_anchor,
+            orchard_anchor,
+            skipped_outputs: 0,
+            spam_outputs: storms.as_ref().map_or(0, |storms| storms.outputs),
+            spam_ratio: storms.as_ref().map_or(0.0, DustStorms::ratio),
+        })
+    }
+}
+
+/// Whether a note received on `scope` is change: sent to one of the key's
+/// internal addresses, which only the wallet itself pays. `scan_block` only
+/// marks a note as change when it is handed the spent notes' nullifiers,
+/// which the scanner keeps to itself to match spends in every transaction.
+fn is_change(scope: Option<Scope>) -> bool {
+    scope == Some(Scope::Internal)
+}
+
+/// Tag the entries of transactions that spent the wallet's notes in one pool
+/// and received non-change notes in another as `self_transfer`.
+fn tag_self_transfers(transactions: &mut [ZecTransaction], spends: &[NoteSpend]) {
+    let spent_from = |txid: &str, pool: ShieldedPool| {
+        spends
+            .iter()
+            .any(|spend| spend.spent_txid == txid && spend.note.pool == pool)
+    };
+    let migrations: Vec<String> = transactions
+        .iter()
+        .filter(|tx| tx.direction == TxDirection::In && !tx.is_change)
+        .filter(|tx| spends.iter().any(|spend| spend.spent_txid == tx.txid))
+        .filter(|tx| !spent_from(&tx.txid, tx.pool))
+        .map(|tx| tx.txid.clone())
+        .collect();
+    for tx in transactions {
+        tx.self_transfer |= migrations.contains(&tx.txid);
+    }
+}
+
+/// Aggregate `transactions` as `options` ask, then drop the entries its
+/// result filters exclude.
+fn finish(transactions: Vec<ZecTransaction>, options: &ScanOptions) -> Vec<ZecTransaction> {
+    let mut transactions = aggregate(transactions, options.aggregation);
+    transactions.retain(|tx| options.includes(tx));
+    transactions
+}
+
+/// Scanning keys for just the pools selected by `options`, or `None` if
+/// that's every pool `ufvk` has.
+fn pool_scanning_keys<P: Parameters>(
+    params: &P,
+    ufvk: &UnifiedFullViewingKey,
+    options: &ScanOptions,
+) -> ScanResult<Option<ScanningKeys<AccountId, (AccountId, Scope)>>> {
+    let invalid = |e: String| ScanError::InvalidViewingKey(e);
+    let (_, encoded) = Ufvk::decode(&ufvk.encode(params)).map_err(|e| invalid(e.to_string()))?;
+    let items = encoded.items();
+    let kept: Vec<Fvk> = items
+        .iter()
+        .filter(|item| match item {
+            Fvk::Sapling(_) => options.includes_pool(ShieldedPool::Sapling),
+            Fvk::Orchard(_) => options.includes_pool(ShieldedPool::Orchard),
+            _ => true,
+        })
+        .cloned()
+        .collect();
+    if kept.len() == items.len() {
+        return Ok(None);
+    }
+    if !kept
+        .iter()
+        .any(|item| matches!(item, Fvk::Sapling(_) | Fvk::Orchard(_)))
+    {
+        return Ok(Some(ScanningKeys::empty()));
+    }
+
+    let restricted = Ufvk::try_from_items(kept).map_err(|e| invalid(e.to_string()))?;
+    let restricted =
+        UnifiedFullViewingKey::parse(&restricted).map_err(|e| invalid(e.to_string()))?;
+    Ok(Some(ScanningKeys::from_account_ufvks(std::iter::once((
+        0u32, restricted,
+    )))))
+}
+
+/// Decode a unified spending key serialized by `UnifiedSpendingKey::to_bytes`.
+#[cfg(feature = "spending-key")]
+fn decode_spending_key(bytes: &[u8]) -> ScanResult<UnifiedSpendingKey> {
+    UnifiedSpendingKey::from_bytes(Era::Orchard, bytes)
+        .map_err(|e| ScanError::InvalidViewingKey(format!("invalid unified spending key: {e}")))
+}
+
+/// Scanning keys for the pools of `uivk` that `options` selects.
+fn incoming_scanning_keys(
+    uivk: &UnifiedIncomingViewingKey,
+    options: &ScanOptions,
+) -> ScanningKeys<AccountId, (AccountId, Scope)> {
+    let tag = (0u32, Scope::External);
+    let sapling = uivk
+        .sapling()
+        .clone()
+        .filter(|_| options.includes_pool(ShieldedPool::Sapling))
+        .map(|ivk| {
+            let key: Box<dyn ScanningKeyOps<_, _, _>> = Box::new(IncomingKey(ivk));
+            (tag, key)
+        });
+    #[cfg(feature = "orchard")]
+    let orchard = uivk
+        .orchard()
+        .clone()
+        .filter(|_| options.includes_pool(ShieldedPool::Orchard))
+        .map(|ivk| {
+            let key: Box<dyn ScanningKeyOps<_, _, _>> = Box::new(IncomingKey(ivk));
+            (tag, key)
+        });
+    ScanningKeys::new(
+        sapling.into_iter().collect(),
+        #[cfg(feature = "orchard")]
+        orchard.into_iter().collect(),
+    )
+}
+
+/// Like [`pool_scanning_keys`], for a UIVK.
+fn incoming_pool_keys(
+    uivk: &UnifiedIncomingViewingKey,
+    options: &ScanOptions,
+) -> Option<ScanningKeys<AccountId, (AccountId, Scope)>> {
+    let all_pools = options.includes_pool(ShieldedPool::Sapling)
+        && options.includes_pool(ShieldedPool::Orchard);
+    (!all_pools).then(|| incoming_scanning_keys(uivk, options))
+}
+
+/// Check that `block` directly follows `prev` (its height and hash).
+fn check_continuity<H: AsRef<[u8]>>(
+    prev: Option<&(u64, H)>,
+    block: &compact_formats::CompactBlock,
+) -> ScanResult<()> {
+    let Some((prev_height, prev_hash)) = prev else {
+        return Ok(());
+    };
+
+    if block.height != prev_height + 1 {
+        return Err(ScanError::ChainDiscontinuity {
+            expected: format!("height {}", prev_height + 1),
+            found: format!("height {}", block.height),
+            height: block.height,
+        });
+    }
+    if block.prev_hash != prev_hash.as_ref() {
+        return Err(ScanError::ChainDiscontinuity {
+            expected: hex::encode(prev_hash),
+            found: hex::encode(&block.prev_hash),
+            height: block.height,
+        });
+    }
+
+    Ok(())
+}
+
+/// Decode one protobuf-encoded compact block.
+fn decode_proto_block(index: usize, bytes: &[u8]) -> ScanResult<compact_formats::CompactBlock> {
+    let invalid = |message: String| ScanError::InvalidProtobuf { index, message };
+    let block = compact_formats::CompactBlock::decode(bytes).map_err(|e| invalid(e.to_string()))?;
+    block_height(block.height).map_err(|e| invalid(e.to_string()))?;
+    Ok(block)
+}
+
+/// Split a buffer of length-delimited messages into the individual messages.
+fn split_length_delimited(mut bytes: &[u8]) -> ScanResult<Vec<&[u8]>> {
+    let mut messages = Vec::new();
+    while !bytes.is_empty() {
+        let invalid = |message: String| ScanError::InvalidProtobuf {
+            index: messages.len(),
+            message,
+        };
+        let len = prost::encoding::decode_varint(&mut bytes).map_err(|e| invalid(e.to_string()))?;
+        let len = usize::try_from(len)
+            .ok()
+            .filter(|&len| len <= bytes.len())
+            .ok_or_else(|| invalid(format!("message length {len} exceeds remaining buffer")))?;
+        let (message, rest) = bytes.split_at(len);
+        messages.push(message);
+        bytes = rest;
+    }
+    Ok(messages)
+}
+
+/// Length-delimited protobuf blocks, read from a stream one at a time.
+struct DelimitedBlocks<R> {
+    reader: R,
+    index: usize,
+    // Reused between messages
+    message: Vec<u8>,
+    done: bool,
+}
+
+impl<R: Read> DelimitedBlocks<R> {
+    fn new(reader: R) -> Self {
+        Self {
+            reader,
+            index: 0,
+            message: Vec::new(),
+            done: false,
+        }
+    }
+
+    /// Read the next message into `self.message`, or return `false` at the
+    /// end of the stream.
+    fn read_message(&mut self) -> ScanResult<bool> {
+        let Some(len) = self.read_length()? else {
+            return Ok(false);
+        };
+        self.message.clear();
+        // Grows with the data read, not the claimed length
+        (&mut self.reader)
+            .take(len)
+            .read_to_end(&mut self.message)
+            .map_err(|e| ScanError::BlockSource(e.to_string()))?;
+        if self.message.len() as u64 != len {
+            return Err(self.invalid(format!(
+                "stream ended {} bytes into a {len}-byte message",
+                self.message.len()
+            )));
+        }
+        Ok(true)
+    }
+
+    /// Read a varint length prefix, or `None` if the stream ends before it.
+    fn read_length(&mut self) -> ScanResult<Option<u64>> {
+        let mut prefix = [0; 10];
+        for i in 0..prefix.len() {
+            match self.reader.read_exact(&mut prefix[i..=i]) {
+                Ok(()) => {}
+                Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof && i == 0 => {
+                    return Ok(None)
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+                    return Err(self.invalid("stream ended in a length prefix".to_string()))
+                }
+                Err(e) => return Err(ScanError::BlockSource(e.to_string())),
+            }
+            if prefix[i] < 0x80 {
+                return prost::encoding::decode_varint(&mut &prefix[..=i])
+                    .map(Some)
+                    .map_err(|e| self.invalid(e.to_string()));
+            }
+        }
+        Err(self.invalid("length prefix longer than 10 bytes".to_string()))
+    }
+
+    fn invalid(&self, message: String) -> ScanError {
+        ScanError::InvalidProtobuf {
+            index: self.index,
+            message,
+        }
+    }
+}
+
+impl<R: Read> Iterator for DelimitedBlocks<R> {
+    type Item = ScanResult<compact_formats::CompactBlock>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        match self.read_message() {
+            Ok(true) => {
+                // A message that doesn't decode leaves the stream readable
+                let block = decode_proto_block(self.index, &self.message);
+                self.index += 1;
+                Some(block)
+            }
+            Ok(false) => {
+                self.done = true;
+                None
+            }
+            Err(e) => {
+                self.done = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+/// The fee `tx` pays. Unknown when it spends transparent inputs, as their
+/// values aren't in the transaction.
+fn fee_paid(tx: &Transaction) -> Option<String> {
+    tx.fee_paid(|_| Ok::<_, BalanceError>(None))
+        .ok()
+        .flatten()
+        .map(|fee| fee.into_u64().to_string())
+}
+
+/// A height under the rules of the latest network upgrade `params` activate.
+fn mempool_height<P: Parameters>(params: &P) -> u64 {
+    [
+        NetworkUpgrade::Nu6,
+        NetworkUpgrade::Nu5,
+        NetworkUpgrade::Canopy,
+        NetworkUpgrade::Sapling,
+    ]
+    .into_iter()
+    .find_map(|upgrade| params.activation_height(upgrade))
+    .map_or(0, |height| u32::from(height) as u64)
+}
+
+/// Add the Sapling outputs and Orchard actions in `blocks` to `stats`, as
+/// attempted if they are trial-decrypted under `options`.
+fn count_outputs<P: Parameters>(
+    network: &P,
+    stats: &mut DecryptionStats,
+    blocks: &[CompactBlock],
+    options: &ScanOptions,
+) {
+    let (sapling, orchard) = tried_pools(options);
+    for block in blocks {
+        // A height past u32::MAX fails the scan once the block is converted
+        let Ok(height) = block_height(block.height) else {
+            continue;
+        };
+        let sapling = sapling && network.is_nu_active(NetworkUpgrade::Sapling, height);
+        let orchard = orchard && network.is_nu_active(NetworkUpgrade::Nu5, height);
+        let tried: Vec<_> = block
+            .vtx
+            .iter()
+            .map(|tx| {
+                stats.sapling.seen += tx.outputs.len();
+                stats.orchard.seen += tx.actions.len();
+                (
+                    if sapling { tx.outputs.len() } else { 0 },
+                    if orchard { tx.actions.len() } else { 0 },
+                )
+            })
+            .collect();
+        for (sapling, orchard) in output_caps(options, tried) {
+            stats.sapling.attempted += sapling;
+            stats.orchard.attempted += orchard;
+        }
+    }
+}
+
+/// Whether Sapling outputs and Orchard actions are trial-decrypted with
+/// `options`.
+fn tried_pools(options: &ScanOptions) -> (bool, bool) {
+    (
+        options.includes_pool(ShieldedPool::Sapling),
+        cfg!(feature = "orchard") && options.includes_pool(ShieldedPool::Orchard),
+    )
+}
+
+/// How many of the Sapling outputs and Orchard actions to be tried in each
+/// of a block's transactions, `(sapling, orchard)`, are left to
+/// trial-decrypt by `options`' caps on outputs per block and transaction.
+/// The first are kept, a transaction's Sapling outputs before its actions.
+fn output_caps(options: &ScanOptions, tried: Vec<(usize, usize)>) -> Vec<(usize, usize)> {
+    let mut block_budget = options.max_outputs_per_block.unwrap_or(usize::MAX);
+    let tx_cap = options.max_outputs_per_tx.unwrap_or(usize::MAX);
+    tried
+        .into_iter()
+        .map(|(sapling, orchard)| {
+            let budget = block_budget.min(tx_cap);
+            let sapling = sapling.min(budget);
+            let orchard = orchard.min(budget - sapling);
+            block_budget -= sapling + orchard;
+            (sapling, orchard)
+        })
+        .collect()
+}
+
+/// An ephemeral key encoding no curve point, neither Jubjub nor Pallas,
+/// given to the outputs [`cap_outputs`] skips: their trial decryption fails
+/// as soon as the key is parsed, before the costly key agreement, while
+/// their note commitments (and Orchard nullifiers) are still processed, so
+/// tree positions and spends stay right.
+const SKIPPED_EPHEMERAL_KEY: [u8; 32] = [0xff; 32];
+
+/// Keep the outputs of `block` past `options`' caps (see
+/// [`ScanOptions::max_outputs_per_block`]) from being trial-decrypted,
+/// returning how many there were.
+fn cap_outputs(block: &mut compact_formats::CompactBlock, options: &ScanOptions) -> usize {
+    if options.max_outputs_per_block.is_none() && options.max_outputs_per_tx.is_none() {
+        return 0;
+    }
+    let (sapling, orchard) = tried_pools(options);
+    let tried: Vec<_> = block
+        .vtx
+        .iter()
+        .map(|tx| {
+            (
+                if sapling { tx.outputs.len() } else { 0 },
+                if orchard { tx.actions.len() } else { 0 },
+            )
+        })
+        .collect();
+    let kept = output_caps(options, tried.clone());
+    let mut skipped = 0;
+    for ((tx, (sapling, orchard)), (sapling_kept, orchard_kept)) in
+        block.vtx.iter_mut().zip(tried).zip(kept)
+    {
+        skipped += sapling - sapling_kept + orchard - orchard_kept;
+        for output in &mut tx.outputs[sapling_kept..sapling] {
+            output.ephemeral_key = SKIPPED_EPHEMERAL_KEY.to_vec();
+        }
+        for action in &mut tx.actions[orchard_kept..orchard] {
+            action.ephemeral_key = SKIPPED_EPHEMERAL_KEY.to_vec();
+        }
+    }
+    skipped
+}
+
+/// Drop the Sapling data of a block below Sapling activation on `network`,
+/// and the Orchard data of one below NU5. Such a block can't hold notes of
+/// the pool, so there is nothing to trial-decrypt, and outputs claiming
+/// otherwise would only fail the block's tree sizes against its chain
+/// metadata.
+fn skip_inactive_pools<P: Parameters>(network: &P, block: &mut compact_formats::CompactBlock) {
+    let height = block.height();
+    let sapling = network.is_nu_active(NetworkUpgrade::Sapling, height);
+    let orchard = network.is_nu_active(NetworkUpgrade::Nu5, height);
+    for tx in &mut block.vtx {
+        if !sapling {
+            tx.spends.clear();
+            tx.outputs.clear();
+        }
+        if !orchard {
+            tx.actions.clear();
+        }
+    }
+}
+
+/// Add `notes` to the decrypted counts of `stats`.
+fn count_decrypted(stats: &mut DecryptionStats, notes: &[DecryptedNote]) {
+    for note in notes {
+        match note.pool {
+            ShieldedPool::Sapling => stats.sapling.decrypted += 1,
+            ShieldedPool::Orchard => stats.orchard.decrypted += 1,
+            ShieldedPool::Transparent => {}
+        }
+    }
+}
+
+/// Number of outputs in a block that will be trial-decrypted: those of the
+/// pools `options` tries, before caps.
+fn trial_decryption_count(block: &compact_formats::CompactBlock, options: &ScanOptions) -> usize {
+    let (sapling, orchard) = tried_pools(options);
+    block
+        .vtx
+        .iter()
+        .map(|tx| {
+            (if sapling { tx.outputs.len() } else { 0 })
+                + (if orchard { tx.actions.len() } else { 0 })
+        })
+        .sum()
+}
+
+/// Normalize a viewing key string.
+///
+/// Some tools export UFVKs with an appended `|uivk...` segment.
+/// This function strips that suffix to get just the UFVK.
+fn normalize_viewing_key(raw: &str) -> String {
+    let trimmed = raw.trim();
+    if let Some(idx) = trimmed.find('|') {
+        trimmed[..idx].to_string()
+    } else {
+        trimmed.to_string()
+    }
+}
+
+/// Whether `viewing_key` looks like a hex-encoded raw Sapling diversifiable
+/// full viewing key (`ak`, `nk`, `ovk` and `dk`, 128 bytes).
+fn is_raw_dfvk(viewing_key: &str) -> bool {
+    viewing_key.len() == 256 && viewing_key.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+/// Decode a hex-encoded raw Sapling diversifiable full viewing key.
+fn decode_raw_dfvk(viewing_key: &str) -> Result<DiversifiableFullViewingKey, String> {
+    let invalid = || "not a valid Sapling diversifiable full viewing key".to_string();
+    let mut bytes = [0u8; 128];
+    hex::decode_to_slice(viewing_key, &mut bytes).map_err(|e| e.to_string())?;
+    // sapling-crypto panics on an `ak` that isn't a Jubjub point at all
+    let ak: [u8; 32] = bytes[..32].try_into().expect("32 bytes");
+    if jubjub::AffinePoint::from_bytes(ak).is_none().into() {
+        return Err(invalid());
+    }
+    DiversifiableFullViewingKey::from_bytes(&bytes).ok_or_else(invalid)
+}
+
+/// A UFVK whose only component is `dfvk`.
+fn sapling_ufvk(dfvk: &DiversifiableFullViewingKey) -> Result<UnifiedFullViewingKey, String> {
+    let ufvk =
+        Ufvk::try_from_items(vec![Fvk::Sapling(dfvk.to_bytes())]).map_err(|e| e.to_string())?;
+    UnifiedFullViewingKey::parse(&ufvk).map_err(|e| e.to_string())
+}
+
+/// Decode a memo into text, if it holds a non-empty UTF-8 string.
+fn decode_memo(memo: &MemoBytes) -> Option<String> {
+    match Memo::try_from(memo) {
+        Ok(Memo::Text(text)) if !text.is_empty() => Some(text.into()),
+        _ => None,
+    }
+}
+
+/// Decode a hex string, returning a descriptive error.
+fn decode_hex(s: &str, field: &str) -> ScanResult<Vec<u8>> {
+    hex::decode(s).map_err(|e| ScanError::InvalidHex {
+        field: field.to_string(),
+        message: e.to_string(),
+    })
+}
+
+/// Decode a hex string that must hold exactly 32 bytes.
+pub(crate) fn decode_hex32(s: &str, field: &str) -> ScanResult<[u8; 32]> {
+    <[u8; 32]>::try_from(decode_hex(s, field)?).map_err(|_| ScanError::InvalidHex {
+        field: field.to_string(),
+        message: "expected 32 bytes".to_string(),
+    })
+}
+
+/// `height` as a [`BlockHeight`], failing with [`ScanError::InvalidRange`]
+/// past `u32::MAX`, the highest height a block can have.
+pub(crate) fn block_height(height: u64) -> ScanResult<BlockHeight> {
+    u32::try_from(height)
+        .map(BlockHeight::from_u32)
+        .map_err(|_| ScanError::InvalidRange {
+            start: height,
+            end: height,
+        })
+}
+
+/// Convert our CompactBlock type to the protobuf format.
+fn map_compact_block(block: &CompactBlock) -> ScanResult<compact_formats::CompactBlock> {
+    block_height(block.height).map_err(|e| ScanError::InvalidCompactBlock {
+        height: block.height,
+        message: e.to_string(),
+    })?;
+    let vtx = block
+        .vtx
+        .iter()
+        .map(map_compact_tx)
+        .collect::<ScanResult<Vec<_>>>()?;
+
+    let chain_metadata = block.chain_metadata.as_ref().map(|m| {
+        compact_formats::ChainMetadata {
+            sapling_commitment_tree_size: m.sapling_commitment_tree_size,
+            orchard_commitment_tree_size: m.orchard_commitment_tree_size.unwrap_or(0),
+        }
+    });
+
+    Ok(compact_formats::CompactBlock {
+        proto_version: block.proto_version,
+        height: block.height,
+        hash: decode_hex(&block.hash, "block hash")?,
+        prev_hash: decode_hex(&block.prev_hash, "block prevHash")?,
+        time: block.time,
+        header: Vec::new(),
+        vtx,
+        chain_metadata,
+    })
+}
+
+/// Convert our CompactTx type to the protobuf format.
+fn map_compact_tx(tx: &CompactTx) -> ScanResult<compact_formats::CompactTx> {
+    let hash = decode_hex(&tx.txid, "txid")?;
+
+    let spends = tx
+        .spends
+        .iter()
+        .map(|s| {
+            Ok(compact_formats::CompactSaplingSpend {
+                nf: decode_hex(&s.nf, "sapling spend nf")?,
+            })
+        })
+        .collect::<ScanResult<Vec<_>>>()?;
+
+    let outputs = tx
+        .outputs
+        .iter()
+        .map(|o| {
+            Ok(compact_formats::CompactSaplingOutput {
+                cmu: decode_hex(&o.cmu, "sapling output cmu")?,
+                ephemeral_key: decode_hex(&o.ephemeral_key, "sapling output ephemeralKey")?,
+                ciphertext: decode_hex(&o.ciphertext, "sapling output ciphertext")?,
+            })
+        })
+        .collect::<ScanResult<Vec<_>>>()?;
+
+    let actions = tx
+        .actions
+        .iter()
         .map(|a| {
             Ok(compact_formats::CompactOrchardAction {
                 nullifier: decode_hex(&a.nf, "orchard action nf")?,
@@ -232,41 +2966,2219 @@ fn map_compact_tx(tx: &CompactTx) -> ScanResult<compact_formats::CompactTx> {
                 ephemeral_key: decode_hex(&a.ephemeral_key, "orchard action ephemeralKey")?,
                 ciphertext: decode_hex(&a.ciphertext, "orchard action ciphertext")?,
             })
-        })
-        .collect::<ScanResult<Vec<_>>>()?;
+        })
+        .collect::<ScanResult<Vec<_>>>()?;
+
+    Ok(compact_formats::CompactTx {
+        index: tx.index,
+        hash,
+        fee: tx.fee.unwrap_or(0),
+        spends,
+        outputs,
+        actions,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::enhance::{MemoryTransactionSource, RawTransaction};
+    use crate::nullifiers::NullifierSet;
+    use crate::source::MemoryBlockSource;
+    use zcash_keys::keys::UnifiedSpendingKey;
+    use zcash_protocol::local_consensus::LocalNetwork;
+
+    fn test_ufvk() -> String {
+        test_ufvk_for(&Network::MainNetwork)
+    }
+
+    fn test_ufvk_for<P: Parameters>(params: &P) -> String {
+        let usk =
+            UnifiedSpendingKey::from_seed(params, &[0u8; 32], zip32::AccountId::ZERO).unwrap();
+        usk.to_unified_full_viewing_key().encode(params)
+    }
+
+    fn test_request(compact_blocks: Vec<CompactBlock>) -> ScanRequest {
+        ScanRequest {
+            viewing_key: test_ufvk(),
+            key_id: "test".to_string(),
+            compact_blocks,
+            options: ScanOptions::default(),
+            nullifiers: Default::default(),
+        }
+    }
+
+    /// A compact Sapling output paying `value` to the default address of
+    /// the mainnet key `ufvk`.
+    fn sapling_output_to(ufvk: &str, value: u64) -> CompactSaplingOutput {
+        sapling_output_on(ufvk, Scope::External, value)
+    }
+
+    /// A compact Sapling output paying `value` to the change address of the
+    /// mainnet key `ufvk`, as the wallet's own spends do.
+    fn sapling_change_to(ufvk: &str, value: u64) -> CompactSaplingOutput {
+        sapling_output_on(ufvk, Scope::Internal, value)
+    }
+
+    fn sapling_output_on(ufvk: &str, scope: Scope, value: u64) -> CompactSaplingOutput {
+        let ufvk = UnifiedFullViewingKey::decode(&Network::MainNetwork, ufvk).unwrap();
+        let dfvk = ufvk.sapling().unwrap();
+        let (_, address) = match scope {
+            Scope::External => dfvk.default_address(),
+            Scope::Internal => dfvk.change_address(),
+        };
+        sapling_output_paying(address, value)
+    }
+
+    /// A compact Sapling output paying `value` to the first external address
+    /// of the mainnet key `ufvk` from diversifier index `index`.
+    fn sapling_output_from_index(ufvk: &str, index: u64, value: u64) -> CompactSaplingOutput {
+        let ufvk = UnifiedFullViewingKey::decode(&Network::MainNetwork, ufvk).unwrap();
+        let (_, address) = ufvk.sapling().unwrap().find_address(index.into()).unwrap();
+        sapling_output_paying(address, value)
+    }
+
+    fn sapling_output_paying(address: sapling::PaymentAddress, value: u64) -> CompactSaplingOutput {
+        use sapling::note_encryption::SaplingDomain;
+        use sapling::value::NoteValue;
+        use zcash_note_encryption::{Domain, NoteEncryption, COMPACT_NOTE_SIZE};
+
+        let note = address.create_note(
+            NoteValue::from_raw(value),
+            sapling::Rseed::AfterZip212([3; 32]),
+        );
+        let cmu = note.cmu();
+        let encryption = NoteEncryption::<SaplingDomain>::new(None, note, [0; 512]);
+        CompactSaplingOutput {
+            cmu: hex::encode(cmu.to_bytes()),
+            ephemeral_key: hex::encode(SaplingDomain::epk_bytes(encryption.epk()).0),
+            ciphertext: hex::encode(&encryption.encrypt_note_plaintext()[..COMPACT_NOTE_SIZE]),
+        }
+    }
+
+    /// A block after Sapling activation (counting its first block from
+    /// 2,000,001) with one transaction paying `value` to `ufvk`.
+    fn block_paying(height: u64, ufvk: &str, value: u64) -> CompactBlock {
+        CompactBlock {
+            proto_version: 1,
+            height,
+            hash: hex::encode([height as u8; 32]),
+            prev_hash: hex::encode([height as u8 - 1; 32]),
+            time: 0,
+            vtx: vec![CompactTx {
+                index: 0,
+                txid: hex::encode([height as u8; 32]),
+                fee: None,
+                spends: Vec::new(),
+                outputs: vec![sapling_output_to(ufvk, value)],
+                actions: Vec::new(),
+            }],
+            chain_metadata: Some(ChainMetadata {
+                sapling_commitment_tree_size: (height - 2_000_000) as u32,
+                orchard_commitment_tree_size: Some(0),
+            }),
+        }
+    }
+
+    /// A compact Orchard action paying `value` to the address of the mainnet
+    /// key `ufvk` on `scope`, its nullifier made up.
+    #[cfg(feature = "orchard")]
+    fn orchard_action_on(ufvk: &str, scope: Scope, value: u64) -> CompactOrchardAction {
+        use orchard::note::{ExtractedNoteCommitment, RandomSeed, Rho};
+        use orchard::note_encryption::OrchardDomain;
+        use zcash_note_encryption::{Domain, NoteEncryption, COMPACT_NOTE_SIZE};
+
+        let ufvk = UnifiedFullViewingKey::decode(&Network::MainNetwork, ufvk).unwrap();
+        let address = ufvk.orchard().unwrap().address_at(0u32, scope);
+        let mut nf = [0; 32];
+        nf[0] = 7;
+        let rho = Rho::from_bytes(&nf).unwrap();
+        // Not every seed gives a valid note; try the next until one does
+        let note = (0u8..)
+            .find_map(|seed| {
+                let rseed = RandomSeed::from_bytes([seed; 32], &rho).into_option()?;
+                let value = orchard::value::NoteValue::from_raw(value);
+                orchard::Note::from_parts(address, value, rho, rseed).into_option()
+            })
+            .unwrap();
+        let cmx = ExtractedNoteCommitment::from(note.commitment());
+        let encryption = NoteEncryption::<OrchardDomain>::new(None, note, [0; 512]);
+        CompactOrchardAction {
+            nf: hex::encode(nf),
+            cmx: hex::encode(cmx.to_bytes()),
+            ephemeral_key: hex::encode(OrchardDomain::epk_bytes(encryption.epk()).0),
+            ciphertext: hex::encode(&encryption.encrypt_note_plaintext()[..COMPACT_NOTE_SIZE]),
+        }
+    }
+
+    /// A mainnet key other than [`test_ufvk`].
+    fn other_ufvk() -> String {
+        UnifiedSpendingKey::from_seed(&Network::MainNetwork, &[1; 32], zip32::AccountId::ZERO)
+            .unwrap()
+            .to_unified_full_viewing_key()
+            .encode(&Network::MainNetwork)
+    }
+
+    /// A payment of 50,000 to [`test_ufvk`] at 2,000,001, and a block
+    /// after it spending the note in a transaction paying 20,000 to
+    /// another key and `change` (Sapling outputs, or Orchard actions)
+    /// back to the wallet, with a fee of 1,000.
+    fn spend_with_change(
+        outputs: Vec<CompactSaplingOutput>,
+        actions: Vec<CompactOrchardAction>,
+    ) -> Vec<CompactBlock> {
+        let paid = block_paying(2_000_001, &test_ufvk(), 50_000);
+        let note = Scanner::mainnet()
+            .scan_summary(&test_request(vec![paid.clone()]))
+            .unwrap()
+            .unspent_notes
+            .remove(0);
+        let mut spend = block_paying(2_000_002, &other_ufvk(), 20_000);
+        let tx = &mut spend.vtx[0];
+        tx.spends.push(CompactSaplingSpend { nf: note.nullifier });
+        tx.fee = Some(1_000);
+        tx.outputs.extend(outputs);
+        tx.actions.extend(actions);
+        let metadata = spend.chain_metadata.as_mut().unwrap();
+        metadata.sapling_commitment_tree_size = 1 + tx.outputs.len() as u32;
+        metadata.orchard_commitment_tree_size = Some(tx.actions.len() as u32);
+        vec![paid, spend]
+    }
+
+    /// Empty, correctly linked blocks below Sapling activation.
+    fn empty_chain(start: u64, count: u64) -> Vec<CompactBlock> {
+        let hash = |h: u64| hex::encode([h.to_le_bytes(), [0; 8], [0; 8], [0; 8]].concat());
+        (start..start + count)
+            .map(|height| CompactBlock {
+                proto_version: 1,
+                height,
+                hash: hash(height),
+                prev_hash: hash(height - 1),
+                time: height as u32,
+                vtx: Vec::new(),
+                chain_metadata: None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_normalize_viewing_key() {
+        // Normal UFVK
+        assert_eq!(
+            normalize_viewing_key("uview1abc123"),
+            "uview1abc123"
+        );
+
+        // UFVK with UIVK suffix
+        assert_eq!(
+            normalize_viewing_key("uview1abc123|uivk1xyz789"),
+            "uview1abc123"
+        );
+
+        // With whitespace
+        assert_eq!(
+            normalize_viewing_key("  uview1abc123  "),
+            "uview1abc123"
+        );
+    }
+
+    fn block(height: u64, hash: &str, prev_hash: &str) -> compact_formats::CompactBlock {
+        compact_formats::CompactBlock {
+            proto_version: 1,
+            height,
+            hash: hex::decode(hash).unwrap(),
+            prev_hash: hex::decode(prev_hash).unwrap(),
+            ..Default::default()
+        }
+    }
+
+    fn tip(height: u64, hash: &str) -> (u64, Vec<u8>) {
+        (height, hex::decode(hash).unwrap())
+    }
+
+    #[test]
+    fn test_check_continuity() {
+        // First block has nothing to follow
+        assert!(check_continuity::<Vec<u8>>(None, &block(10, "bb", "aa")).is_ok());
+
+        // Proper successor
+        assert!(check_continuity(Some(&tip(10, "bb")), &block(11, "cc", "BB")).is_ok());
+
+        // Height gap
+        match check_continuity(Some(&tip(10, "bb")), &block(12, "cc", "bb")) {
+            Err(ScanError::ChainDiscontinuity { height: 12, .. }) => {}
+            other => panic!("expected discontinuity, got {other:?}"),
+        }
+
+        // Hash mismatch
+        match check_continuity(Some(&tip(10, "bb")), &block(11, "cc", "dd")) {
+            Err(ScanError::ChainDiscontinuity {
+                expected, found, ..
+            }) => {
+                assert_eq!(expected, "bb");
+                assert_eq!(found, "dd");
+            }
+            other => panic!("expected discontinuity, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_decode_proto_block() {
+        let original = block(42, "bb", "aa");
+        let decoded = decode_proto_block(0, &original.encode_to_vec()).unwrap();
+        assert_eq!(decoded, original);
+
+        match decode_proto_block(3, &[0xff, 0xff]) {
+            Err(ScanError::InvalidProtobuf { index: 3, .. }) => {}
+            other => panic!("expected protobuf error, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_scan_proto_delimited() {
+        let mut bytes = Vec::new();
+        for block in empty_chain(1, 3) {
+            map_compact_block(&block)
+                .unwrap()
+                .encode_length_delimited(&mut bytes)
+                .unwrap();
+        }
+        let blocks = split_length_delimited(&bytes).unwrap();
+        assert_eq!(blocks.len(), 3);
+        assert_eq!(decode_proto_block(2, blocks[2]).unwrap().height, 3);
+
+        let scanner = Scanner::mainnet();
+        assert!(scanner
+            .scan_proto_delimited(&test_request(Vec::new()), &bytes)
+            .unwrap()
+            .is_empty());
+
+        // A compressed buffer is decompressed before splitting
+        let compressed = ruzstd::encoding::compress_to_vec(
+            &bytes[..],
+            ruzstd::encoding::CompressionLevel::Fastest,
+        );
+        assert!(scanner
+            .scan_proto_delimited(&test_request(Vec::new()), &compressed)
+            .unwrap()
+            .is_empty());
+        assert!(matches!(
+            scanner.scan_proto_delimited(&test_request(Vec::new()), &compressed[..8]),
+            Err(ScanError::Decompression(_))
+        ));
+
+        // A truncated final message is reported against its index
+        match split_length_delimited(&bytes[..bytes.len() - 1]) {
+            Err(ScanError::InvalidProtobuf { index: 2, .. }) => {}
+            other => panic!("expected protobuf error, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_scan_to_proto() {
+        let ufvk = test_ufvk();
+        let mut block = block_paying(2_000_010, &ufvk, 25_000);
+        block.chain_metadata = Some(ChainMetadata {
+            sapling_commitment_tree_size: 1,
+            orchard_commitment_tree_size: Some(0),
+        });
+        let request = ScanRequest {
+            viewing_key: ufvk,
+            ..test_request(vec![block.clone()])
+        };
+        let blocks = vec![map_compact_block(&block).unwrap().encode_to_vec()];
+
+        let scanner = Scanner::mainnet();
+        let result = scanner.scan_to_proto(&request, &blocks).unwrap();
+        assert_eq!(
+            (
+                result.blocks_scanned,
+                result.start_height,
+                result.end_height
+            ),
+            (1, 2_000_010, 2_000_010)
+        );
+        let expected: Vec<_> = scanner
+            .scan(&request)
+            .unwrap()
+            .into_iter()
+            .map(ZecTransactionProto::from)
+            .collect();
+        assert_eq!(result.transactions, expected);
+        assert_eq!(result.transactions[0].amount_zat, "25000");
+        assert_eq!(result.transactions[0].direction, "in");
+        assert_eq!(result.transactions[0].pool, "sapling");
+
+        let decoded = ScanResultProto::decode(&result.encode_to_vec()[..]).unwrap();
+        assert_eq!(decoded, result);
+    }
+
+    #[test]
+    fn test_scan_encoded() {
+        let ufvk = test_ufvk();
+        let mut block = block_paying(2_000_010, &ufvk, 25_000);
+        block.chain_metadata = Some(ChainMetadata {
+            sapling_commitment_tree_size: 1,
+            orchard_commitment_tree_size: Some(0),
+        });
+        let request = ScanRequest {
+            viewing_key: ufvk,
+            ..test_request(vec![block])
+        };
+        let scanner = Scanner::mainnet();
+        let expected = serde_json::to_value(scanner.scan(&request).unwrap()).unwrap();
+        assert_eq!(expected.as_array().unwrap().len(), 1);
+
+        for content_type in [
+            ContentType::Json,
+            ContentType::Cbor,
+            ContentType::MessagePack,
+        ] {
+            if !content_type.is_supported() {
+                continue;
+            }
+            let encoded = content_type.encode(&request).unwrap();
+            let result = scanner.scan_encoded(&encoded, content_type).unwrap();
+            let transactions: Vec<ZecTransaction> = content_type.decode(&result).unwrap();
+            assert_eq!(serde_json::to_value(transactions).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn test_scan_stream() {
+        let ufvk = test_ufvk();
+        let mut bytes = Vec::new();
+        for block in [
+            block_paying(2_000_010, &ufvk, 40_000),
+            block_paying(2_000_011, &ufvk, 2_000),
+        ] {
+            map_compact_block(&block)
+                .unwrap()
+                .encode_length_delimited(&mut bytes)
+                .unwrap();
+        }
+        let scanner = Scanner::mainnet();
+        let request = ScanRequest {
+            viewing_key: ufvk,
+            ..test_request(Vec::new())
+        };
+
+        let transactions = scanner.scan_stream(&bytes[..], &request).unwrap();
+        let amounts: Vec<_> = transactions
+            .iter()
+            .map(|tx| tx.amount_zat.as_str())
+            .collect();
+        assert_eq!(amounts, ["40000", "2000"]);
+
+        let compressed = ruzstd::encoding::compress_to_vec(
+            &bytes[..],
+            ruzstd::encoding::CompressionLevel::Fastest,
+        );
+        assert_eq!(
+            scanner
+                .scan_stream(&compressed[..], &request)
+                .unwrap()
+                .len(),
+            2
+        );
+        assert!(scanner
+            .scan_stream(std::io::empty(), &request)
+            .unwrap()
+            .is_empty());
+
+        // Truncation is reported against the message it cut short
+        match scanner.scan_stream(&bytes[..bytes.len() - 1], &request) {
+            Err(ScanError::InvalidProtobuf { index: 1, .. }) => {}
+            other => panic!("expected protobuf error, got {other:?}"),
+        }
+        let mut lenient = request.clone();
+        lenient.options.lenient = true;
+        let transactions = scanner
+            .scan_stream(&bytes[..bytes.len() - 1], &lenient)
+            .unwrap();
+        assert_eq!(transactions.len(), 1);
+    }
+
+    #[test]
+    fn test_scan_range_chunks() {
+        let mut source = MemoryBlockSource::new(empty_chain(1, 1_500));
+        let summary = Scanner::mainnet()
+            .scan_range(&mut source, 1, 1_500, &test_request(Vec::new()))
+            .unwrap();
+        assert_eq!(summary.blocks_scanned, 1_500);
+        assert_eq!(summary.end_height, 1_500);
+        assert!(summary.transactions.is_empty());
+
+        // Missing blocks are reported rather than silently skipped
+        let mut source = MemoryBlockSource::new(empty_chain(1, 10));
+        assert!(Scanner::mainnet()
+            .scan_range(&mut source, 1, 20, &test_request(Vec::new()))
+            .is_err());
+    }
+
+    #[test]
+    fn test_scan_range_with() {
+        let ufvk = test_ufvk();
+        let mut tree_size = 0;
+        let blocks = (2_000_001..=2_000_030)
+            .map(|height| {
+                let mut block = if height % 10 == 0 {
+                    tree_size += 1;
+                    block_paying(height, &ufvk, 1_000)
+                } else {
+                    CompactBlock {
+                        proto_version: 1,
+                        height,
+                        hash: hex::encode([height as u8; 32]),
+                        prev_hash: hex::encode([height as u8 - 1; 32]),
+                        time: 0,
+                        vtx: Vec::new(),
+                        chain_metadata: None,
+                    }
+                };
+                block.chain_metadata = Some(ChainMetadata {
+                    sapling_commitment_tree_size: tree_size,
+                    orchard_commitment_tree_size: Some(0),
+                });
+                block
+            })
+            .collect();
+        let mut source = MemoryBlockSource::new(blocks);
+        let request = ScanRequest {
+            viewing_key: ufvk,
+            ..test_request(Vec::new())
+        };
+
+        let mut state = ScanState::default();
+        let mut heights = Vec::new();
+        let warnings = Scanner::builder()
+            .range_chunk_size(7)
+            .build()
+            .scan_range_with(
+                &mut source,
+                2_000_001,
+                2_000_030,
+                &request,
+                &mut state,
+                |block| heights.push((block.height, block.transactions.len())),
+            )
+            .unwrap();
+        assert!(warnings.is_empty());
+        assert_eq!(heights.len(), 30);
+        assert!(heights.windows(2).all(|w| w[0].0 + 1 == w[1].0));
+        let paying: Vec<_> = heights.iter().filter(|(_, txs)| *txs == 1).collect();
+        assert_eq!(paying, [&(2_000_010, 1), &(2_000_020, 1), &(2_000_030, 1)]);
+        assert_eq!(state.last_height(), Some(2_000_030));
+    }
+
+    #[test]
+    fn test_scan_registry() {
+        let ufvk = test_ufvk();
+        let mut tree_size = 0;
+        let blocks = (2_000_001..=2_000_020)
+            .map(|height| {
+                let mut block = if height % 10 == 0 {
+                    tree_size += 1;
+                    block_paying(height, &ufvk, 1_000)
+                } else {
+                    CompactBlock {
+                        proto_version: 1,
+                        height,
+                        hash: hex::encode([height as u8; 32]),
+                        prev_hash: hex::encode([height as u8 - 1; 32]),
+                        time: 0,
+                        vtx: Vec::new(),
+                        chain_metadata: None,
+                    }
+                };
+                block.chain_metadata = Some(ChainMetadata {
+                    sapling_commitment_tree_size: tree_size,
+                    orchard_commitment_tree_size: Some(0),
+                });
+                block
+            })
+            .collect();
+        let mut source = MemoryBlockSource::new(blocks);
+        let scanner = Scanner::mainnet();
+        let mut registry = KeyRegistry::new();
+        // Two tenants with the same key id, one registered later
+        registry
+            .insert(&scanner, "early", "wallet", &ufvk, 2_000_001)
+            .unwrap();
+        registry
+            .insert(&scanner, "late", "wallet", &ufvk, 2_000_015)
+            .unwrap();
+
+        let options = ScanOptions::default();
+        let scans = scanner
+            .scan_registry(&mut source, &mut registry, 2_000_020, &options)
+            .unwrap();
+        let found: Vec<_> = scans
+            .iter()
+            .map(|scan| {
+                let heights: Vec<_> = scan
+                    .summary
+                    .transactions
+                    .iter()
+                    .map(|tx| tx.height)
+                    .collect();
+                (scan.tenant.as_str(), scan.summary.start_height, heights)
+            })
+            .collect();
+        assert_eq!(
+            found,
+            [
+                ("early", 2_000_001, vec![2_000_010, 2_000_020]),
+                ("late", 2_000_015, vec![2_000_020])
+            ]
+        );
+        assert!(scans
+            .iter()
+            .flat_map(|scan| &scan.summary.transactions)
+            .all(|tx| tx.key_id == "wallet"));
+        let early = registry.get("early", "wallet").unwrap();
+        assert_eq!(early.last_scanned_height, Some(2_000_020));
+        assert_eq!(early.nullifiers.len(), 2);
+
+        // Nothing is left to scan
+        let scans = scanner
+            .scan_registry(&mut source, &mut registry, 2_000_020, &options)
+            .unwrap();
+        assert!(scans.is_empty());
+    }
+
+    #[test]
+    fn test_scan_range_with_progress() {
+        let mut blocks = empty_chain(2_000_001, 250);
+        for block in &mut blocks {
+            block.chain_metadata = Some(ChainMetadata {
+                sapling_commitment_tree_size: 0,
+                orchard_commitment_tree_size: Some(0),
+            });
+        }
+        let mut source = MemoryBlockSource::new(blocks);
+        let mut reports = Vec::new();
+        Scanner::builder()
+            .range_chunk_size(120)
+            .build()
+            .scan_range_with_progress(
+                &mut source,
+                2_000_001,
+                2_000_250,
+                &test_request(Vec::new()),
+                &mut |progress: &ScanProgress| {
+                    reports.push((progress.blocks_scanned, progress.current_height));
+                    assert_eq!(progress.total_blocks, 250);
+                },
+            )
+            .unwrap();
+        // Every 100 blocks of each chunk, and at the end of each chunk
+        assert_eq!(
+            reports,
+            [
+                (100, 2_000_100),
+                (120, 2_000_120),
+                (220, 2_000_220),
+                (240, 2_000_240),
+                (250, 2_000_250)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_scan_queued() {
+        let ufvk = test_ufvk();
+        // Every tenth block pays the key
+        let mut tree_size = 0;
+        let blocks = (2_000_129..=2_000_328)
+            .map(|height| {
+                let mut block = if height % 10 == 0 {
+                    tree_size += 1;
+                    block_paying(height, &ufvk, 1_000)
+                } else {
+                    CompactBlock {
+                        proto_version: 1,
+                        height,
+                        hash: hex::encode([height as u8; 32]),
+                        prev_hash: hex::encode([height as u8 - 1; 32]),
+                        time: 0,
+                        vtx: Vec::new(),
+                        chain_metadata: None,
+                    }
+                };
+                block.chain_metadata = Some(ChainMetadata {
+                    sapling_commitment_tree_size: tree_size,
+                    orchard_commitment_tree_size: Some(0),
+                });
+                block
+            })
+            .collect();
+        let mut source = MemoryBlockSource::new(blocks);
+        let scanner = Scanner::builder().range_chunk_size(50).build();
+        let request = ScanRequest {
+            viewing_key: ufvk,
+            ..test_request(Vec::new())
+        };
+
+        let mut queue = ScanQueue::new(2_000_129);
+        queue.update_chain_tip(2_000_328);
+        let mut scanned = Vec::new();
+        while let Some(summary) = scanner
+            .scan_queued(&mut source, &mut queue, &request)
+            .unwrap()
+        {
+            assert_eq!(summary.transactions.len(), 5);
+            scanned.push((summary.start_height, summary.end_height));
+            if scanned.len() == 3 {
+                // Notes in the first historic chunk raise the blocks after it
+                let range = queue.ranges()[0];
+                assert_eq!(
+                    (range.start, range.priority),
+                    (2_000_179, crate::ScanPriority::FoundNote)
+                );
+            }
+        }
+        assert_eq!(
+            scanned,
+            [
+                (2_000_229, 2_000_278),
+                (2_000_279, 2_000_328),
+                (2_000_129, 2_000_178),
+                (2_000_179, 2_000_228),
+            ]
+        );
+        assert_eq!(queue.tracked_notes().len(), 20);
+    }
+
+    #[test]
+    fn test_builder() {
+        /// Counts the requests made to the wrapped source.
+        struct Counting(MemoryBlockSource, usize);
+        impl BlockSource for Counting {
+            fn get_block_range(&mut self, start: u64, end: u64) -> ScanResult<Vec<CompactBlock>> {
+                self.1 += 1;
+                self.0.get_block_range(start, end)
+            }
+        }
+
+        let scanner = Scanner::builder().range_chunk_size(4).build();
+        assert_eq!(scanner.params().network_type(), NetworkType::Main);
+        let mut source = Counting(MemoryBlockSource::new(empty_chain(1, 10)), 0);
+        let summary = scanner
+            .scan_range(&mut source, 1, 10, &test_request(Vec::new()))
+            .unwrap();
+        assert_eq!(summary.blocks_scanned, 10);
+        assert_eq!(source.1, 3);
+
+        let scanner = Scanner::builder().network(Network::TestNetwork).build();
+        assert_eq!(scanner.params(), &Network::TestNetwork);
+    }
+
+    #[test]
+    fn test_scan_summary() {
+        let scanner = Scanner::mainnet();
+        let summary = scanner
+            .scan_summary(&test_request(empty_chain(5, 10)))
+            .unwrap();
+        assert_eq!(summary.blocks_scanned, 10);
+        assert_eq!(summary.start_height, 5);
+        assert_eq!(summary.end_height, 14);
+
+        assert_eq!(summary.outputs_scanned, 0);
+        assert_eq!(summary.decryption_hits, 0);
+
+        let summary = scanner.scan_summary(&test_request(Vec::new())).unwrap();
+        assert_eq!(summary.blocks_scanned, 0);
+
+        let request = test_request(vec![block_paying(2_000_001, &test_ufvk(), 1_000)]);
+        let summary = scanner.scan_summary(&request).unwrap();
+        assert_eq!(summary.sapling_received_zat, "1000");
+        assert_eq!(summary.orchard_received_zat, "0");
+        assert_eq!(summary.sapling_sent_zat, "0");
+        let stats = summary.decryption_stats;
+        assert_eq!(stats.sapling.attempted, 1);
+        assert_eq!(stats.sapling.decrypted, 1);
+        assert_eq!(stats.orchard.decrypted, 0);
+    }
+
+    #[test]
+    #[cfg(feature = "orchard")]
+    fn test_outputs_tried_by_pool() {
+        let mut block = block_paying(2_000_001, &test_ufvk(), 50_000);
+        block.vtx[0]
+            .actions
+            .push(orchard_action_on(&other_ufvk(), Scope::External, 10_000));
+        block.chain_metadata = Some(ChainMetadata {
+            sapling_commitment_tree_size: 1,
+            orchard_commitment_tree_size: Some(1),
+        });
+        let outputs_tried = |pools: Vec<ShieldedPool>| {
+            let request = test_request(Vec::new());
+            let request = ScanRequest {
+                options: ScanOptions {
+                    pools,
+                    ..ScanOptions::default()
+                },
+                ..request
+            };
+            let mut last = None;
+            Scanner::mainnet()
+                .scan_range_with_progress(
+                    &mut MemoryBlockSource::new(vec![block.clone()]),
+                    2_000_001,
+                    2_000_001,
+                    &request,
+                    &mut |progress: &ScanProgress| last = Some(progress.outputs_tried),
+                )
+                .unwrap();
+            last.unwrap()
+        };
+        assert_eq!(outputs_tried(Vec::new()), 2);
+        // The Orchard action isn't tried, so isn't counted
+        assert_eq!(outputs_tried(vec![ShieldedPool::Sapling]), 1);
+    }
+
+    #[test]
+    fn test_count_outputs() {
+        let mut blocks = empty_chain(2_000_001, 2);
+        let tx: CompactTx = serde_json::from_value(serde_json::json!({
+            "index": 0,
+            "txid": "00",
+            "outputs": [
+                {"cmu": "", "ephemeralKey": "", "ciphertext": ""},
+                {"cmu": "", "ephemeralKey": "", "ciphertext": ""}
+            ],
+            "actions": [{"nf": "", "cmx": "", "ephemeralKey": "", "ciphertext": ""}]
+        }))
+        .unwrap();
+        blocks[0].vtx.push(tx.clone());
+        blocks[1].vtx.push(tx);
+
+        let counts = |blocks: &[CompactBlock], options: &ScanOptions| {
+            let mut stats = DecryptionStats::default();
+            count_outputs(&Network::MainNetwork, &mut stats, blocks, options);
+            stats
+        };
+
+        let stats = counts(&blocks, &ScanOptions::default());
+        assert_eq!((stats.sapling.seen, stats.sapling.attempted), (4, 4));
+        let actions = if cfg!(feature = "orchard") { 2 } else { 0 };
+        assert_eq!((stats.orchard.seen, stats.orchard.attempted), (2, actions));
+        assert_eq!(stats.orchard_skipped(), !cfg!(feature = "orchard"));
+
+        let sapling_only = ScanOptions {
+            pools: vec![ShieldedPool::Sapling],
+            ..ScanOptions::default()
+        };
+        let stats = counts(&blocks, &sapling_only);
+        assert_eq!((stats.orchard.seen, stats.orchard.attempted), (2, 0));
+        assert!(stats.orchard_skipped());
+
+        // Pools aren't tried below their activation heights
+        blocks[0].height = 400_000;
+        blocks[1].height = 1_600_000;
+        let stats = counts(&blocks, &ScanOptions::default());
+        assert_eq!((stats.sapling.seen, stats.sapling.attempted), (4, 2));
+        assert_eq!((stats.orchard.seen, stats.orchard.attempted), (2, 0));
+    }
+
+    #[test]
+    fn test_batch_size() {
+        let ufvk = test_ufvk();
+        let blocks: Vec<_> = (2_000_001..=2_000_005)
+            .map(|height| block_paying(height, &ufvk, height - 1_990_000))
+            .collect();
+        let scan = |scanner: &Scanner, options: ScanOptions| {
+            let request = ScanRequest {
+                options,
+                ..test_request(blocks.clone())
+            };
+            serde_json::to_value(scanner.scan(&request).unwrap()).unwrap()
+        };
+        let expected = scan(&Scanner::mainnet(), ScanOptions::default());
+        assert_eq!(expected.as_array().unwrap().len(), 5);
+
+        // Batches of one block, two or all of them find the same notes
+        for outputs in [0, 1, 2, DEFAULT_BATCH_SIZE] {
+            let scanner = Scanner::builder().batch_size(outputs).build();
+            assert_eq!(scan(&scanner, ScanOptions::default()), expected);
+            let options: ScanOptions =
+                serde_json::from_value(serde_json::json!({ "batch_size": outputs })).unwrap();
+            assert_eq!(options.batch_size, Some(outputs));
+            assert_eq!(scan(&Scanner::mainnet(), options), expected);
+        }
+    }
+
+    #[test]
+    fn test_flag_spam() {
+        let ufvk = test_ufvk();
+        let output = |value| sapling_output_to(&ufvk, value);
+        let undecryptable = CompactSaplingOutput {
+            ephemeral_key: hex::encode(SKIPPED_EPHEMERAL_KEY),
+            ..output(1)
+        };
+        let mut block = block_paying(2_000_001, &ufvk, 1_000);
+        let mut storm = block.vtx[0].clone();
+        storm.index = 1;
+        storm.txid = hex::encode([9; 32]);
+        storm.outputs = vec![output(2_000), output(50_000)];
+        storm.outputs.resize(60, undecryptable);
+        let mut repeats = storm.clone();
+        repeats.index = 2;
+        repeats.txid = hex::encode([10; 32]);
+        repeats.outputs = vec![output(100); 3];
+        block.vtx.extend([storm, repeats]);
+        block
+            .chain_metadata
+            .as_mut()
+            .unwrap()
+            .sapling_commitment_tree_size = 64;
+
+        let scanner = Scanner::mainnet();
+        let scan = |flag_spam| {
+            let request = ScanRequest {
+                options: ScanOptions {
+                    flag_spam,
+                    ..Default::default()
+                },
+                ..test_request(vec![block.clone()])
+            };
+            let mut blocks = Vec::new();
+            scanner
+                .scan_with(&request, |block| blocks.push(block))
+                .unwrap();
+            (scanner.scan_summary(&request).unwrap(), blocks.remove(0))
+        };
+        let flagged = |summary: &ScanSummary| -> Vec<(String, bool)> {
+            summary
+                .transactions
+                .iter()
+                .map(|tx| (tx.amount_zat.clone(), tx.likely_spam))
+                .collect()
+        };
+
+        let (summary, block) = scan(false);
+        assert!(flagged(&summary).iter().all(|(_, spam)| !spam));
+        assert_eq!((summary.spam_outputs, block.spam_ratio), (0, 0.0));
+
+        // Only dust is flagged: in the storm, or received three times
+        let (summary, block) = scan(true);
+        let spam = |amount: &str, spam| (amount.to_string(), spam);
+        assert_eq!(
+            flagged(&summary),
+            [
+                spam("1000", false),
+                spam("2000", true),
+                spam("50000", false),
+                spam("100", true),
+                spam("100", true),
+                spam("100", true),
+            ]
+        );
+        assert_eq!((summary.spam_outputs, block.spam_outputs), (60, 60));
+        assert_eq!(block.spam_ratio, 60.0 / 64.0);
+    }
+
+    #[test]
+    fn test_flag_spam_own_change() {
+        // Small change, from a transaction of as many outputs as a storm
+        let mut outputs = vec![sapling_output_to(&other_ufvk(), 1); 58];
+        outputs.push(sapling_change_to(&test_ufvk(), 1_000));
+        let mut request = test_request(spend_with_change(outputs, Vec::new()));
+        request.options.include_change = true;
+        request.options.flag_spam = true;
+        let mut blocks = Vec::new();
+        Scanner::mainnet()
+            .scan_with(&request, |block| blocks.push(block))
+            .unwrap();
+
+        let spend = &blocks[1];
+        assert!(spend.transactions.iter().all(|tx| !tx.likely_spam));
+        assert!(spend.transactions.iter().any(|tx| tx.is_change));
+        assert_eq!((spend.spam_outputs, spend.spam_ratio), (0, 0.0));
+    }
+
+    #[test]
+    fn test_output_caps() {
+        let ufvk = test_ufvk();
+        let output = |value| sapling_output_to(&ufvk, value);
+        let mut block = block_paying(2_000_001, &ufvk, 1_000);
+        block.vtx[0].outputs.push(output(2_000));
+        let mut tx = block.vtx[0].clone();
+        tx.index = 1;
+        tx.txid = hex::encode([9; 32]);
+        tx.outputs = vec![output(3_000)];
+        block.vtx.push(tx);
+        block
+            .chain_metadata
+            .as_mut()
+            .unwrap()
+            .sapling_commitment_tree_size = 3;
+
+        let scanner = Scanner::mainnet();
+        let scan = |options: ScanOptions| {
+            let request = ScanRequest {
+                options,
+                ..test_request(vec![block.clone()])
+            };
+            let mut blocks = Vec::new();
+            scanner
+                .scan_range_with(
+                    &mut MemoryBlockSource::new(request.compact_blocks.clone()),
+                    2_000_001,
+                    2_000_001,
+                    &request,
+                    &mut ScanState::default(),
+                    |block| blocks.push(block),
+                )
+                .unwrap();
+            (scanner.scan_summary(&request).unwrap(), blocks)
+        };
+        let found = |summary: &ScanSummary| -> Vec<(String, Option<u64>)> {
+            summary
+                .transactions
+                .iter()
+                .map(|tx| (tx.amount_zat.clone(), tx.note_position))
+                .collect()
+        };
+
+        let (summary, blocks) = scan(ScanOptions::default());
+        assert_eq!(summary.transactions.len(), 3);
+        assert_eq!((summary.skipped_outputs, blocks[0].skipped_outputs), (0, 0));
+
+        let (summary, blocks) = scan(ScanOptions {
+            max_outputs_per_block: Some(1),
+            ..Default::default()
+        });
+        assert_eq!(found(&summary), [("1000".to_string(), Some(0))]);
+        assert_eq!((summary.skipped_outputs, blocks[0].skipped_outputs), (2, 2));
+        let stats = summary.decryption_stats.sapling;
+        assert_eq!((stats.seen, stats.attempted), (3, 1));
+
+        // Skipped outputs still take their place in the tree
+        let (summary, _) = scan(ScanOptions {
+            max_outputs_per_tx: Some(1),
+            ..Default::default()
+        });
+        assert_eq!(
+            found(&summary),
+            [("1000".to_string(), Some(0)), ("3000".to_string(), Some(2))]
+        );
+        assert_eq!(summary.skipped_outputs, 1);
+        assert_eq!(summary.decryption_stats.sapling.attempted, 2);
+
+        // Skipped outputs fail to decrypt before any key agreement
+        use zcash_note_encryption::{Domain, EphemeralKeyBytes};
+        let epk = EphemeralKeyBytes(SKIPPED_EPHEMERAL_KEY);
+        assert!(sapling::note_encryption::SaplingDomain::epk(&epk).is_none());
+        #[cfg(feature = "orchard")]
+        assert!(orchard::note_encryption::OrchardDomain::epk(&epk).is_none());
+    }
+
+    #[test]
+    fn test_height_past_u32() {
+        let height = u64::from(u32::MAX) + 1;
+        assert_eq!(
+            block_height(u64::from(u32::MAX)).unwrap(),
+            BlockHeight::from_u32(u32::MAX)
+        );
+        assert!(matches!(
+            block_height(height),
+            Err(ScanError::InvalidRange { start, end }) if start == height && end == height
+        ));
+
+        let mut block = block_paying(2_000_001, &test_ufvk(), 50_000);
+        block.height = height;
+        let err = Scanner::mainnet()
+            .scan(&test_request(vec![block]))
+            .unwrap_err();
+        assert!(matches!(err, ScanError::InvalidCompactBlock { height: h, .. } if h == height));
+
+        let request = test_request(Vec::new());
+        let err = Scanner::mainnet()
+            .scan_transaction(&request, &[], height)
+            .unwrap_err();
+        assert!(matches!(err, ScanError::InvalidRange { .. }));
+    }
+
+    #[test]
+    fn test_skip_inactive_pools() {
+        // A pre-NU5 block claiming an Orchard action its chain metadata
+        // doesn't count
+        let mut block = block_paying(2_000_001, &test_ufvk(), 50_000);
+        block.height = 1_600_000;
+        block.vtx[0].actions.push(CompactOrchardAction {
+            nf: "00".repeat(32),
+            cmx: "00".repeat(32),
+            ephemeral_key: "00".repeat(32),
+            ciphertext: "00".repeat(52),
+        });
+        let scanner = Scanner::mainnet();
+        let summary = scanner.scan_summary(&test_request(vec![block])).unwrap();
+        assert_eq!(summary.transactions.len(), 1);
+        assert_eq!(summary.transactions[0].height, 1_600_000);
+        let stats = summary.decryption_stats;
+        assert_eq!((stats.orchard.seen, stats.orchard.attempted), (1, 0));
+
+        let mut block = block_paying(2_000_001, &test_ufvk(), 50_000);
+        block.height = 400_000;
+        let summary = scanner.scan_summary(&test_request(vec![block])).unwrap();
+        assert!(summary.transactions.is_empty());
+        assert_eq!(summary.decryption_stats.sapling.attempted, 0);
+    }
+
+    #[test]
+    fn test_pool_scanning_keys() {
+        let network = Network::MainNetwork;
+        let ufvk = UnifiedFullViewingKey::decode(&network, &test_ufvk()).unwrap();
+        let options = |pools: Vec<ShieldedPool>| ScanOptions {
+            pools,
+            ..ScanOptions::default()
+        };
+
+        assert!(pool_scanning_keys(&network, &ufvk, &options(Vec::new()))
+            .unwrap()
+            .is_none());
+
+        let keys = pool_scanning_keys(&network, &ufvk, &options(vec![ShieldedPool::Transparent]))
+            .unwrap()
+            .unwrap();
+        assert!(keys.sapling().is_empty());
+
+        #[cfg(feature = "orchard")]
+        {
+            let keys = pool_scanning_keys(&network, &ufvk, &options(vec![ShieldedPool::Sapling]))
+                .unwrap()
+                .unwrap();
+            assert_eq!(keys.sapling().len(), 2);
+            assert!(keys.orchard().is_empty());
+        }
+    }
+
+    #[cfg(feature = "transparent")]
+    #[test]
+    fn test_scan_transparent() {
+        let scanner = Scanner::mainnet();
+        let request = test_request(Vec::new());
+        let addresses = scanner
+            .transparent_addresses(&request.viewing_key, 5)
+            .unwrap();
+        assert_eq!(addresses.len(), 5);
+        assert!(addresses.iter().all(|a| a.starts_with("t1")));
+
+        let output = |address: &str| TransparentOutput {
+            txid: "aa".to_string(),
+            index: 2,
+            height: 2_000_000,
+            time: 0,
+            address: address.to_string(),
+            value_zat: "25000".to_string(),
+        };
+        let txs = scanner
+            .scan_transparent(
+                &request,
+                &[
+                    output(&addresses[3]),
+                    output("t1Hsc1LR8yKnbbe3twRp88p6vFfC5t7DLbs"),
+                ],
+            )
+            .unwrap();
+        assert_eq!(txs.len(), 1);
+        assert_eq!(txs[0].pool, ShieldedPool::Transparent);
+        assert_eq!(txs[0].address.as_deref(), Some(addresses[3].as_str()));
+        assert_eq!(txs[0].output_index, Some(2));
+    }
+
+    #[test]
+    fn test_scan_with_witnesses() {
+        let scanner = Scanner::mainnet();
+        let request = test_request(empty_chain(1, 3));
+        let mut witnesses = WitnessTracker::new();
+        let mut anchors = Vec::new();
+        scanner
+            .scan_with_witnesses(&request, &mut ScanState::default(), &mut witnesses, |b| {
+                anchors.push(b.sapling_anchor)
+            })
+            .unwrap();
 
-    Ok(compact_formats::CompactTx {
-        index: tx.index,
-        hash,
-        fee: tx.fee.unwrap_or(0),
-        spends,
-        outputs,
-        actions,
-    })
-}
+        let empty_root = witnesses.anchor(ShieldedPool::Sapling).unwrap();
+        assert!(empty_root.is_some());
+        assert_eq!(anchors, vec![empty_root; 3]);
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+        // Trees that don't line up with the scanned blocks are rejected
+        let one_leaf = format!("01{}0000", "00".repeat(32));
+        let mut witnesses = WitnessTracker::from_tree_state(0, &one_leaf, "").unwrap();
+        assert!(matches!(
+            scanner.scan_with_witnesses(
+                &request,
+                &mut ScanState::default(),
+                &mut witnesses,
+                |_| {}
+            ),
+            Err(ScanError::CommitmentTree(_))
+        ));
+    }
 
     #[test]
-    fn test_normalize_viewing_key() {
-        // Normal UFVK
+    fn test_scan_from_subtree_roots() {
+        use crate::witness::SubtreeRoot;
+        use incrementalmerkletree::{Hashable, Level};
+        use zcash_client_backend::data_api::SAPLING_SHARD_HEIGHT;
+
+        // The note lands on the first leaf after a complete subtree
+        let height = 2_000_000 + (1 << SAPLING_SHARD_HEIGHT) + 1;
+        let request = test_request(vec![block_paying(height, &test_ufvk(), 1_000)]);
+        let empty_root =
+            |level: u8| hex::encode(sapling::Node::empty_root(Level::from(level)).to_bytes());
+        let roots = [SubtreeRoot {
+            index: 0,
+            root_hash: empty_root(SAPLING_SHARD_HEIGHT),
+            completing_block_height: height - 1,
+        }];
+        let mut witnesses = WitnessTracker::from_subtree_roots(&roots, &[]).unwrap();
+        let mut notes = Vec::new();
+        Scanner::mainnet()
+            .scan_with_witnesses(&request, &mut ScanState::default(), &mut witnesses, |b| {
+                notes.extend(b.received_notes)
+            })
+            .unwrap();
+
+        assert_eq!(notes[0].position, Some(1 << SAPLING_SHARD_HEIGHT));
+        let witness = witnesses.witness(&notes[0]).unwrap().unwrap();
+        assert_eq!(witness.auth_path[0], empty_root(0));
         assert_eq!(
-            normalize_viewing_key("uview1abc123"),
-            "uview1abc123"
+            witness.auth_path[SAPLING_SHARD_HEIGHT as usize],
+            roots[0].root_hash
         );
 
-        // UFVK with UIVK suffix
+        // A scan must not start past the subtrees
+        let request = test_request(vec![block_paying(height + 1, &test_ufvk(), 1_000)]);
+        let mut witnesses = WitnessTracker::from_subtree_roots(&roots, &[]).unwrap();
+        assert!(matches!(
+            Scanner::mainnet().scan_with_witnesses(
+                &request,
+                &mut ScanState::default(),
+                &mut witnesses,
+                |_| {}
+            ),
+            Err(ScanError::CommitmentTree(_))
+        ));
+    }
+
+    /// A v5 transaction at `height` with one Sapling output of `value` to
+    /// `recipient`, encrypted to the outgoing viewing key of `sender`. Its
+    /// proof and binding signature are zeros, which decryption doesn't check.
+    fn full_tx_sending(sender: &str, recipient: &str, value: u64, height: u32) -> Vec<u8> {
+        use rand::rngs::mock::StepRng;
+        use sapling::bundle::{Authorized, Bundle, OutputDescription};
+        use sapling::note_encryption::SaplingDomain;
+        use sapling::value::{NoteValue, ValueCommitTrapdoor, ValueCommitment};
+        use zcash_note_encryption::{Domain, NoteEncryption};
+        use zcash_primitives::transaction::{TransactionData, TxVersion};
+        use zcash_protocol::consensus::BranchId;
+        use zcash_protocol::value::ZatBalance;
+
+        let sender = UnifiedFullViewingKey::decode(&Network::MainNetwork, sender).unwrap();
+        let recipient = UnifiedFullViewingKey::decode(&Network::MainNetwork, recipient).unwrap();
+        let (_, address) = recipient.sapling().unwrap().default_address();
+        let note = address.create_note(
+            NoteValue::from_raw(value),
+            sapling::Rseed::AfterZip212([5; 32]),
+        );
+        let mut rng = StepRng::new(1, 1);
+        let cv = ValueCommitment::derive(note.value(), ValueCommitTrapdoor::random(&mut rng));
+        let cmu = note.cmu();
+        let ovk = sender.sapling().unwrap().to_ovk(zip32::Scope::External);
+        let mut memo = [0; 512];
+        memo[..5].copy_from_slice(b"rent!");
+        let encryption = NoteEncryption::<SaplingDomain>::new(Some(ovk), note, memo);
+        let output = OutputDescription::from_parts(
+            cv.clone(),
+            cmu,
+            SaplingDomain::epk_bytes(encryption.epk()),
+            encryption.encrypt_note_plaintext(),
+            encryption.encrypt_outgoing_plaintext(&cv, &cmu, &mut rng),
+            [0; 192],
+        );
+        let bundle = Bundle::from_parts(
+            Vec::new(),
+            vec![output],
+            ZatBalance::from_i64(-(value as i64)).unwrap(),
+            Authorized {
+                binding_sig: redjubjub::Signature::from([0; 64]),
+            },
+        );
+
+        let height = BlockHeight::from_u32(height);
+        let tx = TransactionData::from_parts(
+            TxVersion::V5,
+            BranchId::for_height(&Network::MainNetwork, height),
+            0,
+            height + 20,
+            None,
+            None,
+            bundle,
+            None,
+        )
+        .freeze()
+        .unwrap();
+        let mut bytes = Vec::new();
+        tx.write(&mut bytes).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn test_scan_transaction_outgoing() {
+        let recipient_seed = [7; 32];
+        let recipient = UnifiedSpendingKey::from_seed(
+            &Network::MainNetwork,
+            &recipient_seed,
+            zip32::AccountId::ZERO,
+        )
+        .unwrap()
+        .to_unified_full_viewing_key();
+        let raw_tx = full_tx_sending(
+            &test_ufvk(),
+            &recipient.encode(&Network::MainNetwork),
+            25_000,
+            2_000_000,
+        );
+
+        let txs = Scanner::mainnet()
+            .scan_transaction(&test_request(Vec::new()), &raw_tx, 2_000_000)
+            .unwrap();
+        assert_eq!(txs.len(), 1);
+        assert_eq!(txs[0].direction, TxDirection::Out);
+        assert_eq!(txs[0].amount_zat, "25000");
+        assert_eq!(txs[0].memo.as_deref(), Some("rent!"));
+        assert_eq!(txs[0].address, None);
+        let (_, address) = recipient.sapling().unwrap().default_address();
         assert_eq!(
-            normalize_viewing_key("uview1abc123|uivk1xyz789"),
-            "uview1abc123"
+            txs[0].recipient_address,
+            Some(encode_payment_address_p(&Network::MainNetwork, &address))
         );
+    }
 
-        // With whitespace
+    #[test]
+    fn test_enhance() {
+        let recipient =
+            UnifiedSpendingKey::from_seed(&Network::MainNetwork, &[7; 32], zip32::AccountId::ZERO)
+                .unwrap()
+                .to_unified_full_viewing_key()
+                .encode(&Network::MainNetwork);
+        let raw_tx = full_tx_sending(&test_ufvk(), &recipient, 25_000, 2_000_000);
+        let scanner = Scanner::mainnet();
+        let full_entries = |viewing_key: &str| {
+            let request = ScanRequest {
+                viewing_key: viewing_key.to_string(),
+                ..test_request(Vec::new())
+            };
+            scanner
+                .scan_transaction(&request, &raw_tx, 2_000_000)
+                .unwrap()
+        };
+        // What a compact scan reports
+        let compact = |mut tx: ZecTransaction| {
+            tx.memo = None;
+            tx.address = None;
+            tx.recipient_address = None;
+            tx.expiry_height = None;
+            tx.fee_zat = None;
+            tx
+        };
+
+        let received = full_entries(&recipient);
+        let sent = full_entries(&test_ufvk());
+        let txid = received[0].txid.clone();
+        let unknown = ZecTransaction {
+            txid: "00".repeat(32),
+            ..compact(received[0].clone())
+        };
+        let mut transactions = [
+            compact(received[0].clone()),
+            compact(sent[0].clone()),
+            unknown.clone(),
+        ];
+        let json = |tx: &ZecTransaction| serde_json::to_value(tx).unwrap();
+        let mut source = MemoryTransactionSource::new([RawTransaction {
+            txid,
+            data: raw_tx.clone(),
+        }]);
+
+        let enhanced = scanner
+            .enhance(&test_ufvk(), &mut transactions[1..], &mut source)
+            .unwrap();
+        assert_eq!(enhanced, 1);
+        assert_eq!(json(&transactions[1]), json(&sent[0]));
+        assert_eq!(json(&transactions[2]), json(&unknown));
         assert_eq!(
-            normalize_viewing_key("  uview1abc123  "),
-            "uview1abc123"
+            scanner
+                .enhance(&recipient, &mut transactions[..1], &mut source)
+                .unwrap(),
+            1
+        );
+        assert_eq!(json(&transactions[0]), json(&received[0]));
+        assert_eq!(transactions[0].memo.as_deref(), Some("rent!"));
+        assert!(transactions[0].expiry_height.is_some());
+    }
+
+    #[test]
+    fn test_scan_transaction_invalid() {
+        let result = Scanner::mainnet().scan_transaction(
+            &test_request(Vec::new()),
+            &[0x05, 0x00],
+            2_000_000,
+        );
+        assert!(matches!(result, Err(ScanError::InvalidTransaction(_))));
+    }
+
+    #[test]
+    fn test_scan_tx() {
+        let tx = CompactTx {
+            index: 0,
+            txid: "ab".repeat(32),
+            fee: None,
+            spends: Vec::new(),
+            outputs: Vec::new(),
+            actions: Vec::new(),
+        };
+        let txs = Scanner::mainnet()
+            .scan_tx(&test_request(Vec::new()), &tx, 2_000_000, 0)
+            .unwrap();
+        assert!(txs.is_empty());
+    }
+
+    #[test]
+    fn test_scan_mempool_tx() {
+        let tx = CompactTx {
+            index: 0,
+            txid: "cd".repeat(32),
+            fee: None,
+            spends: Vec::new(),
+            outputs: vec![sapling_output_to(&test_ufvk(), 25_000)],
+            actions: Vec::new(),
+        };
+
+        let txs = Scanner::mainnet()
+            .scan_mempool_tx(&test_request(Vec::new()), &tx)
+            .unwrap();
+        assert_eq!(txs.len(), 1);
+        assert_eq!(txs[0].amount_zat, "25000");
+        assert_eq!(txs[0].direction, TxDirection::In);
+        assert!(txs[0].unconfirmed);
+        assert_eq!((txs[0].height, txs[0].time), (0, 0));
+        assert_eq!(txs[0].scope, None);
+
+        // The scope is reported once change is included
+        let mut request = test_request(Vec::new());
+        request.options.include_change = true;
+        let txs = Scanner::mainnet().scan_mempool_tx(&request, &tx).unwrap();
+        assert_eq!(txs[0].scope, Some(KeyScope::External));
+
+        assert_eq!(mempool_height(&Network::MainNetwork), 2_726_400);
+    }
+
+    #[test]
+    fn test_scan_with_key() {
+        let scanner = Scanner::mainnet();
+        assert!(matches!(
+            scanner.prepare_key("uview1invalid"),
+            Err(ScanError::InvalidViewingKey(_))
+        ));
+
+        // The prepared key is reused across batches, continuing the state
+        let key = scanner.prepare_key(&test_ufvk()).unwrap();
+        let mut state = ScanState::default();
+        let mut request = test_request(empty_chain(1, 5));
+        request.viewing_key.clear();
+        scanner.scan_with_key(&key, &request, &mut state).unwrap();
+        request.compact_blocks = empty_chain(6, 5);
+        scanner.scan_with_key(&key, &request, &mut state).unwrap();
+        assert_eq!(state.last_height(), Some(10));
+    }
+
+    #[test]
+    fn test_scan_session() {
+        let scanner = Scanner::mainnet();
+        let ufvk = test_ufvk();
+        let request = test_request(vec![block_paying(2_000_010, &ufvk, 5_000)]);
+
+        let mut session = scanner.session();
+        assert!(session.key("uview1invalid").is_err());
+        assert_eq!(session.prepared_keys(), 0);
+
+        let first = session.scan(&request).unwrap();
+        assert_eq!(first.len(), 1);
+        // The same key with a UIVK suffix reuses the prepared key
+        let suffixed = ScanRequest {
+            viewing_key: format!("{ufvk}|uivk1ignored"),
+            ..request.clone()
+        };
+        let second = session.scan(&suffixed).unwrap();
+        assert_eq!(session.prepared_keys(), 1);
+        assert_eq!(second[0].txid, first[0].txid);
+
+        let mut state = ScanState::default();
+        session.scan_with_state(&request, &mut state).unwrap();
+        assert_eq!(state.next_height(), Some(2_000_011));
+        session.clear();
+        assert_eq!(session.prepared_keys(), 0);
+    }
+
+    #[test]
+    fn test_scan_accounts() {
+        let account_ufvk = |index: u32| {
+            let account = zip32::AccountId::try_from(index).unwrap();
+            UnifiedSpendingKey::from_seed(&Network::MainNetwork, &[0u8; 32], account)
+                .unwrap()
+                .to_unified_full_viewing_key()
+                .encode(&Network::MainNetwork)
+        };
+        let accounts = BTreeMap::from([(0, account_ufvk(0)), (1, account_ufvk(1))]);
+        let request = test_request(vec![
+            block_paying(2_000_001, &account_ufvk(1), 1_000),
+            block_paying(2_000_002, &account_ufvk(0), 2_000),
+        ]);
+
+        let txs = Scanner::mainnet()
+            .scan_accounts(&accounts, &request)
+            .unwrap();
+        let found: Vec<_> = txs
+            .iter()
+            .map(|tx| (tx.height, tx.account_id, tx.amount_zat.as_str()))
+            .collect();
+        assert_eq!(
+            found,
+            [(2_000_001, Some(1), "1000"), (2_000_002, Some(0), "2000")]
+        );
+
+        let accounts = BTreeMap::from([(3, "uview1invalid".to_string())]);
+        let err = Scanner::mainnet()
+            .scan_accounts(&accounts, &request)
+            .unwrap_err();
+        assert!(err.to_string().contains("account 3"));
+    }
+
+    #[test]
+    fn test_tag_self_transfers() {
+        let entry = |txid: &str, direction: &str, pool: &str| -> ZecTransaction {
+            serde_json::from_value(serde_json::json!({
+                "txid": txid, "height": 1, "time": 0, "amountZat": "1000",
+                "direction": direction, "keyId": "test", "pool": pool
+            }))
+            .unwrap()
+        };
+        let spend = |txid: &str| NoteSpend {
+            note: TrackedNote {
+                pool: ShieldedPool::Sapling,
+                nullifier: "00".to_string(),
+                value_zat: "1000".to_string(),
+                height: 0,
+                txid: String::new(),
+                position: None,
+            },
+            spent_txid: txid.to_string(),
+            spent_height: 1,
+        };
+        let mut txs = vec![
+            // Sapling to Orchard migration
+            entry("aa", "out", "sapling"),
+            entry("aa", "in", "orchard"),
+            // Sapling payment to ourselves
+            entry("bb", "out", "sapling"),
+            entry("bb", "in", "sapling"),
+            // Orchard received from someone else
+            entry("cc", "in", "orchard"),
+        ];
+        tag_self_transfers(&mut txs, &[spend("aa"), spend("bb")]);
+        let tagged: Vec<_> = txs.iter().map(|tx| tx.self_transfer).collect();
+        assert_eq!(tagged, [true, true, false, false, false]);
+    }
+
+    #[cfg(feature = "orchard")]
+    #[test]
+    fn test_cross_pool_change() {
+        let scan = |scope| {
+            let action = orchard_action_on(&test_ufvk(), scope, 29_000);
+            let mut request = test_request(spend_with_change(Vec::new(), vec![action]));
+            request.options.include_change = true;
+            let transactions = Scanner::mainnet().scan(&request).unwrap();
+            transactions
+                .iter()
+                .skip(1)
+                .map(|tx| (tx.direction, tx.pool, tx.is_change, tx.self_transfer))
+                .collect::<Vec<_>>()
+        };
+
+        // A Sapling payment with its change in Orchard isn't a migration
+        assert_eq!(
+            scan(Scope::Internal),
+            [
+                (TxDirection::In, ShieldedPool::Orchard, true, false),
+                (TxDirection::Out, ShieldedPool::Sapling, false, false),
+            ]
+        );
+        // A Sapling spend paying the wallet's own Orchard address is
+        assert_eq!(
+            scan(Scope::External),
+            [
+                (TxDirection::In, ShieldedPool::Orchard, false, true),
+                (TxDirection::Out, ShieldedPool::Sapling, false, true),
+            ]
         );
     }
+
+    #[test]
+    fn test_coinbase() {
+        let mut blocks = vec![
+            block_paying(2_000_001, &test_ufvk(), 1_000),
+            block_paying(2_000_002, &test_ufvk(), 1_000),
+        ];
+        blocks[1].vtx[0].index = 1;
+        let scanner = Scanner::mainnet();
+        let txs = scanner.scan(&test_request(blocks.clone())).unwrap();
+        let coinbase: Vec<_> = txs.iter().map(|tx| tx.is_coinbase).collect();
+        assert_eq!(coinbase, [true, false]);
+
+        let request = test_request(Vec::new());
+        let txs = scanner
+            .scan_mempool_tx(&request, &blocks[0].vtx[0])
+            .unwrap();
+        assert!(!txs[0].is_coinbase);
+    }
+
+    #[test]
+    fn test_note_positions() {
+        let blocks: Vec<_> = (1..=3)
+            .map(|i| block_paying(2_000_000 + i, &test_ufvk(), 1_000))
+            .collect();
+        let scanner = Scanner::mainnet();
+        let txs = scanner.scan(&test_request(blocks.clone())).unwrap();
+        let positions: Vec<_> = txs.iter().map(|tx| tx.note_position).collect();
+        assert_eq!(positions, [Some(0), Some(1), Some(2)]);
+
+        let request = test_request(Vec::new());
+        let txs = scanner
+            .scan_tx(&request, &blocks[2].vtx[0], 2_000_003, 0)
+            .unwrap();
+        assert_eq!(txs.len(), 1);
+        assert_eq!(txs[0].note_position, None);
+    }
+
+    #[test]
+    fn test_default_key_id() {
+        let mut request = test_request(vec![block_paying(2_000_001, &test_ufvk(), 1_000)]);
+        request.key_id = String::new();
+        let txs = Scanner::mainnet().scan(&request).unwrap();
+        assert_eq!(txs[0].key_id, ufvk_fingerprint(&test_ufvk()));
+
+        let accounts = BTreeMap::from([(0, test_ufvk())]);
+        request.viewing_key = "uview1other".to_string();
+        let txs = Scanner::mainnet()
+            .scan_accounts(&accounts, &request)
+            .unwrap();
+        assert_eq!(txs[0].key_id, ufvk_fingerprint(&test_ufvk()));
+
+        let json = format!(
+            r#"{{"viewing_key": "{}", "compact_blocks": []}}"#,
+            test_ufvk()
+        );
+        let request: ScanRequest = serde_json::from_str(&json).unwrap();
+        assert_eq!(request.key_id(), ufvk_fingerprint(&test_ufvk()));
+    }
+
+    #[test]
+    fn test_result_filters() {
+        let blocks: Vec<_> = (1..=3)
+            .map(|i| block_paying(2_000_000 + i, &test_ufvk(), i * 1_000))
+            .collect();
+        let scan = |options: ScanOptions| {
+            let mut request = test_request(blocks.clone());
+            request.options = options;
+            let txs = Scanner::mainnet().scan(&request).unwrap();
+            txs.into_iter().map(|tx| tx.height).collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            scan(ScanOptions::default()),
+            [2_000_001, 2_000_002, 2_000_003]
+        );
+        let min_amount = ScanOptions {
+            min_amount_zat: Some(2_000),
+            ..ScanOptions::default()
+        };
+        assert_eq!(scan(min_amount), [2_000_002, 2_000_003]);
+        // Past i64::MAX, nothing is big enough
+        let huge_min_amount = ScanOptions {
+            min_amount_zat: Some(u64::MAX),
+            ..ScanOptions::default()
+        };
+        assert!(scan(huge_min_amount).is_empty());
+        let height_range = ScanOptions {
+            height_range: Some(2_000_001..=2_000_002),
+            ..ScanOptions::default()
+        };
+        assert_eq!(scan(height_range), [2_000_001, 2_000_002]);
+        let allowlist = ScanOptions {
+            txid_allowlist: Some(vec![hex::encode_upper([2_000_003u64 as u8; 32])]),
+            ..ScanOptions::default()
+        };
+        assert_eq!(scan(allowlist), [2_000_003]);
+    }
+
+    #[test]
+    fn test_lenient_scan() {
+        let mut blocks: Vec<_> = (1..=3)
+            .map(|i| block_paying(2_000_000 + i, &test_ufvk(), i * 1_000))
+            .collect();
+        blocks[1].vtx[0].txid = "not hex".to_string();
+        let mut request = test_request(blocks);
+        let scanner = Scanner::mainnet();
+
+        assert!(matches!(
+            scanner.scan_summary(&request),
+            Err(ScanError::InvalidHex { .. })
+        ));
+
+        request.options.lenient = true;
+        let summary = scanner.scan_summary(&request).unwrap();
+        let heights: Vec<_> = summary.transactions.iter().map(|tx| tx.height).collect();
+        assert_eq!(heights, [2_000_001, 2_000_003]);
+        assert_eq!(summary.warnings.len(), 1);
+        assert_eq!(summary.warnings[0].height, Some(2_000_002));
+        assert_eq!(summary.warnings[0].field.as_deref(), Some("txid"));
+    }
+
+    #[test]
+    fn test_lenient_range_scan() {
+        // The last block can't be scanned
+        let mut blocks: Vec<_> = (1..=3)
+            .map(|i| block_paying(2_000_000 + i, &test_ufvk(), i * 1_000))
+            .collect();
+        blocks[2].vtx[0].txid = "not hex".to_string();
+        let mut request = test_request(Vec::new());
+        request.options.lenient = true;
+
+        let mut reports = Vec::new();
+        let summary = Scanner::mainnet()
+            .scan_range_with_progress(
+                &mut MemoryBlockSource::new(blocks),
+                2_000_001,
+                2_000_003,
+                &request,
+                &mut |progress: &ScanProgress| reports.push(*progress),
+            )
+            .unwrap();
+        assert_eq!(summary.blocks_scanned, 2);
+        assert_eq!(summary.warnings.len(), 1);
+        assert_eq!(summary.warnings[0].height, Some(2_000_003));
+        // Progress is still reported after the last block scanned
+        let last = reports.last().unwrap();
+        assert_eq!((last.blocks_scanned, last.current_height), (2, 2_000_002));
+    }
+
+    #[test]
+    fn test_scan_partial() {
+        let mut blocks: Vec<_> = (1..=3)
+            .map(|i| block_paying(2_000_000 + i, &test_ufvk(), i * 1_000))
+            .collect();
+        blocks[2].prev_hash = hex::encode([0; 32]);
+        let request = test_request(blocks);
+
+        let err = Scanner::mainnet().scan_partial(&request).unwrap_err();
+        assert!(matches!(
+            err.error,
+            ScanError::ChainDiscontinuity {
+                height: 2_000_003,
+                ..
+            }
+        ));
+        let heights: Vec<_> = err.transactions.iter().map(|tx| tx.height).collect();
+        assert_eq!(heights, [2_000_001, 2_000_002]);
+        assert_eq!(err.state.next_height(), Some(2_000_003));
+        assert_eq!(err.state.tracked_notes.len(), 2);
+    }
+
+    #[test]
+    fn test_change_detection() {
+        let blocks = spend_with_change(vec![sapling_change_to(&test_ufvk(), 29_000)], Vec::new());
+        let mut request = test_request(blocks);
+        request.options.include_change = true;
+        let mut results = Vec::new();
+        Scanner::mainnet()
+            .scan_with(&request, |block| results.push(block))
+            .unwrap();
+
+        // The note paid to the wallet isn't change, the one it paid itself is
+        let change = |block: &BlockScanResult| -> Vec<(String, bool)> {
+            block
+                .decrypted_notes
+                .iter()
+                .map(|note| (note.value_zat.clone(), note.is_change))
+                .collect()
+        };
+        assert_eq!(change(&results[0]), [("50000".to_string(), false)]);
+        assert_eq!(change(&results[1]), [("29000".to_string(), true)]);
+        let received = &results[1].transactions[0];
+        assert_eq!(
+            (received.direction, received.is_change, received.scope),
+            (TxDirection::In, true, Some(KeyScope::Internal))
+        );
+        // Change is still tracked, so its own spend is detected later
+        assert_eq!(results[1].received_notes[0].value_zat, "29000");
+        assert_eq!(results[1].spent_notes[0].note.value_zat, "50000");
+    }
+
+    #[test]
+    fn test_net_outgoing() {
+        let blocks = spend_with_change(vec![sapling_change_to(&test_ufvk(), 29_000)], Vec::new());
+        let transactions = Scanner::mainnet().scan(&test_request(blocks)).unwrap();
+        let found: Vec<_> = transactions
+            .iter()
+            .map(|tx| (tx.direction, tx.amount_zat.as_str(), tx.fee_zat.as_deref()))
+            .collect();
+        // 50,000 spent less 29,000 of change: the payment and its fee
+        assert_eq!(
+            found,
+            [
+                (TxDirection::In, "50000", None),
+                (TxDirection::Out, "21000", Some("1000")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_diversifier_filter() {
+        // The note spent in the second block is at the default address; the
+        // first block pays another at a later diversifier index
+        let mut blocks = spend_with_change(Vec::new(), Vec::new());
+        blocks[0].vtx[0]
+            .outputs
+            .push(sapling_output_from_index(&test_ufvk(), 1_000, 7_000));
+        blocks[0]
+            .chain_metadata
+            .as_mut()
+            .unwrap()
+            .sapling_commitment_tree_size = 2;
+        blocks[1]
+            .chain_metadata
+            .as_mut()
+            .unwrap()
+            .sapling_commitment_tree_size = 3;
+        let scan = |diversifier_indices| {
+            let mut request = test_request(blocks.clone());
+            request.options.diversifier_indices = diversifier_indices;
+            let summary = Scanner::mainnet().scan_summary(&request).unwrap();
+            let found: Vec<_> = summary
+                .transactions
+                .into_iter()
+                .map(|tx| (tx.direction, tx.amount_zat, tx.diversifier_index))
+                .collect();
+            (found, summary.unspent_notes.len())
+        };
+
+        let (all, unspent) = scan(None);
+        assert_eq!((all.len(), unspent), (3, 1));
+        let index = |amount: &str| all.iter().find(|tx| tx.1 == amount).unwrap().2;
+        let (default_index, later_index) = (index("50000"), index("7000"));
+        assert!(later_index.unwrap() >= 1_000);
+        assert!(default_index < later_index);
+
+        // The note at the default address isn't reported, but is still
+        // tracked, so its spend is
+        let (found, unspent) = scan(Some(vec![later_index.unwrap()]));
+        let found: Vec<_> = found
+            .into_iter()
+            .map(|(d, amount, _)| (d, amount))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (TxDirection::In, "7000".to_string()),
+                (TxDirection::Out, "50000".to_string()),
+            ]
+        );
+        assert_eq!(unspent, 1);
+    }
+
+    #[test]
+    fn test_include_change() {
+        let blocks = spend_with_change(vec![sapling_change_to(&test_ufvk(), 29_000)], Vec::new());
+        let scan = |include_change| {
+            let mut request = test_request(blocks.clone());
+            request.options.include_change = include_change;
+            let summary = Scanner::mainnet().scan_summary(&request).unwrap();
+            let found: Vec<_> = summary
+                .transactions
+                .iter()
+                .map(|tx| (tx.direction, tx.amount_zat.clone(), tx.is_change))
+                .collect();
+            (found, summary.unspent_notes.len())
+        };
+        let entry = |direction, amount: &str, is_change| (direction, amount.to_string(), is_change);
+
+        // Hidden by default, though still tracked for spends
+        assert_eq!(
+            scan(false),
+            (
+                vec![
+                    entry(TxDirection::In, "50000", false),
+                    entry(TxDirection::Out, "21000", false),
+                ],
+                1
+            )
+        );
+        assert_eq!(
+            scan(true),
+            (
+                vec![
+                    entry(TxDirection::In, "50000", false),
+                    entry(TxDirection::In, "29000", true),
+                    entry(TxDirection::Out, "21000", false),
+                ],
+                1
+            )
+        );
+    }
+
+    #[test]
+    fn test_summary_excludes_change() {
+        let blocks = spend_with_change(vec![sapling_change_to(&test_ufvk(), 29_000)], Vec::new());
+        let mut request = test_request(blocks);
+        request.options.include_change = true;
+        let summary = Scanner::mainnet().scan_summary(&request).unwrap();
+        assert_eq!(summary.transactions.len(), 3);
+        assert_eq!(
+            (
+                summary.sapling_received_zat.as_str(),
+                summary.sapling_sent_zat.as_str()
+            ),
+            ("50000", "21000")
+        );
+    }
+
+    #[test]
+    fn test_seeded_nullifiers() {
+        let scanner = Scanner::mainnet();
+        let blocks: Vec<_> = (1..=2)
+            .map(|i| block_paying(2_000_000 + i, &test_ufvk(), i * 1_000))
+            .collect();
+        let first = scanner.scan_summary(&test_request(blocks)).unwrap();
+        let nullifiers: NullifierSet = first.unspent_notes.into_iter().collect();
+        assert_eq!(nullifiers.len(), 2);
+
+        // A later scan spending a note from the first one
+        let spent = nullifiers
+            .notes()
+            .iter()
+            .find(|note| note.height == 2_000_001)
+            .unwrap();
+        let mut block = block_paying(2_000_003, &test_ufvk(), 3_000);
+        block.vtx[0].spends.push(CompactSaplingSpend {
+            nf: spent.nullifier.clone(),
+        });
+        let mut request = test_request(vec![block.clone()]);
+        assert!(scanner
+            .scan(&request)
+            .unwrap()
+            .iter()
+            .all(|tx| tx.direction == TxDirection::In));
+
+        request.nullifiers = nullifiers;
+        let summary = scanner.scan_summary(&request).unwrap();
+        assert!(summary
+            .transactions
+            .iter()
+            .any(|tx| tx.direction == TxDirection::Out && tx.amount_zat == "1000"));
+        let heights: Vec<_> = summary
+            .unspent_notes
+            .iter()
+            .map(|note| note.height)
+            .collect();
+        assert_eq!(heights.len(), 2);
+        assert!(heights.contains(&2_000_002) && heights.contains(&2_000_003));
+    }
+
+    #[test]
+    fn test_min_value() {
+        let blocks = vec![
+            block_paying(2_000_001, &test_ufvk(), 0),
+            block_paying(2_000_002, &test_ufvk(), 500),
+            block_paying(2_000_003, &test_ufvk(), 50_000),
+        ];
+        let scan = |min_value_zat| {
+            let mut request = test_request(blocks.clone());
+            request.options.min_value_zat = min_value_zat;
+            let summary = Scanner::mainnet().scan_summary(&request).unwrap();
+            let heights: Vec<_> = summary.transactions.iter().map(|tx| tx.height).collect();
+            (heights, summary.unspent_notes.len())
+        };
+
+        // Zero-value notes are skipped by default
+        assert_eq!(scan(None), (vec![2_000_002, 2_000_003], 2));
+        assert_eq!(scan(Some(0)), (vec![2_000_001, 2_000_002, 2_000_003], 3));
+        // Dust below the threshold isn't tracked either
+        assert_eq!(scan(Some(1_000)), (vec![2_000_003], 1));
+    }
+
+    #[test]
+    fn test_inspect_key() {
+        let scanner = Scanner::mainnet();
+        let info = scanner.inspect_key(&test_ufvk()).unwrap();
+        assert_eq!(info.kind, KeyKind::Ufvk);
+        assert_eq!(info.network, KeyNetwork::Mainnet);
+        assert!(info.has_sapling);
+        assert_eq!(info.has_orchard, cfg!(feature = "orchard"));
+        assert!(info.scannable);
+
+        // Keys for another network decode but can't be scanned
+        let info = scanner
+            .inspect_key(&test_ufvk_for(&Network::TestNetwork))
+            .unwrap();
+        assert_eq!(info.network, KeyNetwork::Testnet);
+        assert!(!info.scannable);
+
+        let usk = UnifiedSpendingKey::from_seed(
+            &Network::MainNetwork,
+            &[0u8; 32],
+            zip32::AccountId::ZERO,
+        )
+        .unwrap();
+        let uivk = usk
+            .to_unified_full_viewing_key()
+            .to_unified_incoming_viewing_key()
+            .encode(&Network::MainNetwork);
+        let info = scanner.inspect_key(&uivk).unwrap();
+        assert_eq!(info.kind, KeyKind::Uivk);
+        assert!(info.scannable);
+
+        assert!(matches!(
+            scanner.inspect_key("zs1notakey"),
+            Err(ScanError::InvalidViewingKey(_))
+        ));
+    }
+
+    #[test]
+    fn test_sapling_viewing_keys() {
+        let usk = UnifiedSpendingKey::from_seed(
+            &Network::MainNetwork,
+            &[0u8; 32],
+            zip32::AccountId::ZERO,
+        )
+        .unwrap();
+        #[allow(deprecated)]
+        let extfvk = usk.sapling().to_extended_full_viewing_key();
+        let zxviews = zcash_keys::encoding::encode_extended_full_viewing_key(
+            Network::MainNetwork.hrp_sapling_extended_full_viewing_key(),
+            &extfvk,
+        );
+        assert!(zxviews.starts_with("zxviews1"));
+        let dfvk = hex::encode(extfvk.to_diversifiable_full_viewing_key().to_bytes());
+
+        // Sapling-only keys find the same Sapling notes as the UFVK
+        let scanner = Scanner::mainnet();
+        let request = test_request(vec![block_paying(2_000_001, &test_ufvk(), 50_000)]);
+        let expected = serde_json::to_value(scanner.scan(&request).unwrap()).unwrap();
+        for (viewing_key, kind) in [(zxviews.clone(), KeyKind::Extfvk), (dfvk, KeyKind::Dfvk)] {
+            let info = scanner.inspect_key(&viewing_key).unwrap();
+            assert_eq!(info.kind, kind);
+            assert!(info.has_sapling && !info.has_orchard && info.scannable);
+            let request = ScanRequest {
+                viewing_key,
+                ..request.clone()
+            };
+            let found = scanner.scan(&request).unwrap();
+            assert_eq!(found.len(), 1);
+            assert_eq!(serde_json::to_value(found).unwrap(), expected);
+        }
+
+        let testnet = Scanner::testnet();
+        let info = testnet.inspect_key(&zxviews).unwrap();
+        assert_eq!(info.network, KeyNetwork::Mainnet);
+        assert!(!info.scannable);
+        assert!(matches!(
+            testnet.prepare_key(&zxviews),
+            Err(ScanError::NetworkMismatch { .. })
+        ));
+        assert!(matches!(
+            scanner.inspect_key(&"ff".repeat(128)),
+            Err(ScanError::InvalidViewingKey(_))
+        ));
+    }
+
+    #[cfg(feature = "spending-key")]
+    #[test]
+    fn test_spending_key() {
+        let usk = UnifiedSpendingKey::from_seed(
+            &Network::MainNetwork,
+            &[0u8; 32],
+            zip32::AccountId::ZERO,
+        )
+        .unwrap();
+        let bytes = usk.to_bytes(Era::Orchard);
+        let scanner = Scanner::mainnet();
+        assert_eq!(scanner.spending_key_ufvk(&bytes).unwrap(), test_ufvk());
+
+        let request = test_request(vec![block_paying(2_000_001, &test_ufvk(), 50_000)]);
+        let expected = serde_json::to_value(scanner.scan(&request).unwrap()).unwrap();
+        for key in [
+            scanner.prepare_spending_key(&usk),
+            scanner.prepare_spending_key_bytes(&bytes).unwrap(),
+        ] {
+            assert!(!key.incoming_only());
+            let found = scanner
+                .scan_with_key(&key, &request, &mut ScanState::default())
+                .unwrap();
+            assert_eq!(found.len(), 1);
+            assert_eq!(serde_json::to_value(found).unwrap(), expected);
+        }
+
+        for bad in [&[][..], &bytes[..bytes.len() - 1]] {
+            assert!(matches!(
+                scanner.prepare_spending_key_bytes(bad),
+                Err(ScanError::InvalidViewingKey(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_incoming_viewing_key() {
+        let usk = UnifiedSpendingKey::from_seed(
+            &Network::MainNetwork,
+            &[0u8; 32],
+            zip32::AccountId::ZERO,
+        )
+        .unwrap();
+        let uivk = usk
+            .to_unified_full_viewing_key()
+            .to_unified_incoming_viewing_key()
+            .encode(&Network::MainNetwork);
+
+        // A UIVK finds the same received notes as its UFVK, flagged
+        // incoming-only and without nullifiers to track spends by
+        let scanner = Scanner::mainnet();
+        let request = test_request(vec![block_paying(2_000_001, &test_ufvk(), 50_000)]);
+        let expected = scanner.scan_summary(&request).unwrap();
+        assert!(!expected.incoming_only);
+        let request = ScanRequest {
+            viewing_key: uivk.clone(),
+            ..request
+        };
+        assert!(scanner.prepare_key(&uivk).unwrap().incoming_only());
+        let summary = scanner.scan_summary(&request).unwrap();
+        assert!(summary.incoming_only);
+        assert_eq!(summary.transactions.len(), 1);
+        assert_eq!(
+            serde_json::to_value(&summary.transactions).unwrap(),
+            serde_json::to_value(&expected.transactions).unwrap()
+        );
+        assert!(summary.unspent_notes.is_empty());
+
+        let request = ScanRequest {
+            options: ScanOptions {
+                pools: vec![ShieldedPool::Orchard],
+                ..Default::default()
+            },
+            ..request
+        };
+        assert!(scanner.scan(&request).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_network_mismatch() {
+        let testnet_key = test_ufvk_for(&Network::TestNetwork);
+        let request = ScanRequest {
+            viewing_key: testnet_key.clone(),
+            ..test_request(Vec::new())
+        };
+        let err = Scanner::mainnet().scan(&request).unwrap_err();
+        assert!(matches!(
+            err,
+            ScanError::NetworkMismatch {
+                key_network: KeyNetwork::Testnet,
+                scanner_network: KeyNetwork::Mainnet,
+            }
+        ));
+        assert_eq!(err.code(), "NETWORK_MISMATCH");
+        assert_eq!(
+            err.to_string(),
+            "Viewing key is for testnet, but the scanner is for mainnet"
+        );
+
+        // Only the prefix is read, so a mistyped key still reports it
+        let mistyped = format!("{}x", &testnet_key[..testnet_key.len() - 1]);
+        assert!(matches!(
+            Scanner::mainnet().prepare_key(&mistyped),
+            Err(ScanError::NetworkMismatch { .. })
+        ));
+        let uivk = UnifiedSpendingKey::from_seed(
+            &Network::TestNetwork,
+            &[0u8; 32],
+            zip32::AccountId::ZERO,
+        )
+        .unwrap()
+        .to_unified_full_viewing_key()
+        .to_unified_incoming_viewing_key()
+        .encode(&Network::TestNetwork);
+        assert!(matches!(
+            Scanner::mainnet().prepare_key(&uivk),
+            Err(ScanError::NetworkMismatch { .. })
+        ));
+
+        // Or the network can be picked from the key
+        let scanner = Scanner::for_key(&testnet_key).unwrap();
+        assert_eq!(scanner.params().network_type(), NetworkType::Test);
+        assert!(scanner.scan(&request).unwrap().is_empty());
+        let builder = Scanner::builder().range_chunk_size(10);
+        let scanner = builder.network_for_key(&test_ufvk()).unwrap().build();
+        assert_eq!(scanner.params().network_type(), NetworkType::Main);
+        for key in ["uviewregtest1qqqq".to_string(), "ff".repeat(128)] {
+            assert!(matches!(
+                Scanner::for_key(&key),
+                Err(ScanError::InvalidViewingKey(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_custom_params() {
+        let local = LocalNetwork {
+            overwinter: Some(BlockHeight::from_u32(1)),
+            sapling: Some(BlockHeight::from_u32(1)),
+            blossom: Some(BlockHeight::from_u32(1)),
+            heartwood: Some(BlockHeight::from_u32(1)),
+            canopy: Some(BlockHeight::from_u32(1)),
+            nu5: Some(BlockHeight::from_u32(1)),
+            nu6: None,
+            nu6_1: None,
+        };
+        let scanner = Scanner::with_params(local);
+
+        // Keys are encoded for the custom network's type (regtest here)
+        let mut request = test_request(Vec::new());
+        assert!(matches!(
+            scanner.scan(&request),
+            Err(ScanError::NetworkMismatch {
+                key_network: KeyNetwork::Mainnet,
+                scanner_network: KeyNetwork::Regtest,
+            })
+        ));
+        request.viewing_key = test_ufvk_for(&local);
+        assert!(scanner.scan(&request).unwrap().is_empty());
+
+        // Sapling is active from height 1, so tree sizes are required
+        request.compact_blocks = empty_chain(1, 1);
+        assert!(matches!(
+            scanner.scan(&request),
+            Err(ScanError::ScanFailed { height: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn test_decode_memo() {
+        let text = MemoBytes::from_bytes(b"invoice 42").unwrap();
+        assert_eq!(decode_memo(&text), Some("invoice 42".to_string()));
+
+        assert_eq!(decode_memo(&MemoBytes::empty()), None);
+    }
 }
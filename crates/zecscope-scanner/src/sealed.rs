@@ -0,0 +1,314 @@
+//! Envelope encryption of a [`KeyRegistry`]'s viewing keys for storage.
+
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::{Key, XChaCha20Poly1305, XNonce};
+use serde::{Deserialize, Serialize};
+use zeroize::{Zeroize, ZeroizeOnDrop, Zeroizing};
+
+use crate::error::{ScanError, ScanResult};
+use crate::nullifiers::NullifierSet;
+use crate::registry::{KeyRegistry, RegisteredKey};
+
+/// Version of the [`SealedRegistry`] format.
+const SEALED_VERSION: u32 = 1;
+
+/// Length of an XChaCha20-Poly1305 nonce, which prefixes every ciphertext.
+const NONCE_LEN: usize = 24;
+
+/// Associated data binding the wrapped data key to the format version.
+const DATA_KEY_AAD: &[u8] = b"zecscope sealed registry v1";
+
+/// A 256-bit key-encryption key (KEK), supplied by the caller (e.g. from a
+/// KMS or a secrets manager) to seal and unlock a registry. It is wiped
+/// from memory when dropped, and never printed.
+#[derive(Clone, Zeroize, ZeroizeOnDrop)]
+pub struct KeyEncryptionKey([u8; 32]);
+
+impl KeyEncryptionKey {
+    /// Use `bytes` as the key.
+    pub fn from_bytes(bytes: [u8; 32]) -> Self {
+        Self(bytes)
+    }
+
+    /// Decode a key from 64 hex characters.
+    pub fn from_hex(hex: &str) -> ScanResult<Self> {
+        let mut bytes = [0; 32];
+        hex::decode_to_slice(hex.trim(), &mut bytes)
+            .map_err(|e| ScanError::KeyEncryption(format!("invalid key-encryption key: {e}")))?;
+        Ok(Self(bytes))
+    }
+
+    /// Generate a random key.
+    pub fn generate() -> Self {
+        Self(XChaCha20Poly1305::generate_key(&mut OsRng).into())
+    }
+
+    fn cipher(&self) -> XChaCha20Poly1305 {
+        XChaCha20Poly1305::new(Key::from_slice(&self.0))
+    }
+}
+
+impl std::fmt::Debug for KeyEncryptionKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("KeyEncryptionKey(..)")
+    }
+}
+
+/// A [`KeyRegistry`] with its viewing keys encrypted, safe to write to disk
+/// or a database.
+///
+/// Sealing draws a fresh data key, encrypts every viewing key with it
+/// (bound to the key's tenant and key id, so ciphertexts can't be swapped
+/// between entries), and stores the data key wrapped by the caller's
+/// [`KeyEncryptionKey`]. Tenants, key ids and scan heights stay readable,
+/// so a service can list what it holds without unlocking. Rotating the KEK
+/// with [`SealedRegistry::rekey`] only rewraps the data key.
+///
+/// Both ciphers are XChaCha20-Poly1305; hex fields hold the 24-byte nonce
+/// followed by the ciphertext.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SealedRegistry {
+    /// Format version, currently 1
+    pub version: u32,
+    /// The data key, encrypted with the key-encryption key (hex)
+    pub wrapped_data_key: String,
+    /// The registered keys, with their viewing keys encrypted
+    pub keys: Vec<SealedKey>,
+}
+
+/// A [`RegisteredKey`] whose viewing key is encrypted.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SealedKey {
+    /// Tenant the key belongs to
+    pub tenant: String,
+    /// Identifier of the key within its tenant
+    pub key_id: String,
+    /// The viewing key, encrypted with the registry's data key (hex)
+    pub encrypted_viewing_key: String,
+    /// Height to start scanning the key from
+    pub birthday: u64,
+    /// Last height scanned with the key, if any
+    #[serde(default)]
+    pub last_scanned_height: Option<u64>,
+    /// Notes found with the key that weren't spent by the last height
+    /// scanned
+    #[serde(default)]
+    pub nullifiers: NullifierSet,
+}
+
+impl KeyRegistry {
+    /// Encrypt the registry's viewing keys with a fresh data key wrapped by
+    /// `kek`, for storage. Restore it with [`SealedRegistry::unlock`].
+    pub fn seal(&self, kek: &KeyEncryptionKey) -> ScanResult<SealedRegistry> {
+        let data_key = generate_data_key();
+        let cipher = XChaCha20Poly1305::new(Key::from_slice(data_key.as_slice()));
+        let keys = self
+            .keys()
+            .map(|key| {
+                Ok(SealedKey {
+                    tenant: key.tenant.clone(),
+                    key_id: key.key_id.clone(),
+                    encrypted_viewing_key: encrypt(
+                        &cipher,
+                        key.viewing_key.as_bytes(),
+                        &key_aad(&key.tenant, &key.key_id),
+                    )?,
+                    birthday: key.birthday,
+                    last_scanned_height: key.last_scanned_height,
+                    nullifiers: key.nullifiers.clone(),
+                })
+            })
+            .collect::<ScanResult<_>>()?;
+        Ok(SealedRegistry {
+            version: SEALED_VERSION,
+            wrapped_data_key: encrypt(&kek.cipher(), data_key.as_slice(), DATA_KEY_AAD)?,
+            keys,
+        })
+    }
+}
+
+impl SealedRegistry {
+    /// Decrypt the viewing keys with `kek`, giving back the registry.
+    ///
+    /// Fails with [`ScanError::KeyEncryption`] if `kek` isn't the key the
+    /// registry was sealed with, or the sealed data has been tampered with.
+    pub fn unlock(&self, kek: &KeyEncryptionKey) -> ScanResult<KeyRegistry> {
+        let data_key = self.data_key(kek)?;
+        let cipher = XChaCha20Poly1305::new(Key::from_slice(data_key.as_slice()));
+        let keys = self
+            .keys
+            .iter()
+            .map(|key| {
+                let viewing_key = decrypt(
+                    &cipher,
+                    &key.encrypted_viewing_key,
+                    &key_aad(&key.tenant, &key.key_id),
+                )?;
+                Ok(RegisteredKey {
+                    tenant: key.tenant.clone(),
+                    key_id: key.key_id.clone(),
+                    viewing_key: String::from_utf8(viewing_key.to_vec()).map_err(|_| {
+                        ScanError::KeyEncryption("decrypted viewing key isn't UTF-8".to_string())
+                    })?,
+                    birthday: key.birthday,
+                    last_scanned_height: key.last_scanned_height,
+                    nullifiers: key.nullifiers.clone(),
+                })
+            })
+            .collect::<ScanResult<Vec<_>>>()?;
+        Ok(KeyRegistry::from(keys))
+    }
+
+    /// Rewrap the data key from `old` to `new`, leaving the encrypted
+    /// viewing keys as they are.
+    pub fn rekey(&self, old: &KeyEncryptionKey, new: &KeyEncryptionKey) -> ScanResult<Self> {
+        let data_key = self.data_key(old)?;
+        Ok(Self {
+            wrapped_data_key: encrypt(&new.cipher(), data_key.as_slice(), DATA_KEY_AAD)?,
+            ..self.clone()
+        })
+    }
+
+    fn data_key(&self, kek: &KeyEncryptionKey) -> ScanResult<Zeroizing<[u8; 32]>> {
+        if self.version != SEALED_VERSION {
+            return Err(ScanError::KeyEncryption(format!(
+                "unsupported sealed registry version {}",
+                self.version
+            )));
+        }
+        let bytes = decrypt(&kek.cipher(), &self.wrapped_data_key, DATA_KEY_AAD)?;
+        let bytes: [u8; 32] = bytes.as_slice().try_into().map_err(|_| {
+            ScanError::KeyEncryption("wrapped data key has the wrong length".to_string())
+        })?;
+        Ok(Zeroizing::new(bytes))
+    }
+}
+
+/// A random key to encrypt a registry's viewing keys with.
+fn generate_data_key() -> Zeroizing<[u8; 32]> {
+    Zeroizing::new(XChaCha20Poly1305::generate_key(&mut OsRng).into())
+}
+
+/// Associated data of a viewing key's ciphertext: its tenant and key id.
+/// The tenant is length-prefixed, as either may contain any byte.
+fn key_aad(tenant: &str, key_id: &str) -> Vec<u8> {
+    let tenant_len = (tenant.len() as u32).to_le_bytes();
+    [&tenant_len, tenant.as_bytes(), key_id.as_bytes()].concat()
+}
+
+fn encrypt(cipher: &XChaCha20Poly1305, plaintext: &[u8], aad: &[u8]) -> ScanResult<String> {
+    let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let ciphertext = cipher
+        .encrypt(
+            &nonce,
+            Payload {
+                msg: plaintext,
+                aad,
+            },
+        )
+        .map_err(|_| ScanError::KeyEncryption("encryption failed".to_string()))?;
+    Ok(hex::encode([nonce.as_slice(), &ciphertext].concat()))
+}
+
+fn decrypt(cipher: &XChaCha20Poly1305, sealed: &str, aad: &[u8]) -> ScanResult<Zeroizing<Vec<u8>>> {
+    let sealed = hex::decode(sealed).map_err(|e| ScanError::KeyEncryption(e.to_string()))?;
+    if sealed.len() < NONCE_LEN {
+        return Err(ScanError::KeyEncryption("ciphertext too short".to_string()));
+    }
+    let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+    cipher
+        .decrypt(
+            XNonce::from_slice(nonce),
+            Payload {
+                msg: ciphertext,
+                aad,
+            },
+        )
+        .map(Zeroizing::new)
+        .map_err(|_| {
+            ScanError::KeyEncryption(
+                "decryption failed: wrong key-encryption key or tampered data".to_string(),
+            )
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Scanner;
+    use zcash_keys::keys::UnifiedSpendingKey;
+    use zcash_protocol::consensus::Network;
+
+    fn registry() -> (KeyRegistry, String) {
+        let ufvk =
+            UnifiedSpendingKey::from_seed(&Network::MainNetwork, &[1; 32], zip32::AccountId::ZERO)
+                .unwrap()
+                .to_unified_full_viewing_key()
+                .encode(&Network::MainNetwork);
+        let scanner = Scanner::mainnet();
+        let mut registry = KeyRegistry::new();
+        registry
+            .insert(&scanner, "alice", "main", &ufvk, 2_000_000)
+            .unwrap();
+        registry
+            .insert(&scanner, "bob", "main", &ufvk, 2_100_000)
+            .unwrap();
+        (registry, ufvk)
+    }
+
+    #[test]
+    fn test_seal_and_unlock() {
+        let (registry, ufvk) = registry();
+        let kek = KeyEncryptionKey::generate();
+        let sealed = registry.seal(&kek).unwrap();
+        let json = serde_json::to_string(&sealed).unwrap();
+        assert!(!json.contains(&ufvk));
+        assert!(json.contains(r#""tenant":"alice""#));
+        // Each seal uses its own data key and nonces
+        assert_ne!(registry.seal(&kek).unwrap(), sealed);
+
+        let sealed: SealedRegistry = serde_json::from_str(&json).unwrap();
+        assert_eq!(sealed.unlock(&kek).unwrap(), registry);
+        let wrong = KeyEncryptionKey::generate();
+        let err = sealed.unlock(&wrong).unwrap_err();
+        assert!(matches!(err, ScanError::KeyEncryption(_)));
+
+        let rekeyed = sealed.rekey(&kek, &wrong).unwrap();
+        assert_eq!(rekeyed.keys, sealed.keys);
+        assert_eq!(rekeyed.unlock(&wrong).unwrap(), registry);
+        assert!(rekeyed.unlock(&kek).is_err());
+        assert_eq!(format!("{kek:?}"), "KeyEncryptionKey(..)");
+    }
+
+    #[test]
+    fn test_unlock_tampered() {
+        let (registry, ufvk) = registry();
+        let kek = KeyEncryptionKey::from_hex(&"11".repeat(32)).unwrap();
+        assert!(KeyEncryptionKey::from_hex("11").is_err());
+
+        // Moving a ciphertext to another entry is detected
+        let mut sealed = registry.seal(&kek).unwrap();
+        sealed.keys[1].encrypted_viewing_key = sealed.keys[0].encrypted_viewing_key.clone();
+        assert!(sealed.unlock(&kek).is_err());
+
+        let mut sealed = registry.seal(&kek).unwrap();
+        sealed.keys[0].tenant = "mallory".to_string();
+        assert!(sealed.unlock(&kek).is_err());
+
+        // Nor can the tenant's end be moved into the key id
+        let mut registry = KeyRegistry::new();
+        registry
+            .insert(&Scanner::mainnet(), "a\0b", "c", &ufvk, 2_000_000)
+            .unwrap();
+        let mut sealed = registry.seal(&kek).unwrap();
+        sealed.keys[0].tenant = "a".to_string();
+        sealed.keys[0].key_id = "b\0c".to_string();
+        assert!(sealed.unlock(&kek).is_err());
+
+        let mut sealed = registry.seal(&kek).unwrap();
+        sealed.version = 2;
+        assert!(sealed.unlock(&kek).is_err());
+    }
+}
@@ -0,0 +1,53 @@
+//! Block sources for fetch-and-scan workflows.
+
+use crate::error::{ScanError, ScanResult};
+use crate::types::CompactBlock;
+
+/// Somewhere compact blocks can be fetched from by height.
+///
+/// Implement this for your own transport (lightwalletd, an HTTP API, files on
+/// disk) to use [`Scanner::scan_range`].
+///
+/// [`Scanner::scan_range`]: crate::Scanner::scan_range
+pub trait BlockSource {
+    /// Fetch the blocks in the inclusive range `start..=end`, in ascending
+    /// height order.
+    fn get_block_range(&mut self, start: u64, end: u64) -> ScanResult<Vec<CompactBlock>>;
+}
+
+impl<S: BlockSource + ?Sized> BlockSource for &mut S {
+    fn get_block_range(&mut self, start: u64, end: u64) -> ScanResult<Vec<CompactBlock>> {
+        (**self).get_block_range(start, end)
+    }
+}
+
+/// A [`BlockSource`] backed by blocks already held in memory.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryBlockSource {
+    blocks: Vec<CompactBlock>,
+}
+
+impl MemoryBlockSource {
+    /// Create a source from a set of blocks (in any order).
+    pub fn new(mut blocks: Vec<CompactBlock>) -> Self {
+        blocks.sort_by_key(|b| b.height);
+        Self { blocks }
+    }
+}
+
+impl BlockSource for MemoryBlockSource {
+    fn get_block_range(&mut self, start: u64, end: u64) -> ScanResult<Vec<CompactBlock>> {
+        let blocks: Vec<_> = self
+            .blocks
+            .iter()
+            .filter(|b| (start..=end).contains(&b.height))
+            .cloned()
+            .collect();
+        if blocks.len() as u64 != end - start + 1 {
+            return Err(ScanError::BlockSource(format!(
+                "blocks {start}..={end} not available"
+            )));
+        }
+        Ok(blocks)
+    }
+}
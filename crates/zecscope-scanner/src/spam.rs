@@ -0,0 +1,145 @@
+//! Telling dust storms apart from real payments.
+//!
+//! Anyone who knows an address can flood it with tiny notes, and chain-wide
+//! spam runs pad blocks with transactions of hundreds of outputs. Neither
+//! can be refused, so [`ScanOptions::flag_spam`](crate::ScanOptions::flag_spam)
+//! tags the entries that look like them as `likely_spam` for wallets to
+//! de-emphasize. The heuristics only ever flag dust: a note worth spending
+//! is never hidden.
+
+use std::collections::HashMap;
+
+use zcash_client_backend::proto::compact_formats;
+
+use crate::types::{TxDirection, ZecTransaction};
+
+/// Notes worth less than this many zatoshis, the ZIP 317 marginal fee, cost
+/// more to spend than they hold.
+const DUST_THRESHOLD_ZAT: u64 = 5_000;
+
+/// Transactions with at least this many shielded outputs (Sapling outputs
+/// plus Orchard actions) are taken for dust storms.
+const STORM_TX_OUTPUTS: usize = 50;
+
+/// Dust notes of the same value received this many times in a block are
+/// taken for spam.
+const REPEATED_DUST: usize = 3;
+
+/// The dust storms of a block: its transactions of at least
+/// [`STORM_TX_OUTPUTS`] shielded outputs, but for the wallet's own.
+#[derive(Debug, Default)]
+pub(crate) struct DustStorms {
+    /// Shielded outputs of each storm transaction, by txid
+    txids: HashMap<String, usize>,
+    /// Shielded outputs of the storm transactions
+    pub(crate) outputs: usize,
+    /// Shielded outputs of the block
+    pub(crate) total_outputs: usize,
+}
+
+impl DustStorms {
+    /// Find the dust storms of `block`.
+    pub(crate) fn in_block(block: &compact_formats::CompactBlock) -> Self {
+        let mut storms = Self::default();
+        for tx in &block.vtx {
+            let outputs = tx.outputs.len() + tx.actions.len();
+            storms.add(&hex::encode(&tx.hash), outputs);
+        }
+        storms
+    }
+
+    /// Count a transaction with `outputs` shielded outputs.
+    pub(crate) fn add(&mut self, txid: &str, outputs: usize) {
+        self.total_outputs += outputs;
+        if outputs >= STORM_TX_OUTPUTS {
+            self.outputs += outputs;
+            self.txids.insert(txid.to_string(), outputs);
+        }
+    }
+
+    /// Stop counting the transaction `txid` as a storm: one spending the
+    /// wallet's notes is the wallet's own, however many outputs it has.
+    pub(crate) fn exclude(&mut self, txid: &str) {
+        if let Some(outputs) = self.txids.remove(txid) {
+            self.outputs -= outputs;
+        }
+    }
+
+    /// Share of the block's shielded outputs in its dust storms, 0 for a
+    /// block without any.
+    pub(crate) fn ratio(&self) -> f64 {
+        match self.total_outputs {
+            0 => 0.0,
+            total => self.outputs as f64 / total as f64,
+        }
+    }
+
+    /// Tag the incoming dust entries of `transactions`, the entries found in
+    /// one block, as `likely_spam` if they came in a dust storm or their
+    /// value was received at least [`REPEATED_DUST`] times in the block.
+    pub(crate) fn flag(&self, transactions: &mut [ZecTransaction]) {
+        let is_dust = |tx: &ZecTransaction| {
+            tx.direction == TxDirection::In
+                && !tx.is_change
+                && tx.amount_zatoshis() < DUST_THRESHOLD_ZAT as i64
+        };
+        let mut repeats: HashMap<String, usize> = HashMap::new();
+        for tx in transactions.iter().filter(|tx| is_dust(tx)) {
+            *repeats.entry(tx.amount_zat.clone()).or_default() += 1;
+        }
+        for tx in transactions.iter_mut().filter(|tx| is_dust(tx)) {
+            tx.likely_spam |=
+                self.txids.contains_key(&tx.txid) || repeats[&tx.amount_zat] >= REPEATED_DUST;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::ShieldedPool;
+
+    fn test_tx(txid: &str, amount_zat: u64, direction: TxDirection) -> ZecTransaction {
+        ZecTransaction {
+            output_index: Some(0),
+            ..ZecTransaction::test_entry(txid, amount_zat, direction, ShieldedPool::Sapling)
+        }
+    }
+
+    #[test]
+    fn test_dust_storms() {
+        let mut storms = DustStorms::default();
+        assert_eq!(storms.ratio(), 0.0);
+        storms.add("aa", 2);
+        storms.add("bb", 198);
+        assert_eq!((storms.outputs, storms.total_outputs), (198, 200));
+        assert_eq!(storms.ratio(), 0.99);
+        let mut own = DustStorms::default();
+        own.add("cc", 60);
+        own.exclude("cc");
+        own.exclude("dd");
+        assert_eq!((own.outputs, own.ratio()), (0, 0.0));
+
+        let mut txs = vec![
+            // Dust in a storm
+            test_tx("bb", 1_000, TxDirection::In),
+            // A real payment in a storm
+            test_tx("bb", 5_000, TxDirection::In),
+            // The same dust three times, outside any storm
+            test_tx("c1", 10, TxDirection::In),
+            test_tx("c2", 10, TxDirection::In),
+            test_tx("c3", 10, TxDirection::In),
+            // Dust only twice
+            test_tx("d1", 20, TxDirection::In),
+            test_tx("d2", 20, TxDirection::In),
+            // What the wallet sent is never spam
+            test_tx("bb", 1_000, TxDirection::Out),
+        ];
+        storms.flag(&mut txs);
+        let flagged: Vec<_> = txs.iter().map(|tx| tx.likely_spam).collect();
+        assert_eq!(
+            flagged,
+            [true, false, true, true, true, false, false, false]
+        );
+    }
+}
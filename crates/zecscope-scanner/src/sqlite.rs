@@ -0,0 +1,493 @@
+//! SQLite persistence for scan results (requires the `sqlite` feature).
+
+use std::path::Path;
+
+use rusqlite::{params, Connection, OptionalExtension, Row};
+
+use crate::error::{ScanError, ScanResult};
+use crate::notes::StoredNote;
+use crate::state::{ScanState, TrackedNote};
+use crate::types::{BlockScanResult, KeyScope, ShieldedPool, TxDirection, ZecTransaction};
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS transactions (
+    key_id TEXT NOT NULL,
+    txid TEXT NOT NULL,
+    height INTEGER NOT NULL,
+    time INTEGER NOT NULL,
+    amount_zat TEXT NOT NULL,
+    direction TEXT NOT NULL,
+    memo TEXT,
+    pool TEXT NOT NULL,
+    is_change INTEGER NOT NULL,
+    address TEXT,
+    fee_zat TEXT,
+    fiat_value TEXT,
+    fiat_currency TEXT,
+    diversifier_index INTEGER,
+    output_index INTEGER,
+    action_index INTEGER,
+    scope TEXT,
+    account_id INTEGER,
+    invoice_id TEXT,
+    note_position INTEGER,
+    is_coinbase INTEGER NOT NULL DEFAULT 0,
+    self_transfer INTEGER NOT NULL DEFAULT 0,
+    likely_spam INTEGER NOT NULL DEFAULT 0,
+    recipient_address TEXT,
+    expiry_height INTEGER
+);
+CREATE INDEX IF NOT EXISTS transactions_key_height ON transactions (key_id, height);
+
+CREATE TABLE IF NOT EXISTS notes (
+    key_id TEXT NOT NULL,
+    pool TEXT NOT NULL,
+    nullifier TEXT NOT NULL,
+    value_zat TEXT NOT NULL,
+    height INTEGER NOT NULL,
+    txid TEXT NOT NULL,
+    position INTEGER,
+    spent_txid TEXT,
+    spent_height INTEGER,
+    PRIMARY KEY (key_id, pool, nullifier)
+);
+
+CREATE TABLE IF NOT EXISTS scanned_ranges (
+    key_id TEXT NOT NULL,
+    start_height INTEGER NOT NULL,
+    end_height INTEGER NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS scan_states (
+    key_id TEXT PRIMARY KEY,
+    state TEXT NOT NULL
+);
+";
+
+/// A SQLite database of discovered transactions, notes and scanned ranges.
+///
+/// Rows are partitioned by `key_id`, so one database can serve several
+/// viewing keys. Feed it each [`BlockScanResult`] from [`Scanner::scan_with`]
+/// via [`SqliteWalletStore::put_block`]; storing a block again replaces its
+/// transactions, so rescans are idempotent.
+///
+/// [`Scanner::scan_with`]: crate::Scanner::scan_with
+pub struct SqliteWalletStore {
+    conn: Connection,
+}
+
+impl SqliteWalletStore {
+    /// Open (creating if needed) the database at `path`.
+    pub fn open(path: impl AsRef<Path>) -> ScanResult<Self> {
+        Self::with_connection(Connection::open(path).map_err(storage_error)?)
+    }
+
+    /// Open a fresh in-memory database.
+    pub fn open_in_memory() -> ScanResult<Self> {
+        Self::with_connection(Connection::open_in_memory().map_err(storage_error)?)
+    }
+
+    fn with_connection(conn: Connection) -> ScanResult<Self> {
+        conn.execute_batch(SCHEMA).map_err(storage_error)?;
+        Ok(Self { conn })
+    }
+
+    /// Store the transactions and notes of a scanned block and mark it scanned.
+    pub fn put_block(&mut self, key_id: &str, block: &BlockScanResult) -> ScanResult<()> {
+        let tx = self.conn.transaction().map_err(storage_error)?;
+        let height = block.height as i64;
+
+        tx.execute(
+            "DELETE FROM transactions WHERE key_id = ?1 AND height = ?2",
+            params![key_id, height],
+        )
+        .map_err(storage_error)?;
+        for t in &block.transactions {
+            tx.execute(
+                "INSERT INTO transactions
+                     (key_id, txid, height, time, amount_zat, direction, memo, pool, is_change, address, fee_zat, fiat_value, fiat_currency, diversifier_index, output_index, action_index, scope, account_id, invoice_id, note_position, is_coinbase, self_transfer, recipient_address, expiry_height, likely_spam)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19, ?20, ?21, ?22, ?23, ?24, ?25)",
+                params![
+                    key_id,
+                    t.txid,
+                    t.height as i64,
+                    t.time,
+                    t.amount_zat,
+                    direction_name(t.direction),
+                    t.memo,
+                    t.pool.to_string(),
+                    t.is_change,
+                    t.address,
+                    t.fee_zat,
+                    t.fiat_value,
+                    t.fiat_currency,
+                    t.diversifier_index.map(|i| i as i64),
+                    t.output_index,
+                    t.action_index,
+                    t.scope.map(|scope| scope.to_string()),
+                    t.account_id,
+                    t.invoice_id,
+                    t.note_position.map(|p| p as i64),
+                    t.is_coinbase,
+                    t.self_transfer,
+                    t.recipient_address,
+                    t.expiry_height.map(|h| h as i64),
+                    t.likely_spam,
+                ],
+            )
+            .map_err(storage_error)?;
+        }
+
+        let spent = block.spent_notes.iter().map(|s| &s.note);
+        for note in block.received_notes.iter().chain(spent) {
+            tx.execute(
+                "INSERT OR IGNORE INTO notes
+                     (key_id, pool, nullifier, value_zat, height, txid, position)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                params![
+                    key_id,
+                    note.pool.to_string(),
+                    note.nullifier,
+                    note.value_zat,
+                    note.height as i64,
+                    note.txid,
+                    note.position.map(|p| p as i64),
+                ],
+            )
+            .map_err(storage_error)?;
+        }
+        for spend in &block.spent_notes {
+            tx.execute(
+                "UPDATE notes SET spent_txid = ?4, spent_height = ?5
+                 WHERE key_id = ?1 AND pool = ?2 AND nullifier = ?3",
+                params![
+                    key_id,
+                    spend.note.pool.to_string(),
+                    spend.note.nullifier,
+                    spend.spent_txid,
+                    spend.spent_height as i64,
+                ],
+            )
+            .map_err(storage_error)?;
+        }
+
+        // Extend a range ending at the previous block, or start a new one
+        let already_scanned: bool = tx
+            .query_row(
+                "SELECT EXISTS (SELECT 1 FROM scanned_ranges
+                 WHERE key_id = ?1 AND start_height <= ?2 AND end_height >= ?2)",
+                params![key_id, height],
+                |row| row.get(0),
+            )
+            .map_err(storage_error)?;
+        if !already_scanned {
+            let extended = tx
+                .execute(
+                    "UPDATE scanned_ranges SET end_height = ?2
+                     WHERE key_id = ?1 AND end_height = ?2 - 1",
+                    params![key_id, height],
+                )
+                .map_err(storage_error)?;
+            if extended == 0 {
+                tx.execute(
+                    "INSERT INTO scanned_ranges (key_id, start_height, end_height)
+                     VALUES (?1, ?2, ?2)",
+                    params![key_id, height],
+                )
+                .map_err(storage_error)?;
+            }
+        }
+
+        tx.commit().map_err(storage_error)
+    }
+
+    /// All stored transactions for `key_id`, ordered by height.
+    pub fn transactions(&self, key_id: &str) -> ScanResult<Vec<ZecTransaction>> {
+        let mut stmt = self
+            .conn
+            .prepare(
+                "SELECT txid, height, time, amount_zat, direction, memo, key_id, pool, is_change, address, fee_zat, fiat_value, fiat_currency, diversifier_index, output_index, action_index, scope, account_id, invoice_id, note_position, is_coinbase, self_transfer, recipient_address, expiry_height, likely_spam
+                 FROM transactions WHERE key_id = ?1 ORDER BY height, rowid",
+            )
+            .map_err(storage_error)?;
+        let rows = stmt
+            .query_map(params![key_id], |row| {
+                Ok(ZecTransaction {
+                    txid: row.get(0)?,
+                    height: row.get::<_, i64>(1)? as u64,
+                    time: row.get(2)?,
+                    amount_zat: row.get(3)?,
+                    direction: parse_direction(row.get_ref(4)?.as_str()?),
+                    memo: row.get(5)?,
+                    key_id: row.get(6)?,
+                    pool: parse_pool(row.get_ref(7)?.as_str()?),
+                    is_change: row.get(8)?,
+                    address: row.get(9)?,
+                    recipient_address: row.get(22)?,
+                    fee_zat: row.get(10)?,
+                    expiry_height: row.get::<_, Option<i64>>(23)?.map(|h| h as u64),
+                    fiat_value: row.get(11)?,
+                    fiat_currency: row.get(12)?,
+                    diversifier_index: row.get::<_, Option<i64>>(13)?.map(|i| i as u64),
+                    output_index: row.get(14)?,
+                    action_index: row.get(15)?,
+                    unconfirmed: false,
+                    scope: row
+                        .get::<_, Option<String>>(16)?
+                        .as_deref()
+                        .map(parse_scope),
+                    account_id: row.get(17)?,
+                    invoice_id: row.get(18)?,
+                    note_position: row.get::<_, Option<i64>>(19)?.map(|p| p as u64),
+                    is_coinbase: row.get(20)?,
+                    self_transfer: row.get(21)?,
+                    likely_spam: row.get(24)?,
+                })
+            })
+            .map_err(storage_error)?;
+        rows.collect::<Result<_, _>>().map_err(storage_error)
+    }
+
+    /// All stored notes for `key_id`, spent or not, ordered by height.
+    pub fn notes(&self, key_id: &str) -> ScanResult<Vec<StoredNote>> {
+        self.query_notes(key_id, "")
+    }
+
+    /// Notes for `key_id` that haven't been seen spent.
+    pub fn unspent_notes(&self, key_id: &str) -> ScanResult<Vec<StoredNote>> {
+        self.query_notes(key_id, "AND spent_txid IS NULL")
+    }
+
+    fn query_notes(&self, key_id: &str, filter: &str) -> ScanResult<Vec<StoredNote>> {
+        let sql = format!(
+            "SELECT pool, nullifier, value_zat, height, txid, position, spent_txid, spent_height
+             FROM notes WHERE key_id = ?1 {filter} ORDER BY height, nullifier"
+        );
+        let mut stmt = self.conn.prepare(&sql).map_err(storage_error)?;
+        let rows = stmt
+            .query_map(params![key_id], read_note)
+            .map_err(storage_error)?;
+        rows.collect::<Result<_, _>>().map_err(storage_error)
+    }
+
+    /// Scanned height ranges for `key_id`, as inclusive `(start, end)` pairs.
+    pub fn scanned_ranges(&self, key_id: &str) -> ScanResult<Vec<(u64, u64)>> {
+        let mut stmt = self
+            .conn
+            .prepare(
+                "SELECT start_height, end_height FROM scanned_ranges
+                 WHERE key_id = ?1 ORDER BY start_height",
+            )
+            .map_err(storage_error)?;
+        let rows = stmt
+            .query_map(params![key_id], |row| {
+                Ok((row.get::<_, i64>(0)? as u64, row.get::<_, i64>(1)? as u64))
+            })
+            .map_err(storage_error)?;
+        rows.collect::<Result<_, _>>().map_err(storage_error)
+    }
+
+    /// Persist the resumable scan state for `key_id`.
+    pub fn save_state(&self, key_id: &str, state: &ScanState) -> ScanResult<()> {
+        self.conn
+            .execute(
+                "INSERT OR REPLACE INTO scan_states (key_id, state) VALUES (?1, ?2)",
+                params![key_id, serde_json::to_string(state)?],
+            )
+            .map_err(storage_error)?;
+        Ok(())
+    }
+
+    /// Load the scan state saved for `key_id`, if any.
+    pub fn load_state(&self, key_id: &str) -> ScanResult<Option<ScanState>> {
+        let state: Option<String> = self
+            .conn
+            .query_row(
+                "SELECT state FROM scan_states WHERE key_id = ?1",
+                params![key_id],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(storage_error)?;
+        Ok(state.map(|s| serde_json::from_str(&s)).transpose()?)
+    }
+
+    /// Forget everything for `key_id` from `height` up, e.g. after a reorg.
+    ///
+    /// Notes received below `height` but spent at or above it become unspent
+    /// again. Any saved scan state is left alone.
+    pub fn truncate_from(&mut self, key_id: &str, height: u64) -> ScanResult<()> {
+        let tx = self.conn.transaction().map_err(storage_error)?;
+        let height = height as i64;
+        for sql in [
+            "DELETE FROM transactions WHERE key_id = ?1 AND height >= ?2",
+            "DELETE FROM notes WHERE key_id = ?1 AND height >= ?2",
+            "UPDATE notes SET spent_txid = NULL, spent_height = NULL
+             WHERE key_id = ?1 AND spent_height >= ?2",
+            "DELETE FROM scanned_ranges WHERE key_id = ?1 AND start_height >= ?2",
+            "UPDATE scanned_ranges SET end_height = ?2 - 1
+             WHERE key_id = ?1 AND end_height >= ?2",
+        ] {
+            tx.execute(sql, params![key_id, height])
+                .map_err(storage_error)?;
+        }
+        tx.commit().map_err(storage_error)
+    }
+}
+
+fn read_note(row: &Row<'_>) -> rusqlite::Result<StoredNote> {
+    Ok(StoredNote {
+        note: TrackedNote {
+            pool: parse_pool(row.get_ref(0)?.as_str()?),
+            nullifier: row.get(1)?,
+            value_zat: row.get(2)?,
+            height: row.get::<_, i64>(3)? as u64,
+            txid: row.get(4)?,
+            position: row.get::<_, Option<i64>>(5)?.map(|p| p as u64),
+        },
+        spent_txid: row.get(6)?,
+        spent_height: row.get::<_, Option<i64>>(7)?.map(|h| h as u64),
+    })
+}
+
+fn direction_name(direction: TxDirection) -> &'static str {
+    match direction {
+        TxDirection::In => "in",
+        TxDirection::Out => "out",
+    }
+}
+
+fn parse_direction(name: &str) -> TxDirection {
+    if name == "out" {
+        TxDirection::Out
+    } else {
+        TxDirection::In
+    }
+}
+
+fn parse_pool(name: &str) -> ShieldedPool {
+    match name {
+        "orchard" => ShieldedPool::Orchard,
+        "transparent" => ShieldedPool::Transparent,
+        _ => ShieldedPool::Sapling,
+    }
+}
+
+fn parse_scope(name: &str) -> KeyScope {
+    if name == "internal" {
+        KeyScope::Internal
+    } else {
+        KeyScope::External
+    }
+}
+
+fn storage_error(e: rusqlite::Error) -> ScanError {
+    ScanError::Storage(e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::NoteSpend;
+
+    fn note(nullifier: &str, height: u64) -> TrackedNote {
+        TrackedNote {
+            pool: ShieldedPool::Orchard,
+            nullifier: nullifier.to_string(),
+            value_zat: "5000".to_string(),
+            height,
+            txid: format!("{height:02x}"),
+            position: Some(height),
+        }
+    }
+
+    fn block(height: u64) -> BlockScanResult {
+        BlockScanResult {
+            height,
+            hash: String::new(),
+            time: 0,
+            transactions: Vec::new(),
+            received_notes: Vec::new(),
+            decrypted_notes: Vec::new(),
+            spent_notes: Vec::new(),
+            sapling_anchor: None,
+            orchard_anchor: None,
+            skipped_outputs: 0,
+            spam_outputs: 0,
+            spam_ratio: 0.0,
+        }
+    }
+
+    #[test]
+    fn test_sqlite_wallet_store() {
+        let mut store = SqliteWalletStore::open_in_memory().unwrap();
+
+        let mut first = block(10);
+        first.received_notes = vec![note("01", 10)];
+        first.transactions = vec![ZecTransaction {
+            txid: "0a".to_string(),
+            height: 10,
+            time: 0,
+            amount_zat: "5000".to_string(),
+            direction: TxDirection::In,
+            memo: Some("hi".to_string()),
+            key_id: "k".to_string(),
+            pool: ShieldedPool::Orchard,
+            is_change: false,
+            address: None,
+            recipient_address: Some("u1recipient".to_string()),
+            fee_zat: None,
+            expiry_height: Some(30),
+            fiat_value: None,
+            fiat_currency: None,
+            diversifier_index: None,
+            output_index: None,
+            action_index: None,
+            unconfirmed: false,
+            scope: Some(KeyScope::External),
+            account_id: Some(1),
+            invoice_id: Some("INV-7".to_string()),
+            note_position: Some(42),
+            is_coinbase: true,
+            self_transfer: true,
+            likely_spam: true,
+        }];
+        store.put_block("k", &first).unwrap();
+        // Storing a block twice doesn't duplicate it
+        store.put_block("k", &first).unwrap();
+        store.put_block("k", &block(11)).unwrap();
+
+        let mut spend = block(12);
+        spend.spent_notes = vec![NoteSpend {
+            note: note("01", 10),
+            spent_txid: "0c".to_string(),
+            spent_height: 12,
+        }];
+        store.put_block("k", &spend).unwrap();
+        store.put_block("k", &block(20)).unwrap();
+
+        let txs = store.transactions("k").unwrap();
+        assert_eq!(txs.len(), 1);
+        assert_eq!(txs[0].memo.as_deref(), Some("hi"));
+        assert_eq!(txs[0].scope, Some(KeyScope::External));
+        assert_eq!(txs[0].account_id, Some(1));
+        assert_eq!(txs[0].invoice_id.as_deref(), Some("INV-7"));
+        assert_eq!(txs[0].note_position, Some(42));
+        assert!(txs[0].is_coinbase);
+        assert!(txs[0].self_transfer);
+        assert!(txs[0].likely_spam);
+        assert_eq!(txs[0].recipient_address.as_deref(), Some("u1recipient"));
+        assert_eq!(txs[0].expiry_height, Some(30));
+        assert!(store.unspent_notes("k").unwrap().is_empty());
+        assert_eq!(store.scanned_ranges("k").unwrap(), [(10, 12), (20, 20)]);
+        assert!(store.transactions("other").unwrap().is_empty());
+
+        store.truncate_from("k", 11).unwrap();
+        assert_eq!(store.unspent_notes("k").unwrap().len(), 1);
+        assert_eq!(store.scanned_ranges("k").unwrap(), [(10, 10)]);
+
+        assert!(store.load_state("k").unwrap().is_none());
+        store.save_state("k", &ScanState::default()).unwrap();
+        assert!(store.load_state("k").unwrap().is_some());
+    }
+}
@@ -0,0 +1,145 @@
+//! Serializable scan checkpoints for resuming interrupted scans.
+
+use serde::{Deserialize, Serialize};
+use zcash_client_backend::data_api::BlockMetadata;
+use zcash_primitives::block::BlockHash;
+
+use crate::error::ScanResult;
+use crate::scanner::{block_height, decode_hex32};
+use crate::types::ShieldedPool;
+
+/// Everything needed to continue a scan where a previous one stopped.
+///
+/// Pass the same state to successive [`Scanner::scan_with_state`] calls; after
+/// each call it describes the last block that was scanned successfully, even
+/// if the call itself failed part-way through. Persist it (e.g. as JSON)
+/// between calls to survive process restarts.
+///
+/// [`Scanner::scan_with_state`]: crate::Scanner::scan_with_state
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "wasm", derive(tsify::Tsify), tsify(missing_as_null))]
+#[serde(rename_all = "camelCase")]
+pub struct ScanState {
+    /// The last block that was scanned, if any
+    #[serde(default)]
+    pub last_block: Option<BlockCheckpoint>,
+    /// Discovered notes that haven't been seen spent yet
+    #[serde(default)]
+    pub tracked_notes: Vec<TrackedNote>,
+}
+
+impl ScanState {
+    /// Height of the last scanned block, if any.
+    pub fn last_height(&self) -> Option<u64> {
+        self.last_block.as_ref().map(|b| b.height)
+    }
+
+    /// Height the next scan should start from, if known.
+    pub fn next_height(&self) -> Option<u64> {
+        self.last_height().map(|h| h + 1)
+    }
+}
+
+/// Chain position and commitment tree sizes at the end of a scanned block.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "wasm", derive(tsify::Tsify), tsify(missing_as_null))]
+#[serde(rename_all = "camelCase")]
+pub struct BlockCheckpoint {
+    /// Block height
+    pub height: u64,
+    /// Block hash (hex-encoded)
+    pub hash: String,
+    /// Sapling commitment tree size after this block
+    #[serde(default)]
+    pub sapling_tree_size: Option<u32>,
+    /// Orchard commitment tree size after this block
+    #[serde(default)]
+    pub orchard_tree_size: Option<u32>,
+}
+
+impl BlockCheckpoint {
+    pub(crate) fn from_metadata(meta: &BlockMetadata) -> Self {
+        let height: u32 = meta.block_height().into();
+        Self {
+            height: height as u64,
+            hash: hex::encode(meta.block_hash().0),
+            sapling_tree_size: meta.sapling_tree_size(),
+            #[cfg(feature = "orchard")]
+            orchard_tree_size: meta.orchard_tree_size(),
+            #[cfg(not(feature = "orchard"))]
+            orchard_tree_size: None,
+        }
+    }
+
+    pub(crate) fn to_metadata(&self) -> ScanResult<BlockMetadata> {
+        let hash = decode_hex32(&self.hash, "checkpoint hash")?;
+        Ok(BlockMetadata::from_parts(
+            block_height(self.height)?,
+            BlockHash(hash),
+            self.sapling_tree_size,
+            #[cfg(feature = "orchard")]
+            self.orchard_tree_size,
+        ))
+    }
+}
+
+/// A discovered note whose nullifier is watched for spends.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "wasm", derive(tsify::Tsify), tsify(missing_as_null))]
+#[serde(rename_all = "camelCase")]
+pub struct TrackedNote {
+    /// Pool the note belongs to
+    pub pool: ShieldedPool,
+    /// Note nullifier (hex-encoded)
+    pub nullifier: String,
+    /// Note value in zatoshis (as string to avoid precision loss)
+    pub value_zat: String,
+    /// Height of the block the note was received in
+    #[serde(default)]
+    pub height: u64,
+    /// Transaction that created the note (hex-encoded)
+    #[serde(default)]
+    pub txid: String,
+    /// Position of the note commitment in its pool's commitment tree
+    #[serde(default)]
+    pub position: Option<u64>,
+}
+
+impl TrackedNote {
+    /// Get the note value in zatoshis.
+    pub fn value_zatoshis(&self) -> u64 {
+        self.value_zat.parse().unwrap_or(0)
+    }
+}
+
+/// A tracked note seen spent on chain.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NoteSpend {
+    /// The note that was spent
+    pub note: TrackedNote,
+    /// Transaction that spent the note (hex-encoded)
+    pub spent_txid: String,
+    /// Height of the block containing the spend
+    pub spent_height: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_checkpoint_roundtrip() {
+        let checkpoint = BlockCheckpoint {
+            height: 2_000_000,
+            hash: "ab".repeat(32),
+            sapling_tree_size: Some(123),
+            orchard_tree_size: Some(45),
+        };
+        let meta = checkpoint.to_metadata().unwrap();
+        let restored = BlockCheckpoint::from_metadata(&meta);
+        assert_eq!(restored.height, checkpoint.height);
+        assert_eq!(restored.hash, checkpoint.hash);
+        assert_eq!(restored.sapling_tree_size, checkpoint.sapling_tree_size);
+    }
+}
@@ -0,0 +1,109 @@
+//! Known viewing keys, compact blocks and the transactions scanning them
+//! finds, for checking an integration against golden data.
+
+use crate::types::{CompactBlock, ScanOptions, ScanRequest, ShieldedPool, ZecTransaction};
+
+/// Mainnet UFVK of account 0 of the all-zero seed, the key every vector's
+/// blocks pay.
+pub const TEST_UFVK: &str = "uview1lhk8z42t5z50v3hnlvd8kmmny76vzggutq8yqjkj75wks7gn95l4nhdyl02kt5m0xjy44weypu5nvu6j645a8rl5ax4p5y8884reefsgclugmnevqwgxeh9xd8ky68lnz9q4dvmejgxfpp0ku6yzlc27dcqs2zt6xdxacu4ltjg4nzz7ra2t439zycerm0hn7wftgss65m4m2xwlltasx7cj7qd5c0pnn0ey99q7l3dtejsy5udj8sc2ku5gxlxks0z9dsjrf60my2yc7l2yzayfwcdy0z0pwguknvc5cnexd0vnmntv3xzvnlzpnr09uqsfjrgjflgsrdk9w9e0l89zneqlgm9h5w5040k7u7uxwdn0p9d5ztwjt07p7tect8jj8273jxmn0p6pt5n43eju64hk42tkywyyx86udzksedtfrr30y6tqngzu3wsuvgnafmlzvjdaf9gaech533k8s6m7gguja4kcx2kyqhxu57evc5cr3glh";
+
+/// A payment of 125000 zatoshis to [`TEST_UFVK`]'s default Sapling address,
+/// spent three blocks later.
+pub const SAPLING: TestVector = TestVector {
+    name: "sapling",
+    pool: ShieldedPool::Sapling,
+    viewing_key: TEST_UFVK,
+    compact_blocks_json: include_str!("../test-vectors/sapling_blocks.json"),
+    transactions_json: include_str!("../test-vectors/sapling_transactions.json"),
+};
+
+/// A payment of 80000 zatoshis to [`TEST_UFVK`]'s default Orchard address,
+/// spent three blocks later. Only found in builds with the `orchard`
+/// feature.
+pub const ORCHARD: TestVector = TestVector {
+    name: "orchard",
+    pool: ShieldedPool::Orchard,
+    viewing_key: TEST_UFVK,
+    compact_blocks_json: include_str!("../test-vectors/orchard_blocks.json"),
+    transactions_json: include_str!("../test-vectors/orchard_transactions.json"),
+};
+
+/// Mainnet compact blocks and the transactions a scan of them must find.
+///
+/// Blocks are serialized as the JSON API takes them and transactions as it
+/// returns them, so bindings can compare their output directly. Each
+/// transaction also pays two decoy outputs to another key, which a scan
+/// must skip.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TestVector {
+    /// Name of the vector, also the `key_id` it is scanned with
+    pub name: &'static str,
+    /// Pool the vector's notes are in
+    pub pool: ShieldedPool,
+    /// Encoded UFVK to scan with
+    pub viewing_key: &'static str,
+    /// JSON array of the blocks to scan
+    pub compact_blocks_json: &'static str,
+    /// JSON array of the transactions the scan finds, in order
+    pub transactions_json: &'static str,
+}
+
+impl TestVector {
+    /// The blocks to scan.
+    pub fn compact_blocks(&self) -> Vec<CompactBlock> {
+        serde_json::from_str(self.compact_blocks_json).expect("test vector blocks are valid")
+    }
+
+    /// The transactions scanning [`TestVector::request`] finds.
+    pub fn expected_transactions(&self) -> Vec<ZecTransaction> {
+        serde_json::from_str(self.transactions_json).expect("test vector transactions are valid")
+    }
+
+    /// A request scanning the vector's blocks with its key, on mainnet.
+    pub fn request(&self) -> ScanRequest {
+        ScanRequest {
+            viewing_key: self.viewing_key.to_string(),
+            key_id: self.name.to_string(),
+            compact_blocks: self.compact_blocks(),
+            options: ScanOptions::default(),
+            nullifiers: Default::default(),
+        }
+    }
+}
+
+/// The vectors this build's scanner can find notes in.
+pub fn test_vectors() -> Vec<TestVector> {
+    let mut vectors = vec![SAPLING];
+    if cfg!(feature = "orchard") {
+        vectors.push(ORCHARD);
+    }
+    vectors
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Scanner;
+
+    #[test]
+    fn test_vectors_scan() {
+        let scanner = Scanner::mainnet();
+        for vector in test_vectors() {
+            let transactions = scanner.scan(&vector.request()).unwrap();
+            let expected: serde_json::Value =
+                serde_json::from_str(vector.transactions_json).unwrap();
+            assert_eq!(
+                serde_json::to_value(&transactions).unwrap(),
+                expected,
+                "{}",
+                vector.name
+            );
+            assert_eq!(vector.expected_transactions().len(), 2);
+            assert!(transactions.iter().all(|tx| tx.pool == vector.pool));
+        }
+        assert_eq!(
+            test_vectors().len(),
+            if cfg!(feature = "orchard") { 2 } else { 1 }
+        );
+    }
+}
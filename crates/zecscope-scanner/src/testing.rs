@@ -0,0 +1,478 @@
+//! Fabricated compact blocks for tests.
+//!
+//! A [`MockChain`] builds a chain of valid compact blocks paying a viewing
+//! key, so integrations can be tested deterministically without chain data.
+
+use sapling::note_encryption::SaplingDomain;
+use sapling::value::NoteValue;
+use zcash_keys::keys::{UnifiedFullViewingKey, UnifiedSpendingKey};
+use zcash_note_encryption::{Domain, NoteEncryption, COMPACT_NOTE_SIZE};
+use zcash_protocol::consensus::{Network, NetworkUpgrade, Parameters};
+use zip32::Scope;
+
+use crate::error::{ScanError, ScanResult};
+use crate::types::{
+    ChainMetadata, CompactBlock, CompactOrchardAction, CompactSaplingOutput, CompactSaplingSpend,
+    CompactTx, ScanOptions, ScanRequest, ShieldedPool,
+};
+
+/// Time of the first fabricated block (Unix seconds).
+const GENESIS_TIME: u32 = 1_700_000_000;
+
+/// Seconds between fabricated blocks, the post-Blossom target spacing.
+const BLOCK_SPACING: u32 = 75;
+
+/// Seed of the key decoy outputs pay.
+const DECOY_SEED: [u8; 32] = [0xde; 32];
+
+/// A note a [`MockChain`] paid to its key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MockNote {
+    /// Pool the note was paid in
+    pub pool: ShieldedPool,
+    /// Transaction that created the note (hex-encoded, as the scanner
+    /// reports it)
+    pub txid: String,
+    /// Height of the block the note was paid in
+    pub height: u64,
+    /// Note value in zatoshis
+    pub value: u64,
+    nullifier: [u8; 32],
+}
+
+/// A builder of compact blocks paying one viewing key.
+///
+/// Each call appends blocks to the chain: [`MockChain::pay_sapling`] and
+/// [`MockChain::pay_orchard`] a block with one transaction paying the key's
+/// default address, [`MockChain::spend`] one spending a note paid earlier, and
+/// [`MockChain::empty_blocks`] blocks with no transactions. Blocks are linked
+/// by hash and carry consistent commitment tree sizes, so they scan like
+/// real ones. Every transaction can also carry decoy outputs paying another
+/// key, which the scanner must skip.
+///
+/// The output is deterministic: the same calls produce the same blocks.
+///
+/// ```rust,ignore
+/// let mut chain = MockChain::new(&ufvk)?.with_decoys(3);
+/// let note = chain.pay_sapling(50_000)?;
+/// chain.empty_blocks(10);
+/// chain.spend(&note)?;
+///
+/// let transactions = Scanner::mainnet().scan(&chain.scan_request())?;
+/// ```
+pub struct MockChain<P = Network> {
+    params: P,
+    ufvk: UnifiedFullViewingKey,
+    decoy_ufvk: UnifiedFullViewingKey,
+    decoys: usize,
+    next_height: u64,
+    prev_hash: String,
+    sapling_tree_size: u32,
+    orchard_tree_size: u32,
+    // Feeds txids and note randomness
+    counter: u64,
+    blocks: Vec<CompactBlock>,
+}
+
+impl MockChain {
+    /// Start a mainnet chain paying `viewing_key`.
+    pub fn new(viewing_key: &str) -> ScanResult<Self> {
+        Self::with_params(Network::MainNetwork, viewing_key)
+    }
+}
+
+impl<P: Parameters> MockChain<P> {
+    /// Start a chain paying `viewing_key` on the network `params` describe.
+    ///
+    /// Blocks start at NU5 activation, where both pools are active, with
+    /// empty commitment trees; see [`MockChain::starting_at`].
+    pub fn with_params(params: P, viewing_key: &str) -> ScanResult<Self> {
+        let ufvk = UnifiedFullViewingKey::decode(&params, viewing_key.trim())
+            .map_err(ScanError::InvalidViewingKey)?;
+        let decoy_ufvk =
+            UnifiedSpendingKey::from_seed(&params, &DECOY_SEED, zip32::AccountId::ZERO)
+                .map_err(|e| ScanError::InvalidViewingKey(e.to_string()))?
+                .to_unified_full_viewing_key();
+        let start = params
+            .activation_height(NetworkUpgrade::Nu5)
+            .map_or(1, u64::from);
+        Ok(Self {
+            params,
+            ufvk,
+            decoy_ufvk,
+            decoys: 0,
+            next_height: start,
+            prev_hash: block_hash(start - 1),
+            sapling_tree_size: 0,
+            orchard_tree_size: 0,
+            counter: 0,
+            blocks: Vec::new(),
+        })
+    }
+
+    /// Start the chain at `height` instead, with its commitment trees
+    /// holding `sapling_tree_size` and `orchard_tree_size` notes.
+    ///
+    /// `height` must be after Canopy activation, as notes are paid with
+    /// ZIP 212 note plaintexts. Call before adding any blocks.
+    pub fn starting_at(
+        mut self,
+        height: u64,
+        sapling_tree_size: u32,
+        orchard_tree_size: u32,
+    ) -> Self {
+        self.next_height = height;
+        self.prev_hash = block_hash(height - 1);
+        self.sapling_tree_size = sapling_tree_size;
+        self.orchard_tree_size = orchard_tree_size;
+        self
+    }
+
+    /// Add `decoys` outputs paying another key to every transaction.
+    pub fn with_decoys(mut self, decoys: usize) -> Self {
+        self.decoys = decoys;
+        self
+    }
+
+    /// Add a block paying `value` zatoshis to the key's default Sapling
+    /// address.
+    pub fn pay_sapling(&mut self, value: u64) -> ScanResult<MockNote> {
+        let dfvk = self
+            .ufvk
+            .sapling()
+            .cloned()
+            .ok_or_else(|| missing_pool(ShieldedPool::Sapling))?;
+        let (_, address) = dfvk.default_address();
+        let note = address.create_note(
+            NoteValue::from_raw(value),
+            sapling::Rseed::AfterZip212(self.next_seed()),
+        );
+
+        let mut outputs = self.sapling_decoys();
+        let index = outputs.len() / 2;
+        outputs.insert(index, sapling_output(note.clone()));
+        let position = u64::from(self.sapling_tree_size) + index as u64;
+        let nullifier = note.nf(&dfvk.to_nk(Scope::External), position).0;
+
+        let actions = self.orchard_decoys();
+        let tx = self.transaction(Vec::new(), outputs, actions);
+        Ok(self.push_note(ShieldedPool::Sapling, tx, value, nullifier))
+    }
+
+    /// Add a block paying `value` zatoshis to the key's default Orchard
+    /// address.
+    #[cfg(feature = "orchard")]
+    pub fn pay_orchard(&mut self, value: u64) -> ScanResult<MockNote> {
+        let fvk = self
+            .ufvk
+            .orchard()
+            .cloned()
+            .ok_or_else(|| missing_pool(ShieldedPool::Orchard))?;
+        let address = fvk.address_at(0u32, Scope::External);
+        let rho = self.next_rho();
+        let (action, note) = self.orchard_action(address, value, rho);
+
+        let mut actions = self.orchard_decoys();
+        actions.insert(actions.len() / 2, action);
+        let nullifier = note.nullifier(&fvk).to_bytes();
+
+        let outputs = self.sapling_decoys();
+        let tx = self.transaction(Vec::new(), outputs, actions);
+        Ok(self.push_note(ShieldedPool::Orchard, tx, value, nullifier))
+    }
+
+    /// Add a block with a transaction spending `note`, returning its txid.
+    pub fn spend(&mut self, note: &MockNote) -> ScanResult<String> {
+        let nf = hex::encode(note.nullifier);
+        let tx = match note.pool {
+            ShieldedPool::Sapling => {
+                let outputs = self.sapling_decoys();
+                let actions = self.orchard_decoys();
+                self.transaction(vec![CompactSaplingSpend { nf }], outputs, actions)
+            }
+            #[cfg(feature = "orchard")]
+            ShieldedPool::Orchard => {
+                // The spend's action pays its output to the decoy key
+                let address = self.decoy_orchard_address()?;
+                let rho = orchard::note::Rho::from_bytes(&note.nullifier).unwrap();
+                let (mut action, _) = self.orchard_action(address, 1_000, rho);
+                action.nf = nf;
+                let mut actions = self.orchard_decoys();
+                actions.push(action);
+                let outputs = self.sapling_decoys();
+                self.transaction(Vec::new(), outputs, actions)
+            }
+            pool => return Err(missing_pool(pool)),
+        };
+        let txid = tx.txid.clone();
+        self.push_block(vec![tx]);
+        Ok(txid)
+    }
+
+    /// Add `count` blocks with no transactions.
+    pub fn empty_blocks(&mut self, count: u64) {
+        for _ in 0..count {
+            self.push_block(Vec::new());
+        }
+    }
+
+    /// Height the next block will have.
+    pub fn next_height(&self) -> u64 {
+        self.next_height
+    }
+
+    /// The blocks built so far.
+    pub fn blocks(&self) -> &[CompactBlock] {
+        &self.blocks
+    }
+
+    /// Take the blocks built so far.
+    pub fn into_blocks(self) -> Vec<CompactBlock> {
+        self.blocks
+    }
+
+    /// A request to scan the blocks built so far with the chain's key.
+    pub fn scan_request(&self) -> ScanRequest {
+        ScanRequest {
+            viewing_key: self.ufvk.encode(&self.params),
+            key_id: String::new(),
+            compact_blocks: self.blocks.clone(),
+            options: ScanOptions::default(),
+            nullifiers: Default::default(),
+        }
+    }
+
+    fn push_note(
+        &mut self,
+        pool: ShieldedPool,
+        tx: CompactTx,
+        value: u64,
+        nullifier: [u8; 32],
+    ) -> MockNote {
+        let note = MockNote {
+            pool,
+            txid: tx.txid.clone(),
+            height: self.next_height,
+            value,
+            nullifier,
+        };
+        self.push_block(vec![tx]);
+        note
+    }
+
+    fn push_block(&mut self, vtx: Vec<CompactTx>) {
+        let height = self.next_height;
+        for tx in &vtx {
+            self.sapling_tree_size += tx.outputs.len() as u32;
+            self.orchard_tree_size += tx.actions.len() as u32;
+        }
+        let hash = block_hash(height);
+        let offset = self.blocks.len() as u32;
+        self.blocks.push(CompactBlock {
+            proto_version: 1,
+            height,
+            hash: hash.clone(),
+            prev_hash: std::mem::replace(&mut self.prev_hash, hash),
+            time: GENESIS_TIME + offset * BLOCK_SPACING,
+            vtx,
+            chain_metadata: Some(ChainMetadata {
+                sapling_commitment_tree_size: self.sapling_tree_size,
+                orchard_commitment_tree_size: Some(self.orchard_tree_size),
+            }),
+        });
+        self.next_height += 1;
+    }
+
+    fn transaction(
+        &mut self,
+        spends: Vec<CompactSaplingSpend>,
+        outputs: Vec<CompactSaplingOutput>,
+        actions: Vec<CompactOrchardAction>,
+    ) -> CompactTx {
+        let mut txid = [0x7c; 32];
+        txid[..8].copy_from_slice(&self.next_counter().to_le_bytes());
+        CompactTx {
+            // After the block's coinbase, which has no shielded parts to include
+            index: 1,
+            txid: hex::encode(txid),
+            fee: None,
+            spends,
+            outputs,
+            actions,
+        }
+    }
+
+    fn sapling_decoys(&mut self) -> Vec<CompactSaplingOutput> {
+        let Some(dfvk) = self.decoy_ufvk.sapling().cloned() else {
+            return Vec::new();
+        };
+        let (_, address) = dfvk.default_address();
+        (0..self.decoys)
+            .map(|_| {
+                let rseed = sapling::Rseed::AfterZip212(self.next_seed());
+                sapling_output(address.create_note(NoteValue::from_raw(10_000), rseed))
+            })
+            .collect()
+    }
+
+    #[cfg(feature = "orchard")]
+    fn orchard_decoys(&mut self) -> Vec<CompactOrchardAction> {
+        let Ok(address) = self.decoy_orchard_address() else {
+            return Vec::new();
+        };
+        (0..self.decoys)
+            .map(|_| {
+                let rho = self.next_rho();
+                self.orchard_action(address, 10_000, rho).0
+            })
+            .collect()
+    }
+
+    #[cfg(not(feature = "orchard"))]
+    fn orchard_decoys(&mut self) -> Vec<CompactOrchardAction> {
+        Vec::new()
+    }
+
+    #[cfg(feature = "orchard")]
+    fn decoy_orchard_address(&self) -> ScanResult<orchard::Address> {
+        let fvk = self
+            .decoy_ufvk
+            .orchard()
+            .ok_or_else(|| missing_pool(ShieldedPool::Orchard))?;
+        Ok(fvk.address_at(0u32, Scope::External))
+    }
+
+    /// A compact action paying `value` to `address`, with its note.
+    #[cfg(feature = "orchard")]
+    fn orchard_action(
+        &mut self,
+        address: orchard::Address,
+        value: u64,
+        rho: orchard::note::Rho,
+    ) -> (CompactOrchardAction, orchard::Note) {
+        use orchard::note::{ExtractedNoteCommitment, RandomSeed};
+        use orchard::note_encryption::OrchardDomain;
+
+        // Not every seed gives a valid note; try the next until one does
+        let note = loop {
+            let Some(rseed) = RandomSeed::from_bytes(self.next_seed(), &rho).into_option() else {
+                continue;
+            };
+            let value = orchard::value::NoteValue::from_raw(value);
+            if let Some(note) = orchard::Note::from_parts(address, value, rho, rseed).into_option()
+            {
+                break note;
+            }
+        };
+        let cmx = ExtractedNoteCommitment::from(note.commitment());
+        let encryption = NoteEncryption::<OrchardDomain>::new(None, note, [0; 512]);
+        let action = CompactOrchardAction {
+            nf: hex::encode(rho.to_bytes()),
+            cmx: hex::encode(cmx.to_bytes()),
+            ephemeral_key: hex::encode(OrchardDomain::epk_bytes(encryption.epk()).0),
+            ciphertext: hex::encode(&encryption.encrypt_note_plaintext()[..COMPACT_NOTE_SIZE]),
+        };
+        (action, note)
+    }
+
+    /// A fresh nullifier to use as a new Orchard note's rho.
+    #[cfg(feature = "orchard")]
+    fn next_rho(&mut self) -> orchard::note::Rho {
+        let mut bytes = [0; 32];
+        bytes[..8].copy_from_slice(&self.next_counter().to_le_bytes());
+        bytes[8] = 0x4e;
+        // Small enough to be a canonical field element
+        orchard::note::Rho::from_bytes(&bytes).unwrap()
+    }
+
+    fn next_seed(&mut self) -> [u8; 32] {
+        let mut seed = [0x5e; 32];
+        seed[..8].copy_from_slice(&self.next_counter().to_le_bytes());
+        seed
+    }
+
+    fn next_counter(&mut self) -> u64 {
+        self.counter += 1;
+        self.counter
+    }
+}
+
+fn sapling_output(note: sapling::Note) -> CompactSaplingOutput {
+    let cmu = note.cmu();
+    let encryption = NoteEncryption::<SaplingDomain>::new(None, note, [0; 512]);
+    CompactSaplingOutput {
+        cmu: hex::encode(cmu.to_bytes()),
+        ephemeral_key: hex::encode(SaplingDomain::epk_bytes(encryption.epk()).0),
+        ciphertext: hex::encode(&encryption.encrypt_note_plaintext()[..COMPACT_NOTE_SIZE]),
+    }
+}
+
+/// Hash of the fabricated block at `height`.
+fn block_hash(height: u64) -> String {
+    let mut hash = [0xb1; 32];
+    hash[..8].copy_from_slice(&height.to_le_bytes());
+    hex::encode(hash)
+}
+
+fn missing_pool(pool: ShieldedPool) -> ScanError {
+    ScanError::InvalidViewingKey(format!("viewing key has no {pool} component"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::TxDirection;
+    use crate::Scanner;
+
+    fn test_ufvk() -> String {
+        UnifiedSpendingKey::from_seed(&Network::MainNetwork, &[0; 32], zip32::AccountId::ZERO)
+            .unwrap()
+            .to_unified_full_viewing_key()
+            .encode(&Network::MainNetwork)
+    }
+
+    #[test]
+    fn test_mock_chain() {
+        let mut chain = MockChain::new(&test_ufvk()).unwrap().with_decoys(2);
+        let sapling = chain.pay_sapling(50_000).unwrap();
+        chain.empty_blocks(3);
+        let spend_txid = chain.spend(&sapling).unwrap();
+        #[cfg(feature = "orchard")]
+        let (orchard, orchard_spend_txid) = {
+            let note = chain.pay_orchard(20_000).unwrap();
+            let txid = chain.spend(&note).unwrap();
+            (note, txid)
+        };
+        assert_eq!(chain.blocks()[0].vtx[0].outputs.len(), 3);
+
+        let transactions = Scanner::mainnet().scan(&chain.scan_request()).unwrap();
+        let found: Vec<_> = transactions
+            .iter()
+            .map(|tx| (tx.txid.as_str(), tx.direction, tx.amount_zat.as_str()))
+            .collect();
+        assert_eq!(found.len(), if cfg!(feature = "orchard") { 4 } else { 2 });
+        assert_eq!(
+            found[..2],
+            [
+                (sapling.txid.as_str(), TxDirection::In, "50000"),
+                (spend_txid.as_str(), TxDirection::Out, "50000"),
+            ]
+        );
+        #[cfg(feature = "orchard")]
+        assert_eq!(
+            found[2..],
+            [
+                (orchard.txid.as_str(), TxDirection::In, "20000"),
+                (orchard_spend_txid.as_str(), TxDirection::Out, "20000"),
+            ]
+        );
+
+        // The same calls build the same chain
+        let mut again = MockChain::new(&test_ufvk()).unwrap().with_decoys(2);
+        again.pay_sapling(50_000).unwrap();
+        assert_eq!(
+            again.blocks()[0].vtx[0].outputs[1].cmu,
+            chain.blocks()[0].vtx[0].outputs[1].cmu
+        );
+    }
+}
@@ -0,0 +1,73 @@
+//! Transparent address derivation from a UFVK (requires the `transparent` feature).
+
+use std::collections::HashMap;
+
+use transparent::keys::{AccountPubKey, IncomingViewingKey, NonHardenedChildIndex};
+use zcash_keys::encoding::AddressCodec;
+use zcash_keys::keys::UnifiedFullViewingKey;
+use zcash_protocol::consensus::Parameters;
+use zip32::Scope;
+
+use crate::error::{ScanError, ScanResult};
+
+/// Number of external and internal addresses checked by
+/// [`Scanner::scan_transparent`](crate::Scanner::scan_transparent).
+pub const TRANSPARENT_ADDRESS_LOOKAHEAD: u32 = 100;
+
+/// The transparent component of a UFVK.
+fn account_pubkey(ufvk: &UnifiedFullViewingKey) -> ScanResult<&AccountPubKey> {
+    ufvk.transparent().ok_or_else(|| {
+        ScanError::InvalidViewingKey("viewing key has no transparent component".to_string())
+    })
+}
+
+/// Encoded addresses at indices `0..count` of `scope`, skipping indices that
+/// don't yield a valid key.
+pub(crate) fn derive_addresses<P: Parameters>(
+    network: &P,
+    ufvk: &UnifiedFullViewingKey,
+    scope: Scope,
+    count: u32,
+) -> ScanResult<Vec<(u32, String)>> {
+    let account = account_pubkey(ufvk)?;
+
+    let indices = (0..count).filter_map(NonHardenedChildIndex::from_index);
+    let addresses = match scope {
+        Scope::External => {
+            let ivk = account.derive_external_ivk().map_err(derive_error)?;
+            indices
+                .filter_map(|i| Some((i.index(), ivk.derive_address(i).ok()?)))
+                .collect::<Vec<_>>()
+        }
+        Scope::Internal => {
+            let ivk = account.derive_internal_ivk().map_err(derive_error)?;
+            indices
+                .filter_map(|i| Some((i.index(), ivk.derive_address(i).ok()?)))
+                .collect()
+        }
+    };
+
+    Ok(addresses
+        .into_iter()
+        .map(|(index, addr)| (index, addr.encode(network)))
+        .collect())
+}
+
+fn derive_error<E: std::fmt::Display>(e: E) -> ScanError {
+    ScanError::InvalidViewingKey(e.to_string())
+}
+
+/// Map of encoded address to scope for the first `count` addresses of both scopes.
+pub(crate) fn address_map<P: Parameters>(
+    network: &P,
+    ufvk: &UnifiedFullViewingKey,
+    count: u32,
+) -> ScanResult<HashMap<String, Scope>> {
+    let mut map = HashMap::new();
+    for scope in [Scope::External, Scope::Internal] {
+        for (_, address) in derive_addresses(network, ufvk, scope, count)? {
+            map.insert(address, scope);
+        }
+    }
+    Ok(map)
+}
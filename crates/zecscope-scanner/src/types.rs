@@ -1,15 +1,27 @@
 //! Types for scanner input/output.
 
+use std::borrow::Cow;
+use std::ops::RangeInclusive;
+
 use serde::{Deserialize, Serialize};
 
+use crate::decrypted::DecryptedNote;
+use crate::error::ScanError;
+use crate::fingerprint::{redact_viewing_key, ufvk_fingerprint};
+use crate::nullifiers::NullifierSet;
+use crate::state::{NoteSpend, TrackedNote};
+
 /// Which shielded pool a transaction belongs to.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[cfg_attr(feature = "wasm", derive(tsify::Tsify))]
 #[serde(rename_all = "lowercase")]
 pub enum ShieldedPool {
     /// Sapling shielded pool (activated at Sapling upgrade)
     Sapling,
     /// Orchard shielded pool (activated at NU5)
     Orchard,
+    /// Transparent (unshielded) outputs, matched with the `transparent` feature
+    Transparent,
 }
 
 impl std::fmt::Display for ShieldedPool {
@@ -17,12 +29,14 @@ impl std::fmt::Display for ShieldedPool {
         match self {
             ShieldedPool::Sapling => write!(f, "sapling"),
             ShieldedPool::Orchard => write!(f, "orchard"),
+            ShieldedPool::Transparent => write!(f, "transparent"),
         }
     }
 }
 
 /// Direction of a transaction relative to the viewing key.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "wasm", derive(tsify::Tsify))]
 #[serde(rename_all = "lowercase")]
 pub enum TxDirection {
     /// Incoming transaction (received funds)
@@ -31,8 +45,38 @@ pub enum TxDirection {
     Out,
 }
 
+/// ZIP 32 scope of the address a note was received on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "wasm", derive(tsify::Tsify))]
+#[serde(rename_all = "lowercase")]
+pub enum KeyScope {
+    /// One of the key's external (receiving) addresses
+    External,
+    /// The key's internal (change) address
+    Internal,
+}
+
+impl From<zip32::Scope> for KeyScope {
+    fn from(scope: zip32::Scope) -> Self {
+        match scope {
+            zip32::Scope::External => KeyScope::External,
+            zip32::Scope::Internal => KeyScope::Internal,
+        }
+    }
+}
+
+impl std::fmt::Display for KeyScope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            KeyScope::External => write!(f, "external"),
+            KeyScope::Internal => write!(f, "internal"),
+        }
+    }
+}
+
 /// A discovered shielded transaction.
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "wasm", derive(tsify::Tsify), tsify(missing_as_null))]
 #[serde(rename_all = "camelCase")]
 pub struct ZecTransaction {
     /// Transaction ID (hex-encoded)
@@ -51,6 +95,87 @@ pub struct ZecTransaction {
     pub key_id: String,
     /// Which shielded pool this transaction is in
     pub pool: ShieldedPool,
+    /// Whether this is a change note (received on an internal address)
+    #[serde(default)]
+    pub is_change: bool,
+    /// Address that received the note, for incoming transactions
+    /// (`zs1…` for Sapling, an Orchard-only unified address for Orchard)
+    #[serde(default)]
+    pub address: Option<String>,
+    /// Address an outgoing payment was sent to, for outgoing entries of
+    /// full transactions, whose outputs the outgoing viewing key decrypts
+    #[serde(default)]
+    pub recipient_address: Option<String>,
+    /// Fee paid by the transaction (in zatoshis), on its outgoing entry when
+    /// the fee is known
+    #[serde(default)]
+    pub fee_zat: Option<String>,
+    /// Height after which the transaction could no longer be mined (0 if
+    /// it never expires), when known from the full transaction
+    #[serde(default)]
+    pub expiry_height: Option<u64>,
+    /// Value of `amount_zat` in `fiat_currency` at block time, set by
+    /// [`enrich_with_prices`](crate::enrich_with_prices)
+    #[serde(default)]
+    pub fiat_value: Option<String>,
+    /// Currency of `fiat_value`, such as `"USD"`
+    #[serde(default)]
+    pub fiat_currency: Option<String>,
+    /// Diversifier index of the receiving address, for incoming notes sent
+    /// to one of the key's external addresses
+    #[serde(default)]
+    pub diversifier_index: Option<u64>,
+    /// Index of the Sapling output (or transparent output) within the
+    /// transaction that produced this entry; not set on aggregated outgoing
+    /// entries
+    #[serde(default)]
+    pub output_index: Option<u32>,
+    /// Index of the Orchard action within the transaction that produced
+    /// this entry; not set on aggregated outgoing entries
+    #[serde(default)]
+    pub action_index: Option<u32>,
+    /// Whether the transaction is still unmined (found by
+    /// [`Scanner::scan_mempool_tx`](crate::Scanner::scan_mempool_tx)); such
+    /// provisional entries have a `height` and `time` of 0
+    #[serde(default)]
+    pub unconfirmed: bool,
+    /// Scope the note was received on, for incoming entries when
+    /// `include_change` is set, so real receipts can be told apart from
+    /// change without relying on `is_change` alone
+    #[serde(default)]
+    pub scope: Option<KeyScope>,
+    /// ZIP 32 account index of the key that found this entry, for results
+    /// of [`Scanner::scan_accounts`](crate::Scanner::scan_accounts)
+    #[serde(default)]
+    pub account_id: Option<u32>,
+    /// Entry of [`ScanOptions::invoice_ids`] the memo starts with, for
+    /// incoming entries whose memo is known
+    #[serde(default)]
+    pub invoice_id: Option<String>,
+    /// Position of the received note's commitment in its pool's note
+    /// commitment tree, for incoming shielded entries of block scans (the
+    /// tree sizes come from the blocks' `chain_metadata`). Needed to build
+    /// witnesses for the note or check it against a treestate service
+    #[serde(default)]
+    pub note_position: Option<u64>,
+    /// Whether the entry is a coinbase output (mining rewards and funding
+    /// streams). Coinbase notes can only be spent after 100 confirmations
+    #[serde(default)]
+    pub is_coinbase: bool,
+    /// Whether the entry is part of a transaction moving the wallet's own
+    /// funds between pools (e.g. a Sapling to Orchard migration): notes
+    /// spent in one pool and received, not as change, in another. Both the
+    /// incoming and outgoing entries of such a transaction are tagged
+    #[serde(default)]
+    pub self_transfer: bool,
+    /// Whether the entry looks like spam, when [`ScanOptions::flag_spam`]
+    /// is set: an incoming note worth less than the ZIP 317 marginal fee
+    /// (5,000 zatoshis) that came in a dust storm, a transaction of 50 or
+    /// more shielded outputs, or whose value the wallet received at least
+    /// three times in the block. Wallets may hide or de-emphasize such
+    /// entries; they are still scanned and tracked like any other
+    #[serde(default)]
+    pub likely_spam: bool,
 }
 
 impl ZecTransaction {
@@ -68,19 +193,300 @@ impl ZecTransaction {
     }
 }
 
+#[cfg(test)]
+impl ZecTransaction {
+    /// An entry of `amount_zat` zatoshis at height 2,000,000, found with the
+    /// key `"test"`, and every optional field unset. Tests override the
+    /// fields they check.
+    pub(crate) fn test_entry(
+        txid: &str,
+        amount_zat: u64,
+        direction: TxDirection,
+        pool: ShieldedPool,
+    ) -> Self {
+        Self {
+            txid: txid.to_string(),
+            height: 2_000_000,
+            time: 0,
+            amount_zat: amount_zat.to_string(),
+            direction,
+            memo: None,
+            key_id: "test".to_string(),
+            pool,
+            is_change: false,
+            address: None,
+            recipient_address: None,
+            fee_zat: None,
+            expiry_height: None,
+            fiat_value: None,
+            fiat_currency: None,
+            diversifier_index: None,
+            output_index: None,
+            action_index: None,
+            unconfirmed: false,
+            scope: None,
+            account_id: None,
+            invoice_id: None,
+            note_position: None,
+            is_coinbase: false,
+            self_transfer: false,
+            likely_spam: false,
+        }
+    }
+}
+
+/// Kind of viewing key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum KeyKind {
+    /// Unified Full Viewing Key (`uview…`), sees incoming and outgoing funds
+    Ufvk,
+    /// Unified Incoming Viewing Key (`uivk…`), sees incoming funds only
+    Uivk,
+    /// Sapling extended full viewing key (`zxviews…`), as exported by
+    /// pre-NU5 wallets; sees Sapling funds only
+    Extfvk,
+    /// Raw Sapling diversifiable full viewing key, 128 bytes in hex; sees
+    /// Sapling funds only
+    Dfvk,
+}
+
+/// Network a key or address is encoded for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum KeyNetwork {
+    /// Zcash mainnet
+    Mainnet,
+    /// Zcash testnet
+    Testnet,
+    /// A local regtest network
+    Regtest,
+}
+
+impl std::fmt::Display for KeyNetwork {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            KeyNetwork::Mainnet => write!(f, "mainnet"),
+            KeyNetwork::Testnet => write!(f, "testnet"),
+            KeyNetwork::Regtest => write!(f, "regtest"),
+        }
+    }
+}
+
+impl From<zcash_protocol::consensus::NetworkType> for KeyNetwork {
+    fn from(network: zcash_protocol::consensus::NetworkType) -> Self {
+        use zcash_protocol::consensus::NetworkType;
+        match network {
+            NetworkType::Main => KeyNetwork::Mainnet,
+            NetworkType::Test => KeyNetwork::Testnet,
+            NetworkType::Regtest => KeyNetwork::Regtest,
+        }
+    }
+}
+
+/// What a viewing key contains, as reported by
+/// [`Scanner::inspect_key`](crate::Scanner::inspect_key).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KeyInfo {
+    /// What kind of key it is
+    pub kind: KeyKind,
+    /// Network the key is encoded for
+    pub network: KeyNetwork,
+    /// Whether the key has an Orchard component
+    pub has_orchard: bool,
+    /// Whether the key has a Sapling component
+    pub has_sapling: bool,
+    /// Whether the key has a transparent component
+    pub has_transparent: bool,
+    /// Whether finding this key's Orchard funds needs the `orchard` feature
+    pub requires_orchard_feature: bool,
+    /// Whether this scanner can scan with the key: it must be for the
+    /// scanner's network and have a pool this build supports (a UIVK is
+    /// scanned for incoming funds only)
+    pub scannable: bool,
+}
+
 /// Request to scan compact blocks with a viewing key.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+///
+/// Its `Debug` output shows the viewing key redacted (see
+/// [`redact_viewing_key`]), so requests can be logged.
+#[derive(Clone, Serialize, Deserialize)]
 pub struct ScanRequest {
-    /// Unified Full Viewing Key (uview1...)
+    /// Unified Full Viewing Key (uview1...), or a Sapling extended full
+    /// viewing key (zxviews1...) or hex-encoded raw Sapling DFVK
     pub viewing_key: String,
-    /// Identifier for this key (for tracking which key found which tx)
+    /// Identifier for this key (for tracking which key found which tx).
+    /// Left empty, the key's [`ufvk_fingerprint`] is used.
+    #[serde(default)]
     pub key_id: String,
     /// Compact blocks to scan
     pub compact_blocks: Vec<CompactBlock>,
+    /// Options controlling what the scan reports
+    #[serde(default)]
+    pub options: ScanOptions,
+    /// Notes found by earlier scans, whose spends this scan should report
+    #[serde(default)]
+    pub nullifiers: NullifierSet,
+}
+
+impl ScanRequest {
+    /// The `key_id` results are reported under: the one given, or the
+    /// viewing key's fingerprint if it is empty.
+    pub fn key_id(&self) -> Cow<'_, str> {
+        if self.key_id.is_empty() {
+            Cow::Owned(ufvk_fingerprint(&self.viewing_key))
+        } else {
+            Cow::Borrowed(&self.key_id)
+        }
+    }
+}
+
+impl std::fmt::Debug for ScanRequest {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ScanRequest")
+            .field("viewing_key", &redact_viewing_key(&self.viewing_key))
+            .field("key_id", &self.key_id)
+            .field("compact_blocks", &self.compact_blocks)
+            .field("options", &self.options)
+            .field("nullifiers", &self.nullifiers)
+            .finish()
+    }
+}
+
+/// Options controlling what a scan reports.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScanOptions {
+    /// Report change outputs (notes sent back to the wallet's internal
+    /// addresses) alongside regular incoming transactions
+    pub include_change: bool,
+    /// Only report incoming notes received at these diversifier indices, so
+    /// each deposit address can be accounted separately. Filtered-out notes
+    /// are still tracked, so their spends are reported.
+    pub diversifier_indices: Option<Vec<u64>>,
+    /// Collapse entries of the same transaction into one, summing their
+    /// amounts
+    pub aggregation: Aggregation,
+    /// Only look for notes in these pools (all pools if empty). Skipping a
+    /// pool the key has no funds in saves its trial decryption.
+    pub pools: Vec<ShieldedPool>,
+    /// Invoice or order IDs payers are asked to start their memo with;
+    /// incoming entries whose memo matches one report it as `invoice_id`.
+    /// See [`match_invoices`](crate::match_invoices).
+    pub invoice_ids: Vec<String>,
+    /// Only report entries worth at least this many zatoshis (checked after
+    /// aggregation)
+    pub min_amount_zat: Option<u64>,
+    /// Only report entries mined within this inclusive range of heights.
+    /// Blocks outside it are still scanned, so spends within it of notes
+    /// received before it are reported; unmined entries always pass.
+    pub height_range: Option<RangeInclusive<u64>>,
+    /// Only report entries of these transactions (hex txids, in any case)
+    pub txid_allowlist: Option<Vec<String>>,
+    /// Smallest note value (in zatoshis) to pick up. Notes below it are
+    /// ignored entirely: not reported, and not tracked for spends. Defaults
+    /// to 1, skipping only zero-value notes; set 0 to also see zero-value
+    /// "marker" notes.
+    pub min_value_zat: Option<u64>,
+    /// Skip blocks that can't be decoded or scanned (malformed hex, a
+    /// broken chain link, inconsistent tree sizes) instead of failing the
+    /// whole scan. Each skip is recorded as a [`ScanWarning`] in the
+    /// [`ScanSummary`]; notes in skipped blocks are missed. Not suitable
+    /// for witness tracking, which needs every block.
+    pub lenient: bool,
+    /// Trial-decrypt at most this many outputs (Sapling outputs and Orchard
+    /// actions, in block order) per block, so a block padded with tens of
+    /// thousands of outputs can't stall a scan. Notes in the outputs
+    /// skipped are missed, and each block reports how many it skipped as
+    /// [`BlockScanResult::skipped_outputs`]; spends are still all detected.
+    pub max_outputs_per_block: Option<usize>,
+    /// Like `max_outputs_per_block`, per transaction
+    pub max_outputs_per_tx: Option<usize>,
+    /// Tag incoming dust that looks like spam as
+    /// [`ZecTransaction::likely_spam`], and report the share of each
+    /// block's outputs in dust storms as [`BlockScanResult::spam_ratio`]
+    pub flag_spam: bool,
+    /// Number of outputs trial-decrypted together, overriding the
+    /// scanner's [`batch_size`](crate::ScannerBuilder::batch_size) for this
+    /// request: smaller to hold fewer blocks in memory (0 counts as 1),
+    /// larger to spread each batch over more threads
+    pub batch_size: Option<usize>,
+}
+
+impl ScanOptions {
+    /// Whether `tx` passes the result filters: `min_amount_zat`,
+    /// `height_range` and `txid_allowlist`.
+    pub(crate) fn includes(&self, tx: &ZecTransaction) -> bool {
+        self.min_amount_zat
+            .is_none_or(|min| u64::try_from(tx.amount_zatoshis()).is_ok_and(|v| v >= min))
+            && self
+                .height_range
+                .as_ref()
+                .is_none_or(|range| tx.unconfirmed || range.contains(&tx.height))
+            && self
+                .txid_allowlist
+                .as_ref()
+                .is_none_or(|txids| txids.iter().any(|txid| txid.eq_ignore_ascii_case(&tx.txid)))
+    }
+
+    /// Whether a note received at `diversifier_index` passes the filter.
+    pub(crate) fn includes_diversifier(&self, diversifier_index: Option<u64>) -> bool {
+        match &self.diversifier_indices {
+            Some(indices) => diversifier_index.is_some_and(|i| indices.contains(&i)),
+            None => true,
+        }
+    }
+
+    /// Whether a note worth `value` zatoshis is picked up.
+    pub(crate) fn includes_value(&self, value: u64) -> bool {
+        value >= self.min_value_zat.unwrap_or(1)
+    }
+
+    /// Whether notes in `pool` are looked for.
+    pub(crate) fn includes_pool(&self, pool: ShieldedPool) -> bool {
+        self.pools.is_empty() || self.pools.contains(&pool)
+    }
+}
+
+/// How scan results of the same transaction are combined.
+///
+/// Incoming and outgoing entries are never merged with each other.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Aggregation {
+    /// One entry per received note (and per outgoing transaction)
+    #[default]
+    None,
+    /// One entry per transaction and pool
+    TxidPool,
+    /// One entry per transaction, in the pool of its first entry
+    Txid,
+}
+
+/// A transparent output paying one of the viewing key's addresses, as
+/// returned by lightwalletd's `GetAddressUtxos`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TransparentOutput {
+    /// Transaction ID (hex-encoded)
+    pub txid: String,
+    /// Output index within the transaction
+    pub index: u32,
+    /// Block height where the transaction was mined
+    pub height: u64,
+    /// Block timestamp (Unix seconds), if known
+    #[serde(default)]
+    pub time: i64,
+    /// Receiving transparent address (`t1…`/`tm…`)
+    pub address: String,
+    /// Output value in zatoshis (as string to avoid precision loss)
+    pub value_zat: String,
 }
 
 /// A compact block from lightwalletd.
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "wasm", derive(tsify::Tsify), tsify(missing_as_null))]
 #[serde(rename_all = "camelCase")]
 pub struct CompactBlock {
     /// Protocol version
@@ -103,6 +509,7 @@ pub struct CompactBlock {
 
 /// A compact transaction.
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "wasm", derive(tsify::Tsify), tsify(missing_as_null))]
 #[serde(rename_all = "camelCase")]
 pub struct CompactTx {
     /// Transaction index in block
@@ -125,6 +532,7 @@ pub struct CompactTx {
 
 /// A compact Sapling spend.
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "wasm", derive(tsify::Tsify), tsify(missing_as_null))]
 pub struct CompactSaplingSpend {
     /// Nullifier (hex-encoded)
     pub nf: String,
@@ -132,6 +540,7 @@ pub struct CompactSaplingSpend {
 
 /// A compact Sapling output.
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "wasm", derive(tsify::Tsify), tsify(missing_as_null))]
 #[serde(rename_all = "camelCase")]
 pub struct CompactSaplingOutput {
     /// Note commitment (hex-encoded)
@@ -144,6 +553,7 @@ pub struct CompactSaplingOutput {
 
 /// A compact Orchard action.
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "wasm", derive(tsify::Tsify), tsify(missing_as_null))]
 #[serde(rename_all = "camelCase")]
 pub struct CompactOrchardAction {
     /// Nullifier (hex-encoded)
@@ -158,6 +568,7 @@ pub struct CompactOrchardAction {
 
 /// Chain metadata from compact blocks.
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "wasm", derive(tsify::Tsify), tsify(missing_as_null))]
 #[serde(rename_all = "camelCase")]
 pub struct ChainMetadata {
     /// Sapling commitment tree size at this block
@@ -167,6 +578,48 @@ pub struct ChainMetadata {
     pub orchard_commitment_tree_size: Option<u32>,
 }
 
+/// Result of scanning a single block.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BlockScanResult {
+    /// Block height
+    pub height: u64,
+    /// Block hash (hex-encoded)
+    pub hash: String,
+    /// Block timestamp (Unix seconds)
+    pub time: i64,
+    /// Transactions discovered in this block
+    pub transactions: Vec<ZecTransaction>,
+    /// Notes received in this block (including change)
+    #[serde(default)]
+    pub received_notes: Vec<TrackedNote>,
+    /// Full components of the notes received in this block (including
+    /// change)
+    #[serde(default)]
+    pub decrypted_notes: Vec<DecryptedNote>,
+    /// Previously discovered notes spent in this block
+    #[serde(default)]
+    pub spent_notes: Vec<NoteSpend>,
+    /// Sapling tree root after this block (hex), when tracking witnesses
+    #[serde(default)]
+    pub sapling_anchor: Option<String>,
+    /// Orchard tree root after this block (hex), when tracking witnesses
+    #[serde(default)]
+    pub orchard_anchor: Option<String>,
+    /// Outputs the [caps](ScanOptions::max_outputs_per_block) on trial
+    /// decryption skipped in this block
+    #[serde(default)]
+    pub skipped_outputs: usize,
+    /// Shielded outputs of this block in dust storms, transactions of 50
+    /// or more of them, when [`ScanOptions::flag_spam`] is set
+    #[serde(default)]
+    pub spam_outputs: usize,
+    /// Share of this block's shielded outputs in dust storms, from 0 to 1,
+    /// when [`ScanOptions::flag_spam`] is set
+    #[serde(default)]
+    pub spam_ratio: f64,
+}
+
 /// Result of scanning a range of blocks.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -183,20 +636,174 @@ pub struct ScanSummary {
     pub sapling_count: usize,
     /// Orchard transactions found
     pub orchard_count: usize,
+    /// Sum of incoming Sapling amounts, excluding change (in zatoshis)
+    #[serde(default)]
+    pub sapling_received_zat: String,
+    /// Sum of incoming Orchard amounts, excluding change (in zatoshis)
+    #[serde(default)]
+    pub orchard_received_zat: String,
+    /// Sum of outgoing Sapling amounts (in zatoshis)
+    #[serde(default)]
+    pub sapling_sent_zat: String,
+    /// Sum of outgoing Orchard amounts (in zatoshis)
+    #[serde(default)]
+    pub orchard_sent_zat: String,
+    /// Notes discovered by the scan, or passed in the request's
+    /// [`nullifiers`](ScanRequest::nullifiers), that it didn't see spent;
+    /// pass them to the next scan to detect later spends
+    #[serde(default)]
+    pub unspent_notes: Vec<TrackedNote>,
+    /// Sapling outputs trial-decrypted
+    #[serde(default)]
+    pub outputs_scanned: usize,
+    /// Orchard actions trial-decrypted
+    #[serde(default)]
+    pub actions_scanned: usize,
+    /// Notes decrypted with the key (including change)
+    #[serde(default)]
+    pub decryption_hits: usize,
+    /// Outputs seen, tried and decrypted, per pool
+    #[serde(default)]
+    pub decryption_stats: DecryptionStats,
+    /// Wall-clock duration of the scan in milliseconds, including block
+    /// fetching for [`Scanner::scan_range`](crate::Scanner::scan_range)
+    #[serde(default)]
+    pub elapsed_ms: u64,
+    /// Blocks a [lenient](ScanOptions::lenient) scan skipped, and why
+    #[serde(default)]
+    pub warnings: Vec<ScanWarning>,
+    /// Whether the key scanned with was a UIVK, so only notes received at
+    /// its addresses were found: spends, outgoing transactions and change
+    /// are missing, and the notes' nullifiers unknown
+    #[serde(default)]
+    pub incoming_only: bool,
+    /// Outputs the [caps](ScanOptions::max_outputs_per_block) on trial
+    /// decryption skipped, across all blocks
+    #[serde(default)]
+    pub skipped_outputs: usize,
+    /// Shielded outputs in dust storms, across all blocks, when
+    /// [`ScanOptions::flag_spam`] is set; out of
+    /// [`DecryptionStats`]' outputs seen
+    #[serde(default)]
+    pub spam_outputs: usize,
+}
+
+/// Trial decryption counters of a scan, per pool.
+///
+/// Outputs that were seen but not attempted were skipped, because
+/// [`ScanOptions::pools`] left their pool out, because they were past the
+/// [caps](ScanOptions::max_outputs_per_block) on outputs per block or
+/// transaction, because their block is below
+/// the pool's activation height (Sapling, or NU5 for Orchard) and so can't
+/// hold its notes, or because this build can't scan it: Orchard actions are
+/// only tried with the `orchard` feature.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct DecryptionStats {
+    /// Sapling outputs
+    pub sapling: PoolDecryptionStats,
+    /// Orchard actions
+    pub orchard: PoolDecryptionStats,
+}
+
+/// Trial decryption counters for one pool.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct PoolDecryptionStats {
+    /// Outputs in the scanned blocks
+    pub seen: usize,
+    /// Outputs trial-decrypted with the key
+    pub attempted: usize,
+    /// Notes decrypted (including change)
+    pub decrypted: usize,
+}
+
+impl DecryptionStats {
+    /// Whether some Orchard actions weren't trial-decrypted.
+    pub fn orchard_skipped(&self) -> bool {
+        self.orchard.attempted < self.orchard.seen
+    }
+}
+
+/// A block skipped by a [lenient](ScanOptions::lenient) scan.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScanWarning {
+    /// Height of the skipped block, if known; for a block that couldn't be
+    /// decoded, the height following the last block scanned
+    pub height: Option<u64>,
+    /// Field that couldn't be parsed, if the block was malformed
+    pub field: Option<String>,
+    /// What went wrong
+    pub reason: String,
+}
+
+impl ScanWarning {
+    pub(crate) fn from_error(height: Option<u64>, e: &ScanError) -> Self {
+        let field = match e {
+            ScanError::InvalidHex { field, .. } => Some(field.clone()),
+            _ => None,
+        };
+        Self {
+            height: e.height().or(height),
+            field,
+            reason: e.to_string(),
+        }
+    }
 }
 
 impl ScanSummary {
-    /// Create a new scan summary from transactions.
+    /// Create a new scan summary from transactions, counting every block of
+    /// `start..=end` as scanned.
     pub fn from_transactions(txs: Vec<ZecTransaction>, start: u64, end: u64) -> Self {
         let sapling_count = txs.iter().filter(|t| t.pool == ShieldedPool::Sapling).count();
         let orchard_count = txs.iter().filter(|t| t.pool == ShieldedPool::Orchard).count();
+        let total = |pool: ShieldedPool, direction: TxDirection| {
+            txs.iter()
+                .filter(|t| t.pool == pool && t.direction == direction && !t.is_change)
+                .map(ZecTransaction::amount_zatoshis)
+                .sum::<i64>()
+                .to_string()
+        };
         Self {
             blocks_scanned: (end - start + 1) as usize,
             start_height: start,
             end_height: end,
             sapling_count,
             orchard_count,
+            sapling_received_zat: total(ShieldedPool::Sapling, TxDirection::In),
+            orchard_received_zat: total(ShieldedPool::Orchard, TxDirection::In),
+            sapling_sent_zat: total(ShieldedPool::Sapling, TxDirection::Out),
+            orchard_sent_zat: total(ShieldedPool::Orchard, TxDirection::Out),
             transactions: txs,
+            unspent_notes: Vec::new(),
+            outputs_scanned: 0,
+            actions_scanned: 0,
+            decryption_hits: 0,
+            decryption_stats: DecryptionStats::default(),
+            elapsed_ms: 0,
+            warnings: Vec::new(),
+            incoming_only: false,
+            skipped_outputs: 0,
+            spam_outputs: 0,
         }
     }
+
+    /// Record `stats`, and the totals derived from them.
+    pub(crate) fn set_decryption_stats(&mut self, stats: DecryptionStats) {
+        self.outputs_scanned = stats.sapling.attempted;
+        self.actions_scanned = stats.orchard.attempted;
+        self.decryption_hits = stats.sapling.decrypted + stats.orchard.decrypted;
+        self.decryption_stats = stats;
+    }
+
+    /// Pool and hex-encoded nullifier of each unspent note.
+    ///
+    /// A note is spent once its nullifier appears in a later block (in a
+    /// compact block's spends or actions of the same pool).
+    pub fn nullifiers(&self) -> impl Iterator<Item = (ShieldedPool, &str)> + '_ {
+        self.unspent_notes
+            .iter()
+            .map(|note| (note.pool, note.nullifier.as_str()))
+    }
 }
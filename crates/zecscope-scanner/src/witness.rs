@@ -0,0 +1,421 @@
+//! Note commitment tree tracking for note positions, anchors and witnesses.
+
+use incrementalmerkletree::{
+    frontier::Frontier, Address, Hashable, Level, Marking, MerklePath, Position, Retention,
+};
+use serde::{Deserialize, Serialize};
+use shardtree::{store::memory::MemoryShardStore, ShardTree};
+use zcash_client_backend::data_api::{ScannedBlock, ScannedBundles, SAPLING_SHARD_HEIGHT};
+use zcash_client_backend::proto::service::TreeState;
+use zcash_protocol::consensus::BlockHeight;
+
+#[cfg(feature = "orchard")]
+use orchard::tree::MerkleHashOrchard;
+#[cfg(feature = "orchard")]
+use zcash_client_backend::data_api::ORCHARD_SHARD_HEIGHT;
+
+use crate::error::{ScanError, ScanResult};
+use crate::scanner::block_height;
+use crate::state::TrackedNote;
+use crate::types::ShieldedPool;
+
+/// Number of block checkpoints kept in each commitment tree.
+const MAX_CHECKPOINTS: usize = 100;
+
+/// Merkle authentication path for a discovered note.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NoteWitness {
+    /// Pool the note belongs to
+    pub pool: ShieldedPool,
+    /// Position of the note commitment in the tree
+    pub position: u64,
+    /// Tree root the witness is valid for (hex-encoded)
+    pub anchor: String,
+    /// Sibling hashes from the leaf up to the root (hex-encoded)
+    pub auth_path: Vec<String>,
+}
+
+/// Root of a complete subtree of a note commitment tree, as lightwalletd's
+/// `GetSubtreeRoots` returns them.
+///
+/// Both pools split their tree into subtrees of 2^16 leaves, so subtree
+/// `index` holds the note commitments from position `index << 16`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SubtreeRoot {
+    /// Index of the subtree
+    pub index: u64,
+    /// Merkle root of the subtree (hex-encoded)
+    pub root_hash: String,
+    /// Height of the block holding the subtree's last note commitment
+    pub completing_block_height: u64,
+}
+
+/// Sapling and Orchard note commitment trees, kept in step with a scan.
+///
+/// Pass a tracker to [`Scanner::scan_with_witnesses`] to have every scanned
+/// block's commitments appended; blocks then carry the tree anchors as of
+/// that block, and discovered notes can be witnessed with
+/// [`WitnessTracker::witness`]. Only commitments of this key's notes are
+/// retained, so memory stays small.
+///
+/// The trees must start where the scan starts: use [`WitnessTracker::new`]
+/// when scanning from before Sapling activation,
+/// [`WitnessTracker::from_tree_state`] with lightwalletd's tree state for the
+/// block preceding the first scanned block, or
+/// [`WitnessTracker::from_subtree_roots`] to skip the completed subtrees.
+///
+/// [`Scanner::scan_with_witnesses`]: crate::Scanner::scan_with_witnesses
+pub struct WitnessTracker {
+    sapling: PoolTree<sapling::Node, { sapling::NOTE_COMMITMENT_TREE_DEPTH }, SAPLING_SHARD_HEIGHT>,
+    #[cfg(feature = "orchard")]
+    orchard: PoolTree<
+        MerkleHashOrchard,
+        { orchard::NOTE_COMMITMENT_TREE_DEPTH as u8 },
+        ORCHARD_SHARD_HEIGHT,
+    >,
+}
+
+impl Default for WitnessTracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl WitnessTracker {
+    /// Create a tracker with empty commitment trees.
+    pub fn new() -> Self {
+        Self {
+            sapling: PoolTree::new(),
+            #[cfg(feature = "orchard")]
+            orchard: PoolTree::new(),
+        }
+    }
+
+    /// Create a tracker from the hex-encoded trees of lightwalletd's
+    /// `GetTreeState` response for the block at `height`.
+    ///
+    /// Empty strings stand for empty trees. Without the `orchard` feature,
+    /// `orchard_tree` is ignored.
+    pub fn from_tree_state(
+        height: u64,
+        sapling_tree: &str,
+        orchard_tree: &str,
+    ) -> ScanResult<Self> {
+        let state = TreeState {
+            height,
+            sapling_tree: sapling_tree.to_string(),
+            orchard_tree: orchard_tree.to_string(),
+            ..Default::default()
+        };
+        let height = block_height(height)?;
+        let invalid = |e: std::io::Error| ScanError::CommitmentTree(e.to_string());
+
+        Ok(Self {
+            sapling: PoolTree::from_frontier(
+                state.sapling_tree().map_err(invalid)?.to_frontier(),
+                height,
+            )?,
+            #[cfg(feature = "orchard")]
+            orchard: PoolTree::from_frontier(
+                state.orchard_tree().map_err(invalid)?.to_frontier(),
+                height,
+            )?,
+        })
+    }
+
+    /// Create a tracker from the subtree roots of each pool, starting at
+    /// index 0, so a scan can begin at the last root's
+    /// `completing_block_height` instead of at pool activation.
+    ///
+    /// Notes in the subtrees the roots cover can't be witnessed. Without the
+    /// `orchard` feature, `orchard_roots` is ignored.
+    pub fn from_subtree_roots(
+        sapling_roots: &[SubtreeRoot],
+        orchard_roots: &[SubtreeRoot],
+    ) -> ScanResult<Self> {
+        let mut tracker = Self::new();
+        tracker.sapling.bootstrap(sapling_roots)?;
+        #[cfg(feature = "orchard")]
+        tracker.orchard.bootstrap(orchard_roots)?;
+        #[cfg(not(feature = "orchard"))]
+        let _ = orchard_roots;
+        Ok(tracker)
+    }
+
+    /// Add subtree roots to the tree of `pool`, e.g. those completed since
+    /// the tracker was created.
+    ///
+    /// Roots for subtrees already scanned must match the scanned
+    /// commitments. Roots for the transparent pool, or for Orchard without
+    /// the `orchard` feature, are ignored.
+    pub fn insert_subtree_roots(
+        &mut self,
+        pool: ShieldedPool,
+        roots: &[SubtreeRoot],
+    ) -> ScanResult<()> {
+        match pool {
+            ShieldedPool::Sapling => self.sapling.insert_subtree_roots(roots),
+            #[cfg(feature = "orchard")]
+            ShieldedPool::Orchard => self.orchard.insert_subtree_roots(roots),
+            _ => Ok(()),
+        }
+    }
+
+    /// Append a scanned block's note commitments to the trees.
+    pub(crate) fn apply_block<A>(&mut self, block: &ScannedBlock<A>) -> ScanResult<()> {
+        self.sapling.apply(block.height(), block.sapling())?;
+        #[cfg(feature = "orchard")]
+        self.orchard.apply(block.height(), block.orchard())?;
+        Ok(())
+    }
+
+    /// Current tree root of `pool` (hex-encoded), if the tree is complete.
+    ///
+    /// Always `None` for the transparent pool, which has no commitment tree.
+    pub fn anchor(&self, pool: ShieldedPool) -> ScanResult<Option<String>> {
+        match pool {
+            ShieldedPool::Sapling => self.sapling.anchor(),
+            #[cfg(feature = "orchard")]
+            ShieldedPool::Orchard => self.orchard.anchor(),
+            _ => Ok(None),
+        }
+    }
+
+    /// Witness `note` against the current anchor of its pool.
+    ///
+    /// Returns `None` if the note has no recorded position or the tree holds
+    /// no checkpoint yet.
+    pub fn witness(&self, note: &TrackedNote) -> ScanResult<Option<NoteWitness>> {
+        let Some(position) = note.position else {
+            return Ok(None);
+        };
+        let witness = match note.pool {
+            ShieldedPool::Sapling => self.sapling.witness(position)?,
+            #[cfg(feature = "orchard")]
+            ShieldedPool::Orchard => self.orchard.witness(position)?,
+            _ => None,
+        };
+        Ok(witness.map(|(anchor, auth_path)| NoteWitness {
+            pool: note.pool,
+            position,
+            anchor,
+            auth_path,
+        }))
+    }
+}
+
+/// A tree node that can be hex-encoded for input and output.
+trait TreeNode: Hashable + Clone + PartialEq {
+    fn to_hex(&self) -> String;
+    fn from_bytes(bytes: [u8; 32]) -> Option<Self>;
+}
+
+impl TreeNode for sapling::Node {
+    fn to_hex(&self) -> String {
+        hex::encode(self.to_bytes())
+    }
+
+    fn from_bytes(bytes: [u8; 32]) -> Option<Self> {
+        sapling::Node::from_bytes(bytes).into_option()
+    }
+}
+
+#[cfg(feature = "orchard")]
+impl TreeNode for MerkleHashOrchard {
+    fn to_hex(&self) -> String {
+        hex::encode(self.to_bytes())
+    }
+
+    fn from_bytes(bytes: [u8; 32]) -> Option<Self> {
+        MerkleHashOrchard::from_bytes(&bytes).into_option()
+    }
+}
+
+/// Commitment tree of one pool, along with its size.
+struct PoolTree<H: Clone, const DEPTH: u8, const SHARD_HEIGHT: u8> {
+    tree: ShardTree<MemoryShardStore<H, BlockHeight>, DEPTH, SHARD_HEIGHT>,
+    /// Number of leaves, unknown after bootstrapping from subtree roots
+    /// until the first block is applied
+    size: Option<u64>,
+    /// Position after the last bootstrapped subtree
+    subtree_end: u64,
+}
+
+impl<H: TreeNode, const DEPTH: u8, const SHARD_HEIGHT: u8> PoolTree<H, DEPTH, SHARD_HEIGHT> {
+    fn new() -> Self {
+        Self {
+            tree: ShardTree::new(MemoryShardStore::empty(), MAX_CHECKPOINTS),
+            size: Some(0),
+            subtree_end: 0,
+        }
+    }
+
+    fn from_frontier(frontier: Frontier<H, DEPTH>, height: BlockHeight) -> ScanResult<Self> {
+        let mut pool = Self::new();
+        pool.size = Some(frontier.tree_size());
+        pool.tree
+            .insert_frontier(
+                frontier,
+                Retention::Checkpoint {
+                    id: height,
+                    marking: Marking::None,
+                },
+            )
+            .map_err(tree_error)?;
+        Ok(pool)
+    }
+
+    fn bootstrap(&mut self, roots: &[SubtreeRoot]) -> ScanResult<()> {
+        if roots.is_empty() {
+            return Ok(());
+        }
+        for (index, root) in roots.iter().enumerate() {
+            if root.index != index as u64 {
+                return Err(ScanError::CommitmentTree(format!(
+                    "expected subtree root {index}, got {}",
+                    root.index
+                )));
+            }
+        }
+        self.insert_subtree_roots(roots)?;
+        self.size = None;
+        self.subtree_end = (roots.len() as u64) << SHARD_HEIGHT;
+        Ok(())
+    }
+
+    fn insert_subtree_roots(&mut self, roots: &[SubtreeRoot]) -> ScanResult<()> {
+        for root in roots {
+            let hash = hex::decode(&root.root_hash)
+                .ok()
+                .and_then(|bytes| <[u8; 32]>::try_from(bytes).ok())
+                .and_then(H::from_bytes)
+                .ok_or_else(|| {
+                    ScanError::CommitmentTree(format!("invalid root for subtree {}", root.index))
+                })?;
+            self.tree
+                .insert(
+                    Address::from_parts(Level::from(SHARD_HEIGHT), root.index),
+                    hash,
+                )
+                .map_err(tree_error)?;
+        }
+        Ok(())
+    }
+
+    fn apply<NF>(
+        &mut self,
+        height: BlockHeight,
+        bundles: &ScannedBundles<H, NF>,
+    ) -> ScanResult<()> {
+        let commitments = bundles.commitments();
+        let final_size = bundles.final_tree_size() as u64;
+        let start = final_size - commitments.len() as u64;
+        match self.size {
+            Some(size) if start != size => {
+                return Err(ScanError::CommitmentTree(format!(
+                    "tree has {} leaves but block {} starts at position {}",
+                    size,
+                    u32::from(height),
+                    start
+                )));
+            }
+            // The first block may overlap the last subtree, but mustn't leave
+            // a gap after it
+            None if start > self.subtree_end => {
+                return Err(ScanError::CommitmentTree(format!(
+                    "subtree roots end at position {} but block {} starts at position {}",
+                    self.subtree_end,
+                    u32::from(height),
+                    start
+                )));
+            }
+            _ => {}
+        }
+
+        if commitments.is_empty() {
+            self.tree.checkpoint(height).map_err(tree_error)?;
+        } else {
+            self.tree
+                .batch_insert(Position::from(start), commitments.iter().cloned())
+                .map_err(tree_error)?;
+        }
+        self.size = Some(final_size);
+        Ok(())
+    }
+
+    fn anchor(&self) -> ScanResult<Option<String>> {
+        let root = self
+            .tree
+            .root_at_checkpoint_depth(Some(0))
+            .map_err(tree_error)?;
+        Ok(root.map(|root| root.to_hex()))
+    }
+
+    fn witness(&self, position: u64) -> ScanResult<Option<(String, Vec<String>)>> {
+        let Some(path) = self
+            .tree
+            .witness_at_checkpoint_depth(Position::from(position), 0)
+            .map_err(tree_error)?
+        else {
+            return Ok(None);
+        };
+        let anchor = self.anchor()?.unwrap_or_default();
+        Ok(Some((anchor, auth_path(&path))))
+    }
+}
+
+fn auth_path<H: TreeNode, const DEPTH: u8>(path: &MerklePath<H, DEPTH>) -> Vec<String> {
+    path.path_elems().iter().map(TreeNode::to_hex).collect()
+}
+
+fn tree_error<E: std::fmt::Display>(e: E) -> ScanError {
+    ScanError::CommitmentTree(e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_tree_state() {
+        let tracker = WitnessTracker::from_tree_state(419_200, "", "").unwrap();
+        let empty_root =
+            sapling::Node::empty_root(Level::from(sapling::NOTE_COMMITMENT_TREE_DEPTH));
+        assert_eq!(
+            tracker.anchor(ShieldedPool::Sapling).unwrap(),
+            Some(empty_root.to_hex())
+        );
+
+        assert!(matches!(
+            WitnessTracker::from_tree_state(419_200, "zz", ""),
+            Err(ScanError::CommitmentTree(_))
+        ));
+    }
+
+    #[test]
+    fn test_from_subtree_roots() {
+        let root = |index| SubtreeRoot {
+            index,
+            root_hash: sapling::Node::empty_root(Level::from(SAPLING_SHARD_HEIGHT)).to_hex(),
+            completing_block_height: 2_000_000 + index,
+        };
+        let tracker = WitnessTracker::from_subtree_roots(&[root(0), root(1)], &[]).unwrap();
+        assert_eq!(tracker.sapling.subtree_end, 2 << SAPLING_SHARD_HEIGHT);
+        assert_eq!(tracker.sapling.size, None);
+
+        assert!(matches!(
+            WitnessTracker::from_subtree_roots(&[root(1)], &[]),
+            Err(ScanError::CommitmentTree(_))
+        ));
+        let invalid = SubtreeRoot {
+            root_hash: "ff".repeat(32),
+            ..root(0)
+        };
+        assert!(matches!(
+            WitnessTracker::from_subtree_roots(&[invalid], &[]),
+            Err(ScanError::CommitmentTree(_))
+        ));
+    }
+}
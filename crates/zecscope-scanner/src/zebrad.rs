@@ -0,0 +1,339 @@
+//! Block source backed by a zebrad full node's JSON-RPC interface.
+
+use base64::Engine;
+use serde_json::{json, Value};
+use zcash_client_backend::proto::compact_formats;
+use zcash_client_backend::proto::service::TreeState;
+use zcash_encoding::CompactSize;
+use zcash_primitives::block::BlockHeader;
+use zcash_primitives::transaction::Transaction;
+use zcash_protocol::consensus::{BranchId, Network};
+
+use crate::enhance::{RawTransaction, TransactionSource};
+use crate::error::{ScanError, ScanResult};
+use crate::proxy::Proxy;
+use crate::retry::RetryPolicy;
+use crate::scanner::block_height;
+use crate::source::BlockSource;
+use crate::types::CompactBlock;
+
+/// Length of the note ciphertext prefix kept in compact outputs.
+const COMPACT_NOTE_SIZE: usize = 52;
+
+/// Fetches full blocks from zebrad and converts them to compact blocks.
+///
+/// Blocks come from `getblock`; the commitment tree sizes that compact
+/// blocks carry are taken from `z_gettreestate` at the start of each range
+/// and counted forward from there.
+pub struct ZebradBlockSource {
+    agent: ureq::Agent,
+    url: String,
+    network: Network,
+    authorization: Option<String>,
+    retry: RetryPolicy,
+    /// Next height and the tree sizes before it, after a fetch
+    tree_sizes: Option<(u64, u32, u32)>,
+}
+
+impl ZebradBlockSource {
+    /// Use the zebrad RPC endpoint at `url` for `network`.
+    pub fn new(url: &str, network: Network) -> Self {
+        Self {
+            agent: ureq::Agent::new(),
+            url: url.to_string(),
+            network,
+            authorization: None,
+            retry: RetryPolicy::default(),
+            tree_sizes: None,
+        }
+    }
+
+    /// Authenticate with HTTP basic auth, e.g. the `__cookie__:…` contents
+    /// of zebrad's cookie file.
+    pub fn with_auth(mut self, credentials: &str) -> Self {
+        let encoded = base64::engine::general_purpose::STANDARD.encode(credentials);
+        self.authorization = Some(format!("Basic {encoded}"));
+        self
+    }
+
+    /// Send requests through a SOCKS5 proxy.
+    pub fn with_proxy(mut self, proxy: &Proxy) -> ScanResult<Self> {
+        let proxy = ureq::Proxy::new(proxy.url())
+            .map_err(|e| ScanError::BlockSource(format!("invalid SOCKS5 proxy: {e}")))?;
+        self.agent = ureq::AgentBuilder::new().proxy(proxy).build();
+        Ok(self)
+    }
+
+    /// Retry failed requests according to `retry`.
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Height of the node's current chain tip.
+    pub fn latest_height(&mut self) -> ScanResult<u64> {
+        let height = self.retry.run(|| self.call("getblockcount", json!([])))?;
+        height
+            .as_u64()
+            .ok_or_else(|| rpc_error("getblockcount", "expected a height"))
+    }
+
+    fn call(&self, method: &str, params: Value) -> ScanResult<Value> {
+        let mut request = self
+            .agent
+            .post(&self.url)
+            .set("Content-Type", "application/json");
+        if let Some(authorization) = &self.authorization {
+            request = request.set("Authorization", authorization);
+        }
+        let body = json!({"jsonrpc": "2.0", "id": 1, "method": method, "params": params});
+        // JSON-RPC errors come back with an error status but a normal body
+        let response = match request.send_string(&body.to_string()) {
+            Ok(response) | Err(ureq::Error::Status(_, response)) => response,
+            Err(e) => return Err(rpc_error(method, e)),
+        };
+        let response: Value =
+            serde_json::from_str(&response.into_string().map_err(|e| rpc_error(method, e))?)?;
+        rpc_result(method, response)
+    }
+
+    /// Sapling and Orchard tree sizes after the block at `height`.
+    fn tree_sizes_at(&self, height: u64) -> ScanResult<(u32, u32)> {
+        let state = self.call("z_gettreestate", json!([height.to_string()]))?;
+        let final_state = |pool: &str| {
+            state[pool]["commitments"]["finalState"]
+                .as_str()
+                .unwrap_or_default()
+                .to_string()
+        };
+        let state = TreeState {
+            height,
+            sapling_tree: final_state("sapling"),
+            orchard_tree: final_state("orchard"),
+            ..Default::default()
+        };
+        let invalid = |e: std::io::Error| rpc_error("z_gettreestate", e);
+        let sapling = state
+            .sapling_tree()
+            .map_err(invalid)?
+            .to_frontier()
+            .tree_size();
+        let orchard = state
+            .orchard_tree()
+            .map_err(invalid)?
+            .to_frontier()
+            .tree_size();
+        Ok((sapling as u32, orchard as u32))
+    }
+}
+
+impl BlockSource for ZebradBlockSource {
+    /// Fetches the range block by block, resuming at the block that failed.
+    fn get_block_range(&mut self, start: u64, end: u64) -> ScanResult<Vec<CompactBlock>> {
+        let retry = self.retry;
+        retry.fetch_range(start, end, |from, blocks| {
+            let (mut sapling_size, mut orchard_size) = match self.tree_sizes {
+                Some((next, sapling, orchard)) if next == from => (sapling, orchard),
+                _ if from == 0 => (0, 0),
+                _ => self.tree_sizes_at(from - 1)?,
+            };
+
+            for height in from..=end {
+                let raw = self.call("getblock", json!([height.to_string(), 0]))?;
+                let raw = raw
+                    .as_str()
+                    .and_then(|raw| hex::decode(raw).ok())
+                    .ok_or_else(|| rpc_error("getblock", "expected a hex-encoded block"))?;
+                let mut block = compact_block(&self.network, height, &raw)?;
+
+                for tx in &block.vtx {
+                    sapling_size += tx.outputs.len() as u32;
+                    orchard_size += tx.actions.len() as u32;
+                }
+                block.chain_metadata = Some(compact_formats::ChainMetadata {
+                    sapling_commitment_tree_size: sapling_size,
+                    orchard_commitment_tree_size: orchard_size,
+                });
+                blocks.push(block.into());
+                self.tree_sizes = Some((height + 1, sapling_size, orchard_size));
+            }
+            Ok(())
+        })
+    }
+}
+
+impl TransactionSource for ZebradBlockSource {
+    /// Fetches the transactions with `getrawtransaction`. A transaction the
+    /// node doesn't know fails the fetch, as zebrad only indexes mempool and
+    /// best-chain transactions.
+    fn get_transactions(&mut self, txids: &[String]) -> ScanResult<Vec<RawTransaction>> {
+        let retry = self.retry;
+        txids
+            .iter()
+            .map(|txid| {
+                let params = json!([display_txid(txid)?, 0]);
+                let raw = retry.run(|| self.call("getrawtransaction", params.clone()))?;
+                let data = raw
+                    .as_str()
+                    .and_then(|raw| hex::decode(raw).ok())
+                    .ok_or_else(|| {
+                        rpc_error("getrawtransaction", "expected a hex-encoded transaction")
+                    })?;
+                Ok(RawTransaction {
+                    txid: txid.clone(),
+                    data,
+                })
+            })
+            .collect()
+    }
+}
+
+/// A txid as scan results report it (in byte order) in the reversed order
+/// RPC calls take.
+fn display_txid(txid: &str) -> ScanResult<String> {
+    let mut bytes =
+        hex::decode(txid).map_err(|e| rpc_error("getrawtransaction", format!("txid: {e}")))?;
+    bytes.reverse();
+    Ok(hex::encode(bytes))
+}
+
+/// Reduce a serialized full block to a compact block (without chain
+/// metadata).
+fn compact_block(
+    network: &Network,
+    height: u64,
+    mut raw: &[u8],
+) -> ScanResult<compact_formats::CompactBlock> {
+    let invalid = |e: std::io::Error| {
+        ScanError::BlockSource(format!("invalid block at height {height}: {e}"))
+    };
+    let header = BlockHeader::read(&mut raw).map_err(invalid)?;
+    let branch_id = BranchId::for_height(network, block_height(height)?);
+
+    let tx_count = CompactSize::read(&mut raw).map_err(invalid)?;
+    let mut vtx = Vec::new();
+    for index in 0..tx_count {
+        let tx = Transaction::read(&mut raw, branch_id).map_err(invalid)?;
+        let compact = compact_tx(index, &tx);
+        // Fully transparent transactions have nothing to scan
+        if !(compact.spends.is_empty() && compact.outputs.is_empty() && compact.actions.is_empty())
+        {
+            vtx.push(compact);
+        }
+    }
+
+    Ok(compact_formats::CompactBlock {
+        proto_version: 1,
+        height,
+        hash: header.hash().0.to_vec(),
+        prev_hash: header.prev_block.0.to_vec(),
+        time: header.time,
+        vtx,
+        ..Default::default()
+    })
+}
+
+fn compact_tx(index: u64, tx: &Transaction) -> compact_formats::CompactTx {
+    let mut compact = compact_formats::CompactTx {
+        index,
+        hash: tx.txid().as_ref().to_vec(),
+        ..Default::default()
+    };
+    if let Some(bundle) = tx.sapling_bundle() {
+        compact.spends = bundle
+            .shielded_spends()
+            .iter()
+            .map(|spend| compact_formats::CompactSaplingSpend {
+                nf: spend.nullifier().0.to_vec(),
+            })
+            .collect();
+        compact.outputs = bundle
+            .shielded_outputs()
+            .iter()
+            .map(|output| compact_formats::CompactSaplingOutput {
+                cmu: output.cmu().to_bytes().to_vec(),
+                ephemeral_key: output.ephemeral_key().0.to_vec(),
+                ciphertext: output.enc_ciphertext()[..COMPACT_NOTE_SIZE].to_vec(),
+            })
+            .collect();
+    }
+    if let Some(bundle) = tx.orchard_bundle() {
+        compact.actions = bundle
+            .actions()
+            .iter()
+            .map(|action| compact_formats::CompactOrchardAction {
+                nullifier: action.nullifier().to_bytes().to_vec(),
+                cmx: action.cmx().to_bytes().to_vec(),
+                ephemeral_key: action.encrypted_note().epk_bytes.to_vec(),
+                ciphertext: action.encrypted_note().enc_ciphertext[..COMPACT_NOTE_SIZE].to_vec(),
+            })
+            .collect();
+    }
+    compact
+}
+
+/// Unwrap the result of a JSON-RPC response.
+fn rpc_result(method: &str, mut response: Value) -> ScanResult<Value> {
+    match response.get("error") {
+        Some(error) if !error.is_null() => {
+            let message = error["message"].as_str().unwrap_or("unknown error");
+            Err(rpc_error(method, message))
+        }
+        _ => Ok(response["result"].take()),
+    }
+}
+
+fn rpc_error(method: &str, e: impl std::fmt::Display) -> ScanError {
+    ScanError::BlockSource(format!("{method}: {e}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use zcash_primitives::block::{BlockHash, BlockHeaderData};
+
+    #[test]
+    fn test_compact_block() {
+        let header = BlockHeaderData {
+            version: 4,
+            prev_block: BlockHash([7; 32]),
+            merkle_root: [0; 32],
+            final_sapling_root: [0; 32],
+            time: 1_700_000_000,
+            bits: 0,
+            nonce: [0; 32],
+            solution: vec![0; 1344],
+        }
+        .freeze()
+        .unwrap();
+        let mut raw = Vec::new();
+        header.write(&mut raw).unwrap();
+        CompactSize::write(&mut raw, 0).unwrap();
+
+        let block = compact_block(&Network::MainNetwork, 2_000_000, &raw).unwrap();
+        assert_eq!(block.hash, header.hash().0.to_vec());
+        assert_eq!(block.prev_hash, vec![7; 32]);
+        assert_eq!(block.time, 1_700_000_000);
+        assert!(block.vtx.is_empty());
+
+        assert!(compact_block(&Network::MainNetwork, 2_000_000, &raw[..100]).is_err());
+    }
+
+    #[test]
+    fn test_display_txid() {
+        assert_eq!(display_txid("0102ff").unwrap(), "ff0201");
+        assert!(display_txid("xyz").is_err());
+    }
+
+    #[test]
+    fn test_rpc_result() {
+        let ok = json!({"result": 42, "error": null, "id": 1});
+        assert_eq!(rpc_result("getblockcount", ok).unwrap(), json!(42));
+
+        let failed = json!({"error": {"code": -8, "message": "block height not in best chain"}});
+        assert!(matches!(
+            rpc_result("getblock", failed),
+            Err(ScanError::BlockSource(message)) if message.contains("not in best chain")
+        ));
+    }
+}
@@ -0,0 +1,342 @@
+//! UniFFI bindings for zecscope-scanner.
+//!
+//! This crate exposes the scanner to Swift and Kotlin, so iOS and Android
+//! wallets can run the same scanning code as the browser build. Blocks are
+//! passed as protobuf `CompactBlock` messages, as streamed by lightwalletd.
+
+use zecscope_scanner::{redact_viewing_key, ScanError};
+
+uniffi::setup_scaffolding!();
+
+/// Zcash network a scanner and its keys belong to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, uniffi::Enum)]
+pub enum Network {
+    Mainnet,
+    Testnet,
+}
+
+impl From<Network> for zecscope_scanner::Network {
+    fn from(network: Network) -> Self {
+        match network {
+            Network::Mainnet => Self::MainNetwork,
+            Network::Testnet => Self::TestNetwork,
+        }
+    }
+}
+
+/// Shielded pool (or the transparent pool) a transaction was found in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, uniffi::Enum)]
+pub enum ShieldedPool {
+    Sapling,
+    Orchard,
+    Transparent,
+}
+
+impl From<zecscope_scanner::ShieldedPool> for ShieldedPool {
+    fn from(pool: zecscope_scanner::ShieldedPool) -> Self {
+        match pool {
+            zecscope_scanner::ShieldedPool::Sapling => Self::Sapling,
+            zecscope_scanner::ShieldedPool::Orchard => Self::Orchard,
+            zecscope_scanner::ShieldedPool::Transparent => Self::Transparent,
+        }
+    }
+}
+
+/// Whether funds were received or sent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, uniffi::Enum)]
+pub enum TxDirection {
+    In,
+    Out,
+}
+
+impl From<zecscope_scanner::TxDirection> for TxDirection {
+    fn from(direction: zecscope_scanner::TxDirection) -> Self {
+        match direction {
+            zecscope_scanner::TxDirection::In => Self::In,
+            zecscope_scanner::TxDirection::Out => Self::Out,
+        }
+    }
+}
+
+/// ZIP 32 scope of the address a note was received on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, uniffi::Enum)]
+pub enum KeyScope {
+    External,
+    Internal,
+}
+
+impl From<zecscope_scanner::KeyScope> for KeyScope {
+    fn from(scope: zecscope_scanner::KeyScope) -> Self {
+        match scope {
+            zecscope_scanner::KeyScope::External => Self::External,
+            zecscope_scanner::KeyScope::Internal => Self::Internal,
+        }
+    }
+}
+
+/// What to scan with. `Debug` shows the viewing key redacted.
+#[derive(Clone, uniffi::Record)]
+pub struct ScanRequest {
+    /// Unified Full Viewing Key (uview1... or uviewtest1...)
+    pub viewing_key: String,
+    /// Identifier for tracking which key found transactions
+    pub key_id: String,
+    /// Also report change outputs
+    #[uniffi(default = false)]
+    pub include_change: bool,
+}
+
+impl std::fmt::Debug for ScanRequest {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ScanRequest")
+            .field("viewing_key", &redact_viewing_key(&self.viewing_key))
+            .field("key_id", &self.key_id)
+            .field("include_change", &self.include_change)
+            .finish()
+    }
+}
+
+impl ScanRequest {
+    fn to_scanner(&self) -> zecscope_scanner::ScanRequest {
+        zecscope_scanner::ScanRequest {
+            viewing_key: self.viewing_key.clone(),
+            key_id: self.key_id.clone(),
+            compact_blocks: Vec::new(),
+            options: zecscope_scanner::ScanOptions {
+                include_change: self.include_change,
+                ..Default::default()
+            },
+            nullifiers: Default::default(),
+        }
+    }
+}
+
+/// A discovered transaction.
+#[derive(Debug, Clone, PartialEq, Eq, uniffi::Record)]
+pub struct ZecTransaction {
+    /// Transaction ID (hex-encoded)
+    pub txid: String,
+    /// Block height
+    pub height: u64,
+    /// Block timestamp (Unix seconds)
+    pub time: i64,
+    /// Amount in zatoshis
+    pub amount_zat: i64,
+    pub direction: TxDirection,
+    /// Decrypted memo text, if any
+    pub memo: Option<String>,
+    pub key_id: String,
+    pub pool: ShieldedPool,
+    /// Whether this is a change output
+    pub is_change: bool,
+    /// Receiving address, for incoming entries
+    pub address: Option<String>,
+    /// Recipient of an outgoing payment, when decrypted from a full
+    /// transaction
+    pub recipient_address: Option<String>,
+    /// Transaction fee in zatoshis, if known
+    pub fee_zat: Option<u64>,
+    /// Height after which the transaction could no longer be mined, when
+    /// known from the full transaction
+    pub expiry_height: Option<u64>,
+    /// Diversifier index of the receiving address, if known
+    pub diversifier_index: Option<u64>,
+    /// Index of the Sapling output or transparent output
+    pub output_index: Option<u32>,
+    /// Index of the Orchard action
+    pub action_index: Option<u32>,
+    /// Whether the transaction is still unmined (height and time are 0)
+    pub unconfirmed: bool,
+    /// Scope the note was received on, for incoming entries when change is
+    /// included
+    pub scope: Option<KeyScope>,
+    /// ZIP 32 account index of the key that found this entry, if known
+    pub account_id: Option<u32>,
+    /// Invoice ID the memo matched, if any
+    pub invoice_id: Option<String>,
+    /// Position of the note in its pool's commitment tree, if known
+    pub note_position: Option<u64>,
+    /// Whether this is a coinbase output, spendable after 100 confirmations
+    pub is_coinbase: bool,
+    /// Whether this moves the wallet's own funds between pools
+    pub self_transfer: bool,
+    /// Whether this looks like dust spam, when spam flagging is on
+    pub likely_spam: bool,
+}
+
+impl From<zecscope_scanner::ZecTransaction> for ZecTransaction {
+    fn from(tx: zecscope_scanner::ZecTransaction) -> Self {
+        Self {
+            amount_zat: tx.amount_zatoshis(),
+            fee_zat: tx.fee_zat.as_deref().and_then(|fee| fee.parse().ok()),
+            expiry_height: tx.expiry_height,
+            txid: tx.txid,
+            height: tx.height,
+            time: tx.time,
+            direction: tx.direction.into(),
+            memo: tx.memo,
+            key_id: tx.key_id,
+            pool: tx.pool.into(),
+            is_change: tx.is_change,
+            address: tx.address,
+            recipient_address: tx.recipient_address,
+            diversifier_index: tx.diversifier_index,
+            output_index: tx.output_index,
+            action_index: tx.action_index,
+            unconfirmed: tx.unconfirmed,
+            scope: tx.scope.map(Into::into),
+            account_id: tx.account_id,
+            invoice_id: tx.invoice_id,
+            note_position: tx.note_position,
+            is_coinbase: tx.is_coinbase,
+            self_transfer: tx.self_transfer,
+            likely_spam: tx.likely_spam,
+        }
+    }
+}
+
+/// Errors surfaced to Swift and Kotlin.
+#[derive(Debug, thiserror::Error, uniffi::Error)]
+pub enum ZecscopeError {
+    /// The viewing key could not be decoded.
+    #[error("Invalid viewing key: {message}")]
+    InvalidViewingKey { message: String },
+    /// Block data could not be decoded.
+    #[error("Invalid block data: {message}")]
+    InvalidBlockData { message: String },
+    /// Scanning failed.
+    #[error("Scan error: {message}")]
+    Scan { message: String },
+}
+
+impl From<ScanError> for ZecscopeError {
+    fn from(e: ScanError) -> Self {
+        let message = e.to_string();
+        match e {
+            ScanError::InvalidViewingKey(_) => Self::InvalidViewingKey { message },
+            ScanError::InvalidProtobuf { .. }
+            | ScanError::InvalidCompactBlock { .. }
+            | ScanError::InvalidHex { .. }
+            | ScanError::Decompression(_)
+            | ScanError::Json(_) => Self::InvalidBlockData { message },
+            _ => Self::Scan { message },
+        }
+    }
+}
+
+/// A scanner for one network.
+#[derive(uniffi::Object)]
+pub struct Scanner {
+    inner: zecscope_scanner::Scanner,
+}
+
+#[uniffi::export]
+impl Scanner {
+    /// Create a scanner for `network`.
+    #[uniffi::constructor]
+    pub fn new(network: Network) -> Self {
+        Self {
+            inner: zecscope_scanner::Scanner::new(network.into()),
+        }
+    }
+
+    /// Check that `viewing_key` is a valid UFVK for this scanner's network.
+    pub fn validate_key(&self, viewing_key: String) -> Result<(), ZecscopeError> {
+        self.inner.prepare_key(&viewing_key)?;
+        Ok(())
+    }
+
+    /// Scan protobuf-encoded compact blocks, one serialized `CompactBlock`
+    /// per entry, in ascending height order.
+    pub fn scan_blocks(
+        &self,
+        request: ScanRequest,
+        blocks: Vec<Vec<u8>>,
+    ) -> Result<Vec<ZecTransaction>, ZecscopeError> {
+        let transactions = self.inner.scan_proto(&request.to_scanner(), &blocks)?;
+        Ok(transactions.into_iter().map(Into::into).collect())
+    }
+
+    /// Scan a buffer of length-delimited protobuf `CompactBlock` messages,
+    /// optionally gzip- or zstd-compressed.
+    pub fn scan_delimited(
+        &self,
+        request: ScanRequest,
+        bytes: Vec<u8>,
+    ) -> Result<Vec<ZecTransaction>, ZecscopeError> {
+        let transactions = self
+            .inner
+            .scan_proto_delimited(&request.to_scanner(), &bytes)?;
+        Ok(transactions.into_iter().map(Into::into).collect())
+    }
+
+    /// Estimate a block height mined before `time` (Unix seconds), to start
+    /// scanning a wallet created then.
+    pub fn estimate_birthday(&self, time: i64) -> u64 {
+        self.inner.estimate_birthday(time)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(viewing_key: &str) -> ScanRequest {
+        ScanRequest {
+            viewing_key: viewing_key.to_string(),
+            key_id: "mobile".to_string(),
+            include_change: false,
+        }
+    }
+
+    #[test]
+    fn test_errors() {
+        let scanner = Scanner::new(Network::Mainnet);
+        assert!(matches!(
+            scanner.validate_key("uview1invalid".to_string()),
+            Err(ZecscopeError::InvalidViewingKey { .. })
+        ));
+        assert!(matches!(
+            scanner.scan_blocks(request("uview1invalid"), Vec::new()),
+            Err(ZecscopeError::InvalidViewingKey { .. })
+        ));
+    }
+
+    #[test]
+    fn test_transaction_conversion() {
+        let tx = zecscope_scanner::ZecTransaction {
+            txid: "aa".to_string(),
+            height: 2_000_000,
+            time: 1_700_000_000,
+            amount_zat: "150000".to_string(),
+            direction: zecscope_scanner::TxDirection::In,
+            memo: Some("thanks".to_string()),
+            key_id: "mobile".to_string(),
+            pool: zecscope_scanner::ShieldedPool::Orchard,
+            is_change: false,
+            address: None,
+            recipient_address: None,
+            fee_zat: Some("10000".to_string()),
+            expiry_height: None,
+            fiat_value: None,
+            fiat_currency: None,
+            diversifier_index: Some(0),
+            output_index: None,
+            action_index: Some(1),
+            unconfirmed: false,
+            scope: Some(zecscope_scanner::KeyScope::Internal),
+            account_id: None,
+            invoice_id: None,
+            note_position: Some(7),
+            is_coinbase: false,
+            self_transfer: false,
+            likely_spam: false,
+        };
+        let tx = ZecTransaction::from(tx);
+        assert_eq!(tx.amount_zat, 150_000);
+        assert_eq!(tx.fee_zat, Some(10_000));
+        assert_eq!(tx.pool, ShieldedPool::Orchard);
+        assert_eq!(tx.direction, TxDirection::In);
+        assert_eq!(tx.scope, Some(KeyScope::Internal));
+    }
+}
@@ -1,5 +1,5 @@
 export type TxDirection = 'in' | 'out'
-export type ShieldedPool = 'sapling' | 'orchard'
+export type ShieldedPool = 'sapling' | 'orchard' | 'transparent'
 
 export interface ViewingKeyProfile {
   id: string
@@ -17,6 +17,36 @@ export interface ZecTransaction {
   memo?: string
   keyId: string // which viewing key this tx is associated with
   pool: ShieldedPool // which shielded pool: sapling or orchard
+  isChange?: boolean // change note returned to the wallet (only when change is included)
+  address?: string // receiving address for incoming notes (zs1… or unified)
+  recipientAddress?: string // recipient of an outgoing payment, decrypted from a full transaction
+  feeZat?: string // fee paid, on the outgoing entry of a transaction
+  expiryHeight?: number // height after which the transaction can't be mined, from the full transaction
+  fiatValue?: string // value in fiatCurrency at block time, when priced
+  fiatCurrency?: string // currency code of fiatValue, e.g. 'USD'
+  diversifierIndex?: number // diversifier index of the receiving address (incoming only)
+  outputIndex?: number // Sapling (or transparent) output index within the transaction
+  actionIndex?: number // Orchard action index within the transaction
+  unconfirmed?: boolean // still in the mempool; height and time are 0 until mined
+  scope?: 'external' | 'internal' // receiving scope of incoming notes (only when change is included)
+  accountId?: number // ZIP 32 account index, for multi-account scans
+  invoiceId?: string // registered invoice ID the memo starts with
+  notePosition?: number // note commitment tree position of incoming notes
+  isCoinbase?: boolean // coinbase output, spendable after 100 confirmations
+  selfTransfer?: boolean // moves the wallet's own funds between pools (e.g. Sapling to Orchard)
+  likelySpam?: boolean // incoming dust that looks like spam, when scanned with flag_spam
+}
+
+export interface KeyReport {
+  keyId: string
+  transactionCount: number
+  totalReceivedZat: string // incoming amounts, excluding change
+  pools: ShieldedPool[]
+  transactions: ZecTransaction[]
+}
+
+export interface ScanReport {
+  keys: KeyReport[] // one per key, in order of first appearance
 }
 
 export interface AlertRule {
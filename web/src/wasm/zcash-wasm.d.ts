@@ -1,3 +1,91 @@
+// Minimal declarations for type-checking without a built package. The
+// wasm-pack build's generated `zcash_wasm.d.ts` declares the full request
+// and result types (`ZecTransaction`, `CompactBlock`, `ScanState`, ...).
 declare module 'zcash-wasm' {
+  /** Every function below throws an `Error` carrying these fields on failure. */
+  export interface ScanErrorInfo {
+    code: string
+    message: string
+    height?: number
+  }
+
+  export interface WasmScanRequest {
+    viewing_key: string
+    key_id?: string
+    compact_blocks: unknown[]
+    network?: 'mainnet' | 'testnet'
+  }
+
+  export interface WasmScannerConfig {
+    viewing_key: string
+    key_id?: string
+    network?: 'mainnet' | 'testnet'
+  }
+
+  export interface WasmSessionConfig extends WasmScannerConfig {
+    state?: unknown
+  }
+
+  export type WasmProgressCallback = (progress: {
+    blocksScanned: number
+    totalBlocks: number
+    currentHeight: number
+    outputsTried: number
+  }) => void
+
+  export function scan_blocks(request: WasmScanRequest): unknown[]
+  export function scan_blocks_with_progress(
+    request: WasmScanRequest,
+    on_progress: WasmProgressCallback,
+  ): unknown[]
+  export function scan_blocks_bytes(request: WasmScannerConfig, blocks: Uint8Array): unknown[]
+  export function scan_report(transactions: unknown[]): unknown
+
+  /** `{ version, pools, features, networks }` describing this build. */
+  export function capabilities(): unknown
+
+  /** Only in builds with the `threads` feature; see `initScannerThreads`. */
+  export const init_thread_pool: ((threads: number) => Promise<void>) | undefined
+
+  /** @deprecated Use `scan_blocks`. */
   export function scan_compact_blocks(request_json: string): unknown
+  /** @deprecated Use `scan_blocks_with_progress`. */
+  export function scan_compact_blocks_with_progress(
+    request_json: string,
+    on_progress: WasmProgressCallback,
+  ): unknown
+  /** @deprecated Use `scan_blocks_bytes`. */
+  export function scan_compact_blocks_bytes(request_json: string, blocks: Uint8Array): unknown
+  /** @deprecated Use `scan_report`. */
+  export function build_scan_report(transactions_json: string): unknown
+  /** @deprecated Use `capabilities`. */
+  export function scanner_capabilities(): unknown
+
+  export class ScannerSession {
+    /** Passing the config as a JSON string is deprecated. */
+    constructor(config: WasmSessionConfig | string)
+    feed(compact_blocks: unknown[]): unknown[]
+    next_height(): bigint | undefined
+    scan_state(): unknown
+    transactions(): unknown[]
+    /** @deprecated Use `feed`. */
+    feed_blocks(compact_blocks_json: string): unknown
+    /** @deprecated Use `scan_state`. */
+    state(): unknown
+    /** @deprecated Use `transactions`, then `free`. */
+    finish(): unknown
+    free(): void
+  }
+
+  export class WasmScanner {
+    /** Passing the config as a JSON string is deprecated. */
+    constructor(config: WasmScannerConfig | string)
+    scan_blocks(compact_blocks: unknown[]): unknown[]
+    scan_blocks_bytes(blocks: Uint8Array): unknown[]
+    /** @deprecated Use `scan_blocks`. */
+    scan(compact_blocks_json: string): unknown
+    /** @deprecated Use `scan_blocks_bytes`. */
+    scan_bytes(blocks: Uint8Array): unknown
+    free(): void
+  }
 }
@@ -1,4 +1,4 @@
-import type { ZecTransaction } from '../types'
+import type { ShieldedPool, ZecTransaction } from '../types'
 
 // Shape of the request we will send into the WASM scanner.
 // `compactBlocks` should contain the JSON objects returned from /api/blocks
@@ -7,6 +7,7 @@ export interface ScanRequest {
   viewingKey: string
   keyId: string
   compactBlocks: unknown[]
+  network?: 'mainnet' | 'testnet'
 }
 
 type BufferJson = { type: string; data: number[] }
@@ -122,26 +123,40 @@ function normalizeViewingKey(raw: string): string {
   return trimmed
 }
 
-// This is a placeholder implementation that just returns an empty
-// transaction list. Once the Rust WASM module is built and bundled,
-// we will replace this with a dynamic import of the generated
-// `scan_compact_blocks` function and pass the serialized request.
 export async function scanWithViewingKey(req: ScanRequest): Promise<ZecTransaction[]> {
   const wasm = await import('zcash-wasm')
-  const requestPayload = {
+  return wasm.scan_blocks({
     viewing_key: normalizeViewingKey(req.viewingKey),
     key_id: req.keyId,
-    compact_blocks_json: JSON.stringify(req.compactBlocks),
-  }
-  const raw = wasm.scan_compact_blocks(JSON.stringify(requestPayload)) as unknown
+    compact_blocks: req.compactBlocks,
+    network: req.network ?? 'mainnet',
+  }) as ZecTransaction[]
+}
 
-  if (typeof raw === 'string') {
-    return JSON.parse(raw) as ZecTransaction[]
-  }
+// What the bundled WASM scanner was built with, e.g. to warn when Orchard
+// notes can't be found.
+export interface ScannerCapabilities {
+  version: string
+  pools: ShieldedPool[]
+  features: string[]
+  networks: Array<'mainnet' | 'testnet'>
+}
 
-  if (Array.isArray(raw)) {
-    return raw as ZecTransaction[]
-  }
+export async function getScannerCapabilities(): Promise<ScannerCapabilities> {
+  const wasm = await import('zcash-wasm')
+  return wasm.capabilities() as ScannerCapabilities
+}
 
-  return JSON.parse(String(raw)) as ZecTransaction[]
+// Start the scanner's worker pool, if the bundled WASM was built with the
+// `threads` feature and the page is cross-origin isolated. Threaded scans
+// block while the workers decrypt, so only call this (and scan) from a Worker.
+export async function initScannerThreads(
+  threads: number = navigator.hardwareConcurrency,
+): Promise<boolean> {
+  const wasm = await import('zcash-wasm')
+  if (!wasm.init_thread_pool || !globalThis.crossOriginIsolated) {
+    return false
+  }
+  await wasm.init_thread_pool(threads)
+  return true
 }
@@ -2,56 +2,681 @@
 //!
 //! This crate provides WebAssembly bindings for the zecscope-scanner library,
 //! allowing Zcash shielded transaction scanning directly in web browsers.
+//!
+//! Requests and results are plain JS values, converted with
+//! `serde-wasm-bindgen`. The original functions taking and returning JSON
+//! strings remain for existing callers but are deprecated.
+//!
+//! Failures are thrown as JS `Error`s with a `code` property
+//! (`"INVALID_VIEWING_KEY"`, `"CHAIN_DISCONTINUITY"`, `"INVALID_REQUEST"`, …)
+//! and, for errors about a particular block, a `height`.
+//!
+//! The generated `.d.ts` declares the request and result types, derived
+//! from the Rust types with `tsify`, and `ScanErrorInfo` for errors.
 
+use tsify::Tsify;
 use wasm_bindgen::prelude::*;
-use zecscope_scanner::{Scanner, ScanRequest, CompactBlock};
+use zecscope_scanner::{
+    ufvk_fingerprint, Capabilities, CompactBlock, ContentType, Network, PreparedKey, ProgressSink,
+    ScanError, ScanOptions, ScanProgress, ScanReport, ScanRequest, ScanState, Scanner,
+    ZecTransaction,
+};
 
 /// Scan compact blocks with a viewing key.
 ///
-/// Takes a JSON request with:
+/// Takes a request object with:
 /// - `viewing_key`: Unified Full Viewing Key (uview1...)
-/// - `key_id`: Identifier for tracking which key found transactions
+/// - `key_id` (optional): Identifier for tracking which key found
+///   transactions, the key's `ufvk_fingerprint` by default
 /// - `compact_blocks`: Array of compact blocks from lightwalletd
+/// - `network` (optional): `"mainnet"` (default) or `"testnet"`
+///
+/// Returns an array of discovered transactions.
+#[wasm_bindgen(unchecked_return_type = "ZecTransaction[]")]
+pub fn scan_blocks(
+    #[wasm_bindgen(unchecked_param_type = "WasmScanRequest")] request: JsValue,
+) -> Result<JsValue, JsValue> {
+    let transactions = scan(
+        from_js(request, "request")?,
+        &mut zecscope_scanner::NoProgress,
+    )?;
+    to_js(&transactions)
+}
+
+/// Scan compact blocks, calling `on_progress` as the scan advances.
+///
+/// Takes the same request as [`scan_blocks`]. `on_progress` is called with
+/// an object of the form
+/// `{ blocksScanned, totalBlocks, currentHeight, outputsTried }`.
+#[wasm_bindgen(unchecked_return_type = "ZecTransaction[]")]
+pub fn scan_blocks_with_progress(
+    #[wasm_bindgen(unchecked_param_type = "WasmScanRequest")] request: JsValue,
+    #[wasm_bindgen(unchecked_param_type = "(progress: ScanProgress) => void")]
+    on_progress: &js_sys::Function,
+) -> Result<JsValue, JsValue> {
+    let transactions = scan(
+        from_js(request, "request")?,
+        &mut JsProgressSink(on_progress),
+    )?;
+    to_js(&transactions)
+}
+
+/// Scan compact blocks passed as binary protobuf.
 ///
-/// Returns JSON array of discovered transactions.
+/// `blocks` holds length-delimited `CompactBlock` messages, as streamed by
+/// lightwalletd, which avoids the hex encoding of [`scan_blocks`]. The
+/// buffer may be gzip- or zstd-compressed as a whole. Takes a request
+/// object with:
+/// - `viewing_key`: Unified Full Viewing Key (uview1...)
+/// - `key_id` (optional): Identifier for tracking which key found
+///   transactions, the key's `ufvk_fingerprint` by default
+/// - `network` (optional): `"mainnet"` (default) or `"testnet"`
+///
+/// Returns an array of discovered transactions.
+#[wasm_bindgen(unchecked_return_type = "ZecTransaction[]")]
+pub fn scan_blocks_bytes(
+    #[wasm_bindgen(unchecked_param_type = "WasmScannerConfig")] request: JsValue,
+    blocks: &[u8],
+) -> Result<JsValue, JsValue> {
+    to_js(&scan_bytes(from_js(request, "request")?, blocks)?)
+}
+
+/// Scan compact blocks with the request and results in a binary format,
+/// which is much smaller than JS objects or JSON for large scans.
+///
+/// `request` is a request as taken by [`scan_blocks`], serialized as
+/// `content_type`: `"application/cbor"`, `"application/msgpack"` or
+/// `"application/json"` (or just `"cbor"`, `"msgpack"`, `"json"`). Returns
+/// the array of discovered transactions serialized the same way, as a
+/// `Uint8Array`.
 #[wasm_bindgen]
+pub fn scan_blocks_encoded(request: &[u8], content_type: &str) -> Result<Vec<u8>, JsValue> {
+    let content_type: ContentType = content_type.parse().map_err(scan_error)?;
+    let request = content_type
+        .decode(request)
+        .map_err(|e| request_error("request", e))?;
+    let transactions = scan(request, &mut zecscope_scanner::NoProgress)?;
+    content_type.encode(&transactions).map_err(scan_error)
+}
+
+/// Group an array of transactions, e.g. the combined results of several
+/// keys' scans, by `key_id`.
+///
+/// Returns a `ScanReport`: `{ keys: [{ keyId, transactionCount,
+/// totalReceivedZat, pools, transactions }] }`.
+#[wasm_bindgen(unchecked_return_type = "ScanReport")]
+pub fn scan_report(
+    #[wasm_bindgen(unchecked_param_type = "ZecTransaction[]")] transactions: JsValue,
+) -> Result<JsValue, JsValue> {
+    let transactions: Vec<ZecTransaction> = from_js(transactions, "transactions")?;
+    to_js(&ScanReport::from_transactions(transactions))
+}
+
+/// Describe what this build can scan, so a web app can warn when, e.g.,
+/// Orchard scanning isn't available.
+///
+/// Returns `{ version, pools, features, networks }`.
+#[wasm_bindgen(unchecked_return_type = "WasmCapabilities")]
+pub fn capabilities() -> Result<JsValue, JsValue> {
+    to_js(&wasm_capabilities())
+}
+
+/// Scan compact blocks given as a JSON request, with the blocks as a JSON
+/// string in its `compact_blocks_json` field.
+///
+/// Returns a JSON array of discovered transactions.
+///
+/// @deprecated Use `scan_blocks`, which takes the request as an object and
+/// so avoids parsing the blocks twice.
+#[wasm_bindgen(unchecked_return_type = "string")]
 pub fn scan_compact_blocks(request_json: &str) -> Result<JsValue, JsValue> {
-    // Parse the request
-    let request: WasmScanRequest = serde_json::from_str(request_json)
-        .map_err(|e| JsValue::from_str(&format!("Invalid request JSON: {e}")))?;
+    let transactions = scan(
+        json_request(request_json)?,
+        &mut zecscope_scanner::NoProgress,
+    )?;
+    to_json(&transactions)
+}
 
-    // Parse compact blocks from the nested JSON string
-    let compact_blocks: Vec<CompactBlock> = serde_json::from_str(&request.compact_blocks_json)
-        .map_err(|e| JsValue::from_str(&format!("Invalid compact blocks JSON: {e}")))?;
+/// Scan compact blocks given as in [`scan_compact_blocks`], calling
+/// `on_progress` as the scan advances.
+///
+/// @deprecated Use `scan_blocks_with_progress`.
+#[wasm_bindgen(unchecked_return_type = "string")]
+pub fn scan_compact_blocks_with_progress(
+    request_json: &str,
+    on_progress: &js_sys::Function,
+) -> Result<JsValue, JsValue> {
+    let transactions = scan(
+        json_request(request_json)?,
+        &mut JsProgressSink(on_progress),
+    )?;
+    to_json(&transactions)
+}
+
+/// Scan binary protobuf compact blocks, as in [`scan_blocks_bytes`], with
+/// the request as JSON. Returns a JSON array of discovered transactions.
+///
+/// @deprecated Use `scan_blocks_bytes`.
+#[wasm_bindgen(unchecked_return_type = "string")]
+pub fn scan_compact_blocks_bytes(request_json: &str, blocks: &[u8]) -> Result<JsValue, JsValue> {
+    to_json(&scan_bytes(from_json(request_json, "request")?, blocks)?)
+}
+
+/// Group a JSON array of transactions by `key_id`, returning a JSON
+/// `ScanReport`.
+///
+/// @deprecated Use `scan_report`.
+#[wasm_bindgen(unchecked_return_type = "string")]
+pub fn build_scan_report(transactions_json: &str) -> Result<JsValue, JsValue> {
+    let transactions: Vec<ZecTransaction> = from_json(transactions_json, "transactions")?;
+    to_json(&ScanReport::from_transactions(transactions))
+}
+
+/// Describe what this build can scan, as JSON.
+///
+/// @deprecated Use `capabilities`.
+#[wasm_bindgen(unchecked_return_type = "string")]
+pub fn scanner_capabilities() -> Result<JsValue, JsValue> {
+    to_json(&wasm_capabilities())
+}
 
+fn scan(
+    request: WasmScanRequest,
+    progress: &mut dyn ProgressSink,
+) -> Result<Vec<ZecTransaction>, JsValue> {
     // Create the scanner request
     let scan_request = ScanRequest {
         viewing_key: request.viewing_key,
         key_id: request.key_id,
-        compact_blocks,
+        compact_blocks: request.compact_blocks,
+        options: ScanOptions::default(),
+        nullifiers: Default::default(),
+    };
+
+    // Create scanner for the requested network and scan
+    let scanner = Scanner::new(request.network.into());
+    scanner
+        .scan_with_progress(&scan_request, progress)
+        .map_err(scan_error)
+}
+
+fn scan_bytes(request: WasmScannerConfig, blocks: &[u8]) -> Result<Vec<ZecTransaction>, JsValue> {
+    let scan_request = ScanRequest {
+        viewing_key: request.viewing_key,
+        key_id: request.key_id,
+        compact_blocks: Vec::new(),
+        options: ScanOptions::default(),
+        nullifiers: Default::default(),
     };
 
-    // Create scanner for mainnet and scan
-    let scanner = Scanner::mainnet();
-    let transactions = scanner.scan(&scan_request)
-        .map_err(|e| JsValue::from_str(&format!("Scan error: {e}")))?;
+    let scanner = Scanner::new(request.network.into());
+    scanner
+        .scan_proto_delimited(&scan_request, blocks)
+        .map_err(scan_error)
+}
+
+/// Parse a request in the deprecated JSON format, with its compact blocks
+/// in a nested JSON string.
+fn json_request(request_json: &str) -> Result<WasmScanRequest, JsValue> {
+    let request: WasmJsonScanRequest = from_json(request_json, "request")?;
+    Ok(WasmScanRequest {
+        viewing_key: request.viewing_key,
+        key_id: request.key_id,
+        compact_blocks: from_json(&request.compact_blocks_json, "compact blocks")?,
+        network: request.network,
+    })
+}
 
-    // Serialize result to JSON
-    let json = serde_json::to_string(&transactions)
-        .map_err(|e| JsValue::from_str(&format!("Serialization error: {e}")))?;
+fn wasm_capabilities() -> WasmCapabilities {
+    WasmCapabilities {
+        scanner: zecscope_scanner::capabilities(),
+        networks: &[WasmNetwork::Mainnet, WasmNetwork::Testnet],
+    }
+}
+
+/// An incremental scan over blocks fed in chunks.
+///
+/// Holding tens of thousands of blocks in one JSON string is too much for a
+/// browser tab, so a session lets JS stream them instead: construct it once,
+/// call `feed_blocks` with consecutive chunks, then `finish`. The scan state
+/// (the last block's tree sizes and the notes discovered so far) is carried
+/// between chunks, so spends of notes found in earlier chunks are detected.
+#[wasm_bindgen]
+pub struct ScannerSession {
+    scanner: Scanner,
+    key: PreparedKey,
+    key_id: String,
+    state: ScanState,
+    transactions: Vec<ZecTransaction>,
+}
+
+#[wasm_bindgen]
+impl ScannerSession {
+    /// Start a session.
+    ///
+    /// Takes a config object (or, deprecated, its JSON) with:
+    /// - `viewing_key`: Unified Full Viewing Key (uview1...)
+    /// - `key_id` (optional): Identifier for tracking which key found
+    ///   transactions, the key's `ufvk_fingerprint` by default
+    /// - `network` (optional): `"mainnet"` (default) or `"testnet"`
+    /// - `state` (optional): scan state saved from an earlier session
+    #[wasm_bindgen(constructor)]
+    pub fn new(
+        #[wasm_bindgen(unchecked_param_type = "WasmSessionConfig | string")] config: JsValue,
+    ) -> Result<ScannerSession, JsValue> {
+        let config: WasmSessionConfig = from_js_or_json(config, "session")?;
+
+        let scanner = Scanner::new(config.network.into());
+        let key = scanner
+            .prepare_key(&config.viewing_key)
+            .map_err(scan_error)?;
+
+        Ok(Self {
+            scanner,
+            key,
+            key_id: key_id_or_fingerprint(config.key_id, &config.viewing_key),
+            state: config.state,
+            transactions: Vec::new(),
+        })
+    }
+
+    /// Scan the next chunk of blocks, given as an array of compact blocks.
+    ///
+    /// The chunk must directly follow the previous one. Returns an array of
+    /// the transactions discovered in this chunk. If the scan fails
+    /// part-way, the session keeps the blocks scanned before the error;
+    /// retry from `next_height`.
+    #[wasm_bindgen(unchecked_return_type = "ZecTransaction[]")]
+    pub fn feed(
+        &mut self,
+        #[wasm_bindgen(unchecked_param_type = "CompactBlock[]")] compact_blocks: JsValue,
+    ) -> Result<JsValue, JsValue> {
+        let compact_blocks = from_js(compact_blocks, "compact blocks")?;
+        to_js(self.scan_chunk(compact_blocks)?)
+    }
+
+    /// Scan the next chunk of blocks, given as a JSON array, returning a
+    /// JSON array of the transactions discovered in it.
+    ///
+    /// @deprecated Use `feed`.
+    #[wasm_bindgen(unchecked_return_type = "string")]
+    pub fn feed_blocks(&mut self, compact_blocks_json: &str) -> Result<JsValue, JsValue> {
+        let compact_blocks = from_json(compact_blocks_json, "compact blocks")?;
+        to_json(self.scan_chunk(compact_blocks)?)
+    }
+
+    /// Height the next chunk should start from, if any block has been scanned.
+    pub fn next_height(&self) -> Option<u64> {
+        self.state.next_height()
+    }
+
+    /// Current scan state, for resuming in a later session.
+    #[wasm_bindgen(unchecked_return_type = "ScanState")]
+    pub fn scan_state(&self) -> Result<JsValue, JsValue> {
+        to_js(&self.state)
+    }
+
+    /// Current scan state as JSON.
+    ///
+    /// @deprecated Use `scan_state`.
+    #[wasm_bindgen(unchecked_return_type = "string")]
+    pub fn state(&self) -> Result<JsValue, JsValue> {
+        to_json(&self.state)
+    }
+
+    /// Every transaction found so far, as an array.
+    #[wasm_bindgen(unchecked_return_type = "ZecTransaction[]")]
+    pub fn transactions(&self) -> Result<JsValue, JsValue> {
+        to_js(&self.transactions)
+    }
+
+    /// End the session, returning a JSON array of every transaction found.
+    ///
+    /// @deprecated Use `transactions`, then `free`.
+    #[wasm_bindgen(unchecked_return_type = "string")]
+    pub fn finish(self) -> Result<JsValue, JsValue> {
+        to_json(&self.transactions)
+    }
+}
+
+impl ScannerSession {
+    /// Scan `compact_blocks`, returning the transactions found in them.
+    fn scan_chunk(
+        &mut self,
+        compact_blocks: Vec<CompactBlock>,
+    ) -> Result<&[ZecTransaction], JsValue> {
+        let scan_request = block_request(&self.key_id, compact_blocks);
+        let transactions = self
+            .scanner
+            .scan_with_key(&self.key, &scan_request, &mut self.state)
+            .map_err(scan_error)?;
+
+        let start = self.transactions.len();
+        self.transactions.extend(transactions);
+        Ok(&self.transactions[start..])
+    }
+}
 
+/// A scanner bound to one viewing key, reused across calls.
+///
+/// The free functions decode and prepare the viewing key on every call;
+/// a `WasmScanner` does so once, at construction. Each call scans its blocks
+/// independently — use a [`ScannerSession`] to carry state between chunks.
+#[wasm_bindgen]
+pub struct WasmScanner {
+    scanner: Scanner,
+    key: PreparedKey,
+    key_id: String,
+}
+
+#[wasm_bindgen]
+impl WasmScanner {
+    /// Create a scanner.
+    ///
+    /// Takes a config object (or, deprecated, its JSON) with:
+    /// - `viewing_key`: Unified Full Viewing Key (uview1...)
+    /// - `key_id` (optional): Identifier for tracking which key found
+    ///   transactions, the key's `ufvk_fingerprint` by default
+    /// - `network` (optional): `"mainnet"` (default) or `"testnet"`
+    #[wasm_bindgen(constructor)]
+    pub fn new(
+        #[wasm_bindgen(unchecked_param_type = "WasmScannerConfig | string")] config: JsValue,
+    ) -> Result<WasmScanner, JsValue> {
+        let config: WasmScannerConfig = from_js_or_json(config, "scanner")?;
+
+        let scanner = Scanner::new(config.network.into());
+        let key = scanner
+            .prepare_key(&config.viewing_key)
+            .map_err(scan_error)?;
+
+        Ok(Self {
+            scanner,
+            key,
+            key_id: key_id_or_fingerprint(config.key_id, &config.viewing_key),
+        })
+    }
+
+    /// Scan an array of compact blocks, returning an array of discovered
+    /// transactions.
+    #[wasm_bindgen(unchecked_return_type = "ZecTransaction[]")]
+    pub fn scan_blocks(
+        &self,
+        #[wasm_bindgen(unchecked_param_type = "CompactBlock[]")] compact_blocks: JsValue,
+    ) -> Result<JsValue, JsValue> {
+        to_js(&self.scan_chunk(from_js(compact_blocks, "compact blocks")?)?)
+    }
+
+    /// Scan length-delimited protobuf compact blocks, as accepted by
+    /// [`scan_blocks_bytes`], returning an array of discovered transactions.
+    #[wasm_bindgen(unchecked_return_type = "ZecTransaction[]")]
+    pub fn scan_blocks_bytes(&self, blocks: &[u8]) -> Result<JsValue, JsValue> {
+        to_js(&self.scan_proto(blocks)?)
+    }
+
+    /// Scan a JSON array of compact blocks, returning a JSON array of
+    /// discovered transactions.
+    ///
+    /// @deprecated Use `scan_blocks`.
+    #[wasm_bindgen(unchecked_return_type = "string")]
+    pub fn scan(&self, compact_blocks_json: &str) -> Result<JsValue, JsValue> {
+        to_json(&self.scan_chunk(from_json(compact_blocks_json, "compact blocks")?)?)
+    }
+
+    /// Scan length-delimited protobuf compact blocks, returning a JSON array
+    /// of discovered transactions.
+    ///
+    /// @deprecated Use `scan_blocks_bytes`.
+    #[wasm_bindgen(unchecked_return_type = "string")]
+    pub fn scan_bytes(&self, blocks: &[u8]) -> Result<JsValue, JsValue> {
+        to_json(&self.scan_proto(blocks)?)
+    }
+}
+
+impl WasmScanner {
+    fn scan_chunk(
+        &self,
+        compact_blocks: Vec<CompactBlock>,
+    ) -> Result<Vec<ZecTransaction>, JsValue> {
+        self.scanner
+            .scan_with_key(
+                &self.key,
+                &block_request(&self.key_id, compact_blocks),
+                &mut ScanState::default(),
+            )
+            .map_err(scan_error)
+    }
+
+    fn scan_proto(&self, blocks: &[u8]) -> Result<Vec<ZecTransaction>, JsValue> {
+        self.scanner
+            .scan_proto_delimited_with_key(
+                &self.key,
+                &block_request(&self.key_id, Vec::new()),
+                blocks,
+            )
+            .map_err(scan_error)
+    }
+}
+
+/// `key_id`, or the fingerprint of `viewing_key` if it is empty — kept for
+/// sessions, whose per-chunk requests don't carry the key.
+fn key_id_or_fingerprint(key_id: String, viewing_key: &str) -> String {
+    if key_id.is_empty() {
+        ufvk_fingerprint(viewing_key)
+    } else {
+        key_id
+    }
+}
+
+/// Request for scanning with an already prepared key.
+fn block_request(key_id: &str, compact_blocks: Vec<CompactBlock>) -> ScanRequest {
+    ScanRequest {
+        viewing_key: String::new(),
+        key_id: key_id.to_string(),
+        compact_blocks,
+        options: ScanOptions::default(),
+        nullifiers: Default::default(),
+    }
+}
+
+#[wasm_bindgen(typescript_custom_section)]
+const SCAN_ERROR_INFO: &str = r#"
+/** Fields of the `Error` every function throws on failure. */
+export interface ScanErrorInfo {
+    code: string;
+    message: string;
+    height?: number;
+}
+"#;
+
+/// A JS `Error` carrying a machine-readable `code`, and the `height` of the
+/// block it is about when known, so callers can branch on the failure.
+fn js_error(code: &str, message: &str, height: Option<u64>) -> JsValue {
+    let error = js_sys::Error::new(message);
+    // Setting properties on a fresh object can't fail
+    let _ = js_sys::Reflect::set(&error, &"code".into(), &code.into());
+    if let Some(height) = height {
+        let _ = js_sys::Reflect::set(&error, &"height".into(), &(height as f64).into());
+    }
+    error.into()
+}
+
+/// A scanner error, with [`ScanError::code`] as its `code`.
+fn scan_error(e: ScanError) -> JsValue {
+    js_error(e.code(), &e.to_string(), e.height())
+}
+
+/// An `INVALID_REQUEST` error for input that couldn't be parsed.
+fn request_error(what: &str, e: impl std::fmt::Display) -> JsValue {
+    js_error("INVALID_REQUEST", &format!("Invalid {what}: {e}"), None)
+}
+
+/// Deserialize a JS value passed to the binding.
+fn from_js<T: serde::de::DeserializeOwned>(value: JsValue, what: &str) -> Result<T, JsValue> {
+    serde_wasm_bindgen::from_value(value).map_err(|e| request_error(what, e))
+}
+
+/// Deserialize input in the deprecated JSON string format.
+fn from_json<T: serde::de::DeserializeOwned>(json: &str, what: &str) -> Result<T, JsValue> {
+    serde_json::from_str(json).map_err(|e| request_error(&format!("{what} JSON"), e))
+}
+
+/// Deserialize a config object, or its JSON for callers of the deprecated
+/// string API.
+fn from_js_or_json<T: serde::de::DeserializeOwned>(
+    value: JsValue,
+    what: &str,
+) -> Result<T, JsValue> {
+    match value.as_string() {
+        Some(json) => from_json(&json, what),
+        None => from_js(value, what),
+    }
+}
+
+/// Convert an output to a JS value, shaped as its JSON would be (plain
+/// objects rather than `Map`s, `null` for missing values).
+fn to_js<T: serde::Serialize + ?Sized>(value: &T) -> Result<JsValue, JsValue> {
+    value
+        .serialize(&serde_wasm_bindgen::Serializer::json_compatible())
+        .map_err(|e| js_error("SERIALIZATION", &format!("Serialization error: {e}"), None))
+}
+
+/// Serialize an output for the deprecated JSON string API.
+fn to_json<T: serde::Serialize + ?Sized>(value: &T) -> Result<JsValue, JsValue> {
+    let json = serde_json::to_string(value)
+        .map_err(|e| js_error("SERIALIZATION", &format!("Serialization error: {e}"), None))?;
     Ok(JsValue::from_str(&json))
 }
 
+/// Forwards scan progress to a JavaScript callback.
+struct JsProgressSink<'a>(&'a js_sys::Function);
+
+impl ProgressSink for JsProgressSink<'_> {
+    fn on_progress(&mut self, progress: &ScanProgress) {
+        if let Ok(value) = to_js(progress) {
+            // Progress is best-effort; a throwing callback must not abort the scan
+            let _ = self.0.call1(&JsValue::NULL, &value);
+        }
+    }
+}
+
 /// Request format for WASM scanning.
-/// 
-/// Uses a nested JSON string for compact blocks to simplify
-/// the JavaScript-to-WASM data marshalling.
-#[derive(serde::Deserialize)]
+#[derive(serde::Serialize, serde::Deserialize, Tsify)]
 struct WasmScanRequest {
     viewing_key: String,
+    #[serde(default)]
+    key_id: String,
+    compact_blocks: Vec<CompactBlock>,
+    #[serde(default)]
+    network: WasmNetwork,
+}
+
+/// Request format of the deprecated JSON API, with the compact blocks in a
+/// nested JSON string.
+#[derive(serde::Deserialize)]
+struct WasmJsonScanRequest {
+    viewing_key: String,
+    #[serde(default)]
     key_id: String,
     compact_blocks_json: String,
+    #[serde(default)]
+    network: WasmNetwork,
+}
+
+/// Request format for [`scan_blocks_bytes`], where the blocks are
+/// passed separately, and config for a [`WasmScanner`].
+#[derive(serde::Deserialize, Tsify)]
+struct WasmScannerConfig {
+    viewing_key: String,
+    #[serde(default)]
+    key_id: String,
+    #[serde(default)]
+    network: WasmNetwork,
+}
+
+/// Result of [`capabilities`]: the scanner's, plus the networks
+/// this binding accepts.
+#[derive(serde::Serialize, Tsify)]
+struct WasmCapabilities {
+    #[serde(flatten)]
+    scanner: Capabilities,
+    networks: &'static [WasmNetwork],
+}
+
+/// One of the [`test_vectors`]: a request and what scanning it returns.
+#[cfg(feature = "test-vectors")]
+#[derive(serde::Serialize, Tsify)]
+#[serde(rename_all = "camelCase")]
+struct WasmTestVector {
+    name: &'static str,
+    pool: zecscope_scanner::ShieldedPool,
+    request: WasmScanRequest,
+    expected_transactions: Vec<ZecTransaction>,
+}
+
+/// Configuration for a [`ScannerSession`].
+#[derive(serde::Deserialize, Tsify)]
+struct WasmSessionConfig {
+    viewing_key: String,
+    #[serde(default)]
+    key_id: String,
+    #[serde(default)]
+    network: WasmNetwork,
+    #[serde(default)]
+    state: ScanState,
+}
+
+/// Network the compact blocks belong to.
+#[derive(serde::Serialize, serde::Deserialize, Tsify, Default, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+enum WasmNetwork {
+    #[default]
+    Mainnet,
+    Testnet,
+}
+
+impl From<WasmNetwork> for Network {
+    fn from(network: WasmNetwork) -> Self {
+        match network {
+            WasmNetwork::Mainnet => Network::MainNetwork,
+            WasmNetwork::Testnet => Network::TestNetwork,
+        }
+    }
+}
+
+/// Start a pool of `threads` Web Workers for scans to trial-decrypt on, in
+/// builds with the `threads` feature.
+///
+/// Await the returned promise once, before the first scan. Scans then block
+/// their calling thread while the workers decrypt, which browsers only allow
+/// off the main thread, so run them in a Worker. The page must be cross-origin
+/// isolated for the workers to share the module's memory.
+#[cfg(feature = "threads")]
+#[wasm_bindgen]
+pub fn init_thread_pool(threads: usize) -> js_sys::Promise {
+    wasm_bindgen_rayon::init_thread_pool(threads)
+}
+
+/// Golden data to check an integration against, in builds with the
+/// `test-vectors` feature: for each pool this build scans, a request for
+/// `scan_blocks` and the transactions it must return.
+#[cfg(feature = "test-vectors")]
+#[wasm_bindgen(unchecked_return_type = "WasmTestVector[]")]
+pub fn test_vectors() -> Result<JsValue, JsValue> {
+    let vectors: Vec<_> = zecscope_scanner::test_vectors::test_vectors()
+        .into_iter()
+        .map(|vector| WasmTestVector {
+            name: vector.name,
+            pool: vector.pool,
+            request: WasmScanRequest {
+                viewing_key: vector.viewing_key.to_string(),
+                key_id: vector.name.to_string(),
+                compact_blocks: vector.compact_blocks(),
+                network: WasmNetwork::Mainnet,
+            },
+            expected_transactions: vector.expected_transactions(),
+        })
+        .collect();
+    to_js(&vectors)
 }
 
 /// Get the version of the scanner.